@@ -0,0 +1,581 @@
+use crate::opt;
+use std::path::Path;
+
+pub const USAGE: &str = "\
+Usage: course-lab [OPTIONS] <INPUT>
+
+Compile a SysY program. INPUT may be `-` to read from stdin.
+
+Options:
+  --emit=KIND[=PATH],...  Artifacts to produce: ast, ast-json, ast-sexp, koopa, riscv, obj
+                          (default: riscv). ast-json and ast-sexp write the syntax tree with
+                          source spans, in a schema --input-format reads back.
+                          obj is an RV32IM relocatable ELF object.
+                          PATH overrides the output file of that artifact, `-` is stdout.
+  -o <PATH>               Output file. With several artifacts, the common file stem.
+  --input-format=KIND     Read INPUT as sysy source (default), or as a syntax tree written
+                          by --emit=ast-json or --emit=ast-sexp.
+  -O<N>                   Optimization level (default: 1). -O0 keeps every value on the stack,
+                          -O1 promotes local variables to SSA values, removes dead code and
+                          allocates registers,
+                          -O2 also runs the remaining IR optimizations.
+  --passes=PASS,...       Run exactly these IR passes instead of those of the -O level.
+                          PASS is one of mem2reg, inline, sccp (also constprop), gvn, licm,
+                          loop-reduce, dce, simplifycfg.
+  --print-after=PASS,...  Print the Koopa IR to stderr after each run of these passes.
+  --time-passes           Report the time spent in each IR pass on stderr.
+  --no-inline             Do not inline function calls, even if the passes include `inline`.
+  -h, --help              Print this help.
+  -V, --version           Print version information.
+
+Legacy modes:
+  -koopa <INPUT> -o <OUT>          Same as --emit=koopa.
+  -riscv <INPUT> -o <OUT>          Same as --emit=riscv.
+  -all <INPUT> -o <KOOPA> <ASM>    Emit both Koopa IR and RISC-V.
+  -interpret <INPUT>               Run the program on the Koopa IR interpreter, reading
+                                   stdin and exiting with the return value of `main`.
+  -run-riscv <INPUT>               Compile the program to RV32IM and run it on the built-in
+                                   emulator, reporting the instruction count on stderr.
+  -difftest <INPUT>                Run the unoptimized program on the interpreter and the
+                                   compiled one on the emulator, reporting the first
+                                   difference in output or return value.
+  -fmt <INPUT>                     Print the program reformatted with canonical indentation and
+                                   spacing, to stdout or -o. --in-place rewrites INPUT instead,
+                                   unless it has comments, which formatting does not keep.
+  -fuzz <COUNT>                    Compile COUNT random programs and check each end to end,
+                                   down to a differential test of the RISC-V code. The first
+                                   failing program is minimized and printed. --seed=N selects
+                                   the first seed (default: 0); program i uses seed N+i.
+";
+
+/// An artifact the compiler can write.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Emit {
+    Ast,
+    AstJson,
+    AstSexp,
+    Koopa,
+    Riscv,
+    Obj,
+}
+
+impl Emit {
+    fn parse(name: &str) -> Option<Emit> {
+        match name {
+            "ast" => Some(Emit::Ast),
+            "ast-json" => Some(Emit::AstJson),
+            "ast-sexp" => Some(Emit::AstSexp),
+            "koopa" => Some(Emit::Koopa),
+            "riscv" => Some(Emit::Riscv),
+            "obj" => Some(Emit::Obj),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Emit::Ast => "ast",
+            Emit::AstJson => "ast-json",
+            Emit::AstSexp => "ast-sexp",
+            Emit::Koopa => "koopa",
+            Emit::Riscv => "riscv",
+            Emit::Obj => "obj",
+        }
+    }
+
+    /// Extension of the default output file.
+    fn extension(&self) -> &'static str {
+        match self {
+            Emit::Ast => "ast",
+            Emit::AstJson => "ast.json",
+            Emit::AstSexp => "ast.sexp",
+            Emit::Koopa => "koopa",
+            Emit::Riscv => "s",
+            Emit::Obj => "o",
+        }
+    }
+}
+
+/// The syntax of the input file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputFormat {
+    Sysy,
+    AstJson,
+    AstSexp,
+}
+
+impl InputFormat {
+    fn parse(name: &str) -> Result<InputFormat, String> {
+        match name {
+            "sysy" => Ok(InputFormat::Sysy),
+            "ast-json" => Ok(InputFormat::AstJson),
+            "ast-sexp" => Ok(InputFormat::AstSexp),
+            _ => Err(format!(
+                "unknown input format `{0}`, expected one of: sysy, ast-json, ast-sexp",
+                name
+            )),
+        }
+    }
+}
+
+/// Options of a compilation. Paths equal to `-` stand for stdin/stdout.
+#[derive(Debug)]
+pub struct Options {
+    pub input: String,
+    pub input_format: InputFormat,
+    /// 要生成的产物及其输出路径，按命令行中的顺序
+    pub emits: Vec<(Emit, String)>,
+    pub opt_level: u32,
+    /// 用`--passes`指定的pass序列，未指定时由优化级别决定
+    pub passes: Option<Vec<String>>,
+    pub print_after: Vec<String>,
+    pub time_passes: bool,
+    /// 从pass序列中去掉函数内联
+    pub no_inline: bool,
+    /// 是否在解释器中运行程序
+    pub interpret: bool,
+    /// 是否在模拟器中运行生成的RISC-V代码
+    pub run_riscv: bool,
+    /// 是否比较解释器与模拟器的运行结果
+    pub difftest: bool,
+}
+
+/// Options of formatting a source file.
+#[derive(Debug)]
+pub struct FormatOptions {
+    pub input: String,
+    pub input_format: InputFormat,
+    /// 输出路径，`-`为stdout，就地格式化时与输入相同
+    pub output: String,
+    pub in_place: bool,
+}
+
+/// Options of fuzzing with random programs.
+#[derive(Debug)]
+pub struct FuzzOptions {
+    pub count: u64,
+    pub seed: u64,
+    pub opt_level: u32,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Compile(Options),
+    Format(FormatOptions),
+    Fuzz(FuzzOptions),
+    Help,
+    Version,
+}
+
+/// Parse the command line (without the program name).
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut emits: Vec<(Emit, Option<String>)> = Vec::new();
+    let mut positional: Vec<String> = Vec::new();
+    let mut output: Option<String> = None;
+    let mut opt_level = 1;
+    let mut input_format = InputFormat::Sysy;
+    let mut legacy_all = false;
+    let mut interpret = false;
+    let mut run_riscv = false;
+    let mut difftest = false;
+    let mut fmt = false;
+    let mut in_place = false;
+    let mut fuzz_count = None;
+    let mut seed = 0;
+    let mut passes = None;
+    let mut print_after = Vec::new();
+    let mut time_passes = false;
+    let mut no_inline = false;
+
+    let mut add_emit = |emit: Emit, path: Option<String>| {
+        if emits.iter().any(|(e, _)| *e == emit) {
+            return Err(format!("`{0}` is emitted more than once", emit.name()));
+        }
+        emits.push((emit, path));
+        Ok(())
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-koopa" => add_emit(Emit::Koopa, None)?,
+            "-riscv" => add_emit(Emit::Riscv, None)?,
+            "-all" => {
+                add_emit(Emit::Koopa, None)?;
+                add_emit(Emit::Riscv, None)?;
+                legacy_all = true;
+            }
+            "-interpret" => interpret = true,
+            "-run-riscv" => run_riscv = true,
+            "-difftest" => difftest = true,
+            "-fmt" => fmt = true,
+            "--in-place" => in_place = true,
+            "-fuzz" => match args.next() {
+                Some(count) => fuzz_count = Some(parse_number(&count)?),
+                None => return Err("`-fuzz` requires a count".to_string()),
+            },
+            "--seed" => match args.next() {
+                Some(value) => seed = parse_number(&value)?,
+                None => return Err("`--seed` requires a number".to_string()),
+            },
+            "--time-passes" => time_passes = true,
+            "--no-inline" => no_inline = true,
+            "-o" => match args.next() {
+                Some(path) => output = Some(path),
+                None => return Err("`-o` requires a path".to_string()),
+            },
+            "--emit" => match args.next() {
+                Some(list) => parse_emit_list(&list, &mut add_emit)?,
+                None => return Err("`--emit` requires a list of artifacts".to_string()),
+            },
+            "--passes" => match args.next() {
+                Some(list) => passes = Some(parse_pass_list(&list)?),
+                None => return Err("`--passes` requires a list of passes".to_string()),
+            },
+            "--input-format" => match args.next() {
+                Some(name) => input_format = InputFormat::parse(&name)?,
+                None => return Err("`--input-format` requires a format".to_string()),
+            },
+            "--print-after" => match args.next() {
+                Some(list) => print_after.extend(parse_pass_list(&list)?),
+                None => return Err("`--print-after` requires a list of passes".to_string()),
+            },
+            "-" => positional.push(arg),
+            _ => {
+                if let Some(list) = arg.strip_prefix("--emit=") {
+                    parse_emit_list(list, &mut add_emit)?;
+                } else if let Some(list) = arg.strip_prefix("--passes=") {
+                    passes = Some(parse_pass_list(list)?);
+                } else if let Some(list) = arg.strip_prefix("--print-after=") {
+                    print_after.extend(parse_pass_list(list)?);
+                } else if let Some(name) = arg.strip_prefix("--input-format=") {
+                    input_format = InputFormat::parse(name)?;
+                } else if let Some(value) = arg.strip_prefix("--seed=") {
+                    seed = parse_number(value)?;
+                } else if let Some(level) = arg.strip_prefix("-O") {
+                    opt_level = level
+                        .parse()
+                        .map_err(|_| format!("invalid optimization level `{0}`", arg))?;
+                } else if arg.starts_with('-') {
+                    return Err(format!("unknown option `{0}`", arg));
+                } else {
+                    positional.push(arg);
+                }
+            }
+        }
+    }
+
+    // 模糊测试不需要输入文件
+    if let Some(count) = fuzz_count {
+        if let Some(extra) = positional.first() {
+            return Err(format!("unexpected argument `{0}`", extra));
+        }
+        return Ok(Command::Fuzz(FuzzOptions {
+            count,
+            seed,
+            opt_level,
+        }));
+    }
+
+    let mut positional = positional.into_iter();
+    let input = match positional.next() {
+        Some(input) => input,
+        None => return Err("no input file".to_string()),
+    };
+    if fmt {
+        if let Some(extra) = positional.next() {
+            return Err(format!("unexpected argument `{0}`", extra));
+        }
+        let output = match (in_place, output) {
+            (false, output) => output.unwrap_or_else(|| "-".to_string()),
+            (true, _) if input_format != InputFormat::Sysy => {
+                return Err("`--in-place` requires sysy input".to_string())
+            }
+            (true, None) if input != "-" => input.clone(),
+            (true, None) => return Err("`--in-place` requires an input file".to_string()),
+            (true, Some(_)) => return Err("`--in-place` cannot be used with `-o`".to_string()),
+        };
+        return Ok(Command::Format(FormatOptions {
+            input,
+            input_format,
+            output,
+            in_place,
+        }));
+    }
+    if in_place {
+        return Err("`--in-place` requires `-fmt`".to_string());
+    }
+    // -all 的第二个位置参数是汇编的输出路径
+    if legacy_all {
+        if let Some(asm_output) = positional.next() {
+            let koopa_output = output.take();
+            for (emit, path) in emits.iter_mut() {
+                match emit {
+                    Emit::Koopa => *path = koopa_output.clone(),
+                    Emit::Riscv => *path = Some(asm_output.clone()),
+                    _ => {}
+                }
+            }
+        }
+    }
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument `{0}`", extra));
+    }
+    if emits.is_empty() && !interpret && !run_riscv && !difftest {
+        emits.push((Emit::Riscv, None));
+    }
+
+    // 未指定路径的产物：只有一个时使用-o，多个时以-o为文件名主干；
+    // 没有-o时以输入文件名为主干，从stdin读入时输出到stdout
+    let single = emits.iter().filter(|(_, path)| path.is_none()).count() == 1;
+    let emits = emits
+        .into_iter()
+        .map(|(emit, path)| {
+            let path = path.unwrap_or_else(|| match &output {
+                Some(out) if single || out == "-" => out.clone(),
+                Some(out) => with_extension(out, emit),
+                None if input == "-" => "-".to_string(),
+                None => with_extension(&input, emit),
+            });
+            (emit, path)
+        })
+        .collect::<Vec<_>>();
+    if input != "-" && emits.iter().any(|(_, path)| *path == input) {
+        return Err(format!(
+            "output would overwrite the input file `{0}`",
+            input
+        ));
+    }
+    Ok(Command::Compile(Options {
+        input,
+        input_format,
+        emits,
+        opt_level,
+        passes,
+        print_after,
+        time_passes,
+        no_inline,
+        interpret,
+        run_riscv,
+        difftest,
+    }))
+}
+
+/// Parse `kind[=path],...` of `--emit`.
+fn parse_emit_list(
+    list: &str,
+    add_emit: &mut impl FnMut(Emit, Option<String>) -> Result<(), String>,
+) -> Result<(), String> {
+    for item in list.split(',') {
+        let (name, path) = match item.split_once('=') {
+            Some((name, path)) => (name, Some(path.to_string())),
+            None => (item, None),
+        };
+        match Emit::parse(name) {
+            Some(emit) => add_emit(emit, path)?,
+            None => {
+                return Err(format!(
+                    "unknown emit kind `{0}`, expected one of: ast, ast-json, ast-sexp, koopa, riscv, obj",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse a comma-separated list of pass names, resolving aliases; an empty
+/// list selects none.
+fn parse_pass_list(list: &str) -> Result<Vec<String>, String> {
+    let mut passes = Vec::new();
+    for name in list.split(',').filter(|name| !name.is_empty()) {
+        match opt::pass_name(name) {
+            Some(pass) => passes.push(pass.to_string()),
+            None => {
+                return Err(format!(
+                    "unknown pass `{0}`, expected one of: {1}",
+                    name,
+                    opt::pass_names().collect::<Vec<_>>().join(", ")
+                ))
+            }
+        }
+    }
+    Ok(passes)
+}
+
+fn parse_number(value: &str) -> Result<u64, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid number `{0}`", value))
+}
+
+/// `path` with its extension replaced by the default one of `emit`.
+fn with_extension(path: &str, emit: Emit) -> String {
+    Path::new(path)
+        .with_extension(emit.extension())
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn compile(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Compile(options)) => options,
+            other => panic!("{0:?} is not a compilation: {1:?}", args, other),
+        }
+    }
+
+    fn emits(options: &Options) -> Vec<(Emit, &str)> {
+        options
+            .emits
+            .iter()
+            .map(|(emit, path)| (*emit, path.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn emit_list() {
+        let options = compile(&["--emit=koopa,riscv=out.s,obj", "a.c"]);
+        assert_eq!(
+            emits(&options),
+            [
+                (Emit::Koopa, "a.koopa"),
+                (Emit::Riscv, "out.s"),
+                (Emit::Obj, "a.o")
+            ]
+        );
+        let options = compile(&["--emit", "ast-json", "--emit=ast-sexp", "a.c"]);
+        assert_eq!(
+            emits(&options),
+            [(Emit::AstJson, "a.ast.json"), (Emit::AstSexp, "a.ast.sexp")]
+        );
+        assert!(parse(&["--emit=koopa,koopa", "a.c"])
+            .unwrap_err()
+            .contains("more than once"));
+        assert!(parse(&["-koopa", "--emit=koopa", "a.c"])
+            .unwrap_err()
+            .contains("more than once"));
+        assert!(parse(&["--emit=llvm", "a.c"])
+            .unwrap_err()
+            .contains("unknown emit kind `llvm`"));
+        assert!(parse(&["a.c", "--emit"]).is_err());
+    }
+
+    #[test]
+    fn stdio() {
+        let options = compile(&["-"]);
+        assert_eq!(options.input, "-");
+        assert_eq!(emits(&options), [(Emit::Riscv, "-")]);
+        let options = compile(&["--emit=koopa,riscv", "-"]);
+        assert_eq!(emits(&options), [(Emit::Koopa, "-"), (Emit::Riscv, "-")]);
+        let options = compile(&["--emit=koopa,riscv", "-o", "-", "a.c"]);
+        assert_eq!(emits(&options), [(Emit::Koopa, "-"), (Emit::Riscv, "-")]);
+        let options = compile(&["--emit=koopa=-,riscv", "a.c"]);
+        assert_eq!(emits(&options), [(Emit::Koopa, "-"), (Emit::Riscv, "a.s")]);
+        match parse(&["-fmt", "-"]) {
+            Ok(Command::Format(options)) => {
+                assert_eq!(options.input, "-");
+                assert_eq!(options.output, "-");
+            }
+            other => panic!("not a formatting: {0:?}", other),
+        }
+    }
+
+    #[test]
+    fn default_outputs() {
+        let options = compile(&["dir/a.sy"]);
+        assert_eq!(emits(&options), [(Emit::Riscv, "dir/a.s")]);
+        let options = compile(&["-koopa", "a.sy", "-o", "b.txt"]);
+        assert_eq!(emits(&options), [(Emit::Koopa, "b.txt")]);
+        let options = compile(&["--emit=koopa,obj", "-o", "build/b", "a.sy"]);
+        assert_eq!(
+            emits(&options),
+            [(Emit::Koopa, "build/b.koopa"), (Emit::Obj, "build/b.o")]
+        );
+        let options = compile(&["-all", "a.sy", "-o", "a.koopa", "a.asm"]);
+        assert_eq!(
+            emits(&options),
+            [(Emit::Koopa, "a.koopa"), (Emit::Riscv, "a.asm")]
+        );
+        // 只运行程序时不生成任何产物
+        let options = compile(&["-interpret", "a.sy"]);
+        assert!(options.emits.is_empty());
+        assert!(parse(&["--emit=koopa", "a.koopa"])
+            .unwrap_err()
+            .contains("overwrite the input file"));
+    }
+
+    #[test]
+    fn pass_lists() {
+        let options = compile(&[
+            "--passes=mem2reg,constprop,dce",
+            "--print-after=constprop",
+            "a.sy",
+        ]);
+        assert_eq!(options.passes.unwrap(), ["mem2reg", "sccp", "dce"]);
+        assert_eq!(options.print_after, ["sccp"]);
+        assert!(compile(&["--passes=", "a.sy"]).passes.unwrap().is_empty());
+        // 帮助中列出了所有的pass
+        assert!(opt::pass_names().all(|pass| USAGE.contains(pass)));
+    }
+
+    #[test]
+    fn unknown_options() {
+        assert_eq!(
+            parse(&["--frobnicate", "a.sy"]).unwrap_err(),
+            "unknown option `--frobnicate`"
+        );
+        assert!(parse(&["-Ofast", "a.sy"])
+            .unwrap_err()
+            .contains("invalid optimization level"));
+        assert!(parse(&["--passes=nope", "a.sy"])
+            .unwrap_err()
+            .contains("unknown pass `nope`"));
+        assert!(parse(&["--input-format=yaml", "a.sy"])
+            .unwrap_err()
+            .contains("unknown input format `yaml`"));
+        assert!(parse(&["-fuzz", "many"])
+            .unwrap_err()
+            .contains("invalid number `many`"));
+        assert_eq!(parse(&[]).unwrap_err(), "no input file");
+        assert_eq!(
+            parse(&["a.sy", "b.sy"]).unwrap_err(),
+            "unexpected argument `b.sy`"
+        );
+    }
+
+    #[test]
+    fn conflicting_modes() {
+        assert_eq!(
+            parse(&["--in-place", "a.sy"]).unwrap_err(),
+            "`--in-place` requires `-fmt`"
+        );
+        assert_eq!(
+            parse(&["-fmt", "--in-place", "-o", "b.sy", "a.sy"]).unwrap_err(),
+            "`--in-place` cannot be used with `-o`"
+        );
+        assert_eq!(
+            parse(&["-fmt", "--in-place", "-"]).unwrap_err(),
+            "`--in-place` requires an input file"
+        );
+        assert_eq!(
+            parse(&["-fmt", "--in-place", "--input-format=ast-json", "a.json"]).unwrap_err(),
+            "`--in-place` requires sysy input"
+        );
+        assert_eq!(
+            parse(&["-fuzz", "10", "a.sy"]).unwrap_err(),
+            "unexpected argument `a.sy`"
+        );
+        assert!(matches!(parse(&["-h", "a.sy"]), Ok(Command::Help)));
+        assert!(matches!(parse(&["--version"]), Ok(Command::Version)));
+    }
+}
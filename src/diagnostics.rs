@@ -0,0 +1,226 @@
+use lalrpop_util::ParseError;
+use std::fmt;
+
+/// A byte range `[start, end)` in the source file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+}
+
+/// Error codes reported by the compiler. `E00xx` come from the parser and
+/// `E01xx` from the frontend.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ErrorCode {
+    /// 无法识别的字符
+    InvalidToken,
+    /// 语法错误
+    UnexpectedToken,
+    /// 文件意外结束
+    UnexpectedEof,
+    /// 整数字面量超出范围
+    IntegerOverflow,
+    /// 以JSON或S表达式输入的AST不合法
+    InvalidAst,
+    /// 使用未声明的标识符
+    UndeclaredIdentifier,
+    /// 同一作用域内重复定义
+    Redefinition,
+    /// 对常量赋值
+    AssignToConst,
+    /// 调用的对象不是函数
+    NotAFunction,
+    /// 把函数当作变量使用
+    NotAVariable,
+    /// 实参个数与形参不符
+    ArgumentCount,
+    /// 变量或数组的初始化列表不合法
+    InvalidInitializer,
+    /// 循环外的 break/continue
+    OutsideLoop,
+    /// 表达式的类型与所需类型不符
+    TypeMismatch,
+    /// 把 void 函数的调用结果当作值使用
+    VoidValue,
+    /// 下标个数超过数组的维数
+    InvalidSubscript,
+    /// 数组长度不是正数
+    InvalidArraySize,
+    /// 常量表达式中引用了变量或调用了函数
+    NotConstant,
+    /// 常量表达式中除以零
+    DivisionByZero,
+    /// 常量数组的下标越界
+    IndexOutOfBounds,
+}
+
+impl ErrorCode {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidToken => "E0001",
+            ErrorCode::UnexpectedToken => "E0002",
+            ErrorCode::UnexpectedEof => "E0003",
+            ErrorCode::IntegerOverflow => "E0004",
+            ErrorCode::InvalidAst => "E0005",
+            ErrorCode::UndeclaredIdentifier => "E0101",
+            ErrorCode::Redefinition => "E0102",
+            ErrorCode::AssignToConst => "E0103",
+            ErrorCode::NotAFunction => "E0104",
+            ErrorCode::ArgumentCount => "E0105",
+            ErrorCode::InvalidInitializer => "E0106",
+            ErrorCode::OutsideLoop => "E0107",
+            ErrorCode::NotAVariable => "E0108",
+            ErrorCode::TypeMismatch => "E0109",
+            ErrorCode::VoidValue => "E0110",
+            ErrorCode::InvalidSubscript => "E0111",
+            ErrorCode::InvalidArraySize => "E0112",
+            ErrorCode::NotConstant => "E0113",
+            ErrorCode::DivisionByZero => "E0114",
+            ErrorCode::IndexOutOfBounds => "E0115",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// An error pointing at a span of the source file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub code: ErrorCode,
+    pub message: String,
+    pub span: Span,
+}
+
+impl Diagnostic {
+    pub fn new(code: ErrorCode, span: Span, message: impl Into<String>) -> Self {
+        Diagnostic {
+            code,
+            message: message.into(),
+            span,
+        }
+    }
+
+    /// Render the diagnostic with its location and the offending source line,
+    /// underlining the span with carets.
+    pub fn render(&self, file_name: &str, source: &str) -> String {
+        // 不在字符边界上的位置向外扩展到整个字符
+        let start = source.floor_char_boundary(self.span.start);
+        let (line, col) = line_col(source, start);
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let text = source[line_start..].lines().next().unwrap_or("");
+        // 下划线不超过当前行的末尾，且至少有一个 ^
+        let end = source
+            .ceil_char_boundary(self.span.end)
+            .clamp(start, line_start + text.len());
+        let width = source[start..end].chars().count().max(1);
+        let gutter = " ".repeat(line.to_string().len());
+        let mut s = format!(
+            "error[{0}]: {1} at {2}:{3}\n",
+            self.code, self.message, line, col
+        );
+        s += &format!("{0}--> {1}:{2}:{3}\n", gutter, file_name, line, col);
+        s += &format!("{0} |\n", gutter);
+        s += &format!("{0} | {1}\n", line, text);
+        s += &format!(
+            "{0} | {1}{2}\n",
+            gutter,
+            " ".repeat(col - 1),
+            "^".repeat(width)
+        );
+        s
+    }
+}
+
+/// 1-based line and column (in characters) of a byte offset.
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..source.floor_char_boundary(offset)];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Describe the tokens lalrpop expected, naming the regex terminals.
+fn expected_tokens(expected: &[String]) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    for token in expected {
+        let name = if token.starts_with("r#\"[_a-zA-Z]") {
+            "identifier"
+        } else if token.starts_with("r#") {
+            "integer literal"
+        } else {
+            token
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names.join(", ")
+}
+
+impl Diagnostic {
+    /// Convert a syntax error in `source`, underlining the whole of an
+    /// invalid character rather than its first byte.
+    pub fn from_parse_error<T: fmt::Display>(
+        err: ParseError<usize, T, Diagnostic>,
+        source: &str,
+    ) -> Self {
+        let mut diagnostic = Diagnostic::from(err);
+        if diagnostic.code == ErrorCode::InvalidToken {
+            let start = diagnostic.span.start;
+            if let Some(ch) = source.get(start..).and_then(|rest| rest.chars().next()) {
+                diagnostic.span.end = start + ch.len_utf8();
+            }
+        }
+        diagnostic
+    }
+}
+
+impl<T: fmt::Display> From<ParseError<usize, T, Diagnostic>> for Diagnostic {
+    fn from(err: ParseError<usize, T, Diagnostic>) -> Self {
+        match err {
+            ParseError::InvalidToken { location } => Diagnostic::new(
+                ErrorCode::InvalidToken,
+                Span::new(location, location + 1),
+                "invalid token",
+            ),
+            ParseError::UnrecognizedEof { location, expected } => Diagnostic::new(
+                ErrorCode::UnexpectedEof,
+                Span::new(location, location),
+                format!(
+                    "unexpected end of file, expected {0}",
+                    expected_tokens(&expected)
+                ),
+            ),
+            ParseError::UnrecognizedToken {
+                token: (l, token, r),
+                expected,
+            } => Diagnostic::new(
+                ErrorCode::UnexpectedToken,
+                Span::new(l, r),
+                format!(
+                    "unexpected token `{0}`, expected {1}",
+                    token,
+                    expected_tokens(&expected)
+                ),
+            ),
+            ParseError::ExtraToken {
+                token: (l, token, r),
+            } => Diagnostic::new(
+                ErrorCode::UnexpectedToken,
+                Span::new(l, r),
+                format!("unexpected token `{0}`", token),
+            ),
+            ParseError::User { error } => error,
+        }
+    }
+}
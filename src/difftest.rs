@@ -0,0 +1,121 @@
+//! Differential testing of the RISC-V backend against the Koopa IR
+//! interpreter.
+//!
+//! A program is run twice on the same input: its unoptimized Koopa IR on the
+//! interpreter, which is the reference, and its optimized IR compiled to
+//! RV32IM on the [emulator](crate::emulator). Any difference in the output,
+//! the return value of `main` or a fault of the emulated code is a
+//! miscompilation in the IR optimizations or the backend, reported as the
+//! first point where the two runs diverge. The [fuzzer](crate::fuzz) feeds it
+//! random programs.
+
+use crate::emulator::{self, EmulatorError};
+use crate::interpreter::{interpret, RuntimeError};
+use crate::koopa2asm::assemble::{assemble, AssembleError};
+use crate::koopa2asm::generate;
+use koopa::ir::Program;
+use std::fmt;
+
+/// 输出不同时，报告的差异前后保留的字节数
+const CONTEXT: usize = 24;
+
+/// The first point where the compiled program behaves differently from the
+/// interpreted one.
+#[derive(Debug)]
+pub enum Divergence {
+    /// 解释器运行出错，程序的行为没有定义，无法比较
+    Reference(RuntimeError),
+    /// 后端生成的代码无法汇编
+    Assemble(AssembleError),
+    /// 模拟器运行出错，此前的输出与解释器一致
+    Backend(EmulatorError),
+    /// 输出从第`offset`个字节开始不同，两边各保留差异处附近的内容
+    Output {
+        offset: usize,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+    ReturnValue {
+        expected: i32,
+        actual: i32,
+    },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Divergence::Reference(err) => write!(f, "the interpreter failed: {0}", err),
+            Divergence::Assemble(err) => {
+                write!(f, "the compiled program does not assemble: {0}", err)
+            }
+            Divergence::Backend(err) => write!(f, "the compiled program failed: {0}", err),
+            Divergence::Output {
+                offset,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "output differs at byte {0}: expected \"{1}\", got \"{2}\"",
+                offset,
+                expected.escape_ascii(),
+                actual.escape_ascii()
+            ),
+            Divergence::ReturnValue { expected, actual } => {
+                write!(f, "`main` returned {0}, expected {1}", actual, expected)
+            }
+        }
+    }
+}
+
+/// The bytes of `output` around `offset`.
+fn context(output: &[u8], offset: usize) -> Vec<u8> {
+    let start = offset.saturating_sub(CONTEXT);
+    let end = output.len().min(offset + CONTEXT);
+    output[start.min(end)..end].to_vec()
+}
+
+/// Run `reference` on `input` on the interpreter and `program`, compiled with
+/// or without backend optimizations, on the emulator, and return the return
+/// value of `main` if both runs agree. `step_limit` bounds the instructions of
+/// the emulator.
+pub fn compare(
+    reference: &Program,
+    program: &Program,
+    input: &[u8],
+    optimize: bool,
+    step_limit: Option<u64>,
+) -> Result<i32, Divergence> {
+    let mut expected = Vec::new();
+    let ret = interpret(reference, input, &mut expected).map_err(Divergence::Reference)?;
+
+    let obj = assemble(&generate(program, optimize)).map_err(Divergence::Assemble)?;
+    let mut actual = Vec::new();
+    let result = emulator::run(&obj, input, &mut actual, step_limit);
+
+    // 出错之前的输出也要比较，先报告最早出现的差异
+    let offset = expected
+        .iter()
+        .zip(&actual)
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(actual.len()));
+    let output_differs = offset < expected.len().max(actual.len());
+    let diverged_first = offset < expected.len().min(actual.len());
+    let exit = match result {
+        Err(err) if !diverged_first => return Err(Divergence::Backend(err)),
+        Ok(exit) if !output_differs => exit,
+        _ => {
+            return Err(Divergence::Output {
+                offset,
+                expected: context(&expected, offset),
+                actual: context(&actual, offset),
+            })
+        }
+    };
+    if exit.code != ret {
+        return Err(Divergence::ReturnValue {
+            expected: ret,
+            actual: exit.code,
+        });
+    }
+    Ok(ret)
+}
@@ -0,0 +1,486 @@
+//! An emulator for the RV32IM code of the backend.
+//!
+//! An assembled [`Object`] is linked into a flat memory: `.text` at
+//! [`TEXT_BASE`], `.data` and `.bss` from the next page after it, and the
+//! stack below [`STACK_TOP`]. Functions of the SysY runtime library are
+//! linked to reserved addresses in the first page; jumping to one of them runs
+//! the function in the emulator, like a system call, and returns to `ra`.
+//! `main` is called with `ra` pointing to another reserved address, and
+//! returning there ends the program.
+//!
+//! Fetching outside of `.text`, writing to it, and accessing memory outside
+//! of the sections and the stack or at a misaligned address are faults. The
+//! last memory accesses are kept to be reported with a fault.
+
+use crate::interpreter::Input;
+use crate::koopa2asm::assemble::{Object, Section};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// Address `.text` is loaded at.
+pub const TEXT_BASE: u32 = 0x1_0000;
+/// Initial stack pointer, the end of memory.
+pub const STACK_TOP: u32 = 0x800_0000;
+const STACK_SIZE: u32 = 0x200_0000;
+const PAGE_SIZE: u32 = 0x1000;
+
+/// `main`返回到这个地址时程序结束
+const EXIT_ADDR: u32 = 0x100;
+/// 运行时库函数依次位于从这里开始的地址
+const RUNTIME_BASE: u32 = 0x200;
+const RUNTIME: [&str; 8] = [
+    "getint",
+    "getch",
+    "getarray",
+    "putint",
+    "putch",
+    "putarray",
+    "starttime",
+    "stoptime",
+];
+
+/// 出错时报告的最近访存次数
+const TRACE_LEN: usize = 16;
+
+/// A load or store executed by the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub pc: u32,
+    pub store: bool,
+    pub addr: u32,
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FaultKind {
+    /// 取指地址不在代码段中或未对齐
+    InvalidFetch(u32),
+    IllegalInstruction(u32),
+    /// 访问的地址不在各段或栈中、未对齐，或写入代码段
+    InvalidAccess(u32),
+    StepLimit,
+}
+
+/// A fault stopping the program, with the state it happened in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub pc: u32,
+    /// 此前执行的指令数
+    pub steps: u64,
+    /// 最近的访存，最早的在前
+    pub trace: Vec<Access>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EmulatorError {
+    NoMain,
+    UndefinedSymbol(String),
+    /// 代码与数据放不进栈以下的内存
+    ProgramTooLarge,
+    Fault(Fault),
+    Io(String),
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FaultKind::InvalidFetch(addr) => write!(f, "instruction fetch from {0:#x}", addr),
+            FaultKind::IllegalInstruction(word) => {
+                write!(f, "illegal instruction {0:#010x}", word)
+            }
+            FaultKind::InvalidAccess(addr) => write!(f, "invalid memory access at {0:#x}", addr),
+            FaultKind::StepLimit => write!(f, "instruction limit reached"),
+        }
+    }
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulatorError::NoMain => write!(f, "function `main` is not defined"),
+            EmulatorError::UndefinedSymbol(name) => write!(f, "undefined symbol `{0}`", name),
+            EmulatorError::ProgramTooLarge => write!(f, "program does not fit in memory"),
+            EmulatorError::Fault(fault) => {
+                write!(
+                    f,
+                    "{0} (pc {1:#x}, after {2} instructions)",
+                    fault.kind, fault.pc, fault.steps
+                )?;
+                if !fault.trace.is_empty() {
+                    write!(f, "\nlast memory accesses:")?;
+                }
+                for access in &fault.trace {
+                    let (op, arrow) = if access.store {
+                        ("sw", "<-")
+                    } else {
+                        ("lw", "->")
+                    };
+                    write!(
+                        f,
+                        "\n  {0:#010x}: {1} [{2:#010x}] {3} {4}",
+                        access.pc, op, access.addr, arrow, access.value
+                    )?;
+                }
+                Ok(())
+            }
+            EmulatorError::Io(err) => write!(f, "I/O error: {0}", err),
+        }
+    }
+}
+
+/// The result of a program that ran to completion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Exit {
+    /// `main`的返回值
+    pub code: i32,
+    pub steps: u64,
+}
+
+/// A decoded instruction. Registers are numbers, and `funct3`/`funct7`
+/// select the operation within a format.
+#[derive(Debug, Clone, Copy)]
+enum Inst {
+    Lui(usize, u32),
+    Auipc(usize, u32),
+    Jal(usize, i32),
+    Jalr(usize, usize, i32),
+    Branch(u32, usize, usize, i32),
+    Load(u32, usize, usize, i32),
+    Store(u32, usize, usize, i32),
+    OpImm(u32, u32, usize, usize, i32),
+    Op(u32, u32, usize, usize, usize),
+    Illegal(u32),
+}
+
+fn decode(word: u32) -> Inst {
+    let rd = (word >> 7 & 0x1f) as usize;
+    let rs1 = (word >> 15 & 0x1f) as usize;
+    let rs2 = (word >> 20 & 0x1f) as usize;
+    let funct3 = word >> 12 & 0x7;
+    let funct7 = word >> 25;
+    let i_imm = word as i32 >> 20;
+    let s_imm = (word as i32 >> 25) << 5 | (word >> 7 & 0x1f) as i32;
+    let b_imm = (word as i32 >> 31) << 12
+        | ((word >> 7 & 1) << 11 | (word >> 25 & 0x3f) << 5 | (word >> 8 & 0xf) << 1) as i32;
+    let j_imm = (word as i32 >> 31) << 20
+        | ((word >> 12 & 0xff) << 12 | (word >> 20 & 1) << 11 | (word >> 21 & 0x3ff) << 1) as i32;
+    match word & 0x7f {
+        0x37 => Inst::Lui(rd, word & 0xfffff000),
+        0x17 => Inst::Auipc(rd, word & 0xfffff000),
+        0x6f => Inst::Jal(rd, j_imm),
+        0x67 if funct3 == 0 => Inst::Jalr(rd, rs1, i_imm),
+        0x63 if !matches!(funct3, 2 | 3) => Inst::Branch(funct3, rs1, rs2, b_imm),
+        0x03 if matches!(funct3, 0 | 1 | 2 | 4 | 5) => Inst::Load(funct3, rd, rs1, i_imm),
+        0x23 if funct3 <= 2 => Inst::Store(funct3, rs1, rs2, s_imm),
+        0x13 => match (funct3, funct7) {
+            (1, 0) | (5, 0) | (5, 0x20) => Inst::OpImm(funct3, funct7, rd, rs1, i_imm & 0x1f),
+            (1 | 5, _) => Inst::Illegal(word),
+            _ => Inst::OpImm(funct3, 0, rd, rs1, i_imm),
+        },
+        0x33 if matches!((funct7, funct3), (0, _) | (1, _) | (0x20, 0) | (0x20, 5)) => {
+            Inst::Op(funct7, funct3, rd, rs1, rs2)
+        }
+        _ => Inst::Illegal(word),
+    }
+}
+
+/// Compute an operation of `OP` or `OP-IMM`, which share their encoding of
+/// `funct3` and `funct7`.
+fn alu(funct7: u32, funct3: u32, a: i32, b: i32) -> i32 {
+    let shamt = (b & 0x1f) as u32;
+    match (funct7, funct3) {
+        (0, 0) => a.wrapping_add(b),
+        (0x20, 0) => a.wrapping_sub(b),
+        (0, 1) => a << shamt,
+        (0, 2) => (a < b) as i32,
+        (0, 3) => ((a as u32) < (b as u32)) as i32,
+        (0, 4) => a ^ b,
+        (0, 5) => ((a as u32) >> shamt) as i32,
+        (0x20, 5) => a >> shamt,
+        (0, 6) => a | b,
+        (0, 7) => a & b,
+        (1, 0) => a.wrapping_mul(b),
+        (1, 1) => ((a as i64 * b as i64) >> 32) as i32,
+        (1, 2) => ((a as i64 * b as u32 as i64) >> 32) as i32,
+        (1, 3) => ((a as u32 as u64 * b as u32 as u64) >> 32) as i32,
+        // 除以0与溢出不会陷入，结果由规范规定
+        (1, 4) => match b {
+            0 => -1,
+            _ => a.wrapping_div(b),
+        },
+        (1, 5) => match b {
+            0 => -1,
+            _ => ((a as u32) / (b as u32)) as i32,
+        },
+        (1, 6) => match b {
+            0 => a,
+            _ => a.wrapping_rem(b),
+        },
+        (1, 7) => match b {
+            0 => a,
+            _ => ((a as u32) % (b as u32)) as i32,
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// A linked program being executed.
+struct Machine<R: BufRead, W: Write> {
+    regs: [i32; 32],
+    pc: u32,
+    memory: Vec<u8>,
+    /// 预先解码的代码段
+    text: Vec<Inst>,
+    /// .data与.bss段的范围
+    data: (u32, u32),
+    steps: u64,
+    step_limit: Option<u64>,
+    trace: VecDeque<Access>,
+    input: Input<R>,
+    output: W,
+}
+
+/// Link `obj`, then run its `main` reading `input` and writing `output`. With
+/// `step_limit`, executing more instructions than that is a fault. `input` is
+/// read only as far as the program asks for it.
+pub fn run<R: BufRead, W: Write>(
+    obj: &Object,
+    input: R,
+    output: W,
+    step_limit: Option<u64>,
+) -> Result<Exit, EmulatorError> {
+    let text_end = TEXT_BASE + obj.text.len() as u32;
+    let data_base = text_end.next_multiple_of(PAGE_SIZE);
+    let bss_base = data_base + obj.data.len() as u32;
+    let bss_end = bss_base as u64 + obj.bss as u64;
+    if bss_end > (STACK_TOP - STACK_SIZE) as u64 {
+        return Err(EmulatorError::ProgramTooLarge);
+    }
+    let mut addrs = Vec::new();
+    for symbol in &obj.symbols {
+        addrs.push(match symbol.section {
+            Some(Section::Text) => TEXT_BASE + symbol.offset,
+            Some(Section::Data) => data_base + symbol.offset,
+            Some(Section::Bss) => bss_base + symbol.offset,
+            None => match RUNTIME.iter().position(|&name| name == symbol.name) {
+                Some(i) => RUNTIME_BASE + i as u32 * 4,
+                None => return Err(EmulatorError::UndefinedSymbol(symbol.name.clone())),
+            },
+        });
+    }
+    let entry = obj
+        .symbols
+        .iter()
+        .position(|symbol| symbol.name == "main" && symbol.section == Some(Section::Text))
+        .ok_or(EmulatorError::NoMain)?;
+
+    let mut text = obj.text.clone();
+    for reloc in &obj.relocations {
+        let offset = reloc.offset as usize;
+        let pc = TEXT_BASE + reloc.offset;
+        reloc.kind.apply(&mut text, offset, pc, addrs[reloc.symbol]);
+    }
+    let mut memory = vec![0; STACK_TOP as usize];
+    memory[TEXT_BASE as usize..text_end as usize].copy_from_slice(&text);
+    memory[data_base as usize..bss_base as usize].copy_from_slice(&obj.data);
+    let text = text
+        .chunks(4)
+        .map(|word| decode(u32::from_le_bytes(word.try_into().unwrap())))
+        .collect();
+
+    let mut machine = Machine {
+        regs: [0; 32],
+        pc: addrs[entry],
+        memory,
+        text,
+        data: (data_base, bss_end as u32),
+        steps: 0,
+        step_limit,
+        trace: VecDeque::new(),
+        input: Input::new(input),
+        output,
+    };
+    machine.regs[1] = EXIT_ADDR as i32;
+    machine.regs[2] = STACK_TOP as i32;
+    machine.run()
+}
+
+impl<R: BufRead, W: Write> Machine<R, W> {
+    fn fault(&self, kind: FaultKind) -> EmulatorError {
+        EmulatorError::Fault(Fault {
+            kind,
+            pc: self.pc,
+            steps: self.steps,
+            trace: self.trace.iter().copied().collect(),
+        })
+    }
+
+    fn set(&mut self, rd: usize, value: i32) {
+        if rd != 0 {
+            self.regs[rd] = value;
+        }
+    }
+
+    /// Check an access of `size` bytes at `addr`.
+    fn check(&self, addr: u32, size: u32, store: bool) -> Result<usize, EmulatorError> {
+        let end = addr as u64 + size as u64;
+        let text_end = TEXT_BASE as u64 + self.text.len() as u64 * 4;
+        let valid = addr.is_multiple_of(size)
+            && ((addr >= self.data.0 && end <= self.data.1 as u64)
+                || (addr >= STACK_TOP - STACK_SIZE && end <= STACK_TOP as u64)
+                || (!store && addr >= TEXT_BASE && end <= text_end));
+        if valid {
+            Ok(addr as usize)
+        } else {
+            Err(self.fault(FaultKind::InvalidAccess(addr)))
+        }
+    }
+
+    fn record(&mut self, store: bool, addr: u32, value: i32) {
+        if self.trace.len() == TRACE_LEN {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(Access {
+            pc: self.pc,
+            store,
+            addr,
+            value,
+        });
+    }
+
+    /// Load `1 << width` bytes at `addr`, sign-extended unless `unsigned`.
+    fn load(&mut self, addr: u32, width: u32, unsigned: bool) -> Result<i32, EmulatorError> {
+        let size = 1 << width;
+        let index = self.check(addr, size, false)?;
+        let mut bytes = [0; 4];
+        bytes[..size as usize].copy_from_slice(&self.memory[index..index + size as usize]);
+        let value = u32::from_le_bytes(bytes);
+        let value = match (size, unsigned) {
+            (1, false) => value as u8 as i8 as i32,
+            (2, false) => value as u16 as i16 as i32,
+            _ => value as i32,
+        };
+        self.record(false, addr, value);
+        Ok(value)
+    }
+
+    /// Store the low `1 << width` bytes of `value` at `addr`.
+    fn store(&mut self, addr: u32, width: u32, value: i32) -> Result<(), EmulatorError> {
+        let size = 1 << width;
+        let index = self.check(addr, size, true)?;
+        self.memory[index..index + size as usize]
+            .copy_from_slice(&value.to_le_bytes()[..size as usize]);
+        self.record(true, addr, value);
+        Ok(())
+    }
+
+    fn run(&mut self) -> Result<Exit, EmulatorError> {
+        loop {
+            let pc = self.pc;
+            if pc == EXIT_ADDR {
+                return Ok(Exit {
+                    code: self.regs[10],
+                    steps: self.steps,
+                });
+            }
+            let runtime_end = RUNTIME_BASE + RUNTIME.len() as u32 * 4;
+            if (RUNTIME_BASE..runtime_end).contains(&pc) && pc.is_multiple_of(4) {
+                let result = self.call_runtime(RUNTIME[((pc - RUNTIME_BASE) / 4) as usize])?;
+                self.regs[10] = result;
+                self.pc = self.regs[1] as u32;
+                continue;
+            }
+            let index = pc.wrapping_sub(TEXT_BASE) / 4;
+            if pc < TEXT_BASE || !pc.is_multiple_of(4) || index as usize >= self.text.len() {
+                return Err(self.fault(FaultKind::InvalidFetch(pc)));
+            }
+            if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+                return Err(self.fault(FaultKind::StepLimit));
+            }
+            self.steps += 1;
+
+            let mut next = pc.wrapping_add(4);
+            match self.text[index as usize] {
+                Inst::Lui(rd, imm) => self.set(rd, imm as i32),
+                Inst::Auipc(rd, imm) => self.set(rd, pc.wrapping_add(imm) as i32),
+                Inst::Jal(rd, imm) => {
+                    self.set(rd, next as i32);
+                    next = pc.wrapping_add(imm as u32);
+                }
+                Inst::Jalr(rd, rs1, imm) => {
+                    let target = self.regs[rs1].wrapping_add(imm) as u32 & !1;
+                    self.set(rd, next as i32);
+                    next = target;
+                }
+                Inst::Branch(funct3, rs1, rs2, imm) => {
+                    let (a, b) = (self.regs[rs1], self.regs[rs2]);
+                    let taken = match funct3 {
+                        0 => a == b,
+                        1 => a != b,
+                        4 => a < b,
+                        5 => a >= b,
+                        6 => (a as u32) < (b as u32),
+                        _ => (a as u32) >= (b as u32),
+                    };
+                    if taken {
+                        next = pc.wrapping_add(imm as u32);
+                    }
+                }
+                Inst::Load(funct3, rd, rs1, imm) => {
+                    let addr = self.regs[rs1].wrapping_add(imm) as u32;
+                    let value = self.load(addr, funct3 & 3, funct3 & 4 != 0)?;
+                    self.set(rd, value);
+                }
+                Inst::Store(funct3, rs1, rs2, imm) => {
+                    let addr = self.regs[rs1].wrapping_add(imm) as u32;
+                    self.store(addr, funct3, self.regs[rs2])?;
+                }
+                Inst::OpImm(funct3, funct7, rd, rs1, imm) => {
+                    let value = alu(funct7, funct3, self.regs[rs1], imm);
+                    self.set(rd, value);
+                }
+                Inst::Op(funct7, funct3, rd, rs1, rs2) => {
+                    let value = alu(funct7, funct3, self.regs[rs1], self.regs[rs2]);
+                    self.set(rd, value);
+                }
+                Inst::Illegal(word) => return Err(self.fault(FaultKind::IllegalInstruction(word))),
+            }
+            self.pc = next;
+        }
+    }
+
+    /// Serve a call to a function of the SysY runtime library, with the
+    /// arguments in `a0` and `a1`.
+    fn call_runtime(&mut self, name: &str) -> Result<i32, EmulatorError> {
+        let io_err = |err: io::Error| EmulatorError::Io(err.to_string());
+        let (a0, a1) = (self.regs[10], self.regs[11]);
+        match name {
+            "getint" => self.input.getint().map_err(io_err),
+            "getch" => self.input.getch().map_err(io_err),
+            "getarray" => {
+                let len = self.input.getint().map_err(io_err)?;
+                for i in 0..len {
+                    let value = self.input.getint().map_err(io_err)?;
+                    self.store(a0.wrapping_add(i * 4) as u32, 2, value)?;
+                }
+                Ok(len)
+            }
+            "putint" => write!(self.output, "{}", a0).map_err(io_err).map(|_| 0),
+            "putch" => self
+                .output
+                .write_all(&[a0 as u8])
+                .map_err(io_err)
+                .map(|_| 0),
+            "putarray" => {
+                let mut s = format!("{}:", a0);
+                for i in 0..a0 {
+                    s += &format!(" {}", self.load(a1.wrapping_add(i * 4) as u32, 2, false)?);
+                }
+                writeln!(self.output, "{}", s).map_err(io_err).map(|_| 0)
+            }
+            _ => Ok(0),
+        }
+    }
+}
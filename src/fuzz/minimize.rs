@@ -0,0 +1,409 @@
+//! Shrinking a failing program while it keeps failing.
+//!
+//! The minimizer tries one edit of the tree at a time and keeps those after
+//! which the program is still interesting, until no edit is left. Edits
+//! remove global items, block items and initializer elements, replace an
+//! `if` by one of its branches or a `while` by its body, and replace an
+//! expression by one of its operands or by 0, or remove parentheses around
+//! all of it.
+//!
+//! Edits must not make a generated program undefined, since the shrunk
+//! program would then fail for a different reason. Array dimensions,
+//! indices and the values of scalar constants, which dimensions may be
+//! computed from, are therefore left alone, and so are loop conditions and
+//! the first statement of a loop body, which advances the loop counter.
+
+use super::{from_items, into_items};
+use crate::ir_gen::ast::*;
+
+/// Shrink `unit` as long as `interesting` holds for the result.
+pub fn minimize(unit: CompUnit, mut interesting: impl FnMut(&CompUnit) -> bool) -> CompUnit {
+    let mut unit = unit;
+    loop {
+        let mut changed = false;
+        let mut target = 0;
+        // 接受一次修改后，同一序号指向下一个可能的修改
+        while let Some(candidate) = edit(&unit, target) {
+            if interesting(&candidate) {
+                unit = candidate;
+                changed = true;
+            } else {
+                target += 1;
+            }
+        }
+        if !changed {
+            return unit;
+        }
+    }
+}
+
+/// Apply the `target`-th edit of `unit`, if there are that many.
+fn edit(unit: &CompUnit, target: usize) -> Option<CompUnit> {
+    let mut items = into_items(unit.clone());
+    let mut editor = Editor { target, count: 0 };
+    editor.items(&mut items);
+    match editor.count > target {
+        true => from_items(items),
+        false => None,
+    }
+}
+
+/// The expression inside `exp`, if all of `exp` is in parentheses.
+fn parenthesized(exp: &Exp) -> Option<&Exp> {
+    let LOrExp::LAndExp(exp) = exp.lor_exp.as_ref() else {
+        return None;
+    };
+    let LAndExp::EqExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    let EqExp::RelExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    let RelExp::AddExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    let AddExp::MulExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    let MulExp::UnaryExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    let UnaryExp::PrimaryExp(exp) = exp.as_ref() else {
+        return None;
+    };
+    match exp.as_ref() {
+        PrimaryExp::Exp(inner) => Some(inner),
+        _ => None,
+    }
+}
+
+/// Walks the tree counting the possible edits, and applies the one numbered
+/// `target`.
+struct Editor {
+    target: usize,
+    count: usize,
+}
+
+impl Editor {
+    /// Count an edit, returning whether to apply it.
+    fn hit(&mut self) -> bool {
+        self.count += 1;
+        self.count == self.target + 1
+    }
+
+    /// Whether the edit has been applied and the walk can stop.
+    fn done(&self) -> bool {
+        self.count > self.target
+    }
+
+    fn items(&mut self, items: &mut Vec<GlobalItem>) {
+        // 至少保留一项，编译单元不能为空
+        if items.len() > 1 {
+            for i in 0..items.len() {
+                if self.hit() {
+                    items.remove(i);
+                    return;
+                }
+            }
+        }
+        for item in items {
+            match item {
+                GlobalItem::Func(func_def) => self.block(&mut func_def.block, false),
+                GlobalItem::Decl(decl) => self.decl(decl),
+            }
+            if self.done() {
+                return;
+            }
+        }
+    }
+
+    fn decl(&mut self, decl: &mut Decl) {
+        match decl {
+            Decl::ConstDecl(const_decl) => {
+                // 常量标量可能出现在数组的维数中，不能修改
+                for def in &mut const_decl.const_defs {
+                    if !def.dims.is_empty() {
+                        self.const_init(&mut def.const_init_val);
+                    }
+                }
+            }
+            Decl::VarDecl(var_decl) => {
+                for def in &mut var_decl.var_defs {
+                    if let VarDef::Def((_, _, init, _, _)) = def {
+                        self.init(init);
+                    }
+                }
+            }
+        }
+    }
+
+    fn init(&mut self, init: &mut InitVal) {
+        match init {
+            InitVal::Exp(exp) => self.exp(exp),
+            InitVal::Array(inits) => {
+                for i in 0..inits.len() {
+                    if self.hit() {
+                        inits.remove(i);
+                        return;
+                    }
+                }
+                for init in inits {
+                    self.init(init);
+                }
+            }
+        }
+    }
+
+    fn const_init(&mut self, init: &mut ConstInitVal) {
+        match init {
+            ConstInitVal::Exp(exp) => self.exp(&mut exp.exp),
+            ConstInitVal::Array(inits) => {
+                for i in 0..inits.len() {
+                    if self.hit() {
+                        inits.remove(i);
+                        return;
+                    }
+                }
+                for init in inits {
+                    self.const_init(init);
+                }
+            }
+        }
+    }
+
+    /// Edit the items of `block`; `in_loop` keeps the first one.
+    fn block(&mut self, block: &mut Block, in_loop: bool) {
+        let first = in_loop as usize;
+        for i in first..block.items.len() {
+            if self.hit() {
+                block.items.remove(i);
+                return;
+            }
+        }
+        for (i, item) in block.items.iter_mut().enumerate() {
+            if i < first {
+                continue;
+            }
+            match item {
+                BlockItem::Decl(decl) => self.decl(decl),
+                BlockItem::Stmt(stmt) => self.stmt(stmt),
+            }
+            if self.done() {
+                return;
+            }
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            Stmt::Return((Some(exp), _)) | Stmt::Assign((_, exp)) | Stmt::Exp(Some(exp)) => {
+                self.exp(exp)
+            }
+            Stmt::Block(block) => self.block(block, false),
+            Stmt::IF(if_stmt) => {
+                if self.hit() {
+                    *stmt = if_stmt.then_stmt.clone();
+                    return;
+                }
+                if let Some(else_stmt) = &if_stmt.else_stmt {
+                    if self.hit() {
+                        *stmt = else_stmt.clone();
+                        return;
+                    }
+                    if self.hit() {
+                        if_stmt.else_stmt = None;
+                        return;
+                    }
+                }
+                self.exp(&mut if_stmt.cond);
+                self.stmt(&mut if_stmt.then_stmt);
+                if let Some(else_stmt) = &mut if_stmt.else_stmt {
+                    self.stmt(else_stmt);
+                }
+            }
+            Stmt::WHILE(while_stmt) => {
+                if self.hit() {
+                    *stmt = while_stmt.body_stmt.clone();
+                    return;
+                }
+                // 循环体只有一条语句时，它就是推进计数器的语句
+                if let Stmt::Block(block) = &mut while_stmt.body_stmt {
+                    self.block(block, true);
+                }
+            }
+            Stmt::Return((None, _)) | Stmt::Exp(None) | Stmt::Break(_) | Stmt::Continue(_) => {}
+        }
+    }
+
+    // 每一层优先级的二元表达式可以替换为同一层的左操作数，或提升到这一层的右操作数
+
+    fn exp(&mut self, exp: &mut Exp) {
+        if let Some(inner) = parenthesized(exp) {
+            if self.hit() {
+                *exp = inner.clone();
+                return;
+            }
+        }
+        self.lor(&mut exp.lor_exp);
+    }
+
+    fn lor(&mut self, exp: &mut LOrExp) {
+        if let LOrExp::LOrExp((lhs, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = LOrExp::LAndExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            LOrExp::LAndExp(exp) => self.land(exp),
+            LOrExp::LOrExp((lhs, rhs)) => {
+                self.lor(lhs);
+                self.land(rhs);
+            }
+        }
+    }
+
+    fn land(&mut self, exp: &mut LAndExp) {
+        if let LAndExp::LAndExp((lhs, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = LAndExp::EqExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            LAndExp::EqExp(exp) => self.eq(exp),
+            LAndExp::LAndExp((lhs, rhs)) => {
+                self.land(lhs);
+                self.eq(rhs);
+            }
+        }
+    }
+
+    fn eq(&mut self, exp: &mut EqExp) {
+        if let EqExp::EqExp((lhs, _, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = EqExp::RelExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            EqExp::RelExp(exp) => self.rel(exp),
+            EqExp::EqExp((lhs, _, rhs)) => {
+                self.eq(lhs);
+                self.rel(rhs);
+            }
+        }
+    }
+
+    fn rel(&mut self, exp: &mut RelExp) {
+        if let RelExp::CompExp((lhs, _, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = RelExp::AddExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            RelExp::AddExp(exp) => self.add(exp),
+            RelExp::CompExp((lhs, _, rhs)) => {
+                self.rel(lhs);
+                self.add(rhs);
+            }
+        }
+    }
+
+    fn add(&mut self, exp: &mut AddExp) {
+        if let AddExp::AddExp((lhs, _, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = AddExp::MulExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            AddExp::MulExp(exp) => self.mul(exp),
+            AddExp::AddExp((lhs, _, rhs)) => {
+                self.add(lhs);
+                self.mul(rhs);
+            }
+        }
+    }
+
+    fn mul(&mut self, exp: &mut MulExp) {
+        if let MulExp::MulExp((lhs, _, rhs)) = exp {
+            if self.hit() {
+                *exp = (**lhs).clone();
+                return;
+            }
+            if self.hit() {
+                *exp = MulExp::UnaryExp(rhs.clone());
+                return;
+            }
+        }
+        match exp {
+            MulExp::UnaryExp(exp) => self.unary(exp),
+            MulExp::MulExp((lhs, _, rhs)) => {
+                self.mul(lhs);
+                self.unary(rhs);
+            }
+        }
+    }
+
+    fn unary(&mut self, exp: &mut UnaryExp) {
+        let zero = UnaryExp::PrimaryExp(Box::new(PrimaryExp::Number(0)));
+        match exp {
+            UnaryExp::PrimaryExp(primary) => match primary.as_mut() {
+                PrimaryExp::Exp(inner) => self.exp(inner),
+                PrimaryExp::Number(0) => {}
+                PrimaryExp::Number(_) | PrimaryExp::LVal(_) => {
+                    if self.hit() {
+                        *exp = zero;
+                    }
+                }
+            },
+            UnaryExp::UnaryExp((_, inner)) => {
+                // 去掉-2147483648的负号后，字面量就超出了范围
+                let int_min = matches!(inner.as_ref(), UnaryExp::PrimaryExp(primary)
+                    if **primary == PrimaryExp::Number(i32::MIN));
+                if !int_min && self.hit() {
+                    *exp = (**inner).clone();
+                    return;
+                }
+                self.unary(inner);
+            }
+            UnaryExp::FuncItem((_, params, _, _)) => {
+                if self.hit() {
+                    *exp = zero;
+                    return;
+                }
+                for arg in params
+                    .iter_mut()
+                    .flat_map(|params| &mut params.func_r_params)
+                {
+                    self.exp(arg);
+                    if self.done() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
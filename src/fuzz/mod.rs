@@ -0,0 +1,199 @@
+//! Fuzzing of the compiler with random programs.
+//!
+//! [`random`] generates the AST of a well-typed program whose behavior is
+//! defined, and the [AST printer](crate::ir_gen::print) turns it into
+//! source. [`check`] takes that source through the whole compiler:
+//! parsing, which must give back a tree that prints the same, semantic
+//! analysis with constant evaluation, Koopa IR generation and the
+//! optimizations of a `-O` level, and finally the RISC-V backend, whose
+//! result is compared with the interpreter by
+//! [differential testing](crate::difftest). [`minimize`] shrinks a program
+//! that fails while it keeps failing the same way.
+
+pub mod minimize;
+pub mod random;
+
+use crate::diagnostics::Diagnostic;
+use crate::difftest::{self, Divergence};
+use crate::ir_gen::ast::{CompUnit, GlobalItem};
+use crate::opt;
+use crate::sysy::CompUnitParser;
+use koopa::back::KoopaGenerator;
+use std::any::Any;
+use std::fmt;
+use std::mem::discriminant;
+use std::panic;
+
+/// More instructions than any generated program runs.
+pub const STEP_LIMIT: u64 = 50_000_000;
+
+/// A xorshift64* pseudo-random number generator.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // 状态不能为0，先用splitmix64打散种子
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// A number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// A number in `lo..=hi`.
+    pub fn range(&mut self, lo: i32, hi: i32) -> i32 {
+        lo + self.below((hi - lo) as usize + 1) as i32
+    }
+
+    /// True with a probability of `percent`%.
+    pub fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+pub fn into_items(unit: CompUnit) -> Vec<GlobalItem> {
+    let mut items = Vec::new();
+    let mut unit = Some(unit);
+    while let Some(u) = unit {
+        items.push(u.global_item);
+        unit = *u.comp_unit;
+    }
+    items.reverse();
+    items
+}
+
+/// The compilation unit of `items`, or `None` if there are none.
+pub fn from_items(items: Vec<GlobalItem>) -> Option<CompUnit> {
+    let mut unit = None;
+    for global_item in items {
+        unit = Some(CompUnit {
+            comp_unit: Box::new(unit),
+            global_item,
+        });
+    }
+    unit
+}
+
+/// A way a generated program makes the compiler fail.
+#[derive(Debug)]
+pub enum Failure {
+    /// 编译器panic，保存panic的信息
+    Panic(String),
+    /// 打印出的源码无法解析
+    Parse(Diagnostic),
+    /// 解析后重新打印得到不同的源码，保存第一个不同的行号
+    RoundTrip(usize),
+    /// 语义分析或IR生成报错
+    Frontend(Diagnostic),
+    /// 生成的Koopa IR文本无法重新解析
+    Koopa(String),
+    Divergence(Divergence),
+}
+
+impl Failure {
+    /// Whether `self` and `other` are the same failure, as far as
+    /// minimization is concerned: the same error code, or the same kind of
+    /// divergence.
+    pub fn same_kind(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Failure::Parse(a), Failure::Parse(b))
+            | (Failure::Frontend(a), Failure::Frontend(b)) => a.code == b.code,
+            (Failure::Divergence(a), Failure::Divergence(b)) => discriminant(a) == discriminant(b),
+            _ => discriminant(self) == discriminant(other),
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Panic(message) => write!(f, "the compiler panicked: {0}", message),
+            Failure::Parse(diagnostic) => write!(
+                f,
+                "the program does not parse: error[{0}]: {1}",
+                diagnostic.code, diagnostic.message
+            ),
+            Failure::RoundTrip(line) => write!(
+                f,
+                "the parsed program prints differently, from line {0}",
+                line
+            ),
+            Failure::Frontend(diagnostic) => write!(
+                f,
+                "the program is rejected: error[{0}]: {1}",
+                diagnostic.code, diagnostic.message
+            ),
+            Failure::Koopa(err) => write!(f, "the emitted Koopa IR does not parse: {0}", err),
+            Failure::Divergence(divergence) => write!(f, "{0}", divergence),
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Compile `unit` at `opt_level` from its printed source and compare the
+/// compiled program with the interpreter. Panics of the compiler are caught
+/// and reported as failures.
+pub fn check(unit: &CompUnit, opt_level: u32) -> Result<(), Failure> {
+    let source = unit.to_string();
+    match panic::catch_unwind(|| compile_and_compare(&source, opt_level)) {
+        Ok(result) => result,
+        Err(payload) => Err(Failure::Panic(panic_message(payload.as_ref()))),
+    }
+}
+
+fn compile_and_compare(source: &str, opt_level: u32) -> Result<(), Failure> {
+    let mut ast = CompUnitParser::new()
+        .parse(source)
+        .map_err(|err| Failure::Parse(Diagnostic::from_parse_error(err, source)))?;
+    let reprinted = ast.to_string();
+    if let Some(line) = source
+        .lines()
+        .zip(reprinted.lines())
+        .position(|(a, b)| a != b)
+        .or((source != reprinted).then(|| source.lines().count().min(reprinted.lines().count())))
+    {
+        return Err(Failure::RoundTrip(line + 1));
+    }
+    let symbols = ast
+        .analyze()
+        .map_err(|mut diagnostics| Failure::Frontend(diagnostics.remove(0)))?;
+    let generate = || {
+        ast.generate_koopa(&symbols)
+            .map_err(|mut diagnostics| Failure::Frontend(diagnostics.remove(0)))
+    };
+    // 解释未优化的IR作为参照，才能发现IR优化中的错误
+    let reference = generate()?;
+    let mut program = generate()?;
+    opt::optimize(&mut program, opt_level);
+
+    let mut generator = KoopaGenerator::new(Vec::new());
+    generator.generate_on(&program).unwrap();
+    let text = String::from_utf8(generator.writer()).unwrap();
+    koopa::front::Driver::from(text)
+        .generate_program()
+        .map_err(|err| Failure::Koopa(format!("{0:?}", err)))?;
+
+    difftest::compare(&reference, &program, &[], opt_level > 0, Some(STEP_LIMIT))
+        .map_err(Failure::Divergence)?;
+    Ok(())
+}
@@ -0,0 +1,927 @@
+//! Generation of random programs as ASTs.
+//!
+//! The programs are well-typed and their behavior is defined, so the
+//! interpreter and the compiled code must agree on them:
+//! - every local variable and array is initialized, and global ones are
+//!   zero-initialized;
+//! - divisors are never 0 or -1;
+//! - array indices are reduced into range, and arrays passed to functions are
+//!   at least as long as the function assumes;
+//! - loops are counted by a variable that is only advanced at the start of
+//!   the body, so `continue` cannot skip it;
+//! - functions only call functions defined before them;
+//! - programs read no input.
+//!
+//! Inner blocks may shadow outer variables, but never functions.
+
+use super::Rng;
+use crate::diagnostics::Span;
+use crate::ir_gen::ast::*;
+
+/// 数组的最大维数
+const MAX_DIMS: usize = 3;
+/// 数组每一维的最大长度
+const MAX_DIM_LEN: i32 = 4;
+/// 循环的最大次数
+const MAX_TRIPS: i32 = 5;
+/// 表达式的最大深度
+const MAX_DEPTH: u32 = 4;
+
+fn number(value: i32) -> UnaryExp {
+    UnaryExp::PrimaryExp(Box::new(PrimaryExp::Number(value)))
+}
+
+fn paren(exp: Exp) -> UnaryExp {
+    UnaryExp::PrimaryExp(Box::new(PrimaryExp::Exp(Box::new(exp))))
+}
+
+fn lval(ident: &str, indices: Vec<Exp>) -> LVal {
+    LVal {
+        ident: ident.to_string(),
+        indices,
+        span: Span::default(),
+        symbol: None,
+    }
+}
+
+fn call(ident: &str, args: Vec<Exp>) -> UnaryExp {
+    let params = match args.is_empty() {
+        true => None,
+        false => Some(FuncRParams {
+            func_r_params: args,
+        }),
+    };
+    UnaryExp::FuncItem((ident.to_string(), params, Span::default(), None))
+}
+
+// 以下函数把优先级链中某一层的表达式提升为上一层，或在可能时剥去外层，
+// 剥不开时加括号，因此构造出的表达式只在需要时带括号
+
+fn from_land(exp: LAndExp) -> Exp {
+    Exp {
+        lor_exp: Box::new(LOrExp::LAndExp(Box::new(exp))),
+        span: Span::default(),
+    }
+}
+
+fn from_eq(exp: EqExp) -> Exp {
+    from_land(LAndExp::EqExp(Box::new(exp)))
+}
+
+fn from_rel(exp: RelExp) -> Exp {
+    from_eq(EqExp::RelExp(Box::new(exp)))
+}
+
+fn from_add(exp: AddExp) -> Exp {
+    from_rel(RelExp::AddExp(Box::new(exp)))
+}
+
+fn from_mul(exp: MulExp) -> Exp {
+    from_add(AddExp::MulExp(Box::new(exp)))
+}
+
+fn from_unary(exp: UnaryExp) -> Exp {
+    from_mul(MulExp::UnaryExp(Box::new(exp)))
+}
+
+fn as_land(exp: Exp) -> LAndExp {
+    match *exp.lor_exp {
+        LOrExp::LAndExp(exp) => *exp,
+        lor_exp => LAndExp::EqExp(Box::new(as_eq(from_unary(paren(Exp {
+            lor_exp: Box::new(lor_exp),
+            span: Span::default(),
+        }))))),
+    }
+}
+
+fn as_eq(exp: Exp) -> EqExp {
+    match as_land(exp) {
+        LAndExp::EqExp(exp) => *exp,
+        exp => EqExp::RelExp(Box::new(as_rel(from_unary(paren(from_land(exp)))))),
+    }
+}
+
+fn as_rel(exp: Exp) -> RelExp {
+    match as_eq(exp) {
+        EqExp::RelExp(exp) => *exp,
+        exp => RelExp::AddExp(Box::new(as_add(from_unary(paren(from_eq(exp)))))),
+    }
+}
+
+fn as_add(exp: Exp) -> AddExp {
+    match as_rel(exp) {
+        RelExp::AddExp(exp) => *exp,
+        exp => AddExp::MulExp(Box::new(as_mul(from_unary(paren(from_rel(exp)))))),
+    }
+}
+
+fn as_mul(exp: Exp) -> MulExp {
+    match as_add(exp) {
+        AddExp::MulExp(exp) => *exp,
+        exp => MulExp::UnaryExp(Box::new(paren(from_add(exp)))),
+    }
+}
+
+fn as_unary(exp: Exp) -> UnaryExp {
+    match as_mul(exp) {
+        MulExp::UnaryExp(exp) => *exp,
+        exp => paren(from_mul(exp)),
+    }
+}
+
+fn lor(lhs: Exp, rhs: Exp) -> Exp {
+    Exp {
+        lor_exp: Box::new(LOrExp::LOrExp((lhs.lor_exp, Box::new(as_land(rhs))))),
+        span: Span::default(),
+    }
+}
+
+fn land(lhs: Exp, rhs: Exp) -> Exp {
+    from_land(LAndExp::LAndExp((
+        Box::new(as_land(lhs)),
+        Box::new(as_eq(rhs)),
+    )))
+}
+
+fn compare(lhs: Exp, op: CmpOp, rhs: Exp) -> Exp {
+    match op {
+        CmpOp::Eq | CmpOp::NEq => from_eq(EqExp::EqExp((
+            Box::new(as_eq(lhs)),
+            op,
+            Box::new(as_rel(rhs)),
+        ))),
+        _ => from_rel(RelExp::CompExp((
+            Box::new(as_rel(lhs)),
+            op,
+            Box::new(as_add(rhs)),
+        ))),
+    }
+}
+
+fn add(lhs: Exp, op: AddOp, rhs: Exp) -> Exp {
+    from_add(AddExp::AddExp((
+        Box::new(as_add(lhs)),
+        op,
+        Box::new(as_mul(rhs)),
+    )))
+}
+
+fn mul(lhs: Exp, op: MulOp, rhs: Exp) -> Exp {
+    from_mul(MulExp::MulExp((
+        Box::new(as_mul(lhs)),
+        op,
+        Box::new(as_unary(rhs)),
+    )))
+}
+
+fn unary(op: UnaryOp, exp: Exp) -> Exp {
+    from_unary(UnaryExp::UnaryExp((op, Box::new(as_unary(exp)))))
+}
+
+fn int(value: i32) -> Exp {
+    match value {
+        0.. => from_unary(number(value)),
+        _ => unary(UnaryOp::Negative, from_unary(number(value.wrapping_neg()))),
+    }
+}
+
+fn const_exp(exp: Exp) -> ConstExp {
+    ConstExp { exp: Box::new(exp) }
+}
+
+fn to_const_init(init: InitVal) -> ConstInitVal {
+    match init {
+        InitVal::Exp(exp) => ConstInitVal::Exp(const_exp(exp)),
+        InitVal::Array(inits) => {
+            ConstInitVal::Array(inits.into_iter().map(to_const_init).collect())
+        }
+    }
+}
+
+/// A variable visible in the program being generated.
+#[derive(Clone)]
+struct Var {
+    name: String,
+    /// 数组的各维长度，标量为空；数组形参的第一维是函数假定的最小长度
+    dims: Vec<i32>,
+    /// 常量标量的值
+    value: Option<i32>,
+    /// 常量与循环计数器不能被赋值
+    assignable: bool,
+}
+
+#[derive(Clone)]
+enum Param {
+    Int,
+    /// 数组形参的各维长度，第一维是实参至少要有的长度
+    Array(Vec<i32>),
+}
+
+/// A function defined so far.
+struct Func {
+    name: String,
+    params: Vec<Param>,
+    returns_int: bool,
+}
+
+struct Generator {
+    rng: Rng,
+    /// 全局作用域与各层局部作用域
+    scopes: Vec<Vec<Var>>,
+    funcs: Vec<Func>,
+    next_id: usize,
+    loop_depth: usize,
+    /// 正在生成的函数是否返回int
+    returns_int: bool,
+    /// 在`main`中；其他函数中的循环不调用函数，也不嵌套，以免运行时间成倍增长
+    in_main: bool,
+    /// 正在生成其初值的变量名，它遮蔽的外层变量此时不可使用
+    hidden: Option<String>,
+}
+
+/// Generate a random program from `seed`.
+pub fn random_program(seed: u64) -> CompUnit {
+    let mut gen = Generator {
+        rng: Rng::new(seed),
+        scopes: vec![Vec::new()],
+        funcs: Vec::new(),
+        next_id: 0,
+        loop_depth: 0,
+        returns_int: true,
+        in_main: false,
+        hidden: None,
+    };
+    let mut items = Vec::new();
+    for _ in 0..gen.rng.range(1, 4) {
+        items.push(GlobalItem::Decl(gen.global_decl()));
+    }
+    for _ in 0..gen.rng.range(0, 3) {
+        items.push(GlobalItem::Func(gen.function()));
+        if gen.rng.chance(30) {
+            items.push(GlobalItem::Decl(gen.global_decl()));
+        }
+    }
+    items.push(GlobalItem::Func(gen.main()));
+    super::from_items(items).unwrap()
+}
+
+impl Generator {
+    fn fresh(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{0}{1}", prefix, self.next_id)
+    }
+
+    /// A name for a new variable: fresh, or in an inner block sometimes the
+    /// name of an outer variable, which the new one shadows.
+    fn new_name(&mut self, prefix: &str) -> String {
+        if self.scopes.len() > 2 && self.rng.chance(25) {
+            let current = self.scopes.last().unwrap();
+            let outer = self
+                .visible()
+                .into_iter()
+                .filter(|var| !current.iter().any(|v| v.name == var.name))
+                .collect::<Vec<_>>();
+            if !outer.is_empty() {
+                return outer[self.rng.below(outer.len())].name.clone();
+            }
+        }
+        self.fresh(prefix)
+    }
+
+    fn declare(&mut self, var: Var) {
+        self.scopes.last_mut().unwrap().push(var);
+    }
+
+    /// The variables visible here, without those shadowed by inner ones.
+    fn visible(&self) -> Vec<Var> {
+        let mut vars: Vec<Var> = Vec::new();
+        for scope in self.scopes.iter().rev() {
+            for var in scope.iter().rev() {
+                if !vars.iter().any(|v| v.name == var.name) {
+                    vars.push(var.clone());
+                }
+            }
+        }
+        vars.retain(|var| Some(&var.name) != self.hidden.as_ref());
+        vars
+    }
+
+    fn pick<T: Clone>(&mut self, items: &[T]) -> Option<T> {
+        match items.len() {
+            0 => None,
+            n => Some(items[self.rng.below(n)].clone()),
+        }
+    }
+
+    fn global_decl(&mut self) -> Decl {
+        let constant = self.rng.chance(30);
+        let dims = match self.rng.chance(50) {
+            true => self.random_dims(),
+            false => Vec::new(),
+        };
+        let name = self.fresh(if dims.is_empty() { "g" } else { "ga" });
+        let dim_exps = self.dim_exps(&dims);
+        let (init, value) = match dims.is_empty() {
+            true => {
+                let (exp, value) = self.const_value(2);
+                (InitVal::Exp(exp), Some(value))
+            }
+            false => (self.init_list(&dims, true), None),
+        };
+        let decl = if constant {
+            Decl::ConstDecl(ConstDecl {
+                b_type: ItemType::Int,
+                const_defs: vec![ConstDef {
+                    ident: name.clone(),
+                    span: Span::default(),
+                    dims: dim_exps,
+                    const_init_val: to_const_init(init),
+                    symbol: None,
+                }],
+            })
+        } else {
+            // 全局变量可以不写初值，此时为0
+            let def = match self.rng.chance(30) {
+                true => VarDef::Decl((name.clone(), dim_exps, Span::default(), None)),
+                false => VarDef::Def((name.clone(), dim_exps, init, Span::default(), None)),
+            };
+            Decl::VarDecl(VarDecl {
+                b_type: ItemType::Int,
+                var_defs: vec![def],
+            })
+        };
+        self.declare(Var {
+            name,
+            dims,
+            value: value.filter(|_| constant),
+            assignable: !constant,
+        });
+        decl
+    }
+
+    fn random_dims(&mut self) -> Vec<i32> {
+        let count = self.rng.range(1, MAX_DIMS as i32);
+        (0..count).map(|_| self.rng.range(1, MAX_DIM_LEN)).collect()
+    }
+
+    fn dim_exps(&mut self, dims: &[i32]) -> Vec<ConstExp> {
+        dims.iter()
+            .map(|&len| const_exp(self.const_equal(len)))
+            .collect()
+    }
+
+    /// A constant expression equal to `value`, using a constant in scope
+    /// when there is one.
+    fn const_equal(&mut self, value: i32) -> Exp {
+        let consts = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.value.is_some())
+            .collect::<Vec<_>>();
+        match self.pick(&consts) {
+            Some(var) if self.rng.chance(50) => {
+                let base = var.value.unwrap();
+                let name = from_unary(UnaryExp::PrimaryExp(Box::new(PrimaryExp::LVal(lval(
+                    &var.name,
+                    Vec::new(),
+                )))));
+                let diff = value.wrapping_sub(base);
+                match diff {
+                    0.. => add(name, AddOp::Add, int(diff)),
+                    _ => add(name, AddOp::Sub, int(diff.wrapping_neg())),
+                }
+            }
+            _ => int(value),
+        }
+    }
+
+    /// A random constant expression and its value.
+    fn const_value(&mut self, depth: u32) -> (Exp, i32) {
+        if depth == 0 || self.rng.chance(40) {
+            let value = self.rng.range(-20, 100);
+            return (self.const_equal(value), value);
+        }
+        let (lhs, a) = self.const_value(depth - 1);
+        match self.rng.below(4) {
+            0 => {
+                let (rhs, b) = self.const_value(depth - 1);
+                (add(lhs, AddOp::Add, rhs), a.wrapping_add(b))
+            }
+            1 => {
+                let (rhs, b) = self.const_value(depth - 1);
+                (add(lhs, AddOp::Sub, rhs), a.wrapping_sub(b))
+            }
+            2 => {
+                let (rhs, b) = self.const_value(depth - 1);
+                (mul(lhs, MulOp::Multiple, rhs), a.wrapping_mul(b))
+            }
+            _ => {
+                let b = self.rng.range(1, 9);
+                match self.rng.chance(50) {
+                    true => (mul(lhs, MulOp::Divide, int(b)), a / b),
+                    false => (mul(lhs, MulOp::Mod, int(b)), a % b),
+                }
+            }
+        }
+    }
+
+    /// An initializer list for an array of `dims`, either nested like the
+    /// array or, for a multi-dimensional one, flattened. Lists may be
+    /// shorter than the array.
+    fn init_list(&mut self, dims: &[i32], constant: bool) -> InitVal {
+        let flat = dims.len() > 1 && self.rng.chance(25);
+        let (count, inner) = match flat {
+            true => (dims.iter().product::<i32>().min(8), &[][..]),
+            false => (dims[0], &dims[1..]),
+        };
+        let inits = (0..self.rng.range(0, count))
+            .map(|_| match inner.is_empty() {
+                true if constant => InitVal::Exp(self.const_value(1).0),
+                true => InitVal::Exp(self.expr(2)),
+                false => self.init_list(inner, constant),
+            })
+            .collect();
+        InitVal::Array(inits)
+    }
+
+    fn function(&mut self) -> FuncDef {
+        let name = self.fresh("f");
+        let returns_int = self.rng.chance(70);
+        let mut params = Vec::new();
+        let mut f_params = Vec::new();
+        self.scopes.push(Vec::new());
+        for _ in 0..self.rng.range(0, 3) {
+            let id = self.fresh("p");
+            let (param, dims) = match self.rng.chance(60) {
+                true => (Param::Int, Vec::new()),
+                false => {
+                    let mut dims = vec![self.rng.range(1, MAX_DIM_LEN)];
+                    if self.rng.chance(40) {
+                        dims.push(self.rng.range(1, MAX_DIM_LEN));
+                    }
+                    (Param::Array(dims.clone()), dims)
+                }
+            };
+            // 数组形参的第一维不写长度，其余维是常量表达式
+            let dim_exps = match param {
+                Param::Int => None,
+                Param::Array(_) => Some(self.dim_exps(&dims[1..])),
+            };
+            f_params.push(FuncFParam {
+                b_type: ItemType::Int,
+                id: id.clone(),
+                span: Span::default(),
+                dims: dim_exps,
+                symbol: None,
+            });
+            self.declare(Var {
+                name: id,
+                dims,
+                value: None,
+                assignable: true,
+            });
+            params.push(param);
+        }
+        self.returns_int = returns_int;
+        let block = self.body(4);
+        self.scopes.pop();
+        self.funcs.push(Func {
+            name: name.clone(),
+            params,
+            returns_int,
+        });
+        FuncDef {
+            func_type: if returns_int {
+                ItemType::Int
+            } else {
+                ItemType::Void
+            },
+            id: name,
+            span: Span::default(),
+            func_f_params: match f_params.is_empty() {
+                true => None,
+                false => Some(FuncFParams {
+                    func_f_params: f_params,
+                }),
+            },
+            block,
+            symbol: None,
+        }
+    }
+
+    fn main(&mut self) -> FuncDef {
+        self.in_main = true;
+        self.returns_int = true;
+        self.scopes.push(Vec::new());
+        let block = self.body(8);
+        self.scopes.pop();
+        FuncDef {
+            func_type: ItemType::Int,
+            id: "main".to_string(),
+            span: Span::default(),
+            func_f_params: None,
+            block,
+            symbol: None,
+        }
+    }
+
+    /// A function body, in the scope of the parameters, ending with a return.
+    fn body(&mut self, max_stmts: i32) -> Block {
+        let mut items = Vec::new();
+        for _ in 0..self.rng.range(1, max_stmts) {
+            self.item(0, &mut items);
+        }
+        if self.returns_int {
+            let value = self.expr(0);
+            items.push(BlockItem::Stmt(Stmt::Return((
+                Some(value),
+                Span::default(),
+            ))));
+        }
+        Block { items, scope: None }
+    }
+
+    /// A block in a new scope, starting with `items`.
+    fn block(&mut self, depth: u32, mut items: Vec<BlockItem>) -> Block {
+        self.scopes.push(Vec::new());
+        for _ in 0..self.rng.range(1, 3) {
+            self.item(depth + 1, &mut items);
+        }
+        self.scopes.pop();
+        Block { items, scope: None }
+    }
+
+    /// Append a declaration or one or more statements to `items`.
+    fn item(&mut self, depth: u32, items: &mut Vec<BlockItem>) {
+        let nested = depth < 2;
+        let stmt = match self.rng.below(12) {
+            0 | 1 => {
+                items.push(BlockItem::Decl(self.local_decl()));
+                return;
+            }
+            2 if nested => {
+                let cond = self.cond();
+                let then_stmt = self.branch(depth);
+                let else_stmt = match self.rng.chance(50) {
+                    true => Some(self.branch(depth)),
+                    false => None,
+                };
+                Stmt::IF(Box::new(If {
+                    cond,
+                    then_stmt,
+                    else_stmt,
+                }))
+            }
+            3 if nested && self.may_call() => return self.while_loop(depth, items),
+            4 if self.loop_depth > 0 => {
+                let cond = self.cond();
+                let jump = match self.rng.chance(50) {
+                    true => Stmt::Break(Span::default()),
+                    false => Stmt::Continue(Span::default()),
+                };
+                Stmt::IF(Box::new(If {
+                    cond,
+                    then_stmt: jump,
+                    else_stmt: None,
+                }))
+            }
+            5 if nested => Stmt::Block(self.block(depth, Vec::new())),
+            6 if self.may_call() => match self.call(false, 0) {
+                Some(call) => Stmt::Exp(Some(from_unary(call))),
+                None => self.output(),
+            },
+            7 if !self.in_main && self.rng.chance(40) => {
+                let value = match self.returns_int {
+                    true => Some(self.expr(1)),
+                    false => None,
+                };
+                Stmt::IF(Box::new(If {
+                    cond: self.cond(),
+                    then_stmt: Stmt::Return((value, Span::default())),
+                    else_stmt: None,
+                }))
+            }
+            8 if self.rng.chance(10) => Stmt::Exp(None),
+            9 => {
+                items.push(BlockItem::Stmt(self.output()));
+                let sep = if self.rng.chance(30) { 10 } else { 32 };
+                Stmt::Exp(Some(from_unary(call("putch", vec![int(sep)]))))
+            }
+            _ => self.assign(),
+        };
+        items.push(BlockItem::Stmt(stmt));
+    }
+
+    /// The body of an `if` or `else`: a block, or a single statement.
+    fn branch(&mut self, depth: u32) -> Stmt {
+        match self.rng.chance(70) {
+            true => Stmt::Block(self.block(depth, Vec::new())),
+            false => self.assign(),
+        }
+    }
+
+    fn local_decl(&mut self) -> Decl {
+        if self.rng.chance(20) {
+            let name = self.new_name("k");
+            self.hidden = Some(name.clone());
+            let (exp, value) = self.const_value(2);
+            self.hidden = None;
+            self.declare(Var {
+                name: name.clone(),
+                dims: Vec::new(),
+                value: Some(value),
+                assignable: false,
+            });
+            return Decl::ConstDecl(ConstDecl {
+                b_type: ItemType::Int,
+                const_defs: vec![ConstDef {
+                    ident: name,
+                    span: Span::default(),
+                    dims: Vec::new(),
+                    const_init_val: ConstInitVal::Exp(const_exp(exp)),
+                    symbol: None,
+                }],
+            });
+        }
+        // 一个声明中可以定义多个变量，后面的初值可以引用前面的变量
+        let mut var_defs = Vec::new();
+        for _ in 0..self.rng.range(1, 2) {
+            let array = self.rng.chance(30);
+            let name = self.new_name(if array { "a" } else { "v" });
+            let dims = match array {
+                true => self.random_dims(),
+                false => Vec::new(),
+            };
+            self.hidden = Some(name.clone());
+            let dim_exps = self.dim_exps(&dims);
+            let init = match array {
+                true => self.init_list(&dims, false),
+                false => InitVal::Exp(self.expr(0)),
+            };
+            self.hidden = None;
+            var_defs.push(VarDef::Def((
+                name.clone(),
+                dim_exps,
+                init,
+                Span::default(),
+                None,
+            )));
+            self.declare(Var {
+                name,
+                dims,
+                value: None,
+                assignable: true,
+            });
+        }
+        Decl::VarDecl(VarDecl {
+            b_type: ItemType::Int,
+            var_defs,
+        })
+    }
+
+    /// A counted loop: the counter is declared before the loop and advanced
+    /// by the first statement of its body.
+    fn while_loop(&mut self, depth: u32, items: &mut Vec<BlockItem>) {
+        let counter = self.fresh("i");
+        let trips = self.rng.range(0, MAX_TRIPS);
+        items.push(BlockItem::Decl(Decl::VarDecl(VarDecl {
+            b_type: ItemType::Int,
+            var_defs: vec![VarDef::Def((
+                counter.clone(),
+                Vec::new(),
+                InitVal::Exp(int(0)),
+                Span::default(),
+                None,
+            ))],
+        })));
+        self.declare(Var {
+            name: counter.clone(),
+            dims: Vec::new(),
+            value: None,
+            assignable: false,
+        });
+        let read = || {
+            from_unary(UnaryExp::PrimaryExp(Box::new(PrimaryExp::LVal(lval(
+                &counter,
+                Vec::new(),
+            )))))
+        };
+        let mut cond = compare(read(), CmpOp::Less, int(trips));
+        if self.rng.chance(25) {
+            cond = land(cond, self.cond());
+        }
+        let step = Stmt::Assign((lval(&counter, Vec::new()), add(read(), AddOp::Add, int(1))));
+        self.loop_depth += 1;
+        let body = self.block(depth, vec![BlockItem::Stmt(step)]);
+        self.loop_depth -= 1;
+        items.push(BlockItem::Stmt(Stmt::WHILE(Box::new(While {
+            cond,
+            body_stmt: Stmt::Block(body),
+        }))));
+    }
+
+    /// A call of `putint`, or of `putarray` on a row of an array.
+    fn output(&mut self) -> Stmt {
+        if self.rng.chance(20) {
+            if let Some((arg, len)) = self.array_arg(0, |dims| dims.len() == 1) {
+                let count = self.rng.range(1, len[0]);
+                let call = call("putarray", vec![int(count), arg]);
+                return Stmt::Exp(Some(from_unary(call)));
+            }
+        }
+        let value = self.expr(0);
+        Stmt::Exp(Some(from_unary(call("putint", vec![value]))))
+    }
+
+    fn assign(&mut self) -> Stmt {
+        let vars = self
+            .visible()
+            .into_iter()
+            .filter(|var| var.assignable)
+            .collect::<Vec<_>>();
+        match self.pick(&vars) {
+            Some(var) => {
+                let target = self.element(&var, 0);
+                let value = self.expr(0);
+                Stmt::Assign((target, value))
+            }
+            None => self.output(),
+        }
+    }
+
+    /// Whether calls may be generated here: in `main`, or outside of loops.
+    fn may_call(&self) -> bool {
+        self.in_main || self.loop_depth == 0
+    }
+
+    /// A call of a function defined so far, returning int if `int` is set.
+    /// Functions whose array parameters cannot be passed are skipped.
+    fn call(&mut self, int: bool, depth: u32) -> Option<UnaryExp> {
+        let mut candidates = (0..self.funcs.len())
+            .filter(|&i| self.funcs[i].returns_int || !int)
+            .collect::<Vec<_>>();
+        while !candidates.is_empty() {
+            let func = candidates.swap_remove(self.rng.below(candidates.len()));
+            let params = self.funcs[func].params.clone();
+            let mut args = Vec::new();
+            for param in params {
+                match param {
+                    Param::Int => args.push(self.expr(depth + 1)),
+                    Param::Array(dims) => match self.array_arg(depth, |rest| {
+                        rest.len() == dims.len() && rest[0] >= dims[0] && rest[1..] == dims[1..]
+                    }) {
+                        Some((arg, _)) => args.push(arg),
+                        None => break,
+                    },
+                }
+            }
+            if args.len() == self.funcs[func].params.len() {
+                return Some(call(&self.funcs[func].name, args));
+            }
+        }
+        None
+    }
+
+    /// An array, or a row of one, whose remaining dimensions satisfy
+    /// `accept`, with those dimensions.
+    fn array_arg(
+        &mut self,
+        depth: u32,
+        accept: impl Fn(&[i32]) -> bool,
+    ) -> Option<(Exp, Vec<i32>)> {
+        let mut candidates = Vec::new();
+        for var in self.visible() {
+            for fixed in 0..var.dims.len() {
+                if accept(&var.dims[fixed..]) {
+                    candidates.push((var.clone(), fixed));
+                }
+            }
+        }
+        let (var, fixed) = self.pick(&candidates)?;
+        let indices = var.dims[..fixed]
+            .iter()
+            .map(|&len| self.index(len, depth))
+            .collect();
+        let arg = UnaryExp::PrimaryExp(Box::new(PrimaryExp::LVal(lval(&var.name, indices))));
+        Some((from_unary(arg), var.dims[fixed..].to_vec()))
+    }
+
+    /// `var`, or an element of it if it is an array.
+    fn element(&mut self, var: &Var, depth: u32) -> LVal {
+        let indices = var.dims.iter().map(|&len| self.index(len, depth)).collect();
+        lval(&var.name, indices)
+    }
+
+    /// An index into a dimension of length `len`, in an expression at
+    /// `depth`.
+    fn index(&mut self, len: i32, depth: u32) -> Exp {
+        if depth >= MAX_DEPTH || self.rng.chance(50) {
+            return int(self.rng.range(0, len - 1));
+        }
+        // 余数可能为负，加上长度后再取一次余数
+        let value = self.expr(depth + 1);
+        let rem = mul(value, MulOp::Mod, int(len));
+        mul(add(rem, AddOp::Add, int(len)), MulOp::Mod, int(len))
+    }
+
+    /// A condition, often a comparison or a short-circuit operation.
+    fn cond(&mut self) -> Exp {
+        let op = self.cmp_op();
+        let lhs = self.expr(2);
+        let rhs = self.expr(2);
+        let cond = compare(lhs, op, rhs);
+        match self.rng.below(4) {
+            0 => land(cond, self.expr(2)),
+            1 => lor(cond, self.expr(2)),
+            _ => cond,
+        }
+    }
+
+    fn cmp_op(&mut self) -> CmpOp {
+        let ops = [
+            CmpOp::Eq,
+            CmpOp::NEq,
+            CmpOp::Less,
+            CmpOp::Grate,
+            CmpOp::LessEq,
+            CmpOp::GrateEq,
+        ];
+        ops[self.rng.below(ops.len())].clone()
+    }
+
+    fn atom(&mut self, depth: u32) -> Exp {
+        let vars = self.visible();
+        match self.pick(&vars) {
+            Some(var) if self.rng.chance(75) => {
+                let target = self.element(&var, depth);
+                from_unary(UnaryExp::PrimaryExp(Box::new(PrimaryExp::LVal(target))))
+            }
+            _ => int(self.rng.range(0, 100)),
+        }
+    }
+
+    fn expr(&mut self, depth: u32) -> Exp {
+        if depth >= MAX_DEPTH || self.rng.chance(25) {
+            return self.atom(depth);
+        }
+        match self.rng.below(10) {
+            0 => {
+                let ops = [UnaryOp::Negative, UnaryOp::Inversion, UnaryOp::Passive];
+                let op = ops[self.rng.below(ops.len())].clone();
+                unary(op, self.expr(depth + 1))
+            }
+            1 if self.may_call() => match self.call(true, depth) {
+                Some(call) => from_unary(call),
+                None => self.atom(depth),
+            },
+            2 => {
+                let op = match self.rng.chance(50) {
+                    true => MulOp::Divide,
+                    false => MulOp::Mod,
+                };
+                let lhs = self.expr(depth + 1);
+                let rhs = self.divisor(depth);
+                mul(lhs, op, rhs)
+            }
+            3 => {
+                let op = self.cmp_op();
+                let lhs = self.expr(depth + 1);
+                let rhs = self.expr(depth + 1);
+                compare(lhs, op, rhs)
+            }
+            4 => {
+                let lhs = self.expr(depth + 1);
+                let rhs = self.expr(depth + 1);
+                match self.rng.chance(50) {
+                    true => land(lhs, rhs),
+                    false => lor(lhs, rhs),
+                }
+            }
+            5 => {
+                let lhs = self.expr(depth + 1);
+                let rhs = self.expr(depth + 1);
+                mul(lhs, MulOp::Multiple, rhs)
+            }
+            _ => {
+                let op = match self.rng.chance(50) {
+                    true => AddOp::Add,
+                    false => AddOp::Sub,
+                };
+                let lhs = self.expr(depth + 1);
+                let rhs = self.expr(depth + 1);
+                add(lhs, op, rhs)
+            }
+        }
+    }
+
+    /// A divisor that is never 0 or -1.
+    fn divisor(&mut self, depth: u32) -> Exp {
+        if self.rng.chance(50) {
+            let value = self.rng.range(2, 9);
+            return int(if self.rng.chance(30) { -value } else { value });
+        }
+        // 余数在-6到6之间，加8后在2到14之间
+        let rem = mul(self.expr(depth + 1), MulOp::Mod, int(7));
+        add(rem, AddOp::Add, int(8))
+    }
+}
@@ -0,0 +1,482 @@
+use koopa::ir::entities::ValueData;
+use koopa::ir::{
+    BasicBlock, BinaryOp, Function, FunctionData, Program, Type, TypeKind, Value, ValueKind,
+};
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+/// 内存上限（字），超过即认为栈溢出
+const MEMORY_LIMIT: usize = 1 << 26;
+/// 调用深度上限
+const CALL_DEPTH_LIMIT: usize = 1 << 16;
+
+/// An error that stops the interpreted program.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RuntimeError {
+    NoMain,
+    DivisionByZero,
+    /// 访问了非法或未对齐的地址
+    InvalidAddress(i32),
+    StackOverflow,
+    UnknownFunction(String),
+    Io(String),
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::NoMain => write!(f, "function `main` is not defined"),
+            RuntimeError::DivisionByZero => write!(f, "division by zero"),
+            RuntimeError::InvalidAddress(addr) => {
+                write!(f, "invalid memory access at {0:#x}", addr)
+            }
+            RuntimeError::StackOverflow => write!(f, "stack overflow"),
+            RuntimeError::UnknownFunction(name) => {
+                write!(f, "call to undefined function `{0}`", name)
+            }
+            RuntimeError::Io(err) => write!(f, "I/O error: {0}", err),
+        }
+    }
+}
+
+/// A call frame of a function being executed.
+struct Frame {
+    func: Function,
+    bb: BasicBlock,
+    /// 下一条要执行的指令在基本块中的下标
+    pos: usize,
+    values: HashMap<Value, i32>,
+    /// 进入函数时的栈顶，返回时释放其上的alloc
+    stack_base: usize,
+    /// 调用者中接收返回值的指令
+    ret_dest: Option<Value>,
+}
+
+/// Executes a Koopa IR program. Memory is an array of words addressed by
+/// byte, both integers and pointers are `i32`, and runtime library calls are
+/// served from `input`/`output`.
+struct Interpreter<'p, R: BufRead, W: Write> {
+    program: &'p Program,
+    /// 每个函数中各基本块的指令序列
+    code: HashMap<Function, HashMap<BasicBlock, Vec<Value>>>,
+    memory: Vec<i32>,
+    globals: HashMap<Value, i32>,
+    input: Input<R>,
+    output: W,
+}
+
+/// Run `main` of `program` and return its return value. `input` is read only
+/// as far as the program asks for it.
+pub fn interpret<R: BufRead, W: Write>(
+    program: &Program,
+    input: R,
+    output: W,
+) -> Result<i32, RuntimeError> {
+    // 与RISC-V后端一致，指针占4字节
+    Type::set_ptr_size(4);
+    let mut interpreter = Interpreter {
+        program,
+        code: HashMap::new(),
+        // 地址0作为空指针，不分配给任何对象
+        memory: vec![0],
+        globals: HashMap::new(),
+        input: Input::new(input),
+        output,
+    };
+    let ret = interpreter.run();
+    interpreter
+        .output
+        .flush()
+        .map_err(|err| RuntimeError::Io(err.to_string()))?;
+    ret
+}
+
+impl<'p, R: BufRead, W: Write> Interpreter<'p, R, W> {
+    fn run(&mut self) -> Result<i32, RuntimeError> {
+        let program = self.program;
+        for &func in program.func_layout() {
+            let mut blocks = HashMap::new();
+            for (&bb, node) in program.func(func).layout().bbs() {
+                blocks.insert(bb, node.insts().keys().copied().collect());
+            }
+            self.code.insert(func, blocks);
+        }
+        for &global in program.inst_layout() {
+            let data = program.borrow_value(global);
+            let addr = self.alloc(pointee_size(data.ty()))?;
+            if let ValueKind::GlobalAlloc(alloc) = data.kind() {
+                let init = program.borrow_value(alloc.init());
+                self.write_const(addr, &init)?;
+            }
+            self.globals.insert(global, addr);
+        }
+
+        let main = program
+            .func_layout()
+            .iter()
+            .copied()
+            .find(|&f| program.func(f).name() == "@main")
+            .ok_or(RuntimeError::NoMain)?;
+        let mut stack = vec![self.enter(main, Vec::new(), None)?];
+        loop {
+            let frame = stack.last_mut().unwrap();
+            let func_data = program.func(frame.func);
+            let inst = self.code[&frame.func][&frame.bb][frame.pos];
+            frame.pos += 1;
+            let data = func_data.dfg().value(inst);
+            match data.kind() {
+                ValueKind::Alloc(_) => {
+                    let addr = self.alloc(pointee_size(data.ty()))?;
+                    frame.values.insert(inst, addr);
+                }
+                ValueKind::Load(load) => {
+                    let addr = self.operand(frame, func_data, load.src());
+                    let value = self.load(addr)?;
+                    frame.values.insert(inst, value);
+                }
+                ValueKind::Store(store) => {
+                    let addr = self.operand(frame, func_data, store.dest());
+                    let value = func_data.dfg().value(store.value());
+                    match value.kind() {
+                        ValueKind::Aggregate(_) | ValueKind::ZeroInit(_) => {
+                            self.write_const_local(addr, func_data, store.value())?
+                        }
+                        _ => {
+                            let value = self.operand(frame, func_data, store.value());
+                            self.store(addr, value)?;
+                        }
+                    }
+                }
+                ValueKind::GetPtr(get_ptr) => {
+                    let src = self.operand(frame, func_data, get_ptr.src());
+                    let index = self.operand(frame, func_data, get_ptr.index());
+                    let stride = pointee_size(data.ty()) as i32;
+                    frame
+                        .values
+                        .insert(inst, src.wrapping_add(index.wrapping_mul(stride)));
+                }
+                ValueKind::GetElemPtr(gep) => {
+                    let src = self.operand(frame, func_data, gep.src());
+                    let index = self.operand(frame, func_data, gep.index());
+                    let stride = pointee_size(data.ty()) as i32;
+                    frame
+                        .values
+                        .insert(inst, src.wrapping_add(index.wrapping_mul(stride)));
+                }
+                ValueKind::Binary(exp) => {
+                    let lhs = self.operand(frame, func_data, exp.lhs());
+                    let rhs = self.operand(frame, func_data, exp.rhs());
+                    frame.values.insert(inst, binary(exp.op(), lhs, rhs)?);
+                }
+                ValueKind::Branch(branch) => {
+                    let cond = self.operand(frame, func_data, branch.cond());
+                    if cond != 0 {
+                        self.jump(frame, func_data, branch.true_bb(), branch.true_args());
+                    } else {
+                        self.jump(frame, func_data, branch.false_bb(), branch.false_args());
+                    }
+                }
+                ValueKind::Jump(jump) => {
+                    self.jump(frame, func_data, jump.target(), jump.args());
+                }
+                ValueKind::Call(call) => {
+                    let args: Vec<i32> = call
+                        .args()
+                        .iter()
+                        .map(|&arg| self.operand(frame, func_data, arg))
+                        .collect();
+                    let callee = program.func(call.callee());
+                    if callee.layout().entry_bb().is_none() {
+                        let ret = self.call_runtime(callee.name(), &args)?;
+                        frame.values.insert(inst, ret);
+                    } else {
+                        if stack.len() >= CALL_DEPTH_LIMIT {
+                            return Err(RuntimeError::StackOverflow);
+                        }
+                        let new_frame = self.enter(call.callee(), args, Some(inst))?;
+                        stack.push(new_frame);
+                    }
+                }
+                ValueKind::Return(ret) => {
+                    let value = ret.value().map(|v| self.operand(frame, func_data, v));
+                    let frame = stack.pop().unwrap();
+                    self.memory.truncate(frame.stack_base);
+                    match stack.last_mut() {
+                        Some(caller) => {
+                            if let (Some(dest), Some(value)) = (frame.ret_dest, value) {
+                                caller.values.insert(dest, value);
+                            }
+                        }
+                        None => return Ok(value.unwrap_or(0)),
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    /// Create the frame of a call to `func`.
+    fn enter(
+        &mut self,
+        func: Function,
+        args: Vec<i32>,
+        ret_dest: Option<Value>,
+    ) -> Result<Frame, RuntimeError> {
+        let func_data = self.program.func(func);
+        let bb = match func_data.layout().entry_bb() {
+            Some(bb) => bb,
+            None => {
+                return Err(RuntimeError::UnknownFunction(
+                    func_data.name()[1..].to_string(),
+                ))
+            }
+        };
+        Ok(Frame {
+            func,
+            bb,
+            pos: 0,
+            values: func_data.params().iter().copied().zip(args).collect(),
+            stack_base: self.memory.len(),
+            ret_dest,
+        })
+    }
+
+    /// Transfer control to `target`, binding its parameters to `args`.
+    fn jump(
+        &self,
+        frame: &mut Frame,
+        func_data: &FunctionData,
+        target: BasicBlock,
+        args: &[Value],
+    ) {
+        let values: Vec<i32> = args
+            .iter()
+            .map(|&arg| self.operand(frame, func_data, arg))
+            .collect();
+        for (&param, value) in func_data.dfg().bb(target).params().iter().zip(values) {
+            frame.values.insert(param, value);
+        }
+        frame.bb = target;
+        frame.pos = 0;
+    }
+
+    /// The runtime value of an operand.
+    fn operand(&self, frame: &Frame, func_data: &FunctionData, value: Value) -> i32 {
+        if value.is_global() {
+            return self.globals[&value];
+        }
+        match func_data.dfg().value(value).kind() {
+            ValueKind::Integer(int) => int.value(),
+            ValueKind::ZeroInit(_) | ValueKind::Undef(_) => 0,
+            _ => frame.values[&value],
+        }
+    }
+
+    /// Reserve `size` bytes of zeroed memory and return their address.
+    fn alloc(&mut self, size: usize) -> Result<i32, RuntimeError> {
+        let addr = self.memory.len() * 4;
+        let words = size.div_ceil(4).max(1);
+        if self.memory.len() + words > MEMORY_LIMIT {
+            return Err(RuntimeError::StackOverflow);
+        }
+        self.memory.resize(self.memory.len() + words, 0);
+        Ok(addr as i32)
+    }
+
+    fn word_index(&self, addr: i32) -> Result<usize, RuntimeError> {
+        let index = (addr / 4) as usize;
+        if addr <= 0 || addr % 4 != 0 || index >= self.memory.len() {
+            return Err(RuntimeError::InvalidAddress(addr));
+        }
+        Ok(index)
+    }
+
+    fn load(&self, addr: i32) -> Result<i32, RuntimeError> {
+        Ok(self.memory[self.word_index(addr)?])
+    }
+
+    fn store(&mut self, addr: i32, value: i32) -> Result<(), RuntimeError> {
+        let index = self.word_index(addr)?;
+        self.memory[index] = value;
+        Ok(())
+    }
+
+    /// Write a global initializer at `addr`.
+    fn write_const(&mut self, addr: i32, data: &ValueData) -> Result<(), RuntimeError> {
+        match data.kind() {
+            ValueKind::Integer(int) => self.store(addr, int.value()),
+            ValueKind::Aggregate(aggregate) => {
+                let mut elem_addr = addr;
+                for &elem in aggregate.elems() {
+                    let elem_data = self.program.borrow_value(elem);
+                    self.write_const(elem_addr, &elem_data)?;
+                    elem_addr += elem_data.ty().size() as i32;
+                }
+                Ok(())
+            }
+            // 全局内存分配时已经清零
+            _ => Ok(()),
+        }
+    }
+
+    /// Write a constant operand of a local `store` at `addr`.
+    fn write_const_local(
+        &mut self,
+        addr: i32,
+        func_data: &FunctionData,
+        value: Value,
+    ) -> Result<(), RuntimeError> {
+        let data = func_data.dfg().value(value);
+        match data.kind() {
+            ValueKind::Integer(int) => self.store(addr, int.value()),
+            ValueKind::Aggregate(aggregate) => {
+                let mut elem_addr = addr;
+                for &elem in aggregate.elems() {
+                    self.write_const_local(elem_addr, func_data, elem)?;
+                    elem_addr += func_data.dfg().value(elem).ty().size() as i32;
+                }
+                Ok(())
+            }
+            _ => {
+                for offset in (0..data.ty().size() as i32).step_by(4) {
+                    self.store(addr + offset, 0)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Serve a call to a function of the SysY runtime library.
+    fn call_runtime(&mut self, name: &str, args: &[i32]) -> Result<i32, RuntimeError> {
+        let io_err = |err: io::Error| RuntimeError::Io(err.to_string());
+        match &name[1..] {
+            "getint" => self.input.getint().map_err(io_err),
+            "getch" => self.input.getch().map_err(io_err),
+            "getarray" => {
+                let len = self.input.getint().map_err(io_err)?;
+                for i in 0..len {
+                    let value = self.input.getint().map_err(io_err)?;
+                    self.store(args[0] + i * 4, value)?;
+                }
+                Ok(len)
+            }
+            "putint" => write!(self.output, "{}", args[0])
+                .map_err(io_err)
+                .map(|_| 0),
+            "putch" => self
+                .output
+                .write_all(&[args[0] as u8])
+                .map_err(io_err)
+                .map(|_| 0),
+            "putarray" => {
+                let mut s = format!("{}:", args[0]);
+                for i in 0..args[0] {
+                    s += &format!(" {}", self.load(args[1] + i * 4)?);
+                }
+                writeln!(self.output, "{}", s).map_err(io_err).map(|_| 0)
+            }
+            "starttime" | "stoptime" => Ok(0),
+            other => Err(RuntimeError::UnknownFunction(other.to_string())),
+        }
+    }
+}
+
+/// The input of the SysY runtime library, read from a [`BufRead`] only as far
+/// as `getint` and `getch` need, so that a program can interact with a
+/// terminal or a pipe.
+pub struct Input<R: BufRead> {
+    reader: R,
+}
+
+impl<R: BufRead> Input<R> {
+    pub fn new(reader: R) -> Self {
+        Input { reader }
+    }
+
+    /// The next byte, without consuming it.
+    fn peek(&mut self) -> io::Result<Option<u8>> {
+        loop {
+            match self.reader.fill_buf() {
+                Ok(buf) => return Ok(buf.first().copied()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Consume and return the next byte if it satisfies `pred`.
+    fn next_if(&mut self, pred: impl Fn(u8) -> bool) -> io::Result<Option<u8>> {
+        match self.peek()? {
+            Some(byte) if pred(byte) => {
+                self.reader.consume(1);
+                Ok(Some(byte))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Read a byte like `getchar`, returning -1 at end of input.
+    pub fn getch(&mut self) -> io::Result<i32> {
+        Ok(self.next_if(|_| true)?.map_or(-1, i32::from))
+    }
+
+    /// Read a decimal integer like `scanf("%d")`, returning 0 at end of
+    /// input.
+    pub fn getint(&mut self) -> io::Result<i32> {
+        while self.next_if(|b| b.is_ascii_whitespace())?.is_some() {}
+        let sign = self.next_if(|b| b == b'-' || b == b'+')?;
+        let mut value: i32 = 0;
+        while let Some(digit) = self.next_if(|b| b.is_ascii_digit())? {
+            value = value.wrapping_mul(10).wrapping_add((digit - b'0') as i32);
+        }
+        if sign == Some(b'-') {
+            Ok(value.wrapping_neg())
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// Size in bytes of the type a pointer type points to.
+fn pointee_size(ty: &Type) -> usize {
+    match ty.kind() {
+        TypeKind::Pointer(base) => base.size(),
+        _ => unreachable!(),
+    }
+}
+
+/// Evaluate a binary operation with the wrapping semantics of RV32. Constant
+/// folding goes through here as well, so optimized programs compute the same
+/// values they would when interpreted.
+pub fn binary(op: BinaryOp, lhs: i32, rhs: i32) -> Result<i32, RuntimeError> {
+    Ok(match op {
+        BinaryOp::NotEq => (lhs != rhs) as i32,
+        BinaryOp::Eq => (lhs == rhs) as i32,
+        BinaryOp::Gt => (lhs > rhs) as i32,
+        BinaryOp::Lt => (lhs < rhs) as i32,
+        BinaryOp::Ge => (lhs >= rhs) as i32,
+        BinaryOp::Le => (lhs <= rhs) as i32,
+        BinaryOp::Add => lhs.wrapping_add(rhs),
+        BinaryOp::Sub => lhs.wrapping_sub(rhs),
+        BinaryOp::Mul => lhs.wrapping_mul(rhs),
+        BinaryOp::Div => {
+            if rhs == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            lhs.wrapping_div(rhs)
+        }
+        BinaryOp::Mod => {
+            if rhs == 0 {
+                return Err(RuntimeError::DivisionByZero);
+            }
+            lhs.wrapping_rem(rhs)
+        }
+        BinaryOp::And => lhs & rhs,
+        BinaryOp::Or => lhs | rhs,
+        BinaryOp::Xor => lhs ^ rhs,
+        BinaryOp::Shl => lhs.wrapping_shl(rhs as u32),
+        BinaryOp::Shr => ((lhs as u32).wrapping_shr(rhs as u32)) as i32,
+        BinaryOp::Sar => lhs.wrapping_shr(rhs as u32),
+    })
+}
@@ -1,16 +1,20 @@
-use std::collections::HashMap;
+use super::sema::{ScopeId, SymbolId};
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
+use koopa::ir::{Function, Value};
+use lalrpop_util::ParseError;
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Variable {
-    // 对于int类型的变量，存储其在生成的koopaIR中临时变量的名字（e.g. @x）
-    INT(String),
+    // 对于int类型的变量，存储koopaIR中指向它的指针（alloc或全局alloc）
+    INT(Value),
     // 对于int类型的常量，需要保存的信息只有它的值
     ConstINT(i32),
-    // 对于函数对象，保存其koopaIR中的函数名（e.g. @main)及其返回值类型
-    Func((String, ItemType, Vec<bool>)),
-    // 对于数组（指针）对象，需要保存其名
-    Array(String),
-    Ptr((String, ItemType)),
+    // 对于函数对象，保存其koopaIR中的函数，签名见符号表
+    Func(Function),
+    // 对于数组对象，保存指向数组的指针
+    Array(Value),
+    // 对于数组形参，保存存放该指针的alloc
+    Ptr((Value, ItemType)),
 }
 
 /// CompUnit ::= [CompUnit] GlobalItem
@@ -31,8 +35,11 @@ pub enum GlobalItem {
 pub struct FuncDef {
     pub func_type: ItemType,
     pub id: String,
+    pub span: Span,
     pub func_f_params: Option<FuncFParams>,
     pub block: Block,
+    /// 语义分析得到的符号
+    pub symbol: Option<SymbolId>,
 }
 
 /// FuncFParams ::= FuncFParam {"," FuncFParam}
@@ -46,7 +53,9 @@ pub struct FuncFParams {
 pub struct FuncFParam {
     pub b_type: ItemType,
     pub id: String,
+    pub span: Span,
     pub dims: Option<Vec<ConstExp>>,
+    pub symbol: Option<SymbolId>,
 }
 
 /// FuncRParams ::= Exp {"," Exp}
@@ -55,7 +64,7 @@ pub struct FuncRParams {
     pub func_r_params: Vec<Exp>,
 }
 
-/// ItemType ::= "int"
+/// ItemType ::= "int" | "void"
 #[derive(Debug, PartialEq, Clone)]
 pub enum ItemType {
     Int,
@@ -66,7 +75,8 @@ pub enum ItemType {
 #[derive(Debug, PartialEq, Clone)]
 pub struct Block {
     pub items: Vec<BlockItem>,
-    pub vars_table: HashMap<String, i32>,
+    /// 语义分析得到的作用域
+    pub scope: Option<ScopeId>,
 }
 
 /// BlockItem ::= Decl | Stmt
@@ -101,15 +111,17 @@ pub struct ConstDecl {
 #[derive(Debug, PartialEq, Clone)]
 pub struct ConstDef {
     pub ident: String,
+    pub span: Span,
     pub dims: Vec<ConstExp>,
     pub const_init_val: ConstInitVal,
+    pub symbol: Option<SymbolId>,
 }
 
 /// VarDef ::= IDENT | IDENT "=" InitVal
 #[derive(Debug, PartialEq, Clone)]
 pub enum VarDef {
-    Decl((String, Vec<ConstExp>)),
-    Def((String, Vec<ConstExp>, InitVal)),
+    Decl((String, Vec<ConstExp>, Span, Option<SymbolId>)),
+    Def((String, Vec<ConstExp>, InitVal, Span, Option<SymbolId>)),
 }
 
 /// InitVal ::= Exp
@@ -149,20 +161,21 @@ pub struct While {
 ///       | Block
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
-    Return(Option<Exp>),
+    Return((Option<Exp>, Span)),
     Assign((LVal, Exp)),
     Exp(Option<Exp>),
     Block(Block),
     IF(Box<If>),
     WHILE(Box<While>),
-    Break,
-    Continue,
+    Break(Span),
+    Continue(Span),
 }
 
 /// Exp ::= LOrExp
 #[derive(Debug, PartialEq, Clone)]
 pub struct Exp {
     pub lor_exp: Box<LOrExp>,
+    pub span: Span,
 }
 
 /// ConstExp ::= Exp
@@ -185,7 +198,7 @@ pub enum PrimaryExp {
 pub enum UnaryExp {
     PrimaryExp(Box<PrimaryExp>),
     UnaryExp((UnaryOp, Box<UnaryExp>)),
-    FuncItem((String, Option<FuncRParams>)),
+    FuncItem((String, Option<FuncRParams>, Span, Option<SymbolId>)),
 }
 
 /// AddExp ::= MulExp | AddExp AddOp MulExp
@@ -269,4 +282,47 @@ pub enum CmpOp {
 pub struct LVal {
     pub ident: String,
     pub indices: Vec<Exp>,
+    pub span: Span,
+    pub symbol: Option<SymbolId>,
+}
+
+/// Parse an integer literal in the given radix. Literals above `i32::MAX`
+/// are reported as overflowing, except for `2147483648`, which wraps to
+/// `i32::MIN` so that `-2147483648` can be written; [`int_operand`] rejects
+/// it anywhere else.
+pub fn int_literal<T>(
+    digits: &str,
+    radix: u32,
+    span: Span,
+) -> Result<i32, ParseError<usize, T, Diagnostic>> {
+    match u32::from_str_radix(digits, radix) {
+        Ok(value) if value <= i32::MIN as u32 => Ok(value as i32),
+        _ => Err(ParseError::User {
+            error: Diagnostic::new(
+                ErrorCode::IntegerOverflow,
+                span,
+                "integer literal is too large",
+            ),
+        }),
+    }
+}
+
+/// Check a unary expression that is not the operand of unary minus: a
+/// literal there must not be `2147483648`.
+pub fn int_operand<T>(
+    exp: UnaryExp,
+    span: Span,
+) -> Result<UnaryExp, ParseError<usize, T, Diagnostic>> {
+    match &exp {
+        UnaryExp::PrimaryExp(primary) if **primary == PrimaryExp::Number(i32::MIN) => {
+            Err(ParseError::User {
+                error: Diagnostic::new(
+                    ErrorCode::IntegerOverflow,
+                    span,
+                    "integer literal is too large, 2147483648 can only follow a unary `-`",
+                ),
+            })
+        }
+        _ => Ok(exp),
+    }
 }
@@ -1,143 +1,233 @@
-use std::collections::HashMap;
-
+use super::sema::{SymbolKind, SymbolTable};
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
 use crate::ir_gen::ast::*;
+use std::fmt;
 
-pub trait Calc {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32;
+/// Why an expression could not be evaluated at compile time.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ConstEvalError {
+    /// 引用了不是常量的标识符
+    NotConstant(String, Span),
+    /// 调用了函数
+    FunctionCall(String, Span),
+    DivisionByZero(Span),
+    /// 常量数组的下标越界：下标与该维的长度
+    IndexOutOfBounds(i32, i32, Span),
 }
 
-impl Calc for ConstInitVal {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl ConstEvalError {
+    pub fn span(&self) -> Span {
         match self {
-            ConstInitVal::Exp(const_exp) => const_exp.calculate(vars_table),
-            ConstInitVal::Array(array) => 0,
+            ConstEvalError::NotConstant(_, span)
+            | ConstEvalError::FunctionCall(_, span)
+            | ConstEvalError::DivisionByZero(span)
+            | ConstEvalError::IndexOutOfBounds(_, _, span) => *span,
+        }
+    }
+}
+
+impl fmt::Display for ConstEvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstEvalError::NotConstant(ident, _) => {
+                write!(f, "`{0}` is not a constant", ident)
+            }
+            ConstEvalError::FunctionCall(ident, _) => {
+                write!(f, "cannot call `{0}` in a constant expression", ident)
+            }
+            ConstEvalError::DivisionByZero(_) => {
+                write!(f, "division by zero in a constant expression")
+            }
+            ConstEvalError::IndexOutOfBounds(index, len, _) => {
+                write!(f, "index {0} is out of bounds for length {1}", index, len)
+            }
         }
     }
 }
 
+impl From<ConstEvalError> for Diagnostic {
+    fn from(err: ConstEvalError) -> Self {
+        let code = match err {
+            ConstEvalError::NotConstant(..) | ConstEvalError::FunctionCall(..) => {
+                ErrorCode::NotConstant
+            }
+            ConstEvalError::DivisionByZero(_) => ErrorCode::DivisionByZero,
+            ConstEvalError::IndexOutOfBounds(..) => ErrorCode::IndexOutOfBounds,
+        };
+        Diagnostic::new(code, err.span(), err.to_string())
+    }
+}
+
+/// Evaluate an expression at compile time, with the wrapping arithmetic of
+/// the target.
+pub trait Calc {
+    fn calculate(&self, symbols: &SymbolTable) -> Result<i32, ConstEvalError>;
+}
+
+/// 表达式内部的各层，`span`是所在的最内层Exp，用于除零报错
+trait Eval {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError>;
+}
+
 impl Calc for ConstExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
-        self.exp.calculate(vars_table)
+    fn calculate(&self, symbols: &SymbolTable) -> Result<i32, ConstEvalError> {
+        self.exp.calculate(symbols)
     }
 }
 
 impl Calc for Exp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
-        self.lor_exp.calculate(vars_table)
+    fn calculate(&self, symbols: &SymbolTable) -> Result<i32, ConstEvalError> {
+        self.lor_exp.eval(symbols, self.span)
     }
 }
 
-impl Calc for LOrExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for LOrExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            LOrExp::LAndExp(land_exp) => land_exp.calculate(vars_table),
+            LOrExp::LAndExp(land_exp) => land_exp.eval(symbols, span),
+            // 与运行时一致，左侧为真时不再计算右侧
             LOrExp::LOrExp((lor_exp, land_exp)) => {
-                (lor_exp.calculate(vars_table) != 0 || land_exp.calculate(vars_table) != 0) as i32
+                if lor_exp.eval(symbols, span)? != 0 {
+                    return Ok(1);
+                }
+                Ok((land_exp.eval(symbols, span)? != 0) as i32)
             }
         }
     }
 }
 
-impl Calc for LAndExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for LAndExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            LAndExp::EqExp(eq_exp) => eq_exp.calculate(vars_table),
+            LAndExp::EqExp(eq_exp) => eq_exp.eval(symbols, span),
             LAndExp::LAndExp((land_exp, eq_exp)) => {
-                (land_exp.calculate(vars_table) != 0 && eq_exp.calculate(vars_table) != 0) as i32
+                if land_exp.eval(symbols, span)? == 0 {
+                    return Ok(0);
+                }
+                Ok((eq_exp.eval(symbols, span)? != 0) as i32)
             }
         }
     }
 }
 
-impl Calc for EqExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for EqExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            EqExp::RelExp(rel_exp) => rel_exp.calculate(vars_table),
-            EqExp::EqExp((eq_exp, cmp_op, rel_exp)) => match cmp_op {
-                CmpOp::Eq => (eq_exp.calculate(vars_table) == rel_exp.calculate(vars_table)) as i32,
-                CmpOp::NEq => {
-                    (eq_exp.calculate(vars_table) != rel_exp.calculate(vars_table)) as i32
-                }
-                _ => unreachable!(),
-            },
+            EqExp::RelExp(rel_exp) => rel_exp.eval(symbols, span),
+            EqExp::EqExp((eq_exp, cmp_op, rel_exp)) => {
+                let lhs = eq_exp.eval(symbols, span)?;
+                let rhs = rel_exp.eval(symbols, span)?;
+                Ok(match cmp_op {
+                    CmpOp::Eq => (lhs == rhs) as i32,
+                    CmpOp::NEq => (lhs != rhs) as i32,
+                    _ => unreachable!(),
+                })
+            }
         }
     }
 }
 
-impl Calc for RelExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for RelExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            RelExp::AddExp(add_exp) => add_exp.calculate(vars_table),
-            RelExp::CompExp((rel_exp, cmp_op, add_exp)) => match cmp_op {
-                CmpOp::Less => {
-                    (rel_exp.calculate(vars_table) < add_exp.calculate(vars_table)) as i32
-                }
-                CmpOp::Grate => {
-                    (rel_exp.calculate(vars_table) > add_exp.calculate(vars_table)) as i32
-                }
-                CmpOp::LessEq => {
-                    (rel_exp.calculate(vars_table) <= add_exp.calculate(vars_table)) as i32
-                }
-                CmpOp::GrateEq => {
-                    (rel_exp.calculate(vars_table) >= add_exp.calculate(vars_table)) as i32
-                }
-                _ => unreachable!(),
-            },
+            RelExp::AddExp(add_exp) => add_exp.eval(symbols, span),
+            RelExp::CompExp((rel_exp, cmp_op, add_exp)) => {
+                let lhs = rel_exp.eval(symbols, span)?;
+                let rhs = add_exp.eval(symbols, span)?;
+                Ok(match cmp_op {
+                    CmpOp::Less => (lhs < rhs) as i32,
+                    CmpOp::Grate => (lhs > rhs) as i32,
+                    CmpOp::LessEq => (lhs <= rhs) as i32,
+                    CmpOp::GrateEq => (lhs >= rhs) as i32,
+                    _ => unreachable!(),
+                })
+            }
         }
     }
 }
 
-impl Calc for AddExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for AddExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            AddExp::MulExp(mul_exp) => mul_exp.calculate(vars_table),
-            AddExp::AddExp((add_exp, add_op, mul_exp)) => match add_op {
-                AddOp::Add => add_exp.calculate(vars_table) + mul_exp.calculate(vars_table),
-                AddOp::Sub => add_exp.calculate(vars_table) - mul_exp.calculate(vars_table),
-            },
+            AddExp::MulExp(mul_exp) => mul_exp.eval(symbols, span),
+            AddExp::AddExp((add_exp, add_op, mul_exp)) => {
+                let lhs = add_exp.eval(symbols, span)?;
+                let rhs = mul_exp.eval(symbols, span)?;
+                Ok(match add_op {
+                    AddOp::Add => lhs.wrapping_add(rhs),
+                    AddOp::Sub => lhs.wrapping_sub(rhs),
+                })
+            }
         }
     }
 }
 
-impl Calc for MulExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for MulExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            MulExp::UnaryExp(unary_exp) => unary_exp.calculate(vars_table),
-            MulExp::MulExp((mul_exp, mul_op, unary_exp)) => match mul_op {
-                MulOp::Multiple => mul_exp.calculate(vars_table) * unary_exp.calculate(vars_table),
-                MulOp::Divide => mul_exp.calculate(vars_table) / unary_exp.calculate(vars_table),
-                MulOp::Mod => mul_exp.calculate(vars_table) % unary_exp.calculate(vars_table),
-            },
+            MulExp::UnaryExp(unary_exp) => unary_exp.eval(symbols, span),
+            MulExp::MulExp((mul_exp, mul_op, unary_exp)) => {
+                let lhs = mul_exp.eval(symbols, span)?;
+                let rhs = unary_exp.eval(symbols, span)?;
+                // 与RV32的div/rem一致，i32::MIN / -1 得到 i32::MIN
+                match mul_op {
+                    MulOp::Multiple => Ok(lhs.wrapping_mul(rhs)),
+                    _ if rhs == 0 => Err(ConstEvalError::DivisionByZero(span)),
+                    MulOp::Divide => Ok(lhs.wrapping_div(rhs)),
+                    MulOp::Mod => Ok(lhs.wrapping_rem(rhs)),
+                }
+            }
         }
     }
 }
 
-impl Calc for UnaryExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for UnaryExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            UnaryExp::PrimaryExp(pri_exp) => pri_exp.calculate(vars_table),
-            UnaryExp::UnaryExp((unary_op, unary_exp)) => match unary_op {
-                UnaryOp::Passive => unary_exp.calculate(vars_table),
-                UnaryOp::Negative => -unary_exp.calculate(vars_table),
-                UnaryOp::Inversion => (unary_exp.calculate(vars_table) == 0) as i32,
-            },
-            _ => unreachable!(),
+            UnaryExp::PrimaryExp(pri_exp) => pri_exp.eval(symbols, span),
+            UnaryExp::UnaryExp((unary_op, unary_exp)) => {
+                let value = unary_exp.eval(symbols, span)?;
+                Ok(match unary_op {
+                    UnaryOp::Passive => value,
+                    UnaryOp::Negative => value.wrapping_neg(),
+                    UnaryOp::Inversion => (value == 0) as i32,
+                })
+            }
+            UnaryExp::FuncItem((ident, _, span, _)) => {
+                Err(ConstEvalError::FunctionCall(ident.clone(), *span))
+            }
         }
     }
 }
 
-impl Calc for PrimaryExp {
-    fn calculate(&self, vars_table: &mut HashMap<String, Variable>) -> i32 {
+impl Eval for PrimaryExp {
+    fn eval(&self, symbols: &SymbolTable, span: Span) -> Result<i32, ConstEvalError> {
         match self {
-            PrimaryExp::Exp(exp) => exp.calculate(vars_table),
-            PrimaryExp::Number(int) => *int,
-            PrimaryExp::LVal(var) => match vars_table.get(&var.ident) {
-                Some(int) => match int {
-                    Variable::ConstINT(const_int) => *const_int,
-                    _ => unreachable!(),
-                },
-                None => unreachable!(),
-            },
+            PrimaryExp::Exp(exp) => exp.calculate(symbols),
+            PrimaryExp::Number(int) => Ok(*int),
+            PrimaryExp::LVal(var) => var.eval(symbols, span),
+        }
+    }
+}
+
+impl Eval for LVal {
+    fn eval(&self, symbols: &SymbolTable, _span: Span) -> Result<i32, ConstEvalError> {
+        let not_constant = || ConstEvalError::NotConstant(self.ident.clone(), self.span);
+        match &symbols.symbol(self.symbol.ok_or_else(not_constant)?).kind {
+            SymbolKind::Const(const_int) if self.indices.is_empty() => Ok(*const_int),
+            // 常量数组的元素按行优先存放
+            SymbolKind::ConstArray(dims, values) if self.indices.len() == dims.len() => {
+                let mut offset = 0;
+                for (index, &len) in self.indices.iter().zip(dims) {
+                    let index = index.calculate(symbols)?;
+                    if index < 0 || index >= len {
+                        return Err(ConstEvalError::IndexOutOfBounds(index, len, self.span));
+                    }
+                    offset = offset * len as usize + index as usize;
+                }
+                Ok(values[offset])
+            }
+            _ => Err(not_constant()),
         }
     }
 }
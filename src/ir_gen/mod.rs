@@ -1,3 +1,7 @@
 pub mod ast;
 pub mod calc;
+pub mod print;
+pub mod schema;
+pub mod sema;
 pub mod show;
+pub mod tree;
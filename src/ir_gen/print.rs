@@ -0,0 +1,456 @@
+//! Printing ASTs back to SysY source.
+//!
+//! Every node implements [`Display`](fmt::Display) as the canonical source
+//! of that node: two spaces of indentation, one statement per line, single
+//! spaces around binary operators and a blank line around functions.
+//! Statements and blocks print without a trailing newline, and their nested
+//! lines are indented relative to the first one.
+//!
+//! The output parses back to the same tree, spans aside: parentheses appear
+//! exactly where the tree has a parenthesized `PrimaryExp`, and the only
+//! addition is a block around the `then` branch of an `if` that would
+//! otherwise capture the following `else`.
+
+use crate::ir_gen::ast::*;
+use std::fmt::{self, Display, Formatter, Write};
+
+/// 每层缩进的空格数
+const INDENT: &str = "  ";
+
+/// Writes through to a formatter, indenting every line but the first.
+struct Indented<'a, 'b> {
+    f: &'a mut Formatter<'b>,
+    /// 是否刚写完换行，下一行有内容时先写缩进
+    line_start: bool,
+}
+
+impl Write for Indented<'_, '_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for (i, line) in s.split('\n').enumerate() {
+            if i > 0 {
+                self.f.write_char('\n')?;
+                self.line_start = true;
+            }
+            // 空行不缩进
+            if !line.is_empty() {
+                if self.line_start {
+                    self.f.write_str(INDENT)?;
+                    self.line_start = false;
+                }
+                self.f.write_str(line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write `node` on a new line, one level deeper than the current one.
+fn nested(f: &mut Formatter<'_>, node: &impl Display) -> fmt::Result {
+    let mut out = Indented {
+        f,
+        line_start: false,
+    };
+    write!(out, "\n{0}", node)
+}
+
+/// Write `nodes` separated by commas.
+fn comma_list<T: Display>(f: &mut Formatter<'_>, nodes: &[T]) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            f.write_str(", ")?;
+        }
+        write!(f, "{0}", node)?;
+    }
+    Ok(())
+}
+
+fn dims(f: &mut Formatter<'_>, dims: &[ConstExp]) -> fmt::Result {
+    for dim in dims {
+        write!(f, "[{0}]", dim)?;
+    }
+    Ok(())
+}
+
+impl Display for CompUnit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(comp_unit) = self.comp_unit.as_ref() {
+            writeln!(f, "{0}", comp_unit)?;
+            // 函数与相邻的项之间空一行
+            let is_func = |item: &GlobalItem| matches!(item, GlobalItem::Func(_));
+            if is_func(&comp_unit.global_item) || is_func(&self.global_item) {
+                writeln!(f)?;
+            }
+        }
+        write!(f, "{0}", self.global_item)
+    }
+}
+
+impl Display for GlobalItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            GlobalItem::Func(func_def) => write!(f, "{0}", func_def),
+            GlobalItem::Decl(decl) => write!(f, "{0}", decl),
+        }
+    }
+}
+
+impl Display for FuncDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} {1}(", self.func_type, self.id)?;
+        if let Some(params) = &self.func_f_params {
+            write!(f, "{0}", params)?;
+        }
+        write!(f, ") {0}", self.block)
+    }
+}
+
+impl Display for FuncFParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        comma_list(f, &self.func_f_params)
+    }
+}
+
+impl Display for FuncFParam {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} {1}", self.b_type, self.id)?;
+        if let Some(param_dims) = &self.dims {
+            f.write_str("[]")?;
+            dims(f, param_dims)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for FuncRParams {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        comma_list(f, &self.func_r_params)
+    }
+}
+
+impl Display for ItemType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ItemType::Int => "int",
+            ItemType::Void => "void",
+        })
+    }
+}
+
+impl Display for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.items.is_empty() {
+            return f.write_str("{}");
+        }
+        f.write_char('{')?;
+        for item in &self.items {
+            nested(f, item)?;
+        }
+        f.write_str("\n}")
+    }
+}
+
+impl Display for BlockItem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockItem::Decl(decl) => write!(f, "{0}", decl),
+            BlockItem::Stmt(stmt) => write!(f, "{0}", stmt),
+        }
+    }
+}
+
+impl Display for Decl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Decl::ConstDecl(const_decl) => write!(f, "{0}", const_decl),
+            Decl::VarDecl(var_decl) => write!(f, "{0}", var_decl),
+        }
+    }
+}
+
+impl Display for VarDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} ", self.b_type)?;
+        comma_list(f, &self.var_defs)?;
+        f.write_char(';')
+    }
+}
+
+impl Display for ConstDecl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "const {0} ", self.b_type)?;
+        comma_list(f, &self.const_defs)?;
+        f.write_char(';')
+    }
+}
+
+impl Display for ConstDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.ident)?;
+        dims(f, &self.dims)?;
+        write!(f, " = {0}", self.const_init_val)
+    }
+}
+
+impl Display for VarDef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            VarDef::Decl((ident, var_dims, _, _)) => {
+                f.write_str(ident)?;
+                dims(f, var_dims)
+            }
+            VarDef::Def((ident, var_dims, init, _, _)) => {
+                f.write_str(ident)?;
+                dims(f, var_dims)?;
+                write!(f, " = {0}", init)
+            }
+        }
+    }
+}
+
+impl Display for InitVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InitVal::Exp(exp) => write!(f, "{0}", exp),
+            InitVal::Array(inits) => {
+                f.write_char('{')?;
+                comma_list(f, inits)?;
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+impl Display for ConstInitVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstInitVal::Exp(exp) => write!(f, "{0}", exp),
+            ConstInitVal::Array(inits) => {
+                f.write_char('{')?;
+                comma_list(f, inits)?;
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+/// Whether `stmt` ends with an `if` without `else`, which would capture an
+/// `else` printed after it.
+fn open_if(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::IF(if_stmt) => match &if_stmt.else_stmt {
+            None => true,
+            Some(else_stmt) => open_if(else_stmt),
+        },
+        Stmt::WHILE(while_stmt) => open_if(&while_stmt.body_stmt),
+        _ => false,
+    }
+}
+
+/// Write the body of an `if`, `else` or `while`: a block on the same line,
+/// any other statement on the next one.
+fn clause(f: &mut Formatter<'_>, body: &Stmt) -> fmt::Result {
+    match body {
+        Stmt::Block(block) => write!(f, " {0}", block),
+        _ => nested(f, body),
+    }
+}
+
+impl Display for If {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "if ({0})", self.cond)?;
+        let else_stmt = match &self.else_stmt {
+            Some(else_stmt) => else_stmt,
+            None => return clause(f, &self.then_stmt),
+        };
+        // then分支以不带else的if结尾时，加上花括号以免吞掉后面的else
+        if open_if(&self.then_stmt) {
+            f.write_str(" {")?;
+            nested(f, &self.then_stmt)?;
+            f.write_str("\n} else")?;
+        } else {
+            clause(f, &self.then_stmt)?;
+            match &self.then_stmt {
+                Stmt::Block(_) => f.write_str(" else")?,
+                _ => f.write_str("\nelse")?,
+            }
+        }
+        match else_stmt {
+            Stmt::IF(if_stmt) => write!(f, " {0}", if_stmt),
+            _ => clause(f, else_stmt),
+        }
+    }
+}
+
+impl Display for While {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "while ({0})", self.cond)?;
+        clause(f, &self.body_stmt)
+    }
+}
+
+impl Display for Stmt {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Stmt::Return((None, _)) => f.write_str("return;"),
+            Stmt::Return((Some(exp), _)) => write!(f, "return {0};", exp),
+            Stmt::Assign((lval, exp)) => write!(f, "{0} = {1};", lval, exp),
+            Stmt::Exp(None) => f.write_char(';'),
+            Stmt::Exp(Some(exp)) => write!(f, "{0};", exp),
+            Stmt::Block(block) => write!(f, "{0}", block),
+            Stmt::IF(if_stmt) => write!(f, "{0}", if_stmt),
+            Stmt::WHILE(while_stmt) => write!(f, "{0}", while_stmt),
+            Stmt::Break(_) => f.write_str("break;"),
+            Stmt::Continue(_) => f.write_str("continue;"),
+        }
+    }
+}
+
+impl Display for Exp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.lor_exp)
+    }
+}
+
+impl Display for ConstExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{0}", self.exp)
+    }
+}
+
+impl Display for PrimaryExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            PrimaryExp::Exp(exp) => write!(f, "({0})", exp),
+            // 超过i32范围的字面量按补码回绕，原样打印其无符号值
+            PrimaryExp::Number(value) => write!(f, "{0}", *value as u32),
+            PrimaryExp::LVal(lval) => write!(f, "{0}", lval),
+        }
+    }
+}
+
+impl Display for UnaryExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            UnaryExp::PrimaryExp(exp) => write!(f, "{0}", exp),
+            UnaryExp::UnaryExp((op, exp)) => {
+                // 连续两个相同的+或-之间加空格，以免读作C中的--或++
+                let space = *op != UnaryOp::Inversion
+                    && matches!(&**exp, UnaryExp::UnaryExp((inner, _)) if inner == op);
+                write!(f, "{0}{1}{2}", op, if space { " " } else { "" }, exp)
+            }
+            UnaryExp::FuncItem((ident, params, _, _)) => {
+                write!(f, "{0}(", ident)?;
+                if let Some(params) = params {
+                    write!(f, "{0}", params)?;
+                }
+                f.write_char(')')
+            }
+        }
+    }
+}
+
+impl Display for AddExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AddExp::MulExp(exp) => write!(f, "{0}", exp),
+            AddExp::AddExp((lhs, op, rhs)) => write!(f, "{0} {1} {2}", lhs, op, rhs),
+        }
+    }
+}
+
+impl Display for MulExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            MulExp::UnaryExp(exp) => write!(f, "{0}", exp),
+            MulExp::MulExp((lhs, op, rhs)) => write!(f, "{0} {1} {2}", lhs, op, rhs),
+        }
+    }
+}
+
+impl Display for LOrExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LOrExp::LAndExp(exp) => write!(f, "{0}", exp),
+            LOrExp::LOrExp((lhs, rhs)) => write!(f, "{0} || {1}", lhs, rhs),
+        }
+    }
+}
+
+impl Display for LAndExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LAndExp::EqExp(exp) => write!(f, "{0}", exp),
+            LAndExp::LAndExp((lhs, rhs)) => write!(f, "{0} && {1}", lhs, rhs),
+        }
+    }
+}
+
+impl Display for EqExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            EqExp::RelExp(exp) => write!(f, "{0}", exp),
+            EqExp::EqExp((lhs, op, rhs)) => write!(f, "{0} {1} {2}", lhs, op, rhs),
+        }
+    }
+}
+
+impl Display for RelExp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RelExp::AddExp(exp) => write!(f, "{0}", exp),
+            RelExp::CompExp((lhs, op, rhs)) => write!(f, "{0} {1} {2}", lhs, op, rhs),
+        }
+    }
+}
+
+impl Display for UnaryOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOp::Passive => "+",
+            UnaryOp::Negative => "-",
+            UnaryOp::Inversion => "!",
+        })
+    }
+}
+
+impl Display for AddOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            AddOp::Add => "+",
+            AddOp::Sub => "-",
+        })
+    }
+}
+
+impl Display for MulOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MulOp::Multiple => "*",
+            MulOp::Divide => "/",
+            MulOp::Mod => "%",
+        })
+    }
+}
+
+impl Display for CmpOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CmpOp::Eq => "==",
+            CmpOp::NEq => "!=",
+            CmpOp::Less => "<",
+            CmpOp::Grate => ">",
+            CmpOp::LessEq => "<=",
+            CmpOp::GrateEq => ">=",
+        })
+    }
+}
+
+impl Display for LVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.ident)?;
+        for index in &self.indices {
+            write!(f, "[{0}]", index)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,911 @@
+//! The schema of ASTs written as JSON or S-expressions.
+//!
+//! `--emit=ast-json` and `--emit=ast-sexp` write a [tree](super::tree) with
+//! the nodes below, and `--input-format` reads one back in place of SysY
+//! source. Fields appear in the order listed. Spans are `[start, end]` byte
+//! offsets into the source; when reading they may be null or left out, and
+//! are then empty spans at offset 0. Reading rejects
+//! unknown kinds and fields, so that a tool writing a newer or misspelled
+//! schema fails early.
+//!
+//! ```text
+//! CompUnit   version: 1, items: [FuncDef | ConstDecl | VarDecl]
+//! FuncDef    span, type: "int" | "void", name, params: [Param], body: Block
+//! Param      span, type, name, dims: null | [Exp]
+//! Block      items: [ConstDecl | VarDecl | Stmt]
+//! ConstDecl  type, defs: [ConstDef]
+//! ConstDef   span, name, dims: [Exp], init: Exp | InitList
+//! VarDecl    type, defs: [VarDef]
+//! VarDef     span, name, dims: [Exp], init: null | Exp | InitList
+//! InitList   items: [Exp | InitList]
+//! Stmt       Assign | ExpStmt | Return | If | While | Break | Continue | Block
+//! Assign     target: LVal, value: Exp
+//! ExpStmt    value: null | Exp
+//! Return     span, value: null | Exp
+//! If         cond: Exp, then: Stmt, else: null | Stmt
+//! While      cond: Exp, body: Stmt
+//! Break      span
+//! Continue   span
+//! Exp        span, value: LOr
+//! LOr        lhs: LOr, rhs: LAnd                               | LAnd
+//! LAnd       lhs: LAnd, rhs: Eq                                | Eq
+//! Eq         op: "==" | "!=", lhs: Eq, rhs: Rel                | Rel
+//! Rel        op: "<" | ">" | "<=" | ">=", lhs: Rel, rhs: Add   | Add
+//! Add        op: "+" | "-", lhs: Add, rhs: Mul                 | Mul
+//! Mul        op: "*" | "/" | "%", lhs: Mul, rhs: Unary         | Unary
+//! Unary      op: "+" | "-" | "!", operand: Unary               | Call | Paren | Number | LVal
+//! Call       span, name, args: [Exp]
+//! Paren      exp: Exp
+//! Number     value: 0 to 2^31 - 1, or 2^31 as the operand of "-"
+//! LVal       span, name, indices: [Exp]
+//! ```
+//!
+//! The levels of the precedence chain below `Exp` follow the grammar: where
+//! a level is expected, a node of that level or of any tighter one may
+//! appear, and a looser one must be wrapped in `Paren`. The `dims` of a
+//! `Param` are those after its leading `[]`, and null for a scalar. Names must
+//! be identifiers other than keywords.
+
+use super::tree::{self, Node, Value};
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
+use crate::ir_gen::ast::*;
+
+/// 当前的schema版本，不兼容的修改需要增加版本号
+pub const SCHEMA_VERSION: i64 = 1;
+
+impl CompUnit {
+    /// The tree of `self` in the schema of this module.
+    pub fn to_tree(&self) -> Value {
+        let mut items = Vec::new();
+        let mut unit = Some(self);
+        while let Some(u) = unit {
+            items.push(global_item(&u.global_item));
+            unit = u.comp_unit.as_ref().as_ref();
+        }
+        items.reverse();
+        Value::Node(
+            Node::new("CompUnit")
+                .field("version", Value::Int(SCHEMA_VERSION))
+                .field("items", Value::List(items)),
+        )
+    }
+
+    pub fn to_json(&self) -> String {
+        tree::to_json(&self.to_tree())
+    }
+
+    pub fn to_sexp(&self) -> String {
+        tree::to_sexp(&self.to_tree())
+    }
+
+    /// Read a compilation unit from a tree in the schema of this module.
+    pub fn from_tree(value: &Value) -> Result<CompUnit, Diagnostic> {
+        let node = match value {
+            Value::Node(node) => expect(node, "CompUnit")?,
+            _ => {
+                return Err(Diagnostic::new(
+                    ErrorCode::InvalidAst,
+                    Span::default(),
+                    "expected a `CompUnit` node",
+                ))
+            }
+        };
+        check_fields(node, &["version", "items"])?;
+        let version = int(node, "version")?;
+        if version != SCHEMA_VERSION {
+            return Err(error(
+                node,
+                format!(
+                    "unsupported schema version {0}, expected {1}",
+                    version, SCHEMA_VERSION
+                ),
+            ));
+        }
+        let mut unit = None;
+        for item in nodes(node, "items")? {
+            let global_item = match item.kind.as_str() {
+                "FuncDef" => GlobalItem::Func(read_func_def(item)?),
+                _ => GlobalItem::Decl(read_decl(item)?.ok_or_else(|| {
+                    error(
+                        item,
+                        format!(
+                            "expected a function or a declaration, found `{0}`",
+                            item.kind
+                        ),
+                    )
+                })?),
+            };
+            unit = Some(CompUnit {
+                comp_unit: Box::new(unit),
+                global_item,
+            });
+        }
+        unit.ok_or_else(|| error(node, "a compilation unit needs at least one item"))
+    }
+
+    pub fn from_json(text: &str) -> Result<CompUnit, Diagnostic> {
+        CompUnit::from_tree(&tree::parse_json(text)?)
+    }
+
+    pub fn from_sexp(text: &str) -> Result<CompUnit, Diagnostic> {
+        CompUnit::from_tree(&tree::parse_sexp(text)?)
+    }
+}
+
+fn span(span: &Span) -> Value {
+    Value::List(vec![
+        Value::Int(span.start as i64),
+        Value::Int(span.end as i64),
+    ])
+}
+
+fn string(s: &str) -> Value {
+    Value::Str(s.to_string())
+}
+
+fn list<T>(items: &[T], f: impl Fn(&T) -> Value) -> Value {
+    Value::List(items.iter().map(f).collect())
+}
+
+fn optional<T>(item: &Option<T>, f: impl Fn(&T) -> Value) -> Value {
+    item.as_ref().map_or(Value::Null, f)
+}
+
+fn global_item(item: &GlobalItem) -> Value {
+    match item {
+        GlobalItem::Func(func_def) => {
+            let params = func_def
+                .func_f_params
+                .as_ref()
+                .map_or(&[][..], |params| &params.func_f_params);
+            Value::Node(
+                Node::new("FuncDef")
+                    .field("span", span(&func_def.span))
+                    .field("type", string(&func_def.func_type.to_string()))
+                    .field("name", string(&func_def.id))
+                    .field("params", list(params, param))
+                    .field("body", block(&func_def.block)),
+            )
+        }
+        GlobalItem::Decl(decl) => self::decl(decl),
+    }
+}
+
+fn param(param: &FuncFParam) -> Value {
+    Value::Node(
+        Node::new("Param")
+            .field("span", span(&param.span))
+            .field("type", string(&param.b_type.to_string()))
+            .field("name", string(&param.id))
+            .field("dims", optional(&param.dims, |dims| list(dims, const_exp))),
+    )
+}
+
+fn block(block: &Block) -> Value {
+    let items = list(&block.items, |item| match item {
+        BlockItem::Decl(decl) => self::decl(decl),
+        BlockItem::Stmt(stmt) => self::stmt(stmt),
+    });
+    Value::Node(Node::new("Block").field("items", items))
+}
+
+fn decl(decl: &Decl) -> Value {
+    match decl {
+        Decl::ConstDecl(const_decl) => {
+            let defs = list(&const_decl.const_defs, |def| {
+                Value::Node(
+                    Node::new("ConstDef")
+                        .field("span", span(&def.span))
+                        .field("name", string(&def.ident))
+                        .field("dims", list(&def.dims, const_exp))
+                        .field("init", const_init(&def.const_init_val)),
+                )
+            });
+            Value::Node(
+                Node::new("ConstDecl")
+                    .field("type", string(&const_decl.b_type.to_string()))
+                    .field("defs", defs),
+            )
+        }
+        Decl::VarDecl(var_decl) => {
+            let defs = list(&var_decl.var_defs, |def| {
+                let (ident, dims, init, def_span) = match def {
+                    VarDef::Decl((ident, dims, def_span, _)) => (ident, dims, None, def_span),
+                    VarDef::Def((ident, dims, init, def_span, _)) => {
+                        (ident, dims, Some(init), def_span)
+                    }
+                };
+                Value::Node(
+                    Node::new("VarDef")
+                        .field("span", span(def_span))
+                        .field("name", string(ident))
+                        .field("dims", list(dims, const_exp))
+                        .field("init", init.map_or(Value::Null, self::init)),
+                )
+            });
+            Value::Node(
+                Node::new("VarDecl")
+                    .field("type", string(&var_decl.b_type.to_string()))
+                    .field("defs", defs),
+            )
+        }
+    }
+}
+
+fn init(init: &InitVal) -> Value {
+    match init {
+        InitVal::Exp(e) => exp(e),
+        InitVal::Array(inits) => {
+            Value::Node(Node::new("InitList").field("items", list(inits, self::init)))
+        }
+    }
+}
+
+fn const_init(init: &ConstInitVal) -> Value {
+    match init {
+        ConstInitVal::Exp(e) => const_exp(e),
+        ConstInitVal::Array(inits) => {
+            Value::Node(Node::new("InitList").field("items", list(inits, const_init)))
+        }
+    }
+}
+
+fn stmt(stmt: &Stmt) -> Value {
+    let node = match stmt {
+        Stmt::Assign((target, value)) => Node::new("Assign")
+            .field("target", lval(target))
+            .field("value", exp(value)),
+        Stmt::Exp(value) => Node::new("ExpStmt").field("value", optional(value, exp)),
+        Stmt::Return((value, stmt_span)) => Node::new("Return")
+            .field("span", span(stmt_span))
+            .field("value", optional(value, exp)),
+        Stmt::IF(if_stmt) => Node::new("If")
+            .field("cond", exp(&if_stmt.cond))
+            .field("then", self::stmt(&if_stmt.then_stmt))
+            .field("else", optional(&if_stmt.else_stmt, self::stmt)),
+        Stmt::WHILE(while_stmt) => Node::new("While")
+            .field("cond", exp(&while_stmt.cond))
+            .field("body", self::stmt(&while_stmt.body_stmt)),
+        Stmt::Break(stmt_span) => Node::new("Break").field("span", span(stmt_span)),
+        Stmt::Continue(stmt_span) => Node::new("Continue").field("span", span(stmt_span)),
+        Stmt::Block(b) => return block(b),
+    };
+    Value::Node(node)
+}
+
+fn const_exp(e: &ConstExp) -> Value {
+    exp(&e.exp)
+}
+
+fn exp(e: &Exp) -> Value {
+    Value::Node(
+        Node::new("Exp")
+            .field("span", span(&e.span))
+            .field("value", lor(&e.lor_exp)),
+    )
+}
+
+/// A binary node of the precedence chain.
+fn binary(kind: &str, op: Option<String>, lhs: Value, rhs: Value) -> Value {
+    let mut node = Node::new(kind);
+    if let Some(op) = op {
+        node = node.field("op", Value::Str(op));
+    }
+    Value::Node(node.field("lhs", lhs).field("rhs", rhs))
+}
+
+fn lor(e: &LOrExp) -> Value {
+    match e {
+        LOrExp::LAndExp(e) => land(e),
+        LOrExp::LOrExp((lhs, rhs)) => binary("LOr", None, lor(lhs), land(rhs)),
+    }
+}
+
+fn land(e: &LAndExp) -> Value {
+    match e {
+        LAndExp::EqExp(e) => eq(e),
+        LAndExp::LAndExp((lhs, rhs)) => binary("LAnd", None, land(lhs), eq(rhs)),
+    }
+}
+
+fn eq(e: &EqExp) -> Value {
+    match e {
+        EqExp::RelExp(e) => rel(e),
+        EqExp::EqExp((lhs, op, rhs)) => binary("Eq", Some(op.to_string()), eq(lhs), rel(rhs)),
+    }
+}
+
+fn rel(e: &RelExp) -> Value {
+    match e {
+        RelExp::AddExp(e) => add(e),
+        RelExp::CompExp((lhs, op, rhs)) => binary("Rel", Some(op.to_string()), rel(lhs), add(rhs)),
+    }
+}
+
+fn add(e: &AddExp) -> Value {
+    match e {
+        AddExp::MulExp(e) => mul(e),
+        AddExp::AddExp((lhs, op, rhs)) => binary("Add", Some(op.to_string()), add(lhs), mul(rhs)),
+    }
+}
+
+fn mul(e: &MulExp) -> Value {
+    match e {
+        MulExp::UnaryExp(e) => unary(e),
+        MulExp::MulExp((lhs, op, rhs)) => binary("Mul", Some(op.to_string()), mul(lhs), unary(rhs)),
+    }
+}
+
+fn unary(e: &UnaryExp) -> Value {
+    let node = match e {
+        UnaryExp::PrimaryExp(primary) => match primary.as_ref() {
+            PrimaryExp::Exp(e) => Node::new("Paren").field("exp", exp(e)),
+            PrimaryExp::Number(value) => {
+                Node::new("Number").field("value", Value::Int(*value as u32 as i64))
+            }
+            PrimaryExp::LVal(l) => return lval(l),
+        },
+        UnaryExp::UnaryExp((op, operand)) => Node::new("Unary")
+            .field("op", string(&op.to_string()))
+            .field("operand", unary(operand)),
+        UnaryExp::FuncItem((name, args, call_span, _)) => {
+            let args = args.as_ref().map_or(&[][..], |args| &args.func_r_params);
+            Node::new("Call")
+                .field("span", span(call_span))
+                .field("name", string(name))
+                .field("args", list(args, exp))
+        }
+    };
+    Value::Node(node)
+}
+
+fn lval(l: &LVal) -> Value {
+    Value::Node(
+        Node::new("LVal")
+            .field("span", span(&l.span))
+            .field("name", string(&l.ident))
+            .field("indices", list(&l.indices, exp)),
+    )
+}
+
+// 以下从树中读出AST，出错时指向出错的节点
+
+fn error(node: &Node, message: impl Into<String>) -> Diagnostic {
+    Diagnostic::new(ErrorCode::InvalidAst, node.span, message)
+}
+
+fn check_fields(node: &Node, names: &[&str]) -> Result<(), Diagnostic> {
+    match node
+        .fields
+        .iter()
+        .find(|(name, _)| !names.contains(&name.as_str()))
+    {
+        Some((name, _)) => Err(error(
+            node,
+            format!("`{0}` has no field `{1}`", node.kind, name),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn field<'a>(node: &'a Node, name: &str) -> Result<&'a Value, Diagnostic> {
+    node.get(name).ok_or_else(|| {
+        error(
+            node,
+            format!("`{0}` needs the field `{1}`", node.kind, name),
+        )
+    })
+}
+
+fn wrong_type(node: &Node, name: &str, expected: &str) -> Diagnostic {
+    error(
+        node,
+        format!(
+            "the field `{0}` of `{1}` must be {2}",
+            name, node.kind, expected
+        ),
+    )
+}
+
+fn child<'a>(node: &'a Node, name: &str) -> Result<&'a Node, Diagnostic> {
+    match field(node, name)? {
+        Value::Node(child) => Ok(child),
+        _ => Err(wrong_type(node, name, "a node")),
+    }
+}
+
+/// A field that may be null.
+fn optional_child<'a>(node: &'a Node, name: &str) -> Result<Option<&'a Node>, Diagnostic> {
+    match field(node, name)? {
+        Value::Null => Ok(None),
+        Value::Node(child) => Ok(Some(child)),
+        _ => Err(wrong_type(node, name, "a node or null")),
+    }
+}
+
+fn nodes<'a>(node: &'a Node, name: &str) -> Result<Vec<&'a Node>, Diagnostic> {
+    match field(node, name)? {
+        Value::List(values) => values
+            .iter()
+            .map(|value| match value {
+                Value::Node(child) => Ok(child),
+                _ => Err(wrong_type(node, name, "a list of nodes")),
+            })
+            .collect(),
+        _ => Err(wrong_type(node, name, "a list of nodes")),
+    }
+}
+
+fn text<'a>(node: &'a Node, name: &str) -> Result<&'a str, Diagnostic> {
+    match field(node, name)? {
+        Value::Str(s) => Ok(s),
+        _ => Err(wrong_type(node, name, "a string")),
+    }
+}
+
+/// SysY的关键字，不能用作名字
+const KEYWORDS: [&str; 9] = [
+    "int", "void", "const", "if", "else", "while", "break", "continue", "return",
+];
+
+/// The `name` field of `node`, which must be an identifier.
+fn name(node: &Node) -> Result<&str, Diagnostic> {
+    let name = text(node, "name")?;
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c == '_' || c.is_ascii_alphabetic())
+        && chars.all(|c| c == '_' || c.is_ascii_alphanumeric());
+    if !valid {
+        return Err(error(node, format!("`{0}` is not an identifier", name)));
+    }
+    if KEYWORDS.contains(&name) {
+        return Err(error(
+            node,
+            format!("the keyword `{0}` cannot be a name", name),
+        ));
+    }
+    Ok(name)
+}
+
+fn int(node: &Node, name: &str) -> Result<i64, Diagnostic> {
+    match field(node, name)? {
+        Value::Int(value) => Ok(*value),
+        _ => Err(wrong_type(node, name, "an integer")),
+    }
+}
+
+fn read_span(node: &Node) -> Result<Span, Diagnostic> {
+    match node.get("span") {
+        None | Some(Value::Null) => return Ok(Span::default()),
+        Some(Value::List(values)) => {
+            if let [Value::Int(start), Value::Int(end)] = values.as_slice() {
+                if 0 <= *start && start <= end {
+                    return Ok(Span::new(*start as usize, *end as usize));
+                }
+            }
+        }
+        Some(_) => {}
+    }
+    Err(wrong_type(
+        node,
+        "span",
+        "a list of a start and an end offset",
+    ))
+}
+
+fn read_type(node: &Node) -> Result<ItemType, Diagnostic> {
+    match text(node, "type")? {
+        "int" => Ok(ItemType::Int),
+        "void" => Ok(ItemType::Void),
+        _ => Err(wrong_type(node, "type", "\"int\" or \"void\"")),
+    }
+}
+
+fn read_func_def(node: &Node) -> Result<FuncDef, Diagnostic> {
+    check_fields(node, &["span", "type", "name", "params", "body"])?;
+    let params = nodes(node, "params")?
+        .into_iter()
+        .map(read_param)
+        .collect::<Result<Vec<_>, Diagnostic>>()?;
+    Ok(FuncDef {
+        func_type: read_type(node)?,
+        id: name(node)?.to_string(),
+        span: read_span(node)?,
+        func_f_params: (!params.is_empty()).then_some(FuncFParams {
+            func_f_params: params,
+        }),
+        block: read_block(expect(child(node, "body")?, "Block")?)?,
+        symbol: None,
+    })
+}
+
+/// `node` if it has the kind `kind`.
+fn expect<'a>(node: &'a Node, kind: &str) -> Result<&'a Node, Diagnostic> {
+    match node.kind == kind {
+        true => Ok(node),
+        false => Err(error(
+            node,
+            format!("expected a `{0}` node, found `{1}`", kind, node.kind),
+        )),
+    }
+}
+
+fn read_param(node: &Node) -> Result<FuncFParam, Diagnostic> {
+    let node = expect(node, "Param")?;
+    check_fields(node, &["span", "type", "name", "dims"])?;
+    let dims = match field(node, "dims")? {
+        Value::Null => None,
+        _ => Some(read_dims(node)?),
+    };
+    Ok(FuncFParam {
+        b_type: read_type(node)?,
+        id: name(node)?.to_string(),
+        span: read_span(node)?,
+        dims,
+        symbol: None,
+    })
+}
+
+fn read_dims(node: &Node) -> Result<Vec<ConstExp>, Diagnostic> {
+    nodes(node, "dims")?
+        .into_iter()
+        .map(read_const_exp)
+        .collect()
+}
+
+fn read_block(node: &Node) -> Result<Block, Diagnostic> {
+    check_fields(node, &["items"])?;
+    let items = nodes(node, "items")?
+        .into_iter()
+        .map(|item| match read_decl(item)? {
+            Some(decl) => Ok(BlockItem::Decl(decl)),
+            None => Ok(BlockItem::Stmt(read_stmt(item)?)),
+        })
+        .collect::<Result<Vec<_>, Diagnostic>>()?;
+    Ok(Block { items, scope: None })
+}
+
+/// The declaration of `node`, or `None` if it is not one.
+fn read_decl(node: &Node) -> Result<Option<Decl>, Diagnostic> {
+    let decl = match node.kind.as_str() {
+        "ConstDecl" => {
+            check_fields(node, &["type", "defs"])?;
+            let const_defs = nodes(node, "defs")?
+                .into_iter()
+                .map(|def| {
+                    let def = expect(def, "ConstDef")?;
+                    check_fields(def, &["span", "name", "dims", "init"])?;
+                    Ok(ConstDef {
+                        ident: name(def)?.to_string(),
+                        span: read_span(def)?,
+                        dims: read_dims(def)?,
+                        const_init_val: read_const_init(child(def, "init")?)?,
+                        symbol: None,
+                    })
+                })
+                .collect::<Result<Vec<_>, Diagnostic>>()?;
+            Decl::ConstDecl(ConstDecl {
+                b_type: read_type(node)?,
+                const_defs,
+            })
+        }
+        "VarDecl" => {
+            check_fields(node, &["type", "defs"])?;
+            let var_defs = nodes(node, "defs")?
+                .into_iter()
+                .map(|def| {
+                    let def = expect(def, "VarDef")?;
+                    check_fields(def, &["span", "name", "dims", "init"])?;
+                    let ident = name(def)?.to_string();
+                    let dims = read_dims(def)?;
+                    let def_span = read_span(def)?;
+                    Ok(match optional_child(def, "init")? {
+                        None => VarDef::Decl((ident, dims, def_span, None)),
+                        Some(init) => VarDef::Def((ident, dims, read_init(init)?, def_span, None)),
+                    })
+                })
+                .collect::<Result<Vec<_>, Diagnostic>>()?;
+            Decl::VarDecl(VarDecl {
+                b_type: read_type(node)?,
+                var_defs,
+            })
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(decl))
+}
+
+fn read_init(node: &Node) -> Result<InitVal, Diagnostic> {
+    match node.kind.as_str() {
+        "InitList" => {
+            check_fields(node, &["items"])?;
+            let inits = nodes(node, "items")?
+                .into_iter()
+                .map(read_init)
+                .collect::<Result<Vec<_>, Diagnostic>>()?;
+            Ok(InitVal::Array(inits))
+        }
+        _ => Ok(InitVal::Exp(read_exp(node)?)),
+    }
+}
+
+fn read_const_init(node: &Node) -> Result<ConstInitVal, Diagnostic> {
+    match node.kind.as_str() {
+        "InitList" => {
+            check_fields(node, &["items"])?;
+            let inits = nodes(node, "items")?
+                .into_iter()
+                .map(read_const_init)
+                .collect::<Result<Vec<_>, Diagnostic>>()?;
+            Ok(ConstInitVal::Array(inits))
+        }
+        _ => Ok(ConstInitVal::Exp(read_const_exp(node)?)),
+    }
+}
+
+fn read_stmt(node: &Node) -> Result<Stmt, Diagnostic> {
+    Ok(match node.kind.as_str() {
+        "Assign" => {
+            check_fields(node, &["target", "value"])?;
+            let target = read_lval(expect(child(node, "target")?, "LVal")?)?;
+            Stmt::Assign((target, read_exp(child(node, "value")?)?))
+        }
+        "ExpStmt" => {
+            check_fields(node, &["value"])?;
+            Stmt::Exp(optional_child(node, "value")?.map(read_exp).transpose()?)
+        }
+        "Return" => {
+            check_fields(node, &["span", "value"])?;
+            let value = optional_child(node, "value")?.map(read_exp).transpose()?;
+            Stmt::Return((value, read_span(node)?))
+        }
+        "If" => {
+            check_fields(node, &["cond", "then", "else"])?;
+            Stmt::IF(Box::new(If {
+                cond: read_exp(child(node, "cond")?)?,
+                then_stmt: read_stmt(child(node, "then")?)?,
+                else_stmt: optional_child(node, "else")?.map(read_stmt).transpose()?,
+            }))
+        }
+        "While" => {
+            check_fields(node, &["cond", "body"])?;
+            Stmt::WHILE(Box::new(While {
+                cond: read_exp(child(node, "cond")?)?,
+                body_stmt: read_stmt(child(node, "body")?)?,
+            }))
+        }
+        "Break" => {
+            check_fields(node, &["span"])?;
+            Stmt::Break(read_span(node)?)
+        }
+        "Continue" => {
+            check_fields(node, &["span"])?;
+            Stmt::Continue(read_span(node)?)
+        }
+        "Block" => Stmt::Block(read_block(node)?),
+        _ => {
+            return Err(error(
+                node,
+                format!("expected a statement, found `{0}`", node.kind),
+            ))
+        }
+    })
+}
+
+fn read_const_exp(node: &Node) -> Result<ConstExp, Diagnostic> {
+    Ok(ConstExp {
+        exp: Box::new(read_exp(node)?),
+    })
+}
+
+fn read_exp(node: &Node) -> Result<Exp, Diagnostic> {
+    let node = expect(node, "Exp")?;
+    check_fields(node, &["span", "value"])?;
+    Ok(Exp {
+        lor_exp: Box::new(read_lor(child(node, "value")?)?),
+        span: read_span(node)?,
+    })
+}
+
+/// The operands of a binary node, with its operator if `ops` is not empty.
+fn operands<'a>(node: &'a Node, ops: &[&str]) -> Result<(&'a str, &'a Node, &'a Node), Diagnostic> {
+    let op = match ops.is_empty() {
+        true => {
+            check_fields(node, &["lhs", "rhs"])?;
+            ""
+        }
+        false => {
+            check_fields(node, &["op", "lhs", "rhs"])?;
+            let op = text(node, "op")?;
+            if !ops.contains(&op) {
+                let expected = ops
+                    .iter()
+                    .map(|op| format!("\"{0}\"", op))
+                    .collect::<Vec<_>>();
+                return Err(wrong_type(
+                    node,
+                    "op",
+                    &format!("one of {0}", expected.join(", ")),
+                ));
+            }
+            op
+        }
+    };
+    Ok((op, child(node, "lhs")?, child(node, "rhs")?))
+}
+
+fn cmp_op(op: &str) -> CmpOp {
+    match op {
+        "==" => CmpOp::Eq,
+        "!=" => CmpOp::NEq,
+        "<" => CmpOp::Less,
+        ">" => CmpOp::Grate,
+        "<=" => CmpOp::LessEq,
+        _ => CmpOp::GrateEq,
+    }
+}
+
+fn read_lor(node: &Node) -> Result<LOrExp, Diagnostic> {
+    if node.kind != "LOr" {
+        return Ok(LOrExp::LAndExp(Box::new(read_land(node)?)));
+    }
+    let (_, lhs, rhs) = operands(node, &[])?;
+    Ok(LOrExp::LOrExp((
+        Box::new(read_lor(lhs)?),
+        Box::new(read_land(rhs)?),
+    )))
+}
+
+fn read_land(node: &Node) -> Result<LAndExp, Diagnostic> {
+    if node.kind != "LAnd" {
+        return Ok(LAndExp::EqExp(Box::new(read_eq(node)?)));
+    }
+    let (_, lhs, rhs) = operands(node, &[])?;
+    Ok(LAndExp::LAndExp((
+        Box::new(read_land(lhs)?),
+        Box::new(read_eq(rhs)?),
+    )))
+}
+
+fn read_eq(node: &Node) -> Result<EqExp, Diagnostic> {
+    if node.kind != "Eq" {
+        return Ok(EqExp::RelExp(Box::new(read_rel(node)?)));
+    }
+    let (op, lhs, rhs) = operands(node, &["==", "!="])?;
+    Ok(EqExp::EqExp((
+        Box::new(read_eq(lhs)?),
+        cmp_op(op),
+        Box::new(read_rel(rhs)?),
+    )))
+}
+
+fn read_rel(node: &Node) -> Result<RelExp, Diagnostic> {
+    if node.kind != "Rel" {
+        return Ok(RelExp::AddExp(Box::new(read_add(node)?)));
+    }
+    let (op, lhs, rhs) = operands(node, &["<", ">", "<=", ">="])?;
+    Ok(RelExp::CompExp((
+        Box::new(read_rel(lhs)?),
+        cmp_op(op),
+        Box::new(read_add(rhs)?),
+    )))
+}
+
+fn read_add(node: &Node) -> Result<AddExp, Diagnostic> {
+    if node.kind != "Add" {
+        return Ok(AddExp::MulExp(Box::new(read_mul(node)?)));
+    }
+    let (op, lhs, rhs) = operands(node, &["+", "-"])?;
+    let op = match op {
+        "+" => AddOp::Add,
+        _ => AddOp::Sub,
+    };
+    Ok(AddExp::AddExp((
+        Box::new(read_add(lhs)?),
+        op,
+        Box::new(read_mul(rhs)?),
+    )))
+}
+
+fn read_mul(node: &Node) -> Result<MulExp, Diagnostic> {
+    if node.kind != "Mul" {
+        return Ok(MulExp::UnaryExp(Box::new(read_unary(node, false)?)));
+    }
+    let (op, lhs, rhs) = operands(node, &["*", "/", "%"])?;
+    let op = match op {
+        "*" => MulOp::Multiple,
+        "/" => MulOp::Divide,
+        _ => MulOp::Mod,
+    };
+    Ok(MulExp::MulExp((
+        Box::new(read_mul(lhs)?),
+        op,
+        Box::new(read_unary(rhs, false)?),
+    )))
+}
+
+/// Read a unary expression; `negated` if it is the operand of unary minus.
+fn read_unary(node: &Node, negated: bool) -> Result<UnaryExp, Diagnostic> {
+    let primary = match node.kind.as_str() {
+        "Unary" => {
+            check_fields(node, &["op", "operand"])?;
+            let op = match text(node, "op")? {
+                "+" => UnaryOp::Passive,
+                "-" => UnaryOp::Negative,
+                "!" => UnaryOp::Inversion,
+                _ => return Err(wrong_type(node, "op", "one of \"+\", \"-\", \"!\"")),
+            };
+            let operand = read_unary(child(node, "operand")?, op == UnaryOp::Negative)?;
+            return Ok(UnaryExp::UnaryExp((op, Box::new(operand))));
+        }
+        "Call" => {
+            check_fields(node, &["span", "name", "args"])?;
+            let args = nodes(node, "args")?
+                .into_iter()
+                .map(read_exp)
+                .collect::<Result<Vec<_>, Diagnostic>>()?;
+            return Ok(UnaryExp::FuncItem((
+                name(node)?.to_string(),
+                (!args.is_empty()).then_some(FuncRParams {
+                    func_r_params: args,
+                }),
+                read_span(node)?,
+                None,
+            )));
+        }
+        "Paren" => {
+            check_fields(node, &["exp"])?;
+            PrimaryExp::Exp(Box::new(read_exp(child(node, "exp")?)?))
+        }
+        "Number" => {
+            check_fields(node, &["value"])?;
+            // 与源码中的字面量一样，2147483648只能跟在负号之后，按补码回绕
+            let max = if negated { 1 << 31 } else { i32::MAX as i64 };
+            match int(node, "value")? {
+                value @ 0.. if value <= max => PrimaryExp::Number(value as i32),
+                _ if negated => {
+                    return Err(wrong_type(node, "value", "an integer from 0 to 2147483648"))
+                }
+                _ => {
+                    return Err(wrong_type(
+                        node,
+                        "value",
+                        "an integer from 0 to 2147483647, or 2147483648 after a unary `-`",
+                    ))
+                }
+            }
+        }
+        "LVal" => PrimaryExp::LVal(read_lval(node)?),
+        "LOr" | "LAnd" | "Eq" | "Rel" | "Add" | "Mul" | "Exp" => {
+            return Err(error(
+                node,
+                format!(
+                    "`{0}` cannot be an operand here without a `Paren`",
+                    node.kind
+                ),
+            ))
+        }
+        _ => {
+            return Err(error(
+                node,
+                format!("expected an expression, found `{0}`", node.kind),
+            ))
+        }
+    };
+    Ok(UnaryExp::PrimaryExp(Box::new(primary)))
+}
+
+fn read_lval(node: &Node) -> Result<LVal, Diagnostic> {
+    check_fields(node, &["span", "name", "indices"])?;
+    let indices = nodes(node, "indices")?
+        .into_iter()
+        .map(read_exp)
+        .collect::<Result<Vec<_>, Diagnostic>>()?;
+    Ok(LVal {
+        ident: name(node)?.to_string(),
+        indices,
+        span: read_span(node)?,
+        symbol: None,
+    })
+}
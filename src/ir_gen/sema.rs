@@ -0,0 +1,927 @@
+use super::calc::Calc;
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
+use crate::ir_gen::ast::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Index of a symbol in `SymbolTable::symbols`.
+pub type SymbolId = usize;
+/// Index of a scope in `SymbolTable::scopes`.
+pub type ScopeId = usize;
+
+/// 全局作用域，运行时库函数也定义在其中
+pub const GLOBAL_SCOPE: ScopeId = 0;
+
+/// 运行时库函数：名字、返回值类型、各参数是否为指针
+pub const RUNTIME_FUNCS: [(&str, ItemType, &[bool]); 8] = [
+    ("getint", ItemType::Int, &[]),
+    ("getch", ItemType::Int, &[]),
+    ("getarray", ItemType::Int, &[true]),
+    ("putint", ItemType::Void, &[false]),
+    ("putch", ItemType::Void, &[false]),
+    ("putarray", ItemType::Void, &[false, true]),
+    ("starttime", ItemType::Void, &[]),
+    ("stoptime", ItemType::Void, &[]),
+];
+
+/// The type of a function parameter.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParamType {
+    Int,
+    /// 数组形参，保存除第一维以外的各维长度
+    Ptr(Vec<i32>),
+}
+
+/// What a name refers to.
+#[derive(Debug, PartialEq, Clone)]
+pub enum SymbolKind {
+    /// int常量及其值
+    Const(i32),
+    /// 常量数组的各维长度，以及按行优先展开的各元素
+    ConstArray(Vec<i32>, Vec<i32>),
+    /// 变量及其各维长度，没有维度时是int变量
+    Var(Vec<i32>),
+    Param(ParamType),
+    /// 函数的返回值类型与各形参类型
+    Func(ItemType, Vec<ParamType>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Symbol {
+    pub name: String,
+    /// 定义处的位置，运行时库函数为默认值
+    pub span: Span,
+    pub scope: ScopeId,
+    pub kind: SymbolKind,
+}
+
+/// A scope of the scope tree: the names defined directly in it and the
+/// enclosing scope.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Scope {
+    pub parent: Option<ScopeId>,
+    pub names: HashMap<String, SymbolId>,
+}
+
+/// Every scope and symbol of a program. Nothing is removed when a scope is
+/// left, so the IDs stored in the AST stay valid after the analysis.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SymbolTable {
+    pub scopes: Vec<Scope>,
+    pub symbols: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    pub fn symbol(&self, id: SymbolId) -> &Symbol {
+        &self.symbols[id]
+    }
+
+    /// Find the symbol `name` refers to in `scope`, searching outwards.
+    pub fn lookup(&self, scope: ScopeId, name: &str) -> Option<SymbolId> {
+        let mut scope = Some(scope);
+        while let Some(id) = scope {
+            if let Some(&symbol) = self.scopes[id].names.get(name) {
+                return Some(symbol);
+            }
+            scope = self.scopes[id].parent;
+        }
+        None
+    }
+
+    fn new_scope(&mut self, parent: Option<ScopeId>) -> ScopeId {
+        self.scopes.push(Scope {
+            parent,
+            names: HashMap::new(),
+        });
+        self.scopes.len() - 1
+    }
+
+    fn define(&mut self, scope: ScopeId, name: &str, span: Span, kind: SymbolKind) -> SymbolId {
+        let id = self.symbols.len();
+        self.symbols.push(Symbol {
+            name: name.to_string(),
+            span,
+            scope,
+            kind,
+        });
+        self.scopes[scope].names.insert(name.to_string(), id);
+        id
+    }
+}
+
+impl CompUnit {
+    /// resolve every name of a CompUnit and check its types, writing the
+    /// symbol IDs into the AST; returns the symbol table or the errors found
+    pub fn analyze(&mut self) -> Result<SymbolTable, Vec<Diagnostic>> {
+        let mut info = SemaInfo {
+            table: SymbolTable::default(),
+            scope: GLOBAL_SCOPE,
+            func: None,
+            loop_depth: 0,
+            diagnostics: Vec::new(),
+        };
+        info.table.new_scope(None);
+        for (name, ret_type, ptr_flags) in RUNTIME_FUNCS {
+            let params = ptr_flags
+                .iter()
+                .map(|&is_ptr| match is_ptr {
+                    true => ParamType::Ptr(vec![]),
+                    false => ParamType::Int,
+                })
+                .collect();
+            let kind = SymbolKind::Func(ret_type, params);
+            info.table.define(GLOBAL_SCOPE, name, Span::default(), kind);
+        }
+
+        self.check(&mut info);
+        if info.diagnostics.is_empty() {
+            Ok(info.table)
+        } else {
+            Err(info.diagnostics)
+        }
+    }
+}
+
+/// 表达式的类型
+#[derive(Debug, PartialEq, Clone)]
+enum ExpType {
+    Int,
+    Void,
+    /// 数组退化得到的指针，保存所指数组的各维长度
+    Ptr(Vec<i32>),
+}
+
+impl From<&ParamType> for ExpType {
+    fn from(param: &ParamType) -> Self {
+        match param {
+            ParamType::Int => ExpType::Int,
+            ParamType::Ptr(dims) => ExpType::Ptr(dims.clone()),
+        }
+    }
+}
+
+/// 按SysY中数组参数的写法显示类型，如`int[][3]`
+impl fmt::Display for ExpType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpType::Int => write!(f, "int"),
+            ExpType::Void => write!(f, "void"),
+            ExpType::Ptr(dims) => {
+                write!(f, "int[]")?;
+                for dim in dims {
+                    write!(f, "[{0}]", dim)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// 表达式的类型及其位置，位置只对左值和函数调用记录
+struct Typed {
+    ty: ExpType,
+    span: Option<Span>,
+}
+
+impl Typed {
+    fn int() -> Self {
+        Typed {
+            ty: ExpType::Int,
+            span: None,
+        }
+    }
+}
+
+struct SemaInfo {
+    table: SymbolTable,
+    /// 当前所在的作用域
+    scope: ScopeId,
+    /// 当前函数的名字与返回值类型
+    func: Option<(String, ItemType)>,
+    /// 当前所在循环的层数，供break/continue检查
+    loop_depth: u32,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl SemaInfo {
+    fn error(&mut self, code: ErrorCode, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic::new(code, span, message));
+    }
+
+    fn enter_scope(&mut self) {
+        self.scope = self.table.new_scope(Some(self.scope));
+    }
+
+    fn exit_scope(&mut self) {
+        self.scope = self.table.scopes[self.scope].parent.unwrap();
+    }
+
+    fn kind(&self, id: SymbolId) -> &SymbolKind {
+        &self.table.symbol(id).kind
+    }
+
+    /// 在当前作用域中定义标识符，同一作用域内重复定义时报错
+    fn define(&mut self, name: &str, span: Span, kind: SymbolKind) -> Option<SymbolId> {
+        if self.table.scopes[self.scope].names.contains_key(name) {
+            self.error(
+                ErrorCode::Redefinition,
+                span,
+                format!("redefinition of `{0}`", name),
+            );
+            return None;
+        }
+        Some(self.table.define(self.scope, name, span, kind))
+    }
+
+    /// 用作值的表达式必须是int
+    fn expect_int(&mut self, typed: &Typed) {
+        let span = typed.span.unwrap_or_default();
+        match &typed.ty {
+            ExpType::Int => {}
+            ExpType::Void => self.error(
+                ErrorCode::VoidValue,
+                span,
+                "a `void` call cannot be used as a value".to_string(),
+            ),
+            ty => self.error(
+                ErrorCode::TypeMismatch,
+                span,
+                format!("expected `int`, found `{0}`", ty),
+            ),
+        }
+    }
+
+    /// 常量表达式的值，表达式有错误或不是常量时为None
+    fn const_value(&mut self, exp: &mut Exp) -> Option<i32> {
+        let errors = self.diagnostics.len();
+        let typed = exp.type_check(self);
+        self.expect_int(&typed);
+        if self.diagnostics.len() > errors {
+            return None;
+        }
+        match exp.calculate(&self.table) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.diagnostics.push(err.into());
+                None
+            }
+        }
+    }
+
+    /// 计算数组各维的长度，长度必须是正数
+    fn array_dims(&mut self, dims: &mut [ConstExp], span: Span) -> Vec<i32> {
+        let mut lens = Vec::new();
+        for dim in dims {
+            let len = match self.const_value(&mut dim.exp) {
+                Some(len) if len <= 0 => {
+                    self.error(
+                        ErrorCode::InvalidArraySize,
+                        span,
+                        format!("array size must be positive, found {0}", len),
+                    );
+                    1
+                }
+                Some(len) => len,
+                None => 1,
+            };
+            lens.push(len);
+        }
+        lens
+    }
+
+    fn check_in_loop(&mut self, span: Span, keyword: &str) {
+        if self.loop_depth == 0 {
+            self.error(
+                ErrorCode::OutsideLoop,
+                span,
+                format!("`{0}` outside of a loop", keyword),
+            );
+        }
+    }
+
+    /// 变量、常量与形参都不能是void
+    fn check_not_void(&mut self, b_type: &ItemType, ident: &str, span: Span) {
+        if *b_type == ItemType::Void {
+            self.error(
+                ErrorCode::TypeMismatch,
+                span,
+                format!("`{0}` cannot be declared `void`", ident),
+            );
+        }
+    }
+}
+
+/// Resolve the names in a statement or definition and check its types.
+trait Check {
+    fn check(&mut self, info: &mut SemaInfo);
+}
+
+/// Resolve the names in an expression and compute its type.
+trait TypeCheck {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed;
+}
+
+impl Check for CompUnit {
+    fn check(&mut self, info: &mut SemaInfo) {
+        if let Some(sub_comp_unit) = &mut *self.comp_unit {
+            sub_comp_unit.check(info);
+        }
+        match &mut self.global_item {
+            GlobalItem::Func(func_def) => func_def.check(info),
+            GlobalItem::Decl(decl) => decl.check(info),
+        }
+    }
+}
+
+impl Check for FuncDef {
+    fn check(&mut self, info: &mut SemaInfo) {
+        // 形参的各维长度在函数外的作用域中计算
+        let mut params = Vec::new();
+        for param in self
+            .func_f_params
+            .iter_mut()
+            .flat_map(|p| &mut p.func_f_params)
+        {
+            info.check_not_void(&param.b_type, &param.id, param.span);
+            params.push(match &mut param.dims {
+                None => ParamType::Int,
+                Some(dims) => ParamType::Ptr(info.array_dims(dims, param.span)),
+            });
+        }
+        // 先定义函数，以便函数递归调用自身
+        let kind = SymbolKind::Func(self.func_type.clone(), params.clone());
+        self.symbol = info.define(&self.id, self.span, kind);
+
+        // 形参与函数体最外层的定义在同一个作用域中
+        info.enter_scope();
+        for (param, ty) in self
+            .func_f_params
+            .iter_mut()
+            .flat_map(|p| &mut p.func_f_params)
+            .zip(params)
+        {
+            param.symbol = info.define(&param.id, param.span, SymbolKind::Param(ty));
+        }
+        info.func = Some((self.id.clone(), self.func_type.clone()));
+        self.block.check(info);
+        info.func = None;
+        info.exit_scope();
+    }
+}
+
+impl Check for Block {
+    fn check(&mut self, info: &mut SemaInfo) {
+        self.scope = Some(info.scope);
+        for item in &mut self.items {
+            match item {
+                BlockItem::Decl(decl) => decl.check(info),
+                BlockItem::Stmt(stmt) => stmt.check(info),
+            }
+        }
+    }
+}
+
+impl Check for Decl {
+    fn check(&mut self, info: &mut SemaInfo) {
+        match self {
+            Decl::ConstDecl(const_decl) => {
+                for const_def in &mut const_decl.const_defs {
+                    info.check_not_void(&const_decl.b_type, &const_def.ident, const_def.span);
+                    const_def.check(info);
+                }
+            }
+            Decl::VarDecl(var_decl) => {
+                for var_def in &mut var_decl.var_defs {
+                    let (ident, span) = match var_def {
+                        VarDef::Decl((ident, _, span, _)) => (ident, *span),
+                        VarDef::Def((ident, _, _, span, _)) => (ident, *span),
+                    };
+                    info.check_not_void(&var_decl.b_type, ident, span);
+                    var_def.check(info);
+                }
+            }
+        }
+    }
+}
+
+/// 检查初始化列表中的每个表达式，全局变量的初值必须是常量
+fn check_init_list(info: &mut SemaInfo, init_val: &mut InitVal, global: bool) {
+    match init_val {
+        InitVal::Exp(exp) if global => {
+            info.const_value(exp);
+        }
+        InitVal::Exp(exp) => {
+            let typed = exp.type_check(info);
+            info.expect_int(&typed);
+        }
+        InitVal::Array(array) => {
+            for init in array {
+                check_init_list(info, init, global);
+            }
+        }
+    }
+}
+
+/// An element of the initializer list of a variable or a constant.
+trait InitElement: Sized {
+    /// 嵌套的列表，单个表达式时为None
+    fn elements(&self) -> Option<&[Self]>;
+    /// 元素中第一个表达式的位置
+    fn span(&self) -> Option<Span>;
+}
+
+impl InitElement for InitVal {
+    fn elements(&self) -> Option<&[Self]> {
+        match self {
+            InitVal::Exp(_) => None,
+            InitVal::Array(array) => Some(array),
+        }
+    }
+
+    fn span(&self) -> Option<Span> {
+        match self {
+            InitVal::Exp(exp) => Some(exp.span),
+            InitVal::Array(array) => array.iter().find_map(InitElement::span),
+        }
+    }
+}
+
+impl InitElement for ConstInitVal {
+    fn elements(&self) -> Option<&[Self]> {
+        match self {
+            ConstInitVal::Exp(_) => None,
+            ConstInitVal::Array(array) => Some(array),
+        }
+    }
+
+    fn span(&self) -> Option<Span> {
+        match self {
+            ConstInitVal::Exp(exp) => Some(exp.exp.span),
+            ConstInitVal::Array(array) => array.iter().find_map(InitElement::span),
+        }
+    }
+}
+
+/// Flatten the initializer list `list` of an array with dimensions `dims`
+/// into the elements of the array in row-major order, eliding braces like C
+/// does. Elements the list leaves out are `None`. Excess elements and braces
+/// around a scalar are reported at the element, or at `span` when it has no
+/// expression to point to.
+fn flatten_init<'a, T: InitElement>(
+    info: &mut SemaInfo,
+    list: &'a [T],
+    dims: &[i32],
+    span: Span,
+) -> Vec<Option<&'a T>> {
+    let mut elements = Vec::new();
+    flatten_list(info, list, dims, span, &mut elements);
+    elements
+}
+
+/// 展开花括号括起的整个列表，其中不能剩下元素
+fn flatten_list<'a, T: InitElement>(
+    info: &mut SemaInfo,
+    list: &'a [T],
+    dims: &[i32],
+    span: Span,
+    elements: &mut Vec<Option<&'a T>>,
+) {
+    let mut idx = 0;
+    take_init_elements(info, list, dims, &mut idx, span, elements);
+    if let Some(excess) = list.get(idx) {
+        info.error(
+            ErrorCode::InvalidInitializer,
+            excess.span().unwrap_or(span),
+            "excess elements in array initializer".to_string(),
+        );
+    }
+}
+
+/// 从`list[*idx]`起取出一个形状为`dims`的子数组的元素，花括号括起的元素各自展开
+fn take_init_elements<'a, T: InitElement>(
+    info: &mut SemaInfo,
+    list: &'a [T],
+    dims: &[i32],
+    idx: &mut usize,
+    span: Span,
+    elements: &mut Vec<Option<&'a T>>,
+) {
+    let end = elements.len() + dims.iter().product::<i32>() as usize;
+    if dims.len() == 1 {
+        while elements.len() < end && *idx < list.len() {
+            let element = &list[*idx];
+            if element.elements().is_some() {
+                info.error(
+                    ErrorCode::InvalidInitializer,
+                    element.span().unwrap_or(span),
+                    "too many braces around scalar initializer".to_string(),
+                );
+                elements.push(None);
+            } else {
+                elements.push(Some(element));
+            }
+            *idx += 1;
+        }
+    } else {
+        for _ in 0..dims[0] {
+            match list.get(*idx).map(InitElement::elements) {
+                None => break,
+                Some(None) => take_init_elements(info, list, &dims[1..], idx, span, elements),
+                Some(Some(inner)) => {
+                    flatten_list(info, inner, &dims[1..], span, elements);
+                    *idx += 1;
+                }
+            }
+        }
+    }
+    elements.resize(end, None);
+}
+
+fn check_const_init_list(info: &mut SemaInfo, init_val: &mut ConstInitVal) {
+    match init_val {
+        ConstInitVal::Exp(exp) => {
+            let typed = exp.exp.type_check(info);
+            info.expect_int(&typed);
+        }
+        ConstInitVal::Array(array) => {
+            for init in array {
+                check_const_init_list(info, init);
+            }
+        }
+    }
+}
+
+impl Check for ConstDef {
+    fn check(&mut self, info: &mut SemaInfo) {
+        let dims = info.array_dims(&mut self.dims, self.span);
+        // 初始化表达式中的名字在定义之前解析
+        let kind = if !dims.is_empty() {
+            let errors = info.diagnostics.len();
+            check_const_init_list(info, &mut self.const_init_val);
+            let len = dims.iter().product::<i32>() as usize;
+            // 用标量初始化数组留给生成IR时报错
+            let elements = match &self.const_init_val {
+                ConstInitVal::Array(list) => flatten_init(info, list, &dims, self.span),
+                ConstInitVal::Exp(_) => vec![None; len],
+            };
+            let mut values = vec![0; len];
+            if info.diagnostics.len() == errors {
+                for (value, element) in values.iter_mut().zip(elements) {
+                    if let Some(ConstInitVal::Exp(exp)) = element {
+                        match exp.calculate(&info.table) {
+                            Ok(result) => *value = result,
+                            Err(err) => info.diagnostics.push(err.into()),
+                        }
+                    }
+                }
+            }
+            SymbolKind::ConstArray(dims, values)
+        } else {
+            match &mut self.const_init_val {
+                ConstInitVal::Exp(exp) => {
+                    SymbolKind::Const(info.const_value(&mut exp.exp).unwrap_or(0))
+                }
+                ConstInitVal::Array(_) => {
+                    info.error(
+                        ErrorCode::InvalidInitializer,
+                        self.span,
+                        format!("cannot initialize `{0}` with a list", self.ident),
+                    );
+                    SymbolKind::Const(0)
+                }
+            }
+        };
+        self.symbol = info.define(&self.ident, self.span, kind);
+    }
+}
+
+impl Check for VarDef {
+    fn check(&mut self, info: &mut SemaInfo) {
+        let (ident, dims, init_val, span, symbol) = match self {
+            VarDef::Def((ident, dims, init_val, span, symbol)) => {
+                (ident, dims, Some(init_val), *span, symbol)
+            }
+            VarDef::Decl((ident, dims, span, symbol)) => (ident, dims, None, *span, symbol),
+        };
+        let dims = info.array_dims(dims, span);
+        match init_val {
+            None => {}
+            Some(InitVal::Array(_)) if dims.is_empty() => info.error(
+                ErrorCode::InvalidInitializer,
+                span,
+                format!("cannot initialize `{0}` with a list", ident),
+            ),
+            Some(init_val) => {
+                check_init_list(info, init_val, info.func.is_none());
+                if let InitVal::Array(list) = init_val {
+                    flatten_init(info, list, &dims, span);
+                }
+            }
+        }
+        *symbol = info.define(ident, span, SymbolKind::Var(dims));
+    }
+}
+
+impl Check for Stmt {
+    fn check(&mut self, info: &mut SemaInfo) {
+        match self {
+            Stmt::Return((exp, span)) => {
+                let (func_name, func_type) = info.func.clone().unwrap();
+                match (exp, func_type) {
+                    (Some(exp), ItemType::Int) => {
+                        let typed = exp.type_check(info);
+                        info.expect_int(&typed);
+                    }
+                    (None, ItemType::Int) => info.error(
+                        ErrorCode::TypeMismatch,
+                        *span,
+                        format!("function `{0}` must return an `int` value", func_name),
+                    ),
+                    (Some(exp), ItemType::Void) => {
+                        exp.type_check(info);
+                        info.error(
+                            ErrorCode::TypeMismatch,
+                            *span,
+                            format!("`void` function `{0}` cannot return a value", func_name),
+                        );
+                    }
+                    (None, ItemType::Void) => {}
+                }
+            }
+            Stmt::Assign((lval, exp)) => {
+                let target = lval.type_check(info);
+                if let Some(id) = lval.symbol {
+                    match info.kind(id) {
+                        SymbolKind::Const(_) | SymbolKind::ConstArray(..) => info.error(
+                            ErrorCode::AssignToConst,
+                            lval.span,
+                            format!("cannot assign to constant `{0}`", lval.ident),
+                        ),
+                        _ if target.ty != ExpType::Int => info.error(
+                            ErrorCode::TypeMismatch,
+                            lval.span,
+                            format!("cannot assign to array `{0}`", lval.ident),
+                        ),
+                        _ => {}
+                    }
+                }
+                let value = exp.type_check(info);
+                info.expect_int(&value);
+            }
+            Stmt::Exp(exp) => {
+                // 单独的表达式语句可以是void调用
+                if let Some(exp) = exp {
+                    exp.type_check(info);
+                }
+            }
+            Stmt::Block(block) => {
+                info.enter_scope();
+                block.check(info);
+                info.exit_scope();
+            }
+            Stmt::IF(if_stmt) => {
+                let cond = if_stmt.cond.type_check(info);
+                info.expect_int(&cond);
+                if_stmt.then_stmt.check(info);
+                if let Some(else_stmt) = &mut if_stmt.else_stmt {
+                    else_stmt.check(info);
+                }
+            }
+            Stmt::WHILE(while_stmt) => {
+                let cond = while_stmt.cond.type_check(info);
+                info.expect_int(&cond);
+                info.loop_depth += 1;
+                while_stmt.body_stmt.check(info);
+                info.loop_depth -= 1;
+            }
+            Stmt::Break(span) => info.check_in_loop(*span, "break"),
+            Stmt::Continue(span) => info.check_in_loop(*span, "continue"),
+        }
+    }
+}
+
+impl TypeCheck for Exp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        self.lor_exp.type_check(info)
+    }
+}
+
+impl TypeCheck for LVal {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        for index in &mut self.indices {
+            let typed = index.type_check(info);
+            info.expect_int(&typed);
+        }
+        let id = match info.table.lookup(info.scope, &self.ident) {
+            Some(id) => id,
+            None => {
+                info.error(
+                    ErrorCode::UndeclaredIdentifier,
+                    self.span,
+                    format!("use of undeclared identifier `{0}`", self.ident),
+                );
+                return Typed::int();
+            }
+        };
+        self.symbol = Some(id);
+        // 数组形参的第一维长度未知
+        let (dims, is_ptr) = match info.kind(id) {
+            SymbolKind::Const(_) | SymbolKind::Param(ParamType::Int) => (vec![], false),
+            SymbolKind::ConstArray(dims, _) | SymbolKind::Var(dims) => (dims.clone(), false),
+            SymbolKind::Param(ParamType::Ptr(dims)) => (dims.clone(), true),
+            SymbolKind::Func(..) => {
+                info.error(
+                    ErrorCode::NotAVariable,
+                    self.span,
+                    format!("`{0}` is a function, not a variable", self.ident),
+                );
+                return Typed::int();
+            }
+        };
+        let rank = dims.len() + is_ptr as usize;
+        let count = self.indices.len();
+        let ty = if count > rank {
+            let message = match rank {
+                0 => format!("`{0}` is not an array", self.ident),
+                _ => format!(
+                    "`{0}` has {1} dimensions but is subscripted {2} times",
+                    self.ident, rank, count
+                ),
+            };
+            info.error(ErrorCode::InvalidSubscript, self.span, message);
+            ExpType::Int
+        } else if count == rank {
+            ExpType::Int
+        } else {
+            // 没有取到元素时，数组退化为指向其下一维的指针
+            ExpType::Ptr(dims[count + 1 - is_ptr as usize..].to_vec())
+        };
+        Typed {
+            ty,
+            span: Some(self.span),
+        }
+    }
+}
+
+impl TypeCheck for PrimaryExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            PrimaryExp::Exp(exp) => exp.type_check(info),
+            PrimaryExp::Number(_) => Typed::int(),
+            PrimaryExp::LVal(lval) => lval.type_check(info),
+        }
+    }
+}
+
+impl TypeCheck for UnaryExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            UnaryExp::PrimaryExp(pri_exp) => pri_exp.type_check(info),
+            UnaryExp::UnaryExp((_, sub_exp)) => {
+                let typed = sub_exp.type_check(info);
+                info.expect_int(&typed);
+                Typed {
+                    ty: ExpType::Int,
+                    span: typed.span,
+                }
+            }
+            UnaryExp::FuncItem((id, func_r_params, span, symbol)) => {
+                let callee = info.table.lookup(info.scope, id);
+                let args: Vec<Typed> = func_r_params
+                    .iter_mut()
+                    .flat_map(|params| &mut params.func_r_params)
+                    .map(|exp| exp.type_check(info))
+                    .collect();
+                let callee = match callee {
+                    Some(callee) => callee,
+                    None => {
+                        info.error(
+                            ErrorCode::UndeclaredIdentifier,
+                            *span,
+                            format!("use of undeclared function `{0}`", id),
+                        );
+                        return Typed::int();
+                    }
+                };
+                *symbol = Some(callee);
+                let (func_type, params) = match info.kind(callee) {
+                    SymbolKind::Func(func_type, params) => (func_type.clone(), params.clone()),
+                    _ => {
+                        info.error(
+                            ErrorCode::NotAFunction,
+                            *span,
+                            format!("`{0}` is not a function", id),
+                        );
+                        return Typed::int();
+                    }
+                };
+                if params.len() != args.len() {
+                    let message = format!(
+                        "function `{0}` expects {1} arguments, found {2}",
+                        id,
+                        params.len(),
+                        args.len()
+                    );
+                    info.error(ErrorCode::ArgumentCount, *span, message);
+                } else {
+                    for (i, (param, arg)) in params.iter().zip(&args).enumerate() {
+                        let expected = ExpType::from(param);
+                        if arg.ty != expected {
+                            let message = format!(
+                                "argument {0} of `{1}` expects `{2}`, found `{3}`",
+                                i + 1,
+                                id,
+                                expected,
+                                arg.ty
+                            );
+                            info.error(ErrorCode::TypeMismatch, arg.span.unwrap_or(*span), message);
+                        }
+                    }
+                }
+                let ty = match func_type {
+                    ItemType::Int => ExpType::Int,
+                    ItemType::Void => ExpType::Void,
+                };
+                Typed {
+                    ty,
+                    span: Some(*span),
+                }
+            }
+        }
+    }
+}
+
+/// 二元运算的两侧都必须是int
+fn binary_type(info: &mut SemaInfo, lhs: &mut dyn TypeCheck, rhs: &mut dyn TypeCheck) -> Typed {
+    let lhs = lhs.type_check(info);
+    info.expect_int(&lhs);
+    let rhs = rhs.type_check(info);
+    info.expect_int(&rhs);
+    Typed::int()
+}
+
+impl TypeCheck for MulExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            MulExp::UnaryExp(unary_exp) => unary_exp.type_check(info),
+            MulExp::MulExp((mul_exp, _, unary_exp)) => {
+                binary_type(info, mul_exp.as_mut(), unary_exp.as_mut())
+            }
+        }
+    }
+}
+
+impl TypeCheck for AddExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            AddExp::MulExp(mul_exp) => mul_exp.type_check(info),
+            AddExp::AddExp((add_exp, _, mul_exp)) => {
+                binary_type(info, add_exp.as_mut(), mul_exp.as_mut())
+            }
+        }
+    }
+}
+
+impl TypeCheck for RelExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            RelExp::AddExp(add_exp) => add_exp.type_check(info),
+            RelExp::CompExp((rel_exp, _, add_exp)) => {
+                binary_type(info, rel_exp.as_mut(), add_exp.as_mut())
+            }
+        }
+    }
+}
+
+impl TypeCheck for EqExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            EqExp::RelExp(rel_exp) => rel_exp.type_check(info),
+            EqExp::EqExp((eq_exp, _, rel_exp)) => {
+                binary_type(info, eq_exp.as_mut(), rel_exp.as_mut())
+            }
+        }
+    }
+}
+
+impl TypeCheck for LAndExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            LAndExp::EqExp(eq_exp) => eq_exp.type_check(info),
+            LAndExp::LAndExp((land_exp, eq_exp)) => {
+                binary_type(info, land_exp.as_mut(), eq_exp.as_mut())
+            }
+        }
+    }
+}
+
+impl TypeCheck for LOrExp {
+    fn type_check(&mut self, info: &mut SemaInfo) -> Typed {
+        match self {
+            LOrExp::LAndExp(land_exp) => land_exp.type_check(info),
+            LOrExp::LOrExp((lor_exp, land_exp)) => {
+                binary_type(info, lor_exp.as_mut(), land_exp.as_mut())
+            }
+        }
+    }
+}
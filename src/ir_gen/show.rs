@@ -1,385 +1,409 @@
 use super::calc::Calc;
+use super::sema::{ParamType, SymbolId, SymbolKind, SymbolTable, GLOBAL_SCOPE, RUNTIME_FUNCS};
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
 use crate::ir_gen::ast::*;
-use std::collections::HashMap;
+use koopa::ir::builder::LocalBuilder;
+use koopa::ir::builder_traits::*;
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Program, Type, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+/// 由各维长度构造数组类型
+fn array_type(dims: &[i32]) -> Type {
+    let mut ty = Type::get_i32();
+    for dim in dims.iter().rev() {
+        ty = Type::get_array(ty, *dim as usize);
+    }
+    ty
+}
 
-fn global_zero_array(dims: &Vec<i32>) -> String {
-    let mut s = "".to_string();
-    s += "{";
-    for i in 0..dims[0] {
-        if i > 0 {
-            s += ", ";
-        }
+fn param_type(param: &ParamType) -> Type {
+    match param {
+        ParamType::Int => Type::get_i32(),
+        ParamType::Ptr(dims) => Type::get_pointer(array_type(dims)),
+    }
+}
+
+fn ret_type(func_type: &ItemType) -> Type {
+    match func_type {
+        ItemType::Int => Type::get_i32(),
+        ItemType::Void => Type::get_unit(),
+    }
+}
+
+fn global_zero_array(info: &mut CompilerInfo, dims: &[i32]) -> Value {
+    let mut elems = Vec::new();
+    for _ in 0..dims[0] {
         if dims.len() == 1 {
-            s += "0";
+            elems.push(info.program.new_value().integer(0));
         } else {
-            s += &global_zero_array(&dims[1..].to_vec());
+            elems.push(global_zero_array(info, &dims[1..]));
         }
     }
-    s += "}";
-    s
+    info.program.new_value().aggregate(elems)
 }
-fn local_zero_array(info: &mut CompilerInfo, dims: &Vec<i32>, base_ptr: String) -> String {
-    let mut s = "".to_string();
+
+fn local_zero_array(info: &mut CompilerInfo, dims: &[i32], base_ptr: Value) {
     for i in 0..dims[0] {
+        let index = info.integer(i);
+        let elem_ptr = info.get_elem_ptr(base_ptr, index);
         if dims.len() == 1 {
-            s += &format!(
-                "\t%{0} = getelemptr {1}, {2}\n\tstore 0, %{0}\n",
-                info.temp_id, base_ptr, i
-            );
-            info.temp_id += 1;
+            let zero = info.integer(0);
+            info.store(zero, elem_ptr);
         } else {
-            s += &format!("\t%{0} = getelemptr {1}, {2}\n", info.temp_id, base_ptr, i);
-            let sub_base = format!("%{0}", info.temp_id);
-            info.temp_id += 1;
-            s += &local_zero_array(info, &dims[1..].to_vec(), sub_base);
+            local_zero_array(info, &dims[1..], elem_ptr);
         }
     }
-    s
 }
 
 impl CompUnit {
-    /// generate koopa ir from a CompUnit in String form
-    pub fn generate_koopa(&self) -> String {
+    /// generate koopa ir from a CompUnit that passed `analyze`, or the errors
+    /// found in its initializers
+    pub fn generate_koopa(&self, symbols: &SymbolTable) -> Result<Program, Vec<Diagnostic>> {
         let mut compiler_info = CompilerInfo {
-            temp_id: 0,
-            vars_table: HashMap::new(),
-            field_depth: 0,
-            flag_id: 0,
-            var_id: 0,
-            enter_flag: -1,
-            end_flag: -1,
+            program: Program::new(),
+            symbols,
+            vars: HashMap::new(),
+            func: None,
+            cur_bb: None,
+            enter_bb: None,
+            end_bb: None,
             func_param: false,
-            func_name: "".to_string(),
+            decl_span: Span::default(),
+            diagnostics: Vec::new(),
         };
 
-        let var_getint = Variable::Func(("@getint".to_string(), ItemType::Int, vec![]));
-        compiler_info
-            .vars_table
-            .insert("getint".to_string(), (var_getint, 0));
-
-        let var_getch = Variable::Func(("@getch".to_string(), ItemType::Int, vec![]));
-        compiler_info
-            .vars_table
-            .insert("getch".to_string(), (var_getch, 0));
-
-        let var_getarray = Variable::Func(("@getarray".to_string(), ItemType::Int, vec![true]));
-        compiler_info
-            .vars_table
-            .insert("getarray".to_string(), (var_getarray, 0));
-
-        let var_putint = Variable::Func(("@putint".to_string(), ItemType::Void, vec![false]));
-        compiler_info
-            .vars_table
-            .insert("putint".to_string(), (var_putint, 0));
-
-        let var_putch = Variable::Func(("@putch".to_string(), ItemType::Void, vec![false]));
-        compiler_info
-            .vars_table
-            .insert("putch".to_string(), (var_putch, 0));
-
-        let var_putarray =
-            Variable::Func(("@putarray".to_string(), ItemType::Void, vec![false, true]));
-        compiler_info
-            .vars_table
-            .insert("putarray".to_string(), (var_putarray, 0));
-
-        let var_starttime = Variable::Func(("@starttime".to_string(), ItemType::Void, vec![]));
-        compiler_info
-            .vars_table
-            .insert("starttime".to_string(), (var_starttime, 0));
-
-        let var_stoptime = Variable::Func(("@stoptime".to_string(), ItemType::Void, vec![]));
-        compiler_info
-            .vars_table
-            .insert("stoptime".to_string(), (var_stoptime, 0));
-
-        let mut s = "decl @getint(): i32\ndecl @getch(): i32\ndecl @getarray(*i32): i32\ndecl @putint(i32)\ndecl @putch(i32)\ndecl @putarray(i32, *i32)\ndecl @starttime()\ndecl @stoptime()\n\n".to_string();
-        s += &self.show(&mut compiler_info).0;
-        s
+        for (name, _, _) in RUNTIME_FUNCS {
+            let id = symbols.lookup(GLOBAL_SCOPE, name).unwrap();
+            let (func_type, params) = compiler_info.signature(id);
+            let params = params.iter().map(param_type).collect();
+            let func = compiler_info.program.new_func(FunctionData::new_decl(
+                format!("@{0}", name),
+                params,
+                ret_type(&func_type),
+            ));
+            compiler_info.vars.insert(id, Variable::Func(func));
+        }
+
+        self.show(&mut compiler_info);
+        if compiler_info.diagnostics.is_empty() {
+            Ok(compiler_info.program)
+        } else {
+            Err(compiler_info.diagnostics)
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct CompilerInfo {
-    pub temp_id: i32,
-    /// var_ident, (variable, depth)
-    pub vars_table: HashMap<String, (Variable, i32)>,
-    pub field_depth: i32,
-    pub flag_id: i32,
-    pub var_id: i32,
-    pub enter_flag: i32,
-    pub end_flag: i32,
+pub struct CompilerInfo<'a> {
+    /// 正在生成的程序
+    pub program: Program,
+    /// 语义分析得到的符号表
+    pub symbols: &'a SymbolTable,
+    /// 各符号在koopaIR中对应的对象
+    pub vars: HashMap<SymbolId, Variable>,
+    /// 当前正在生成的函数与基本块
+    pub func: Option<Function>,
+    pub cur_bb: Option<BasicBlock>,
+    /// 当前所在循环的入口与出口，供continue/break跳转
+    pub enter_bb: Option<BasicBlock>,
+    pub end_bb: Option<BasicBlock>,
     pub func_param: bool,
-    pub func_name: String,
+    /// 当前正在初始化的变量的位置，用于初始化列表报错
+    pub decl_span: Span,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompilerInfo<'_> {
+    fn error(&mut self, code: ErrorCode, span: Span, message: String) {
+        self.diagnostics.push(Diagnostic::new(code, span, message));
+    }
+
+    fn var(&self, id: Option<SymbolId>) -> Variable {
+        self.vars[&id.unwrap()].clone()
+    }
+
+    /// 函数的返回值类型与各形参类型
+    fn signature(&self, id: SymbolId) -> (ItemType, Vec<ParamType>) {
+        match &self.symbols.symbol(id).kind {
+            SymbolKind::Func(func_type, params) => (func_type.clone(), params.clone()),
+            _ => unreachable!(),
+        }
+    }
+
+    /// 常量表达式的值，语义分析时已确认可以求值
+    fn const_value(&self, exp: &Exp) -> i32 {
+        exp.calculate(self.symbols).unwrap()
+    }
+
+    /// 数组的各维长度，int变量没有维度
+    fn dims(&self, id: SymbolId) -> Vec<i32> {
+        match &self.symbols.symbol(id).kind {
+            SymbolKind::Var(dims) | SymbolKind::ConstArray(dims, _) => dims.clone(),
+            _ => vec![],
+        }
+    }
+
+    fn func_data(&mut self) -> &mut FunctionData {
+        self.program.func_mut(self.func.unwrap())
+    }
+
+    fn new_value(&mut self) -> LocalBuilder<'_> {
+        self.func_data().dfg_mut().new_value()
+    }
+
+    /// 新建一个基本块，名字只用于提高可读性，不要求唯一
+    fn new_bb(&mut self, name: &str) -> BasicBlock {
+        self.func_data()
+            .dfg_mut()
+            .new_bb()
+            .basic_block(Some(format!("%{0}", name)))
+    }
+
+    /// 将基本块加入函数，之后的指令都生成在该基本块中
+    fn enter_bb(&mut self, bb: BasicBlock) {
+        self.func_data()
+            .layout_mut()
+            .bbs_mut()
+            .push_key_back(bb)
+            .unwrap();
+        self.cur_bb = Some(bb);
+    }
+
+    /// 将指令加到当前基本块末尾
+    fn push_inst(&mut self, inst: Value) -> Value {
+        let bb = self.cur_bb.unwrap();
+        self.func_data()
+            .layout_mut()
+            .bb_mut(bb)
+            .insts_mut()
+            .push_key_back(inst)
+            .unwrap();
+        inst
+    }
+
+    fn integer(&mut self, value: i32) -> Value {
+        self.new_value().integer(value)
+    }
+
+    /// 表达式结果对应的值，立即数在此时才生成
+    fn value(&mut self, res: Res) -> Value {
+        match res {
+            Res::Imm(imm) => self.integer(imm),
+            Res::Temp(value) => value,
+            _ => unreachable!(),
+        }
+    }
+
+    fn alloc(&mut self, ty: Type, name: &str) -> Value {
+        let alloc = self.new_value().alloc(ty);
+        self.func_data()
+            .dfg_mut()
+            .set_value_name(alloc, Some(format!("@{0}", name)));
+        self.push_inst(alloc)
+    }
+
+    fn load(&mut self, src: Value) -> Value {
+        let load = self.new_value().load(src);
+        self.push_inst(load)
+    }
+
+    fn store(&mut self, value: Value, dest: Value) {
+        let store = self.new_value().store(value, dest);
+        self.push_inst(store);
+    }
+
+    fn get_elem_ptr(&mut self, src: Value, index: Value) -> Value {
+        let ptr = self.new_value().get_elem_ptr(src, index);
+        self.push_inst(ptr)
+    }
+
+    fn get_ptr(&mut self, src: Value, index: Value) -> Value {
+        let ptr = self.new_value().get_ptr(src, index);
+        self.push_inst(ptr)
+    }
+
+    fn binary(&mut self, op: BinaryOp, lhs: Value, rhs: Value) -> Value {
+        let exp = self.new_value().binary(op, lhs, rhs);
+        self.push_inst(exp)
+    }
+
+    fn call(&mut self, callee: Function, args: Vec<Value>) -> Value {
+        let call = self.new_value().call(callee, args);
+        self.push_inst(call)
+    }
+
+    fn branch(&mut self, cond: Value, true_bb: BasicBlock, false_bb: BasicBlock) {
+        let branch = self.new_value().branch(cond, true_bb, false_bb);
+        self.push_inst(branch);
+    }
+
+    fn jump(&mut self, target: BasicBlock) {
+        let jump = self.new_value().jump(target);
+        self.push_inst(jump);
+    }
+
+    fn ret(&mut self, value: Option<Value>) {
+        let ret = self.new_value().ret(value);
+        self.push_inst(ret);
+    }
+
+    /// 结束当前函数：为最后一个基本块补上ret，并删去不可达的基本块
+    fn finish_func(&mut self, ret_type: &ItemType) {
+        let value = match ret_type {
+            ItemType::Int => Some(self.integer(0)),
+            ItemType::Void => None,
+        };
+        self.ret(value);
+
+        let func_data = self.func_data();
+        let entry = func_data.layout().entry_bb().unwrap();
+        let mut reachable = HashSet::from([entry]);
+        let mut worklist = vec![entry];
+        while let Some(bb) = worklist.pop() {
+            let node = func_data.layout().bbs().node(&bb).unwrap();
+            let last = *node.insts().back_key().unwrap();
+            let targets = match func_data.dfg().value(last).kind() {
+                ValueKind::Branch(branch) => vec![branch.true_bb(), branch.false_bb()],
+                ValueKind::Jump(jump) => vec![jump.target()],
+                _ => vec![],
+            };
+            for target in targets {
+                if reachable.insert(target) {
+                    worklist.push(target);
+                }
+            }
+        }
+
+        let dead_bbs: Vec<BasicBlock> = func_data
+            .layout()
+            .bbs()
+            .keys()
+            .copied()
+            .filter(|bb| !reachable.contains(bb))
+            .collect();
+        let mut dead_insts = Vec::new();
+        for bb in &dead_bbs {
+            let (_, node) = func_data.layout_mut().bbs_mut().remove(bb).unwrap();
+            dead_insts.extend(node.insts().keys().copied());
+        }
+        // 不可达的指令只被不可达的指令使用，反复删除已无使用者的指令即可
+        while !dead_insts.is_empty() {
+            dead_insts.retain(|&inst| {
+                if func_data.dfg().value(inst).used_by().is_empty() {
+                    func_data.dfg_mut().remove_value(inst);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+        for bb in dead_bbs {
+            func_data.dfg_mut().remove_bb(bb);
+        }
+    }
 }
 
 enum Res {
     Nothing,
     Imm(i32),
-    Temp(i32),
+    Temp(Value),
     Ret,
-    Params(String),
-    PtrFlag(Vec<bool>),
 }
 
+/// Generate the IR of an AST node into `info.program`.
 trait Show {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res);
+    fn show(&self, info: &mut CompilerInfo) -> Res;
 }
 
 impl Show for CompUnit {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let res = Res::Nothing;
-        match &*self.comp_unit {
-            Some(sub_comp_unit) => s += &sub_comp_unit.show(info).0,
-            None => {}
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        if let Some(sub_comp_unit) = &*self.comp_unit {
+            sub_comp_unit.show(info);
         }
         match &self.global_item {
             GlobalItem::Func(func_def) => {
-                let (_func_str, func_res) = func_def.pre_show(info);
-                let mut ptr_flags: Vec<bool> = Vec::new();
-                match func_res {
-                    Res::PtrFlag(flags) => {
-                        ptr_flags = flags.clone();
-                    }
-                    _ => unreachable!(),
-                }
-                let fun_var = Variable::Func((
-                    format!("@{0}", func_def.id),
-                    func_def.func_type.clone(),
-                    ptr_flags,
-                ));
-                info.vars_table.insert(func_def.id.clone(), (fun_var, 0));
-                let (func_str, func_res) = func_def.show(info);
-                s += &func_str;
+                func_def.show(info);
             }
             GlobalItem::Decl(decl) => {
-                s += &decl.global_show(info);
+                decl.global_show(info);
             }
-            _ => unreachable!(),
         }
-        (s, res)
-    }
-}
-
-impl FuncDef {
-    fn pre_show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "fun @".to_string();
-        let mut res = Res::PtrFlag(Vec::new());
-        match &self.func_f_params {
-            None => {
-                s += "()";
-            }
-            Some(func_f_params) => {
-                let (sub_str, sub_res) = func_f_params.show(info);
-                s += &format!("({0})", sub_str);
-                res = sub_res;
-            }
-        }
-        (s, res)
+        Res::Nothing
     }
 }
 
 impl Show for FuncDef {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "fun @".to_string();
-        let mut res = Res::PtrFlag(Vec::new());
-        s += &self.id;
-        match &self.func_f_params {
-            None => {
-                s += "()";
-            }
-            Some(func_f_params) => {
-                let (sub_str, sub_res) = func_f_params.show(info);
-                s += &format!("({0})", sub_str);
-                res = sub_res;
-            }
-        }
-        match self.func_type {
-            ItemType::Int => {
-                s += ": i32";
-            }
-            ItemType::Void => {}
-            _ => unreachable!(),
-        }
-        s += "{\n%entry:\n";
-        let mut next_info = info.clone();
-        next_info.field_depth += 1;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        let id = self.symbol.unwrap();
+        let (_, param_types) = info.signature(id);
+        let params = self
+            .func_f_params
+            .iter()
+            .flat_map(|p| &p.func_f_params)
+            .zip(&param_types)
+            .map(|(param, ty)| (Some(format!("@{0}", param.id)), param_type(ty)))
+            .collect();
+        let func = info.program.new_func(FunctionData::with_param_names(
+            format!("@{0}", self.id),
+            params,
+            ret_type(&self.func_type),
+        ));
+        // 先加入变量表，以便函数递归调用自身
+        info.vars.insert(id, Variable::Func(func));
+
+        info.func = Some(func);
+        let entry = info.new_bb("entry");
+        info.enter_bb(entry);
         match &self.func_f_params {
             None => {}
             Some(func_f_params) => {
-                s += &func_f_params.allocate_for_params(&mut next_info);
-            }
-        }
-        s += &self.block.show(&mut next_info).0;
-        s += "\tret\n}\n";
-        (s, res)
-    }
-}
-
-impl Show for FuncFParams {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut flags: Vec<bool> = Vec::new();
-        let mut i = 0;
-        for param in &self.func_f_params {
-            match &param.dims {
-                None => {
-                    flags.push(false);
-                    if i == 0 {
-                        s += &format!("@{0}: i32", param.id);
-                    } else {
-                        s += &format!(", @{0}: i32", param.id);
-                    }
-                }
-                Some(indices) => {
-                    flags.push(true);
-                    let mut calculate_info = info
-                        .clone()
-                        .vars_table
-                        .into_iter()
-                        .map(|(k, (v, _))| (k, v))
-                        .collect();
-
-                    let mut dims: Vec<i32> = Vec::new();
-                    let mut array_str = "i32".to_string();
-                    for dim in indices {
-                        dims.push(dim.calculate(&mut calculate_info));
-                    }
-                    for dim in dims.iter().rev() {
-                        array_str = format!("[{0}, {1}]", array_str, dim);
-                    }
-
-                    if i == 0 {
-                        s += &format!("@{0}: *{1}", param.id, array_str);
-                    } else {
-                        s += &format!(", @{0}: *{1}", param.id, array_str);
-                    }
-                }
+                func_f_params.allocate_for_params(info);
             }
-            i += 1;
         }
-        (s, Res::PtrFlag(flags))
+        self.block.show(info);
+        info.finish_func(&self.func_type);
+        info.func = None;
+        info.cur_bb = None;
+        Res::Nothing
     }
 }
 
 impl FuncFParams {
-    pub fn allocate_for_params(&self, info: &mut CompilerInfo) -> String {
-        let mut s = "".to_string();
-        for param in &self.func_f_params {
-            match &param.dims {
-                None => {
-                    let var = Variable::INT(format!("%{0}", param.id));
-                    info.vars_table
-                        .insert(param.id.clone(), (var, info.field_depth));
-                    s += &format!("\t%{0} = alloc i32\n\tstore @{0}, %{0}\n", param.id);
-                }
-                Some(indices) => {
-                    let mut calculate_info = info
-                        .clone()
-                        .vars_table
-                        .into_iter()
-                        .map(|(k, (v, _))| (k, v))
-                        .collect();
-
-                    let mut dims: Vec<i32> = Vec::new();
-                    let mut array_str = "i32".to_string();
-                    for dim in indices {
-                        dims.push(dim.calculate(&mut calculate_info));
-                    }
-                    for dim in dims.iter().rev() {
-                        array_str = format!("[{0}, {1}]", array_str, dim);
-                    }
-
-                    let mut var = Variable::Ptr((format!("%{0}", param.id), ItemType::Int));
-                    info.vars_table
-                        .insert(param.id.clone(), (var, info.field_depth));
-                    s += &format!(
-                        "\t%{1} = alloc *{0}\n\tstore @{1}, %{1}\n",
-                        array_str, param.id
-                    );
-                }
-            }
+    pub fn allocate_for_params(&self, info: &mut CompilerInfo) {
+        let params = info.func_data().params().to_vec();
+        for (param, &value) in self.func_f_params.iter().zip(&params) {
+            let ty = info.func_data().dfg().value(value).ty().clone();
+            let alloc = info.alloc(ty, &param.id);
+            info.store(value, alloc);
+            let var = match &param.dims {
+                None => Variable::INT(alloc),
+                Some(_) => Variable::Ptr((alloc, ItemType::Int)),
+            };
+            info.vars.insert(param.symbol.unwrap(), var);
         }
-        s
-    }
-}
-
-impl Show for FuncRParams {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut pre_s = "".to_string();
-        let mut i = 0;
-        let mut ptr_flags: Vec<bool> = Vec::new();
-        match info.vars_table.get(&info.func_name) {
-            None => unreachable!(),
-            Some((func, depth)) => match func {
-                Variable::Func((func_name, item_type, flags)) => {
-                    ptr_flags = flags.clone();
-                }
-                _ => unreachable!(),
-            },
-        }
-        // println!("{:?}", ptr_flags);
-        for exp in &self.func_r_params {
-            info.func_param = ptr_flags[i];
-            let (exp_str, exp_res) = exp.show(info);
-            info.func_param = false;
-            match exp_res {
-                Res::Imm(imm) => {
-                    if i == 0 {
-                        s += &exp_str;
-                    } else {
-                        s += &format!(", {0}", exp_str);
-                    }
-                }
-                Res::Temp(temp) => {
-                    pre_s += &exp_str;
-                    if i == 0 {
-                        s += &format!("%{0}", temp);
-                    } else {
-                        s += &format!(", %{0}", temp);
-                    }
-                }
-                _ => unreachable!(),
-            }
-            i += 1;
-        }
-        (pre_s, Res::Params(s))
     }
 }
 
 impl Show for Block {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut ds = "".to_string();
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         let mut res = Res::Nothing;
         for item in &self.items {
-            (ds, res) = item.show(info);
-            s += &ds;
+            res = item.show(info);
         }
-        (s, res)
+        res
     }
 }
 
 impl Show for BlockItem {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            BlockItem::Decl(decl) => {
-                s += &*decl.show(info).0;
-            }
-            BlockItem::Stmt(stmt) => {
-                (s, res) = stmt.show(info);
-            }
+            BlockItem::Decl(decl) => decl.show(info),
+            BlockItem::Stmt(stmt) => stmt.show(info),
         }
-        (s, res)
     }
 }
 
 impl Show for Decl {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
             Decl::ConstDecl(const_decl) => const_decl.show(info),
             Decl::VarDecl(var_decl) => var_decl.show(info),
@@ -388,1998 +412,722 @@ impl Show for Decl {
 }
 
 impl Show for VarDecl {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self.b_type {
             ItemType::Int => {}
             _ => unreachable!(),
         }
         for var_def in &self.var_defs {
-            s += &var_def.show(info).0;
+            var_def.show(info);
         }
-        (s, Res::Nothing)
+        Res::Nothing
     }
 }
 
 impl Show for ConstDecl {
     /// 目前只能处理int类型的常量定义
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self.b_type {
             ItemType::Int => {}
             _ => unreachable!(),
         }
         for const_def in &self.const_defs {
-            s += &const_def.show(info).0;
+            const_def.show(info);
         }
-        (s, Res::Nothing)
+        Res::Nothing
     }
 }
 
 impl Show for ConstDef {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut calculate_info = info
-            .clone()
-            .vars_table
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect();
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        let id = self.symbol.unwrap();
         // 首先判断该定义是常量定义还是数组定义
-        if self.dims.len() == 0 {
-            // 在变量表中寻找是否该变量已经被定义
-            match info.vars_table.get_mut(&self.ident) {
-                // 若未被定义，将其添加进变量表中
-                None => {
-                    let value = self.const_init_val.calculate(&mut calculate_info);
-                    info.vars_table.insert(
-                        self.ident.clone(),
-                        (Variable::ConstINT(value), info.field_depth),
-                    );
-                }
-                // 否则，判断已定义的变量是否跟当前处于同一作用域
-                Some(value) => {
-                    if value.1 == info.field_depth {
-                        // 若处于同一作用域，属于重复定义
-                        panic!("变量{:?}重复定义！\n", self.ident);
-                    } else {
-                        // 否则覆盖定义
-                        let new_value = self.const_init_val.calculate(&mut calculate_info);
-                        // 更新变量表
-                        *value = (Variable::ConstINT(new_value), info.field_depth);
-                    }
-                }
-            }
+        if let SymbolKind::Const(value) = info.symbols.symbol(id).kind {
+            info.vars.insert(id, Variable::ConstINT(value));
         } else {
-            match info.vars_table.get_mut(&self.ident) {
-                None => {
-                    let mut dims: Vec<i32> = Vec::new();
-                    let mut array_str = "i32".to_string();
-                    for dim in &self.dims {
-                        dims.push(dim.calculate(&mut calculate_info));
-                    }
-                    for dim in dims.iter().rev() {
-                        array_str = format!("[{0}, {1}]", array_str, dim);
-                    }
-                    let mut val_idx = 0;
-                    let init_str = self.const_init_val.local_array_init(
-                        info,
-                        &dims,
-                        &mut val_idx,
-                        format!("@{0}", self.ident),
-                    );
-                    s += &format!("\t@{0} = alloc {1}\n", self.ident, array_str);
-                    s += &init_str;
-                    let new_var = Variable::Array(format!("@{0}", self.ident));
-                    info.vars_table.insert(self.ident.clone(), (new_var, 0));
-                }
-                Some(value) => {
-                    panic!("define a global const variable more than once!");
-                }
-            }
+            let dims = info.dims(id);
+            let array = info.alloc(array_type(&dims), &self.ident);
+            let mut val_idx = 0;
+            info.decl_span = self.span;
+            self.const_init_val
+                .local_array_init(info, &dims, &mut val_idx, array);
+            info.vars.insert(id, Variable::Array(array));
         }
-        (s, Res::Nothing)
+        Res::Nothing
     }
 }
 
 impl Show for VarDef {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        match self {
-            VarDef::Def((ident, dims, init_val)) => {
-                if dims.len() == 0 {
-                    let mut vt = info.vars_table.clone();
-                    // 首先检查该变量是否已被定义。
-                    match vt.get_mut(ident) {
-                        // 若已被定义，检查作用域
-                        Some(int) => {
-                            if int.1 == info.field_depth {
-                                // 若处于同一作用域，属于重复定义
-                                panic!("变量{:?}重复定义！\n", ident);
-                            } else {
-                                // 生成该变量对应的指针的名字：@ident_depth
-                                let var_name = format!("@{0}_{1}", ident, info.var_id);
-                                info.var_id += 1;
-                                // 为该变量进行alloc操作
-                                s += &format!("\t{0} = alloc i32\n", var_name);
-                                // 计算init_val
-                                let (init_s, init_res) = init_val.show(info);
-                                // 将结果存进内存
-                                match init_res {
-                                    Res::Nothing => unreachable!(),
-                                    Res::Imm(imm) => {
-                                        s += &format!("\tstore {0}, {1}\n", init_s, var_name);
-                                    }
-                                    Res::Temp(idx) => {
-                                        s += &init_s;
-                                        s += &format!("\tstore %{0}, {1}\n", idx, var_name);
-                                    }
-                                    _ => {}
-                                }
-                                let var = Variable::INT(var_name);
-                                // 修改变量表
-                                info.vars_table
-                                    .insert(ident.clone(), (var, info.field_depth));
-                            }
-                        }
-                        // 若尚未被定义，将其加入变量表
-                        None => {
-                            // 生成该变量对应的指针的名字：@ident_depth
-                            let var_name = format!("@{0}_{1}", ident, info.var_id);
-                            info.var_id += 1;
-                            // 为该变量进行alloc操作
-                            s += &format!("\t{0} = alloc i32\n", var_name);
-                            // 计算init_val
-                            let (init_s, init_res) = init_val.show(info);
-                            // 将结果存进内存
-                            match init_res {
-                                Res::Nothing => unreachable!(),
-                                Res::Imm(imm) => {
-                                    s += &format!("\tstore {0}, {1}\n", init_s, var_name);
-                                }
-                                Res::Temp(idx) => {
-                                    s += &init_s;
-                                    s += &format!("\tstore %{0}, {1}\n", idx, var_name);
-                                }
-                                _ => {}
-                            }
-                            let var = Variable::INT(var_name);
-                            // 将其插入变量表中
-                            info.vars_table
-                                .insert(ident.clone(), (var, info.field_depth));
-                        }
-                    }
-                } else {
-                    let mut vt = info.vars_table.clone();
-                    // 首先检查该变量是否已被定义。
-                    match vt.get_mut(ident) {
-                        // 若已被定义，检查作用域
-                        Some(int) => {
-                            if int.1 == info.field_depth {
-                                // 若处于同一作用域，属于重复定义
-                                panic!("变量{:?}重复定义！\n", ident);
-                            } else {
-                                let mut calculate_info = info
-                                    .clone()
-                                    .vars_table
-                                    .into_iter()
-                                    .map(|(k, (v, _))| (k, v))
-                                    .collect();
-
-                                // 计算数组维度信息
-                                let mut dims_i32: Vec<i32> = Vec::new();
-                                let mut array_str = "i32".to_string();
-                                for dim in dims {
-                                    dims_i32.push(dim.calculate(&mut calculate_info));
-                                }
-                                for dim in dims_i32.iter().rev() {
-                                    array_str = format!("[{0}, {1}]", array_str, dim);
-                                }
-
-                                // 生成该变量对应的指针的名字：@ident_depth
-                                let var_name = format!("@{0}_{1}", ident, info.var_id);
-                                info.var_id += 1;
-                                // 为该变量进行alloc操作
-                                s += &format!("\t{0} = alloc {1}\n", var_name, array_str);
-                                // 初始化
-                                let mut val_idx = 0;
-                                let init_s = init_val.local_array_init(
-                                    info,
-                                    &dims_i32,
-                                    &mut val_idx,
-                                    var_name.clone(),
-                                );
-                                s += &init_s;
-                                let var = Variable::Array(var_name);
-                                // 修改变量表
-                                info.vars_table
-                                    .insert(ident.clone(), (var, info.field_depth));
-                            }
-                        }
-                        // 若尚未被定义，将其加入变量表
-                        None => {
-                            let mut calculate_info = info
-                                .clone()
-                                .vars_table
-                                .into_iter()
-                                .map(|(k, (v, _))| (k, v))
-                                .collect();
-
-                            // 计算数组维度信息
-                            let mut dims_i32: Vec<i32> = Vec::new();
-                            let mut array_str = "i32".to_string();
-                            for dim in dims {
-                                dims_i32.push(dim.calculate(&mut calculate_info));
-                            }
-                            for dim in dims_i32.iter().rev() {
-                                array_str = format!("[{0}, {1}]", array_str, dim);
-                            }
-
-                            // 生成该变量对应的指针的名字：@ident_depth
-                            let var_name = format!("@{0}_{1}", ident, info.var_id);
-                            info.var_id += 1;
-                            // 为该变量进行alloc操作
-                            s += &format!("\t{0} = alloc {1}\n", var_name, array_str);
-                            // 计算init_val
-                            let mut val_idx = 0;
-                            let init_s = init_val.local_array_init(
-                                info,
-                                &dims_i32,
-                                &mut val_idx,
-                                var_name.clone(),
-                            );
-                            s += &init_s;
-                            let var = Variable::Array(var_name);
-                            // 将其插入变量表中
-                            info.vars_table
-                                .insert(ident.clone(), (var, info.field_depth));
-                        }
-                    }
-                }
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        let (ident, init_val, span, id) = match self {
+            VarDef::Def((ident, _, init_val, span, id)) => (ident, Some(init_val), span, id),
+            VarDef::Decl((ident, _, span, id)) => (ident, None, span, id),
+        };
+        let id = id.unwrap();
+        info.decl_span = *span;
+        let dims = info.dims(id);
+        // 为该变量进行alloc操作
+        let var_ptr = info.alloc(array_type(&dims), ident);
+        let var = if dims.is_empty() {
+            // 计算init_val并将结果存进内存
+            if let Some(init_val) = init_val {
+                let init_res = init_val.show(info);
+                let value = info.value(init_res);
+                info.store(value, var_ptr);
             }
-            VarDef::Decl((ident, dims)) => {
-                if dims.len() == 0 {
-                    match info.vars_table.get_mut(&ident.clone()) {
-                        // 若已被定义，检查作用域
-                        Some(int) => {
-                            if int.1 == info.field_depth {
-                                // 若处于同一作用域，属于重复定义
-                                panic!("变量{:?}重复定义！\n", ident);
-                            } else {
-                                // 生成该变量对应的指针的名字：@ident_depth
-                                let var_name = format!("@{0}_{1}", ident, info.var_id);
-                                info.var_id += 1;
-                                // 为该变量进行alloc操作
-                                s += &format!("\t{0} = alloc i32\n", var_name);
-                                let var = Variable::INT(var_name);
-                                // 将其插入变量表中
-                                *int = (var, info.field_depth);
-                            }
-                        }
-                        // 若尚未被定义，将其加入变量表
-                        None => {
-                            // 生成该变量对应的指针的名字：@ident_depth
-                            let var_name = format!("@{0}_{1}", ident, info.var_id);
-                            info.var_id += 1;
-                            // 为该变量进行alloc操作
-                            s += &format!("\t{0} = alloc i32\n", var_name);
-                            let var = Variable::INT(var_name);
-                            // 将其插入变量表中
-                            info.vars_table
-                                .insert(ident.clone(), (var, info.field_depth));
-                        }
-                    }
-                } else {
-                    let mut vt = info.vars_table.clone();
-                    // 首先检查该变量是否已被定义。
-                    match vt.get_mut(ident) {
-                        // 若已被定义，检查作用域
-                        Some(int) => {
-                            if int.1 == info.field_depth {
-                                // 若处于同一作用域，属于重复定义
-                                panic!("变量{:?}重复定义！\n", ident);
-                            } else {
-                                let mut calculate_info = info
-                                    .clone()
-                                    .vars_table
-                                    .into_iter()
-                                    .map(|(k, (v, _))| (k, v))
-                                    .collect();
-
-                                // 计算数组维度信息
-                                let mut dims_i32: Vec<i32> = Vec::new();
-                                let mut array_str = "i32".to_string();
-                                for dim in dims {
-                                    dims_i32.push(dim.calculate(&mut calculate_info));
-                                }
-                                for dim in dims_i32.iter().rev() {
-                                    array_str = format!("[{0}, {1}]", array_str, dim);
-                                }
-
-                                // 生成该变量对应的指针的名字：@ident_depth
-                                let var_name = format!("@{0}_{1}", ident, info.var_id);
-                                info.var_id += 1;
-                                // 为该变量进行alloc操作
-                                s += &format!("\t{0} = alloc {1}\n", var_name, array_str);
-                                let var = Variable::Array(var_name);
-                                // 修改变量表
-                                info.vars_table
-                                    .insert(ident.clone(), (var, info.field_depth));
-                            }
-                        }
-                        // 若尚未被定义，将其加入变量表
-                        None => {
-                            let mut calculate_info = info
-                                .clone()
-                                .vars_table
-                                .into_iter()
-                                .map(|(k, (v, _))| (k, v))
-                                .collect();
-
-                            // 计算数组维度信息
-                            let mut dims_i32: Vec<i32> = Vec::new();
-                            let mut array_str = "i32".to_string();
-                            for dim in dims {
-                                dims_i32.push(dim.calculate(&mut calculate_info));
-                            }
-                            for dim in dims_i32.iter().rev() {
-                                array_str = format!("[{0}, {1}]", array_str, dim);
-                            }
-
-                            // 生成该变量对应的指针的名字：@ident_depth
-                            let var_name = format!("@{0}_{1}", ident, info.var_id);
-                            info.var_id += 1;
-                            // 为该变量进行alloc操作
-                            s += &format!("\t{0} = alloc {1}\n", var_name, array_str);
-                            let var = Variable::Array(var_name);
-                            // 将其插入变量表中
-                            info.vars_table
-                                .insert(ident.clone(), (var, info.field_depth));
-                        }
-                    }
-                }
+            Variable::INT(var_ptr)
+        } else {
+            if let Some(init_val) = init_val {
+                let mut val_idx = 0;
+                init_val.local_array_init(info, &dims, &mut val_idx, var_ptr);
             }
-        }
-        (s, Res::Nothing)
+            Variable::Array(var_ptr)
+        };
+        info.vars.insert(id, var);
+        Res::Nothing
     }
 }
 
 impl Show for InitVal {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
             InitVal::Exp(exp) => exp.show(info),
-            InitVal::Array(array) => ("".to_string(), Res::Nothing),
+            InitVal::Array(_) => unreachable!(),
         }
     }
 }
 
 impl Show for If {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let res = Res::Nothing;
-
-        let then_flag = format!("%flag{0}", info.flag_id);
-        let else_flag = format!("%flag{0}", info.flag_id + 1);
-        let end_flag = format!("%flag{0}", info.flag_id + 2);
-        info.flag_id += 3;
-
-        let (cond_str, cond_res) = self.cond.show(info);
-        match cond_res {
-            Res::Temp(temp_id) => {
-                s += &cond_str;
-                s += &format!("\tbr %{0}, {1}, {2}\n", temp_id, then_flag, else_flag);
-            }
-            Res::Imm(imm) => {
-                s += &format!(
-                    "\t%{0} = add {1}, 0\n\tbr %{0}, {2}, {3}\n",
-                    info.temp_id, cond_str, then_flag, else_flag
-                );
-                info.temp_id += 1;
-            }
-            _ => unreachable!(),
-        }
-
-        s += &(then_flag + ":\n");
-        let (then_str, then_res) = self.then_stmt.show(info);
-        s += &(then_str + "\tjump " + &end_flag + "\n");
-        s += &(else_flag + ":\n");
-        match &self.else_stmt {
-            None => {
-                s += &format!("\tjump {0}\n", end_flag);
-            }
-            Some(stmt) => {
-                let (else_str, else_res) = stmt.show(info);
-                s += &else_str;
-                s += &format!("\tjump {0}\n", end_flag);
-            }
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        let then_bb = info.new_bb("then");
+        let else_bb = info.new_bb("else");
+        let end_bb = info.new_bb("if_end");
+
+        let cond_res = self.cond.show(info);
+        let cond = info.value(cond_res);
+        info.branch(cond, then_bb, else_bb);
+
+        info.enter_bb(then_bb);
+        self.then_stmt.show(info);
+        info.jump(end_bb);
+        info.enter_bb(else_bb);
+        if let Some(stmt) = &self.else_stmt {
+            stmt.show(info);
         }
+        info.jump(end_bb);
+        info.enter_bb(end_bb);
+        Res::Nothing
+    }
+}
 
-        s += &(end_flag + ":\n");
+impl Show for While {
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        let enter_bb = info.new_bb("while_entry");
+        let body_bb = info.new_bb("while_body");
+        let end_bb = info.new_bb("while_end");
+        info.jump(enter_bb);
+        info.enter_bb(enter_bb);
+        let cond_res = self.cond.show(info);
+        let cond = info.value(cond_res);
+        info.branch(cond, body_bb, end_bb);
+
+        info.enter_bb(body_bb);
+        let ori_enter_bb = info.enter_bb.replace(enter_bb);
+        let ori_end_bb = info.end_bb.replace(end_bb);
+        self.body_stmt.show(info);
+        info.enter_bb = ori_enter_bb;
+        info.end_bb = ori_end_bb;
+        info.jump(enter_bb);
+        info.enter_bb(end_bb);
+        Res::Nothing
+    }
+}
 
-        (s, res)
+/// 生成数组元素的地址：先对`base`依次用各下标取元素指针，`first_getptr`为真时
+/// 第一个下标用getptr（`base`是由数组形参得到的指针）
+fn element_ptr(
+    info: &mut CompilerInfo,
+    base: Value,
+    indices: Vec<Value>,
+    first_getptr: bool,
+) -> Value {
+    let mut ptr = base;
+    for (i, index) in indices.into_iter().enumerate() {
+        ptr = if i == 0 && first_getptr {
+            info.get_ptr(ptr, index)
+        } else {
+            info.get_elem_ptr(ptr, index)
+        };
     }
+    ptr
 }
 
-impl Show for While {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let res = Res::Nothing;
-
-        let enter_flag = format!("%flag{0}", info.flag_id);
-        let body_flag = format!("%flag{0}", info.flag_id + 1);
-        let end_flag = format!("%flag{0}", info.flag_id + 2);
-        info.flag_id += 3;
-        s += &format!("{0}:\n", enter_flag);
-        let (cond_str, cond_res) = self.cond.show(info);
-        match cond_res {
-            Res::Temp(temp_id) => {
-                s += &cond_str;
-                s += &format!("\tbr %{0}, {1}, {2}\n", temp_id, body_flag, end_flag);
-            }
-            Res::Imm(imm) => {
-                s += &format!(
-                    "\t%{0} = add {1}, 0\n\tbr %{0}, {2}, {3}\n",
-                    info.temp_id, cond_str, body_flag, end_flag
-                );
-                info.temp_id += 1;
-            }
-            _ => unreachable!(),
-        }
-        s += &(body_flag + ":\n");
-        let ori_enter_flag = info.enter_flag;
-        let ori_end_flag = info.end_flag;
-        info.enter_flag = info.flag_id - 3;
-        info.end_flag = info.flag_id - 1;
-        let (body_str, body_res) = self.body_stmt.show(info);
-        info.enter_flag = ori_enter_flag;
-        info.end_flag = ori_end_flag;
-        s += &body_str;
-        s += &format!("\tjump {0}\n", enter_flag);
-        s += &(end_flag + ":\n");
-        (s, res)
+/// 计算左值的各个下标
+fn lval_indices(lval: &LVal, info: &mut CompilerInfo) -> Vec<Value> {
+    // 下标本身不是函数实参
+    let func_param = std::mem::replace(&mut info.func_param, false);
+    let mut indices = Vec::new();
+    for index in &lval.indices {
+        let index_res = index.show(info);
+        indices.push(info.value(index_res));
     }
+    info.func_param = func_param;
+    indices
 }
 
 impl Show for Stmt {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
             // 对于返回语句，计算返回值并ret即可
-            Stmt::Return(exp) => {
-                match exp {
-                    None => {
-                        s += &format!("\tret\n%flag{0}:\n", info.flag_id);
-                        info.flag_id += 1;
-                    }
-                    Some(e) => {
-                        let (sub_exp_str, sub_res) = e.show(info);
-                        match sub_res {
-                            Res::Nothing => {}
-                            Res::Imm(imm) => {
-                                s += &format!("\tret {0}\n%flag{1}:\n", sub_exp_str, info.flag_id);
-                                info.flag_id += 1;
-                            }
-                            Res::Temp(id) => {
-                                s += &sub_exp_str;
-                                s += &format!("\tret %{0}\n%flag{1}:\n", id, info.flag_id);
-                                info.flag_id += 1;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-                (s, Res::Ret)
+            Stmt::Return((exp, _)) => {
+                let value = match exp {
+                    None => None,
+                    Some(e) => match e.show(info) {
+                        Res::Nothing => None,
+                        res => Some(info.value(res)),
+                    },
+                };
+                info.ret(value);
+                // ret之后的语句不可达
+                let next_bb = info.new_bb("after_ret");
+                info.enter_bb(next_bb);
+                Res::Ret
             }
 
             Stmt::Assign((lval, exp)) => {
-                let mut indices: Vec<Res> = Vec::new();
-                for index in &lval.indices {
-                    let (ind_str, ind_res) = index.show(info);
-                    match ind_res {
-                        Res::Imm(imm) => indices.push(Res::Imm(imm)),
-                        Res::Temp(temp) => {
-                            s += &ind_str;
-                            indices.push(Res::Temp(temp));
-                        }
-                        _ => unreachable!(),
-                    }
-                }
-                // 首先检查变量是否被定义
-                match info.clone().vars_table.get(&lval.ident) {
-                    Some(var) => {
-                        // 检查赋值语句左侧是否是常量
-                        match &var.0 {
-                            // 若是常量，报错
-                            Variable::ConstINT(_) => unreachable!(),
-                            Variable::INT(ptr_name) => {
-                                // 计算右侧表达式
-                                let (exp_str, exp_res) = exp.show(info);
-                                match exp_res {
-                                    Res::Imm(imm) => {
-                                        s += &format!("\tstore {0}, {1}\n", exp_str, ptr_name);
-                                    }
-                                    Res::Temp(idx) => {
-                                        s += &exp_str;
-                                        s += &format!("\tstore %{0}, {1}\n", idx, ptr_name);
-                                    }
-                                    Res::Nothing => unreachable!(),
-                                    _ => {}
-                                }
-                            }
-                            Variable::Array(array_name) => {
-                                let mut source_addr = array_name.clone();
-                                for index in indices {
-                                    match index {
-                                        Res::Imm(imm) => {
-                                            s += &format!(
-                                                "\t%{0} = getelemptr {1}, {2}\n",
-                                                info.temp_id, source_addr, imm
-                                            );
-                                            source_addr = format!("%{0}", info.temp_id);
-                                            info.temp_id += 1;
-                                        }
-                                        Res::Temp(temp) => {
-                                            s += &format!(
-                                                "\t%{0} = getelemptr {1}, %{2}\n",
-                                                info.temp_id, source_addr, temp
-                                            );
-                                            source_addr = format!("%{0}", info.temp_id);
-                                            info.temp_id += 1;
-                                        }
-                                        _ => unreachable!(),
-                                    }
-                                }
-                                let (exp_str, exp_res) = exp.show(info);
-                                match exp_res {
-                                    Res::Imm(imm) => {
-                                        s += &format!("\tstore {0}, {1}\n", exp_str, source_addr);
-                                    }
-                                    Res::Temp(idx) => {
-                                        s += &exp_str;
-                                        s += &format!("\tstore %{0}, {1}\n", idx, source_addr);
-                                    }
-                                    _ => unreachable!(),
-                                }
-                            }
-                            Variable::Ptr((ptr_name, tar_type)) => {
-                                let mut source_addr = ptr_name.clone();
-                                let mut flag = 0;
-                                s += &format!("\t%{0} = load {1}\n", info.temp_id, source_addr);
-                                source_addr = format!("%{0}", info.temp_id);
-                                info.temp_id += 1;
-                                for index in indices {
-                                    match index {
-                                        Res::Imm(imm) => {
-                                            if flag == 0 {
-                                                s += &format!(
-                                                    "\t%{0} = getptr {1}, {2}\n",
-                                                    info.temp_id, source_addr, imm
-                                                );
-                                                flag += 1;
-                                            } else {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                                    info.temp_id, source_addr, imm
-                                                );
-                                            }
-                                            source_addr = format!("%{0}", info.temp_id);
-                                            info.temp_id += 1;
-                                        }
-                                        Res::Temp(temp) => {
-                                            if flag == 0 {
-                                                s += &format!(
-                                                    "\t%{0} = getptr {1}, %{2}\n",
-                                                    info.temp_id, source_addr, temp
-                                                );
-                                                flag += 1;
-                                            } else {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, %{2}\n",
-                                                    info.temp_id, source_addr, temp
-                                                );
-                                            }
-                                            source_addr = format!("%{0}", info.temp_id);
-                                            info.temp_id += 1;
-                                        }
-                                        _ => unreachable!(),
-                                    }
-                                }
-                                let (exp_str, exp_res) = exp.show(info);
-                                match exp_res {
-                                    Res::Imm(imm) => {
-                                        s += &format!("\tstore {0}, {1}\n", exp_str, source_addr);
-                                    }
-                                    Res::Temp(idx) => {
-                                        s += &exp_str;
-                                        s += &format!("\tstore %{0}, {1}\n", idx, source_addr);
-                                    }
-                                    _ => unreachable!(),
-                                }
-                            }
-                            _ => unreachable!(),
-                        }
+                let indices = lval_indices(lval, info);
+                let dest = match info.var(lval.symbol) {
+                    Variable::INT(ptr) => ptr,
+                    Variable::Array(array) => element_ptr(info, array, indices, false),
+                    Variable::Ptr((ptr, _)) => {
+                        let base = info.load(ptr);
+                        element_ptr(info, base, indices, true)
                     }
-                    // 若变量未被定义过，报错
-                    None => unreachable!(),
-                }
-                (s, Res::Nothing)
-            }
-            Stmt::Block(block) => {
-                let mut next_info = info.clone();
-                next_info.field_depth += 1;
-                let (blk_str, blk_res) = block.show(&mut next_info);
-                s += &blk_str;
-                info.temp_id = next_info.temp_id;
-                info.flag_id = next_info.flag_id;
-                info.var_id = next_info.var_id;
-                (s, blk_res)
+                    _ => unreachable!(),
+                };
+                // 计算右侧表达式
+                let exp_res = exp.show(info);
+                let value = info.value(exp_res);
+                info.store(value, dest);
+                Res::Nothing
             }
+            Stmt::Block(block) => block.show(info),
             Stmt::Exp(exp) => {
-                match exp {
-                    None => {}
-                    Some(e) => {
-                        let (exp_str, exp_res) = e.show(info);
-                        match exp_res {
-                            Res::Temp(temp_id) => {
-                                s += &exp_str;
-                            }
-                            Res::Imm(imm) => {}
-                            Res::Nothing => {
-                                s += &exp_str;
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
+                if let Some(e) = exp {
+                    e.show(info);
                 }
-                (s, Res::Nothing)
-            }
-            Stmt::IF(if_stmt) => {
-                s += &if_stmt.show(info).0;
-                (s, Res::Nothing)
-            }
-            Stmt::WHILE(while_stmt) => {
-                s += &format!("\tjump %flag{0}\n", info.flag_id);
-                s += &while_stmt.show(info).0;
-                (s, Res::Nothing)
+                Res::Nothing
             }
-            Stmt::Break => {
-                s += &format!("\tjump %flag{0}\n%flag{1}:\n", info.end_flag, info.flag_id);
-                info.flag_id += 1;
-                (s, Res::Ret)
+            Stmt::IF(if_stmt) => if_stmt.show(info),
+            Stmt::WHILE(while_stmt) => while_stmt.show(info),
+            Stmt::Break(_) => {
+                let end_bb = info.end_bb.unwrap();
+                info.jump(end_bb);
+                let next_bb = info.new_bb("after_break");
+                info.enter_bb(next_bb);
+                Res::Ret
             }
-            Stmt::Continue => {
-                s += &format!(
-                    "\tjump %flag{0}\n%flag{1}:\n",
-                    info.enter_flag, info.flag_id
-                );
-                info.flag_id += 1;
-                (s, Res::Ret)
+            Stmt::Continue(_) => {
+                let enter_bb = info.enter_bb.unwrap();
+                info.jump(enter_bb);
+                let next_bb = info.new_bb("after_continue");
+                info.enter_bb(next_bb);
+                Res::Ret
             }
-            _ => unreachable!(),
         }
     }
 }
 
 impl Show for Exp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let (sub_exp_str, sub_flag) = self.lor_exp.show(info);
-        s += &sub_exp_str;
-        (s, sub_flag)
+    fn show(&self, info: &mut CompilerInfo) -> Res {
+        self.lor_exp.show(info)
     }
 }
 
 impl Show for UnaryExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            UnaryExp::PrimaryExp(pri_exp) => {
-                let (sub_exp_str, sub_res) = pri_exp.show(info);
-                s += &sub_exp_str;
-                res = sub_res;
-            }
+            UnaryExp::PrimaryExp(pri_exp) => pri_exp.show(info),
             UnaryExp::UnaryExp((unary_op, sub_exp)) => {
-                let (sub_exp_str, sub_res) = sub_exp.show(info);
+                let sub_res = sub_exp.show(info);
                 match unary_op {
-                    UnaryOp::Passive => {
-                        s += &sub_exp_str;
-                        res = sub_res;
-                    }
+                    UnaryOp::Passive => sub_res,
                     UnaryOp::Negative => {
-                        s += &sub_exp_str;
-                        match sub_res {
-                            Res::Nothing => {}
-                            Res::Imm(imm) => {
-                                s = format!("    %{0} = sub 0, {1}\n", info.temp_id, s);
-                                res = Res::Temp(info.temp_id);
-                                info.temp_id += 1;
-                            }
-                            Res::Temp(id) => {
-                                s += &format!("    %{0} = sub 0, %{1}\n", info.temp_id, id);
-                                res = Res::Temp(info.temp_id);
-                                info.temp_id += 1;
-                            }
-                            _ => {}
-                        }
+                        let zero = info.integer(0);
+                        let value = info.value(sub_res);
+                        Res::Temp(info.binary(BinaryOp::Sub, zero, value))
                     }
                     UnaryOp::Inversion => {
-                        s += &sub_exp_str;
-                        match sub_res {
-                            Res::Nothing => {}
-                            Res::Imm(imm) => {
-                                s = format!("    %{0} = eq 0, {1}\n", info.temp_id, s);
-                                res = Res::Temp(info.temp_id);
-                                info.temp_id += 1;
-                            }
-                            Res::Temp(id) => {
-                                s += &format!("    %{0} = eq 0, %{1}\n", info.temp_id, id);
-                                res = Res::Temp(info.temp_id);
-                                info.temp_id += 1;
-                            }
-                            _ => {}
-                        }
+                        let zero = info.integer(0);
+                        let value = info.value(sub_res);
+                        Res::Temp(info.binary(BinaryOp::Eq, zero, value))
                     }
                 }
             }
-            UnaryExp::FuncItem((id, func_r_params)) => {
-                let mut func_r_params_str = "".to_string();
-                match func_r_params {
-                    Some(func_params) => {
-                        info.func_name = id.clone();
-                        let (param_str, param_res) = func_params.show(info);
-                        s += &param_str;
-                        match param_res {
-                            Res::Params(pstr) => {
-                                func_r_params_str += &pstr;
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                    None => {}
+            UnaryExp::FuncItem((_, func_r_params, _, symbol)) => {
+                let (func_type, param_types) = info.signature(symbol.unwrap());
+                let callee = match info.var(*symbol) {
+                    Variable::Func(callee) => callee,
+                    _ => unreachable!(),
+                };
+                let mut args = Vec::new();
+                let exps = func_r_params.iter().flat_map(|p| &p.func_r_params);
+                for (exp, param) in exps.zip(&param_types) {
+                    // 数组形参对应的实参传递地址
+                    info.func_param = matches!(param, ParamType::Ptr(_));
+                    let exp_res = exp.show(info);
+                    info.func_param = false;
+                    args.push(info.value(exp_res));
                 }
-                match info.vars_table.get(id) {
-                    Some((func, depth)) => match func {
-                        Variable::Func((func_name, func_type, ptr_flags)) => {
-                            match func_type {
-                                ItemType::Int => {
-                                    s += &format!("\t%{0} = ", info.temp_id);
-                                    res = Res::Temp(info.temp_id);
-                                    info.temp_id += 1;
-                                }
-                                ItemType::Void => {
-                                    s += "\t";
-                                }
-                                _ => unreachable!(),
-                            }
-                            s += &format!("call {0}({1})\n", func_name, func_r_params_str);
-                        }
-                        _ => unreachable!(),
-                    },
-                    None => unreachable!(),
+                let call = info.call(callee, args);
+                match func_type {
+                    ItemType::Int => Res::Temp(call),
+                    ItemType::Void => Res::Nothing,
                 }
             }
-            _ => unreachable!(),
         }
-        (s, res)
     }
 }
 
 impl Show for PrimaryExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            PrimaryExp::Exp(exp) => {
-                let (exp_str, sub_res) = exp.show(info);
-                s += &exp_str;
-                res = sub_res;
-            }
-            PrimaryExp::Number(num) => {
-                s += &num.to_string();
-                res = Res::Imm(*num);
-            }
+            PrimaryExp::Exp(exp) => exp.show(info),
+            PrimaryExp::Number(num) => Res::Imm(*num),
             PrimaryExp::LVal(var) => {
-                let mut indices: Vec<Res> = Vec::new();
-                for index in &var.indices {
-                    let (ind_str, ind_res) = index.show(info);
-                    match ind_res {
-                        Res::Imm(imm) => indices.push(Res::Imm(imm)),
-                        Res::Temp(temp) => {
-                            s += &ind_str;
-                            indices.push(Res::Temp(temp));
+                let indices = lval_indices(var, info);
+                match info.var(var.symbol) {
+                    // 对于常量，直接将值代入即可
+                    Variable::ConstINT(const_int) => Res::Imm(const_int),
+                    // 对于变量，将其load进一个临时变量
+                    Variable::INT(ptr) => Res::Temp(info.load(ptr)),
+                    Variable::Array(array) => {
+                        let ptr = element_ptr(info, array, indices, false);
+                        if info.func_param {
+                            // 作为实参时传递数组首元素的地址
+                            let zero = info.integer(0);
+                            Res::Temp(info.get_elem_ptr(ptr, zero))
+                        } else {
+                            Res::Temp(info.load(ptr))
                         }
-                        _ => unreachable!(),
                     }
-                }
-                match info.vars_table.get_mut(&var.ident) {
-                    Some(const_val) => {
-                        match &const_val.0 {
-                            // 对于常量，直接将值代入即可
-                            Variable::ConstINT(const_int) => {
-                                s += &const_int.to_string();
-                                res = Res::Imm(*const_int);
-                            }
-                            Variable::INT(ptr_name) => {
-                                // 首先将变量load进一个临时变量
-                                s += &format!("\t%{0} = load {1}\n", info.temp_id, ptr_name);
-                                // 返回这个临时变量
-                                res = Res::Temp(info.temp_id);
-                                info.temp_id += 1;
-                            }
-                            Variable::Array(array_name) => {
-                                if info.func_param {
-                                    let mut source_addr = array_name.clone();
-                                    for index in indices {
-                                        match index {
-                                            Res::Imm(imm) => {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                                    info.temp_id, source_addr, imm
-                                                );
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            Res::Temp(temp) => {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, %{2}\n",
-                                                    info.temp_id, source_addr, temp
-                                                );
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                    }
-                                    s += &format!(
-                                        "\t%{0} = getelemptr {1}, 0\n",
-                                        info.temp_id, source_addr
-                                    );
-                                    res = Res::Temp(info.temp_id);
-                                    info.temp_id += 1;
-                                } else {
-                                    let mut source_addr = array_name.clone();
-                                    for index in indices {
-                                        match index {
-                                            Res::Imm(imm) => {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                                    info.temp_id, source_addr, imm
-                                                );
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            Res::Temp(temp) => {
-                                                s += &format!(
-                                                    "\t%{0} = getelemptr {1}, %{2}\n",
-                                                    info.temp_id, source_addr, temp
-                                                );
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                    }
-                                    s += &format!("\t%{0} = load {1}\n", info.temp_id, source_addr);
-                                    res = Res::Temp(info.temp_id);
-                                    info.temp_id += 1;
-                                }
-                            }
-                            Variable::Ptr((ptr_name, tar_type)) => {
-                                if info.func_param {
-                                    let mut source_addr = ptr_name.clone();
-                                    s += &format!("\t%{0} = load {1}\n", info.temp_id, source_addr);
-                                    source_addr = format!("%{0}", info.temp_id);
-                                    info.temp_id += 1;
-                                    let mut flag = 0;
-                                    for index in indices {
-                                        match index {
-                                            Res::Imm(imm) => {
-                                                if flag == 0 {
-                                                    s += &format!(
-                                                        "\t%{0} = getptr {1}, {2}\n",
-                                                        info.temp_id, source_addr, imm
-                                                    );
-                                                    flag += 1;
-                                                } else {
-                                                    s += &format!(
-                                                        "\t%{0} = getelemptr {1}, {2}\n",
-                                                        info.temp_id, source_addr, imm
-                                                    );
-                                                }
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            Res::Temp(temp) => {
-                                                if flag == 0 {
-                                                    s += &format!(
-                                                        "\t%{0} = getptr {1}, %{2}\n",
-                                                        info.temp_id, source_addr, temp
-                                                    );
-                                                    flag += 1;
-                                                } else {
-                                                    s += &format!(
-                                                        "\t%{0} = getelemptr {1}, %{2}\n",
-                                                        info.temp_id, source_addr, temp
-                                                    );
-                                                }
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                    }
-                                    if flag > 0 {
-                                        s += &format!(
-                                            "\t%{0} = getelemptr {1}, 0\n",
-                                            info.temp_id, source_addr
-                                        );
-                                        res = Res::Temp(info.temp_id);
-                                        info.temp_id += 1;
-                                    } else {
-                                        res = Res::Temp(info.temp_id - 1);
-                                    }
-                                } else {
-                                    let mut source_addr = ptr_name.clone();
-                                    s += &format!("\t%{0} = load {1}\n", info.temp_id, source_addr);
-                                    source_addr = format!("%{0}", info.temp_id);
-                                    info.temp_id += 1;
-                                    let mut flag = 0;
-                                    for index in indices {
-                                        match index {
-                                            Res::Imm(imm) => {
-                                                if flag == 0 {
-                                                    s += &format!(
-                                                        "\t%{0} = getptr {1}, {2}\n",
-                                                        info.temp_id, source_addr, imm
-                                                    );
-                                                    flag += 1;
-                                                } else {
-                                                    s += &format!(
-                                                        "\t%{0} = getelemptr {1}, {2}\n",
-                                                        info.temp_id, source_addr, imm
-                                                    );
-                                                }
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            Res::Temp(temp) => {
-                                                if flag == 0 {
-                                                    s += &format!(
-                                                        "\t%{0} = getptr {1}, %{2}\n",
-                                                        info.temp_id, source_addr, temp
-                                                    );
-                                                    flag += 1;
-                                                } else {
-                                                    s += &format!(
-                                                        "\t%{0} = getelemptr {1}, %{2}\n",
-                                                        info.temp_id, source_addr, temp
-                                                    );
-                                                }
-                                                source_addr = format!("%{0}", info.temp_id);
-                                                info.temp_id += 1;
-                                            }
-                                            _ => unreachable!(),
-                                        }
-                                    }
-                                    s += &format!("\t%{0} = load {1}\n", info.temp_id, source_addr);
-                                    res = Res::Temp(info.temp_id);
-                                    info.temp_id += 1;
-                                }
-                            }
-                            _ => unreachable!(),
+                    Variable::Ptr((ptr, _)) => {
+                        let base = info.load(ptr);
+                        let has_indices = !indices.is_empty();
+                        let ptr = element_ptr(info, base, indices, true);
+                        if !info.func_param {
+                            Res::Temp(info.load(ptr))
+                        } else if has_indices {
+                            let zero = info.integer(0);
+                            Res::Temp(info.get_elem_ptr(ptr, zero))
+                        } else {
+                            Res::Temp(ptr)
                         }
                     }
-                    None => unreachable!(),
+                    Variable::Func(_) => unreachable!(),
                 }
             }
         }
-        (s, res)
     }
 }
 
+/// 生成二元运算，两侧的表达式从左到右求值
+fn binary_exp(info: &mut CompilerInfo, op: BinaryOp, lhs: Res, rhs: Res) -> Res {
+    let lhs = info.value(lhs);
+    let rhs = info.value(rhs);
+    Res::Temp(info.binary(op, lhs, rhs))
+}
+
 impl Show for AddExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
             AddExp::AddExp((add_exp, add_op, mul_exp)) => {
-                let (add_exp_str, add_exp_res) = add_exp.show(info);
-                let (mul_exp_str, mul_exp_res) = mul_exp.show(info);
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match add_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op1 = add_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &add_exp_str;
-                    }
-                    _ => {}
-                }
-                // 获取第一个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match mul_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op2 = mul_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &mul_exp_str;
-                    }
-                    _ => {}
-                }
+                let add_exp_res = add_exp.show(info);
+                let mul_exp_res = mul_exp.show(info);
                 // 按照不同运算符生成运算表达式
-                match add_op {
-                    AddOp::Add => {
-                        s += &format!("    %{0} = add {1}, {2}\n", info.temp_id, op1, op2);
-                        res = Res::Temp(info.temp_id);
-                        info.temp_id += 1;
-                    }
-                    AddOp::Sub => {
-                        s += &format!("    %{0} = sub {1}, {2}\n", info.temp_id, op1, op2);
-                        res = Res::Temp(info.temp_id);
-                        info.temp_id += 1;
-                    }
-                }
-            }
-            AddExp::MulExp(mul_exp) => {
-                let (sub_str, sub_res) = mul_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
+                let op = match add_op {
+                    AddOp::Add => BinaryOp::Add,
+                    AddOp::Sub => BinaryOp::Sub,
+                };
+                binary_exp(info, op, add_exp_res, mul_exp_res)
             }
+            AddExp::MulExp(mul_exp) => mul_exp.show(info),
         }
-        (s, res)
     }
 }
 
 impl Show for MulExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            MulExp::UnaryExp(unary_exp) => {
-                let (sub_str, sub_res) = unary_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
-            }
+            MulExp::UnaryExp(unary_exp) => unary_exp.show(info),
             MulExp::MulExp((mul_exp, mul_op, unary_exp)) => {
-                let (mul_exp_str, mul_exp_res) = mul_exp.show(info);
-                let (unary_exp_str, unary_exp_res) = unary_exp.show(info);
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match mul_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op1 = mul_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &mul_exp_str;
-                    }
-                    _ => {}
-                }
-                // 获取第一个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match unary_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op2 = unary_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &unary_exp_str;
-                    }
-                    _ => {}
-                }
+                let mul_exp_res = mul_exp.show(info);
+                let unary_exp_res = unary_exp.show(info);
                 // 按照不同运算符生成运算表达式
-                match mul_op {
-                    MulOp::Multiple => {
-                        s += &format!("    %{0} = mul {1}, {2}\n", info.temp_id, op1, op2);
-                        res = Res::Temp(info.temp_id);
-                        info.temp_id += 1;
-                    }
-                    MulOp::Divide => {
-                        s += &format!("    %{0} = div {1}, {2}\n", info.temp_id, op1, op2);
-                        res = Res::Temp(info.temp_id);
-                        info.temp_id += 1;
-                    }
-                    MulOp::Mod => {
-                        s += &format!("    %{0} = mod {1}, {2}\n", info.temp_id, op1, op2);
-                        res = Res::Temp(info.temp_id);
-                        info.temp_id += 1;
-                    }
-                }
+                let op = match mul_op {
+                    MulOp::Multiple => BinaryOp::Mul,
+                    MulOp::Divide => BinaryOp::Div,
+                    MulOp::Mod => BinaryOp::Mod,
+                };
+                binary_exp(info, op, mul_exp_res, unary_exp_res)
             }
         }
-        (s, res)
     }
 }
 
+/// 生成短路求值的逻辑运算：左侧为`short_value`对应的真假时直接得到结果，
+/// 否则结果为右侧是否非零
+fn short_circuit(
+    info: &mut CompilerInfo,
+    lhs: &dyn Show,
+    rhs: &dyn Show,
+    short_value: i32,
+    name: &str,
+) -> Res {
+    let rhs_bb = info.new_bb(&format!("{0}_rhs", name));
+    let short_bb = info.new_bb(&format!("{0}_short", name));
+    let end_bb = info.new_bb(&format!("{0}_end", name));
+    let result = info.alloc(Type::get_i32(), "bool");
+
+    let lhs_res = lhs.show(info);
+    let lhs = info.value(lhs_res);
+    if short_value != 0 {
+        info.branch(lhs, short_bb, rhs_bb);
+    } else {
+        info.branch(lhs, rhs_bb, short_bb);
+    }
+
+    info.enter_bb(rhs_bb);
+    let rhs_res = rhs.show(info);
+    let rhs = info.value(rhs_res);
+    let zero = info.integer(0);
+    let rhs = info.binary(BinaryOp::NotEq, rhs, zero);
+    info.store(rhs, result);
+    info.jump(end_bb);
+
+    info.enter_bb(short_bb);
+    let short = info.integer(short_value);
+    info.store(short, result);
+    info.jump(end_bb);
+
+    info.enter_bb(end_bb);
+    Res::Temp(info.load(result))
+}
+
 impl Show for LOrExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            LOrExp::LAndExp(land_exp) => {
-                let (sub_str, sub_res) = land_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
-            }
+            LOrExp::LAndExp(land_exp) => land_exp.show(info),
             LOrExp::LOrExp((lor_exp, land_exp)) => {
-                let true_flag = info.flag_id;
-                let false_flag = info.flag_id + 1;
-                let end_flag = info.flag_id + 2;
-                info.flag_id += 3;
-                let (lor_exp_str, lor_exp_res) = lor_exp.show(info);
-                let (land_exp_str, land_exp_res) = land_exp.show(info);
-                let ans_val = info.var_id;
-                s += &format!("\t@bool{0} = alloc i32\n", ans_val);
-                info.var_id += 1;
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match lor_exp_res {
-                    Res::Imm(imm) => {
-                        op1 = lor_exp_str;
-                        s += &format!("\tbr {0}, %flag{1}, %flag{2}\n", op1, true_flag, false_flag);
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &lor_exp_str;
-                        s += &format!("\tbr %{0}, %flag{1}, %flag{2}\n", id, true_flag, false_flag);
-                    }
-                    _ => unreachable!(),
-                }
-                s += &format!("%flag{0}:\n", false_flag);
-
-                // 获取第二个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match land_exp_res {
-                    Res::Imm(imm) => {
-                        op2 = land_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &land_exp_str;
-                    }
-                    _ => unreachable!(),
-                }
-                s += &format!("\t%{0} = or {1}, {2}\n", info.temp_id, op1, op2);
-                s += &format!("\t%{0} = ne %{1}, {2}\n", info.temp_id + 1, info.temp_id, 0);
-                s += &format!("\tstore %{0}, @bool{1}\n", info.temp_id + 1, ans_val);
-                info.temp_id += 2;
-
-                s += &format!("\tjump %flag{0}\n", end_flag);
-                s += &format!("%flag{0}:\n", true_flag);
-                s += &format!("\tstore 1, @bool{0}\n", ans_val);
-                s += &format!("\tjump %flag{0}\n", end_flag);
-                s += &format!("%flag{0}:\n", end_flag);
-                s += &format!("\t%{0} = load @bool{1}\n", info.temp_id, ans_val);
-                res = Res::Temp(info.temp_id);
-                info.temp_id += 1;
+                short_circuit(info, lor_exp.as_ref(), land_exp.as_ref(), 1, "lor")
             }
         }
-        (s, res)
     }
 }
 
 impl Show for LAndExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            LAndExp::EqExp(land_exp) => {
-                let (sub_str, sub_res) = land_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
-            }
+            LAndExp::EqExp(eq_exp) => eq_exp.show(info),
             LAndExp::LAndExp((land_exp, eq_exp)) => {
-                let true_flag = info.flag_id;
-                let false_flag = info.flag_id + 1;
-                let end_flag = info.flag_id + 2;
-                info.flag_id += 3;
-                let (land_exp_str, land_exp_res) = land_exp.show(info);
-                let (eq_exp_str, eq_exp_res) = eq_exp.show(info);
-                let ans_val = info.var_id;
-                s += &format!("\t@bool{0} = alloc i32\n", ans_val);
-                info.var_id += 1;
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match land_exp_res {
-                    Res::Imm(imm) => {
-                        op1 = land_exp_str;
-                        s += &format!("\tbr {0}, %flag{1}, %flag{2}\n", op1, false_flag, true_flag);
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &land_exp_str;
-                        s += &format!("\tbr %{0}, %flag{1}, %flag{2}\n", id, false_flag, true_flag);
-                    }
-                    _ => unreachable!(),
-                }
-                s += &format!("%flag{0}:\n", false_flag);
-
-                // 获取第二个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match eq_exp_res {
-                    Res::Imm(imm) => {
-                        op2 = eq_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &eq_exp_str;
-                    }
-                    _ => unreachable!(),
-                }
-
-                s += &format!("\t%{0} = ne {1}, {2}\n", info.temp_id, op1, 0);
-                s += &format!("\t%{0} = ne {1}, {2}\n", info.temp_id + 1, op2, 0);
-                s += &format!(
-                    "\t%{0} = and %{1}, %{2}\n",
-                    info.temp_id + 2,
-                    info.temp_id + 1,
-                    info.temp_id
-                );
-                s += &format!("\tstore %{0}, @bool{1}\n", info.temp_id + 2, ans_val);
-                info.temp_id += 3;
-
-                s += &format!("\tjump %flag{0}\n", end_flag);
-                s += &format!("%flag{0}:\n", true_flag);
-                s += &format!("\tstore 0, @bool{0}\n", ans_val);
-                s += &format!("\tjump %flag{0}\n", end_flag);
-                s += &format!("%flag{0}:\n", end_flag);
-                s += &format!("\t%{0} = load @bool{1}\n", info.temp_id, ans_val);
-                res = Res::Temp(info.temp_id);
-                info.temp_id += 1;
+                short_circuit(info, land_exp.as_ref(), eq_exp.as_ref(), 0, "land")
             }
         }
-        (s, res)
     }
 }
 
 impl Show for EqExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            EqExp::RelExp(rel_exp) => {
-                let (sub_str, sub_res) = rel_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
-            }
+            EqExp::RelExp(rel_exp) => rel_exp.show(info),
             EqExp::EqExp((eq_exp, comp_op, rel_exp)) => {
-                let (eq_exp_str, eq_exp_res) = eq_exp.show(info);
-                let (rel_exp_str, rel_exp_res) = rel_exp.show(info);
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match eq_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op1 = eq_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &eq_exp_str;
-                    }
-                    _ => {}
-                }
-                // 获取第一个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match rel_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op2 = rel_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &rel_exp_str;
-                    }
-                    _ => {}
-                }
-
-                let mut op = "".to_string();
-                match comp_op {
-                    CmpOp::Eq => op += "eq",
-                    CmpOp::NEq => op += "ne",
+                let eq_exp_res = eq_exp.show(info);
+                let rel_exp_res = rel_exp.show(info);
+                let op = match comp_op {
+                    CmpOp::Eq => BinaryOp::Eq,
+                    CmpOp::NEq => BinaryOp::NotEq,
                     _ => unreachable!(),
-                }
-                s += &format!("    %{0} = {1} {2}, {3}\n", info.temp_id, op, op1, op2);
-                res = Res::Temp(info.temp_id);
-                info.temp_id += 1;
+                };
+                binary_exp(info, op, eq_exp_res, rel_exp_res)
             }
         }
-        (s, res)
     }
 }
 
 impl Show for RelExp {
-    fn show(&self, info: &mut CompilerInfo) -> (String, Res) {
-        let mut s = "".to_string();
-        let mut res = Res::Nothing;
+    fn show(&self, info: &mut CompilerInfo) -> Res {
         match self {
-            RelExp::AddExp(add_exp) => {
-                let (sub_str, sub_res) = add_exp.show(info);
-                s += &sub_str;
-                res = sub_res;
-            }
+            RelExp::AddExp(add_exp) => add_exp.show(info),
             RelExp::CompExp((rel_exp, comp_op, add_exp)) => {
-                let (rel_exp_str, rel_exp_res) = rel_exp.show(info);
-                let (add_exp_str, add_exp_res) = add_exp.show(info);
-                // 获取第一个操作数的字符串表示
-                let mut op1 = "".to_string();
-                match rel_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op1 = rel_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op1 = format!("%{}", id);
-                        s += &rel_exp_str;
-                    }
-                    _ => {}
-                }
-                // 获取第一个操作数的字符串表示
-                let mut op2 = "".to_string();
-                match add_exp_res {
-                    Res::Nothing => {}
-                    Res::Imm(imm) => {
-                        op2 = add_exp_str;
-                    }
-                    Res::Temp(id) => {
-                        op2 = format!("%{}", id);
-                        s += &add_exp_str;
-                    }
-                    _ => {}
-                }
-
-                let mut op = "".to_string();
-                match comp_op {
-                    CmpOp::Less => op += "lt",
-                    CmpOp::Grate => op += "gt",
-                    CmpOp::LessEq => op += "le",
-                    CmpOp::GrateEq => op += "ge",
+                let rel_exp_res = rel_exp.show(info);
+                let add_exp_res = add_exp.show(info);
+                let op = match comp_op {
+                    CmpOp::Less => BinaryOp::Lt,
+                    CmpOp::Grate => BinaryOp::Gt,
+                    CmpOp::LessEq => BinaryOp::Le,
+                    CmpOp::GrateEq => BinaryOp::Ge,
                     _ => unreachable!(),
-                }
-                s += &format!("    %{0} = {1} {2}, {3}\n", info.temp_id, op, op1, op2);
-                res = Res::Temp(info.temp_id);
-                info.temp_id += 1;
+                };
+                binary_exp(info, op, rel_exp_res, add_exp_res)
             }
         }
-        (s, res)
     }
 }
 
 trait GlobalShow {
-    fn global_show(&self, info: &mut CompilerInfo) -> String;
+    fn global_show(&self, info: &mut CompilerInfo);
 }
 
 impl GlobalShow for Decl {
-    fn global_show(&self, info: &mut CompilerInfo) -> String {
-        let mut s = "".to_string();
+    fn global_show(&self, info: &mut CompilerInfo) {
         match self {
-            Decl::ConstDecl(const_decl) => {
-                s += &const_decl.global_show(info);
-            }
-            Decl::VarDecl(var_decl) => {
-                s += &var_decl.global_show(info);
-            }
+            Decl::ConstDecl(const_decl) => const_decl.global_show(info),
+            Decl::VarDecl(var_decl) => var_decl.global_show(info),
         }
-        s
     }
 }
 
 impl GlobalShow for ConstDecl {
-    fn global_show(&self, info: &mut CompilerInfo) -> String {
-        let mut s = "".to_string();
+    fn global_show(&self, info: &mut CompilerInfo) {
         for const_def in &self.const_defs {
-            s += &const_def.global_show(info);
+            const_def.global_show(info);
         }
-        s
     }
 }
 
+/// 生成全局变量
+fn global_alloc(info: &mut CompilerInfo, ident: &str, init: Value) -> Value {
+    let alloc = info.program.new_value().global_alloc(init);
+    info.program
+        .set_value_name(alloc, Some(format!("@{0}", ident)));
+    alloc
+}
+
 impl GlobalShow for ConstDef {
-    fn global_show(&self, info: &mut CompilerInfo) -> String {
-        let mut ret_s = "".to_string();
-        let mut calculate_info = info
-            .clone()
-            .vars_table
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect();
+    fn global_show(&self, info: &mut CompilerInfo) {
+        let id = self.symbol.unwrap();
         // 判断该定义是常量定义还是数组定义
-        if self.dims.len() == 0 {
-            match info.vars_table.get_mut(&self.ident) {
-                None => {
-                    let value = self.const_init_val.calculate(&mut calculate_info);
-                    info.vars_table.insert(
-                        self.ident.clone(),
-                        (Variable::ConstINT(value), info.field_depth),
-                    );
-                }
-                Some(value) => {
-                    panic!("define a global const variable more than once!");
-                }
-            }
+        if let SymbolKind::Const(value) = info.symbols.symbol(id).kind {
+            info.vars.insert(id, Variable::ConstINT(value));
         } else {
-            match info.vars_table.get_mut(&self.ident) {
-                None => {
-                    let mut dims: Vec<i32> = Vec::new();
-                    let mut array_str = "i32".to_string();
-                    for dim in &self.dims {
-                        dims.push(dim.calculate(&mut calculate_info));
-                    }
-                    for dim in dims.iter().rev() {
-                        array_str = format!("[{0}, {1}]", array_str, dim);
-                    }
-                    let mut val_idx = 0;
-                    let init_str = self
-                        .const_init_val
-                        .global_array_init(info, &dims, &mut val_idx);
-                    ret_s += &format!(
-                        "global @{0}_global = alloc {1}, {2}\n",
-                        self.ident, array_str, init_str
-                    );
-                    let new_var = Variable::Array(format!("@{0}_global", self.ident));
-                    info.vars_table.insert(self.ident.clone(), (new_var, 0));
-                }
-                Some(value) => {
-                    panic!("define a global const variable more than once!");
-                }
-            }
+            let dims = info.dims(id);
+            let mut val_idx = 0;
+            info.decl_span = self.span;
+            let init = self
+                .const_init_val
+                .global_array_init(info, &dims, &mut val_idx);
+            let array = global_alloc(info, &self.ident, init);
+            info.vars.insert(id, Variable::Array(array));
         }
-        ret_s
     }
 }
 
 impl GlobalShow for VarDecl {
-    fn global_show(&self, info: &mut CompilerInfo) -> String {
-        let mut s = "".to_string();
+    fn global_show(&self, info: &mut CompilerInfo) {
         for var_def in &self.var_defs {
-            s += &var_def.global_show(info);
+            var_def.global_show(info);
         }
-        s
     }
 }
 
 impl GlobalShow for VarDef {
-    fn global_show(&self, info: &mut CompilerInfo) -> String {
-        let mut s = "".to_string();
-        match self {
-            VarDef::Decl((var_name, dims)) => {
-                let mut calculate_info = info
-                    .clone()
-                    .vars_table
-                    .into_iter()
-                    .map(|(k, (v, _))| (k, v))
-                    .collect();
-                let mut dims_i32: Vec<i32> = Vec::new();
-                let mut array_str = "i32".to_string();
-                for dim in dims {
-                    dims_i32.push(dim.calculate(&mut calculate_info));
-                }
-                for dim in dims_i32.iter().rev() {
-                    array_str = format!("[{0}, {1}]", array_str, dim);
-                }
-
-                s += &format!(
-                    "global @{0}_global = alloc {1}, zeroinit\n",
-                    var_name, array_str
-                );
-                if dims.len() == 0 {
-                    info.vars_table.insert(
-                        var_name.clone(),
-                        (Variable::INT(format!("@{0}_global", var_name)), 0),
-                    );
-                } else {
-                    info.vars_table.insert(
-                        var_name.clone(),
-                        (Variable::Array(format!("@{0}_global", var_name)), 0),
-                    );
-                }
+    fn global_show(&self, info: &mut CompilerInfo) {
+        let (var_name, init_val, span, id) = match self {
+            VarDef::Def((var_name, _, init_val, span, id)) => (var_name, Some(init_val), span, id),
+            VarDef::Decl((var_name, _, span, id)) => (var_name, None, span, id),
+        };
+        let id = id.unwrap();
+        info.decl_span = *span;
+        let dims = info.dims(id);
+
+        let init = match init_val {
+            None => info.program.new_value().zero_init(array_type(&dims)),
+            Some(InitVal::Exp(exp)) if dims.is_empty() => {
+                let value = info.const_value(exp);
+                info.program.new_value().integer(value)
             }
-            VarDef::Def((var_name, dims, init_val)) => {
-                let mut calculate_info = info
-                    .clone()
-                    .vars_table
-                    .into_iter()
-                    .map(|(k, (v, _))| (k, v))
-                    .collect();
-
-                if dims.len() == 0 {
-                    let mut init_str = "".to_string();
-                    match init_val {
-                        InitVal::Exp(exp) => {
-                            init_str += &exp.calculate(&mut calculate_info).to_string();
-                        }
-                        InitVal::Array(array) => unreachable!(),
-                    }
-                    s += &format!("global @{0}_global = alloc i32, {1}\n", var_name, init_str);
-                    info.vars_table.insert(
-                        var_name.clone(),
-                        (Variable::INT(format!("@{0}_global", var_name)), 0),
-                    );
-                } else {
-                    let mut dims_i32: Vec<i32> = Vec::new();
-                    let mut array_str = "i32".to_string();
-                    for dim in dims {
-                        dims_i32.push(dim.calculate(&mut calculate_info));
-                    }
-                    for dim in dims_i32.iter().rev() {
-                        array_str = format!("[{0}, {1}]", array_str, dim);
-                    }
-
-                    match init_val {
-                        InitVal::Exp(exp) => unreachable!(),
-                        InitVal::Array(array) => {
-                            let mut val_idx = 0;
-                            let init_str =
-                                &init_val.global_array_init(info, &dims_i32, &mut val_idx);
-                            s += &format!(
-                                "global @{0}_global = alloc {1}, {2}\n",
-                                var_name, array_str, init_str
-                            );
-                            let new_var = Variable::Array(format!("@{0}_global", var_name));
-                            info.vars_table.insert(var_name.clone(), (new_var, 0));
-                        }
-                    }
-                }
+            Some(init_val) => {
+                let mut val_idx = 0;
+                init_val.global_array_init(info, &dims, &mut val_idx)
             }
-        }
-        s
+        };
+        let alloc = global_alloc(info, var_name, init);
+        let var = if dims.is_empty() {
+            Variable::INT(alloc)
+        } else {
+            Variable::Array(alloc)
+        };
+        info.vars.insert(id, var);
     }
 }
 
+/// An initializer list of an array, whose elements are either constant
+/// expressions or nested lists.
 trait ArrayInit {
-    fn global_array_init(
-        &self,
-        info: &mut CompilerInfo,
-        dims: &Vec<i32>,
-        val_idx: &mut usize,
-    ) -> String;
-    fn local_array_init(
-        &self,
-        info: &mut CompilerInfo,
-        dims: &Vec<i32>,
-        val_idx: &mut usize,
-        base_ptr: String,
-    ) -> String;
-}
-
-impl ArrayInit for ConstInitVal {
-    fn global_array_init(
-        &self,
-        info: &mut CompilerInfo,
-        dims: &Vec<i32>,
-        val_idx: &mut usize,
-    ) -> String {
-        let mut init_s = "".to_string();
-        let mut calculate_info: HashMap<String, Variable> = info
-            .clone()
-            .vars_table
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect();
-        if dims.len() <= 0 {
-            panic!("Wrong array dimension length!!");
-        }
+    /// 初始化列表中的元素：单个表达式或嵌套的列表
+    fn elements(&self) -> Option<&Vec<Self>>
+    where
+        Self: Sized;
+    /// 单个表达式元素的值，全局初始化时必须是常量
+    fn element_value(&self, info: &mut CompilerInfo, global: bool) -> Value;
+
+    fn global_array_init(&self, info: &mut CompilerInfo, dims: &[i32], val_idx: &mut usize) -> Value
+    where
+        Self: Sized,
+    {
+        let array = match self.elements() {
+            Some(array) => array,
+            None => {
+                info.error(
+                    ErrorCode::InvalidInitializer,
+                    info.decl_span,
+                    "cannot initialize an array with a scalar".to_string(),
+                );
+                return global_zero_array(info, dims);
+            }
+        };
+        let mut elems = Vec::new();
         // 一维数组
         if dims.len() == 1 {
-            init_s += "{";
-            let mut flag = 0;
-            match self {
-                ConstInitVal::Exp(exp) => {
-                    panic!("Try to assign an i32 to array!");
-                }
-                ConstInitVal::Array(array) => {
-                    while (*val_idx + flag as usize) < array.len() {
-                        if flag >= dims[0] {
-                            break;
-                        }
-                        match &array[*val_idx + flag as usize] {
-                            ConstInitVal::Exp(exp) => {
-                                let init_val = exp.calculate(&mut calculate_info).to_string();
-                                if flag > 0 {
-                                    init_s += &format!(", {0}", init_val);
-                                } else {
-                                    init_s += &format!("{0}", init_val);
-                                }
-                                flag += 1;
-                            }
-                            ConstInitVal::Array(array) => {
-                                panic!("Try to assign an array to i32!");
-                            }
-                        }
-                    }
-                    for i in 0..dims[0] - flag {
-                        if flag > 0 {
-                            init_s += ", 0";
-                        } else {
-                            init_s += "0";
-                            flag += 1;
-                        }
-                    }
-                    init_s += "}";
+            while *val_idx < array.len() && (elems.len() as i32) < dims[0] {
+                if array[*val_idx].elements().is_some() {
+                    info.error(
+                        ErrorCode::InvalidInitializer,
+                        info.decl_span,
+                        "too many braces around scalar initializer".to_string(),
+                    );
+                    break;
                 }
+                elems.push(array[*val_idx].element_value(info, true));
+                *val_idx += 1;
+            }
+            while (elems.len() as i32) < dims[0] {
+                elems.push(info.program.new_value().integer(0));
             }
-            *val_idx += flag as usize;
         }
         //多维数组
         else {
-            init_s += "{";
-            for i in 0..dims[0] {
-                if i > 0 {
-                    init_s += ", ";
-                }
-                match self {
-                    ConstInitVal::Exp(exp) => {
-                        panic!("Try to assign an i32 to array!");
-                    }
-                    ConstInitVal::Array(array) => {
-                        if *val_idx >= array.len() {
-                            init_s += &global_zero_array(&dims[1..].to_vec());
-                            continue;
-                        }
-                        match &array[*val_idx] {
-                            ConstInitVal::Exp(_exp) => {
-                                init_s +=
-                                    &self.global_array_init(info, &dims[1..].to_vec(), val_idx);
-                            }
-                            ConstInitVal::Array(_array) => {
-                                let mut new_idx = 0;
-                                init_s += &array[*val_idx].global_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    &mut new_idx,
-                                );
-                                *val_idx += 1;
-                            }
-                        }
-                    }
+            for _ in 0..dims[0] {
+                if *val_idx >= array.len() {
+                    elems.push(global_zero_array(info, &dims[1..]));
+                } else if array[*val_idx].elements().is_none() {
+                    elems.push(self.global_array_init(info, &dims[1..], val_idx));
+                } else {
+                    let mut new_idx = 0;
+                    elems.push(array[*val_idx].global_array_init(info, &dims[1..], &mut new_idx));
+                    *val_idx += 1;
                 }
             }
-            init_s += "}";
         }
-        init_s
+        info.program.new_value().aggregate(elems)
     }
 
     fn local_array_init(
         &self,
         info: &mut CompilerInfo,
-        dims: &Vec<i32>,
+        dims: &[i32],
         val_idx: &mut usize,
-        base_ptr: String,
-    ) -> String {
-        let mut init_s = "".to_string();
-        let mut calculate_info: HashMap<String, Variable> = info
-            .clone()
-            .vars_table
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect();
-        if dims.len() <= 0 {
-            panic!("Wrong array dimension length!!");
-        }
+        base_ptr: Value,
+    ) where
+        Self: Sized,
+    {
+        let array = match self.elements() {
+            Some(array) => array,
+            None => {
+                info.error(
+                    ErrorCode::InvalidInitializer,
+                    info.decl_span,
+                    "cannot initialize an array with a scalar".to_string(),
+                );
+                return;
+            }
+        };
         // 一维数组
         if dims.len() == 1 {
             let mut flag = 0;
-            match self {
-                ConstInitVal::Exp(exp) => {
-                    panic!("Try to assign an i32 to array!");
-                }
-                ConstInitVal::Array(array) => {
-                    while (*val_idx + flag as usize) < array.len() {
-                        if flag >= dims[0] {
-                            break;
-                        }
-                        match &array[*val_idx + flag as usize] {
-                            ConstInitVal::Exp(exp) => {
-                                let init_val = exp.calculate(&mut calculate_info).to_string();
-                                init_s += &format!(
-                                    "\t%{0} = getelemptr {1}, {2}\n\tstore {3}, %{0}\n",
-                                    info.temp_id, base_ptr, flag, init_val
-                                );
-                                info.temp_id += 1;
-                                flag += 1;
-                            }
-                            ConstInitVal::Array(array) => {
-                                panic!("Try to assign an array to i32!");
-                            }
-                        }
-                    }
-                    for i in 0..dims[0] - flag {
-                        init_s += &format!(
-                            "\t%{0} = getelemptr {1}, {2}\n\tstore 0, %{0}\n",
-                            info.temp_id, base_ptr, flag
-                        );
-                        info.temp_id += 1;
-                        flag += 1;
-                    }
+            while *val_idx < array.len() && flag < dims[0] {
+                if array[*val_idx].elements().is_some() {
+                    info.error(
+                        ErrorCode::InvalidInitializer,
+                        info.decl_span,
+                        "too many braces around scalar initializer".to_string(),
+                    );
+                    break;
                 }
+                let value = array[*val_idx].element_value(info, false);
+                let index = info.integer(flag);
+                let elem_ptr = info.get_elem_ptr(base_ptr, index);
+                info.store(value, elem_ptr);
+                *val_idx += 1;
+                flag += 1;
+            }
+            while flag < dims[0] {
+                let index = info.integer(flag);
+                let elem_ptr = info.get_elem_ptr(base_ptr, index);
+                let zero = info.integer(0);
+                info.store(zero, elem_ptr);
+                flag += 1;
             }
-            *val_idx += flag as usize;
         }
         //多维数组
         else {
             for i in 0..dims[0] {
-                match self {
-                    ConstInitVal::Exp(exp) => {
-                        panic!("Try to assign an i32 to array!");
-                    }
-                    ConstInitVal::Array(array) => {
-                        if *val_idx >= array.len() {
-                            init_s += &format!(
-                                "\t%{0} = getelemptr {1}, {2}\n",
-                                info.temp_id, base_ptr, i
-                            );
-                            let sub_base = format!("%{0}", info.temp_id);
-                            info.temp_id += 1;
-                            init_s += &local_zero_array(info, &dims[1..].to_vec(), sub_base);
-                            continue;
-                        }
-                        match &array[*val_idx] {
-                            ConstInitVal::Exp(_exp) => {
-                                init_s += &format!(
-                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                    info.temp_id, base_ptr, i
-                                );
-                                let sub_base = format!("%{0}", info.temp_id);
-                                info.temp_id += 1;
-                                init_s += &self.local_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    val_idx,
-                                    sub_base,
-                                );
-                            }
-                            ConstInitVal::Array(_array) => {
-                                let mut new_idx = 0;
-                                init_s += &format!(
-                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                    info.temp_id, base_ptr, i
-                                );
-                                let sub_base = format!("%{0}", info.temp_id);
-                                info.temp_id += 1;
-                                init_s += &array[*val_idx].local_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    &mut new_idx,
-                                    sub_base,
-                                );
-                                *val_idx += 1;
-                            }
-                        }
-                    }
+                let index = info.integer(i);
+                let sub_base = info.get_elem_ptr(base_ptr, index);
+                if *val_idx >= array.len() {
+                    local_zero_array(info, &dims[1..], sub_base);
+                } else if array[*val_idx].elements().is_none() {
+                    self.local_array_init(info, &dims[1..], val_idx, sub_base);
+                } else {
+                    let mut new_idx = 0;
+                    array[*val_idx].local_array_init(info, &dims[1..], &mut new_idx, sub_base);
+                    *val_idx += 1;
                 }
             }
         }
-        init_s
     }
 }
 
-impl ArrayInit for InitVal {
-    fn global_array_init(
-        &self,
-        info: &mut CompilerInfo,
-        dims: &Vec<i32>,
-        val_idx: &mut usize,
-    ) -> String {
-        let mut init_s = "".to_string();
-        let mut calculate_info: HashMap<String, Variable> = info
-            .clone()
-            .vars_table
-            .into_iter()
-            .map(|(k, (v, _))| (k, v))
-            .collect();
-        if dims.len() <= 0 {
-            panic!("Wrong array dimension length!!");
-        }
-        // 一维数组
-        if dims.len() == 1 {
-            init_s += "{";
-            let mut flag = 0;
-            match self {
-                InitVal::Exp(exp) => {
-                    panic!("Try to assign an i32 to array!");
-                }
-                InitVal::Array(array) => {
-                    while (*val_idx + flag as usize) < array.len() {
-                        if flag >= dims[0] {
-                            break;
-                        }
-                        match &array[*val_idx + flag as usize] {
-                            InitVal::Exp(exp) => {
-                                let init_val = exp.calculate(&mut calculate_info).to_string();
-                                if flag > 0 {
-                                    init_s += &format!(", {0}", init_val);
-                                } else {
-                                    init_s += &format!("{0}", init_val);
-                                }
-                                flag += 1;
-                            }
-                            InitVal::Array(array) => {
-                                panic!("Try to assign an array to i32!");
-                            }
-                        }
-                    }
-                    for i in 0..dims[0] - flag {
-                        if flag > 0 {
-                            init_s += ", 0";
-                        } else {
-                            init_s += "0";
-                            flag += 1;
-                        }
-                    }
-                    init_s += "}";
-                }
-            }
-            *val_idx += flag as usize;
+impl ArrayInit for ConstInitVal {
+    fn elements(&self) -> Option<&Vec<Self>> {
+        match self {
+            ConstInitVal::Exp(_) => None,
+            ConstInitVal::Array(array) => Some(array),
         }
-        //多维数组
-        else {
-            init_s += "{";
-            for i in 0..dims[0] {
-                if i > 0 {
-                    init_s += ", ";
-                }
-                match self {
-                    InitVal::Exp(exp) => {
-                        panic!("Try to assign an i32 to array!");
-                    }
-                    InitVal::Array(array) => {
-                        if *val_idx >= array.len() {
-                            init_s += &global_zero_array(&dims[1..].to_vec());
-                            continue;
-                        }
-                        match &array[*val_idx] {
-                            InitVal::Exp(_exp) => {
-                                init_s +=
-                                    &self.global_array_init(info, &dims[1..].to_vec(), val_idx);
-                            }
-                            InitVal::Array(_array) => {
-                                let mut new_idx = 0;
-                                init_s += &array[*val_idx].global_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    &mut new_idx,
-                                );
-                                *val_idx += 1;
-                            }
-                        }
-                    }
-                }
-            }
-            init_s += "}";
+    }
+
+    fn element_value(&self, info: &mut CompilerInfo, global: bool) -> Value {
+        let value = match self {
+            ConstInitVal::Exp(const_exp) => info.const_value(&const_exp.exp),
+            ConstInitVal::Array(_) => unreachable!(),
+        };
+        if global {
+            info.program.new_value().integer(value)
+        } else {
+            info.integer(value)
         }
-        init_s
     }
+}
 
-    fn local_array_init(
-        &self,
-        info: &mut CompilerInfo,
-        dims: &Vec<i32>,
-        val_idx: &mut usize,
-        base_ptr: String,
-    ) -> String {
-        let mut init_s = "".to_string();
-        if dims.len() <= 0 {
-            panic!("Wrong array dimension length!!");
+impl ArrayInit for InitVal {
+    fn elements(&self) -> Option<&Vec<Self>> {
+        match self {
+            InitVal::Exp(_) => None,
+            InitVal::Array(array) => Some(array),
         }
-        // 一维数组
-        if dims.len() == 1 {
-            let mut flag = 0;
-            match self {
-                InitVal::Exp(exp) => {
-                    panic!("Try to assign an i32 to array!");
-                }
-                InitVal::Array(array) => {
-                    while (*val_idx + flag as usize) < array.len() {
-                        if flag >= dims[0] {
-                            break;
-                        }
-                        match &array[*val_idx + flag as usize] {
-                            InitVal::Exp(exp) => {
-                                let (init_val, init_res) = exp.show(info);
-                                match init_res {
-                                    Res::Imm(imm) => {
-                                        init_s += &format!(
-                                            "\t%{0} = getelemptr {1}, {2}\n",
-                                            info.temp_id, base_ptr, flag
-                                        );
-                                        init_s +=
-                                            &format!("\tstore {0}, %{1}\n", imm, info.temp_id);
-                                        info.temp_id += 1;
-                                    }
-                                    Res::Temp(temp) => {
-                                        init_s += &init_val;
-                                        init_s += &format!(
-                                            "\t%{0} = getelemptr {1}, {2}\n",
-                                            info.temp_id, base_ptr, flag
-                                        );
-                                        init_s +=
-                                            &format!("\tstore %{0}, %{1}\n", temp, info.temp_id);
-                                        info.temp_id += 1;
-                                    }
-                                    _ => unreachable!(),
-                                }
-                                flag += 1;
-                            }
-                            InitVal::Array(array) => {
-                                panic!("Try to assign an array to i32!");
-                            }
-                        }
-                    }
-                    for i in 0..dims[0] - flag {
-                        init_s += &format!(
-                            "\t%{0} = getelemptr {1}, {2}\n",
-                            info.temp_id, base_ptr, flag
-                        );
-                        init_s += &format!("\tstore 0, %{0}\n", info.temp_id);
-                        info.temp_id += 1;
-                        flag += 1;
-                    }
-                }
+    }
+
+    fn element_value(&self, info: &mut CompilerInfo, global: bool) -> Value {
+        match self {
+            InitVal::Exp(exp) if global => {
+                let value = info.const_value(exp);
+                info.program.new_value().integer(value)
             }
-            *val_idx += flag as usize;
-        }
-        //多维数组
-        else {
-            for i in 0..dims[0] {
-                match self {
-                    InitVal::Exp(exp) => {
-                        panic!("Try to assign an i32 to array!");
-                    }
-                    InitVal::Array(array) => {
-                        if *val_idx >= array.len() {
-                            init_s += &format!(
-                                "\t%{0} = getelemptr {1}, {2}\n",
-                                info.temp_id, base_ptr, i
-                            );
-                            let sub_base = format!("%{0}", info.temp_id);
-                            info.temp_id += 1;
-                            init_s += &local_zero_array(info, &dims[1..].to_vec(), sub_base);
-                            continue;
-                        }
-                        match &array[*val_idx] {
-                            InitVal::Exp(_exp) => {
-                                init_s += &format!(
-                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                    info.temp_id, base_ptr, i
-                                );
-                                let sub_base = format!("%{0}", info.temp_id);
-                                info.temp_id += 1;
-                                init_s += &self.local_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    val_idx,
-                                    sub_base,
-                                );
-                            }
-                            InitVal::Array(_array) => {
-                                init_s += &format!(
-                                    "\t%{0} = getelemptr {1}, {2}\n",
-                                    info.temp_id, base_ptr, i
-                                );
-                                let sub_base = format!("%{0}", info.temp_id);
-                                info.temp_id += 1;
-
-                                let mut new_idx = 0;
-                                init_s += &array[*val_idx].local_array_init(
-                                    info,
-                                    &dims[1..].to_vec(),
-                                    &mut new_idx,
-                                    sub_base,
-                                );
-                                *val_idx += 1;
-                            }
-                        }
-                    }
-                }
+            InitVal::Exp(exp) => {
+                let res = exp.show(info);
+                info.value(res)
             }
+            InitVal::Array(_) => unreachable!(),
         }
-        init_s
     }
 }
@@ -0,0 +1,542 @@
+//! Generic trees of named nodes, written and read as JSON or S-expressions.
+//!
+//! A [`Value`] is null, an integer, a string, a list or a [`Node`], which has
+//! a kind and named fields in a fixed order. Both syntaxes express exactly
+//! these values:
+//!
+//! | value   | JSON                             | S-expression         |
+//! |---------|----------------------------------|----------------------|
+//! | null    | `null`                           | `nil`                |
+//! | integer | `-12`                            | `-12`                |
+//! | string  | `"main"`                         | `"main"`             |
+//! | list    | `[1, 2]`                         | `(1 2)`              |
+//! | node    | `{"kind": "Number", "value": 1}` | `(Number :value 1)`  |
+//!
+//! In S-expressions a parenthesized form is a node when it starts with a
+//! symbol other than `nil`, and `;` starts a comment. Strings use the escapes
+//! of JSON in both syntaxes. Readers report errors as diagnostics pointing
+//! into the text they read, and reject values nested more than [`MAX_DEPTH`]
+//! levels deep rather than exhausting the stack.
+
+use crate::diagnostics::{Diagnostic, ErrorCode, Span};
+use std::fmt::Write;
+
+/// Deepest nesting of values the readers accept.
+pub const MAX_DEPTH: usize = 1000;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Null,
+    Int(i64),
+    Str(String),
+    List(Vec<Value>),
+    Node(Node),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Node {
+    pub kind: String,
+    pub fields: Vec<(String, Value)>,
+    /// 读入的节点在文本中的位置，用于报错
+    pub span: Span,
+}
+
+impl Node {
+    pub fn new(kind: &str) -> Self {
+        Node {
+            kind: kind.to_string(),
+            fields: Vec::new(),
+            span: Span::default(),
+        }
+    }
+
+    /// `self` with the field `name` added after the others.
+    pub fn field(mut self, name: &str, value: Value) -> Self {
+        self.fields.push((name.to_string(), value));
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+impl Value {
+    fn is_scalar(&self) -> bool {
+        matches!(self, Value::Null | Value::Int(_) | Value::Str(_))
+    }
+
+    /// Whether the value is printed on a single line: it has no nodes or
+    /// lists nested in it besides lists of scalars.
+    fn is_flat(&self) -> bool {
+        let flat_field = |value: &Value| match value {
+            Value::List(values) => values.iter().all(Value::is_scalar),
+            _ => value.is_scalar(),
+        };
+        match self {
+            Value::Node(node) => node.fields.iter().all(|(_, value)| flat_field(value)),
+            _ => flat_field(self),
+        }
+    }
+}
+
+/// 每层缩进的空格数
+const INDENT: usize = 2;
+
+fn newline(out: &mut String, indent: usize) {
+    out.push('\n');
+    out.push_str(&" ".repeat(indent));
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => write!(out, "\\u{0:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// Write `value` as JSON.
+pub fn to_json(value: &Value) -> String {
+    let mut out = String::new();
+    write_json(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+fn write_json(out: &mut String, value: &Value, indent: usize) {
+    // 只含标量的节点和列表写在一行
+    let flat = value.is_flat();
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Int(value) => write!(out, "{0}", value).unwrap(),
+        Value::Str(s) => write_string(out, s),
+        Value::List(values) => {
+            out.push('[');
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                    if flat {
+                        out.push(' ');
+                    }
+                }
+                if !flat {
+                    newline(out, indent + INDENT);
+                }
+                write_json(out, value, indent + INDENT);
+            }
+            if !flat && !values.is_empty() {
+                newline(out, indent);
+            }
+            out.push(']');
+        }
+        Value::Node(node) => {
+            out.push('{');
+            let kind = Value::Str(node.kind.clone());
+            let fields = [("kind", &kind)].into_iter().chain(
+                node.fields
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value)),
+            );
+            for (i, (name, value)) in fields.enumerate() {
+                if i > 0 {
+                    out.push(',');
+                    if flat {
+                        out.push(' ');
+                    }
+                }
+                if !flat {
+                    newline(out, indent + INDENT);
+                }
+                write_string(out, name);
+                out.push_str(": ");
+                write_json(out, value, indent + INDENT);
+            }
+            if !flat {
+                newline(out, indent);
+            }
+            out.push('}');
+        }
+    }
+}
+
+/// Write `value` as an S-expression.
+pub fn to_sexp(value: &Value) -> String {
+    let mut out = String::new();
+    write_sexp(&mut out, value, 0);
+    out.push('\n');
+    out
+}
+
+fn write_sexp(out: &mut String, value: &Value, indent: usize) {
+    let flat = value.is_flat();
+    match value {
+        Value::Null => out.push_str("nil"),
+        Value::Int(value) => write!(out, "{0}", value).unwrap(),
+        Value::Str(s) => write_string(out, s),
+        Value::List(values) => {
+            out.push('(');
+            for (i, value) in values.iter().enumerate() {
+                if !flat {
+                    newline(out, indent + INDENT);
+                } else if i > 0 {
+                    out.push(' ');
+                }
+                write_sexp(out, value, indent + INDENT);
+            }
+            out.push(')');
+        }
+        Value::Node(node) => {
+            out.push('(');
+            out.push_str(&node.kind);
+            for (name, value) in &node.fields {
+                if flat {
+                    out.push(' ');
+                } else {
+                    newline(out, indent + INDENT);
+                }
+                write!(out, ":{0} ", name).unwrap();
+                write_sexp(out, value, indent + INDENT);
+            }
+            out.push(')');
+        }
+    }
+}
+
+/// A cursor over the text being read.
+struct Reader<'a> {
+    text: &'a str,
+    pos: usize,
+    /// 是否为S表达式，S表达式中`;`开始注释
+    sexp: bool,
+    /// 当前所在的嵌套层数
+    depth: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, start: usize, message: impl Into<String>) -> Diagnostic {
+        let end = (start + 1).min(self.text.len()).max(start);
+        Diagnostic::new(ErrorCode::InvalidAst, Span::new(start, end), message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.text[self.pos..].chars().next()
+    }
+
+    fn skip_space(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.pos += c.len_utf8();
+            } else if c == ';' && self.sexp {
+                self.pos += self.text[self.pos..]
+                    .find('\n')
+                    .unwrap_or(self.text.len() - self.pos);
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Skip spaces and consume `c`.
+    fn expect(&mut self, c: char) -> Result<(), Diagnostic> {
+        self.skip_space();
+        match self.peek() {
+            Some(next) if next == c => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(next) => {
+                Err(self.error(self.pos, format!("expected `{0}`, found `{1}`", c, next)))
+            }
+            None => Err(self.error(self.pos, format!("expected `{0}`, found end of input", c))),
+        }
+    }
+
+    /// Skip spaces and consume `c` if it is next.
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_space();
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn end(&mut self) -> Result<(), Diagnostic> {
+        self.skip_space();
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error(self.pos, "unexpected text after the end of the tree")),
+        }
+    }
+
+    /// A run of characters that may form a symbol or keyword.
+    fn word(&mut self) -> &'a str {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if !(c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+                break;
+            }
+            self.pos += 1;
+        }
+        &self.text[start..self.pos]
+    }
+
+    fn int(&mut self) -> Result<Value, Diagnostic> {
+        let start = self.pos;
+        let word = self.word();
+        match word.parse() {
+            Ok(value) => Ok(Value::Int(value)),
+            Err(_) => Err(self.error(start, format!("invalid integer `{0}`", word))),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Diagnostic> {
+        let start = self.pos;
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let c = match self.peek() {
+                Some(c) => c,
+                None => return Err(self.error(start, "unterminated string")),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => s.push(self.escape()?),
+                c => s.push(c),
+            }
+        }
+    }
+
+    /// The character of an escape sequence, after its `\`.
+    fn escape(&mut self) -> Result<char, Diagnostic> {
+        let start = self.pos - 1;
+        let c = self
+            .peek()
+            .ok_or_else(|| self.error(start, "unterminated string"))?;
+        self.pos += c.len_utf8();
+        Ok(match c {
+            '"' => '"',
+            '\\' => '\\',
+            '/' => '/',
+            'b' => '\u{8}',
+            'f' => '\u{c}',
+            'n' => '\n',
+            'r' => '\r',
+            't' => '\t',
+            'u' => {
+                let mut code = self.hex4(start)?;
+                // UTF-16的代理对
+                if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with("\\u") {
+                    self.pos += 2;
+                    let low = self.hex4(start)?;
+                    if !(0xdc00..0xe000).contains(&low) {
+                        return Err(self.error(start, "invalid unicode escape"));
+                    }
+                    code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                }
+                char::from_u32(code).ok_or_else(|| self.error(start, "invalid unicode escape"))?
+            }
+            c => return Err(self.error(start, format!("invalid escape `\\{0}`", c))),
+        })
+    }
+
+    fn hex4(&mut self, start: usize) -> Result<u32, Diagnostic> {
+        let digits = self.text.get(self.pos..self.pos + 4);
+        match digits.and_then(|digits| u32::from_str_radix(digits, 16).ok()) {
+            Some(code) => {
+                self.pos += 4;
+                Ok(code)
+            }
+            None => Err(self.error(start, "invalid unicode escape")),
+        }
+    }
+
+    /// Read a value with `read`, one level deeper than the current one.
+    fn nested(
+        &mut self,
+        read: fn(&mut Self) -> Result<Value, Diagnostic>,
+    ) -> Result<Value, Diagnostic> {
+        if self.depth == MAX_DEPTH {
+            self.skip_space();
+            let message = format!("values nested more than {0} levels deep", MAX_DEPTH);
+            return Err(self.error(self.pos, message));
+        }
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn json(&mut self) -> Result<Value, Diagnostic> {
+        self.nested(Reader::json_value)
+    }
+
+    fn json_value(&mut self) -> Result<Value, Diagnostic> {
+        self.skip_space();
+        let start = self.pos;
+        match self.peek() {
+            Some('{') => self.json_object(),
+            Some('[') => {
+                self.pos += 1;
+                let mut values = Vec::new();
+                if !self.eat(']') {
+                    loop {
+                        values.push(self.json()?);
+                        if self.eat(']') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+                Ok(Value::List(values))
+            }
+            Some('"') => Ok(Value::Str(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.int(),
+            Some(c) if c.is_ascii_alphabetic() => match self.word() {
+                "null" => Ok(Value::Null),
+                word => Err(self.error(start, format!("unexpected `{0}`", word))),
+            },
+            Some(c) => Err(self.error(start, format!("unexpected `{0}`", c))),
+            None => Err(self.error(start, "unexpected end of input")),
+        }
+    }
+
+    fn json_object(&mut self) -> Result<Value, Diagnostic> {
+        let start = self.pos;
+        self.expect('{')?;
+        let mut kind = None;
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        if !self.eat('}') {
+            loop {
+                self.skip_space();
+                let name_start = self.pos;
+                let name = self.string()?;
+                self.expect(':')?;
+                let value = self.json()?;
+                if name == "kind" {
+                    match value {
+                        Value::Str(s) if kind.is_none() => kind = Some(s),
+                        Value::Str(_) => {
+                            return Err(self.error(name_start, "duplicate field `kind`"))
+                        }
+                        _ => return Err(self.error(name_start, "`kind` must be a string")),
+                    }
+                } else if fields.iter().any(|(field, _)| *field == name) {
+                    return Err(self.error(name_start, format!("duplicate field `{0}`", name)));
+                } else {
+                    fields.push((name, value));
+                }
+                if self.eat('}') {
+                    break;
+                }
+                self.expect(',')?;
+            }
+        }
+        match kind {
+            Some(kind) => Ok(Value::Node(Node {
+                kind,
+                fields,
+                span: Span::new(start, self.pos),
+            })),
+            None => Err(self.error(start, "object without a `kind`")),
+        }
+    }
+
+    fn sexp(&mut self) -> Result<Value, Diagnostic> {
+        self.nested(Reader::sexp_value)
+    }
+
+    fn sexp_value(&mut self) -> Result<Value, Diagnostic> {
+        self.skip_space();
+        let start = self.pos;
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                self.skip_space();
+                if self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    let word_start = self.pos;
+                    let kind = self.word().to_string();
+                    if kind != "nil" {
+                        return self.sexp_node(start, kind);
+                    }
+                    self.pos = word_start;
+                }
+                let mut values = Vec::new();
+                while !self.eat(')') {
+                    values.push(self.sexp()?);
+                }
+                Ok(Value::List(values))
+            }
+            Some('"') => Ok(Value::Str(self.string()?)),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.int(),
+            Some(c) if c.is_ascii_alphabetic() => match self.word() {
+                "nil" => Ok(Value::Null),
+                word => Err(self.error(start, format!("unexpected symbol `{0}`", word))),
+            },
+            Some(c) => Err(self.error(start, format!("unexpected `{0}`", c))),
+            None => Err(self.error(start, "unexpected end of input")),
+        }
+    }
+
+    /// The fields of a node after its kind, up to the closing parenthesis.
+    fn sexp_node(&mut self, start: usize, kind: String) -> Result<Value, Diagnostic> {
+        let mut fields: Vec<(String, Value)> = Vec::new();
+        while !self.eat(')') {
+            let name_start = self.pos;
+            self.expect(':')?;
+            let name = self.word().to_string();
+            if name.is_empty() {
+                return Err(self.error(name_start, "expected a field name after `:`"));
+            }
+            if fields.iter().any(|(field, _)| *field == name) {
+                return Err(self.error(name_start, format!("duplicate field `{0}`", name)));
+            }
+            let value = self.sexp()?;
+            fields.push((name, value));
+        }
+        Ok(Value::Node(Node {
+            kind,
+            fields,
+            span: Span::new(start, self.pos),
+        }))
+    }
+}
+
+/// Read a value written as JSON.
+pub fn parse_json(text: &str) -> Result<Value, Diagnostic> {
+    let mut reader = Reader {
+        text,
+        pos: 0,
+        sexp: false,
+        depth: 0,
+    };
+    let value = reader.json()?;
+    reader.end()?;
+    Ok(value)
+}
+
+/// Read a value written as an S-expression.
+pub fn parse_sexp(text: &str) -> Result<Value, Diagnostic> {
+    let mut reader = Reader {
+        text,
+        pos: 0,
+        sexp: true,
+        depth: 0,
+    };
+    let value = reader.sexp()?;
+    reader.end()?;
+    Ok(value)
+}
@@ -0,0 +1,406 @@
+//! Encoding of [`RvInst`]s into RV32IM machine code.
+//!
+//! Pseudo-instructions are expanded the way an assembler would: `li` into
+//! `lui`/`addi`, `la` into `lui`/`addi` with `%hi`/`%lo` relocations, and
+//! `call` into `auipc`/`jalr` with a call relocation. Branches to labels out
+//! of the ±4 KiB range of `bnez` become `beqz` over a `jal`. Local labels are
+//! resolved here; only `.globl` labels and undefined symbols are kept.
+//! `.bss` holds no bytes, only its size.
+
+use super::riscv::{fits_imm12, BinOp, ImmOp, Reg, RvInst};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Section {
+    Text,
+    Data,
+    Bss,
+}
+
+impl Section {
+    pub fn name(self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Data => ".data",
+            Section::Bss => ".bss",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssembleError {
+    /// 同一标号被定义了多次
+    DuplicateLabel(String),
+    /// 跳转的目标标号没有定义
+    UndefinedLabel(String),
+    /// 段的大小超出32位地址空间
+    SectionTooLarge(Section),
+    /// `.bss`中只能有`.zero`
+    InitializedBss,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssembleError::DuplicateLabel(label) => {
+                write!(f, "label `{0}` is defined more than once", label)
+            }
+            AssembleError::UndefinedLabel(label) => write!(f, "label `{0}` is not defined", label),
+            AssembleError::SectionTooLarge(section) => {
+                write!(f, "section `{0}` exceeds 4 GiB", section.name())
+            }
+            AssembleError::InitializedBss => write!(f, "`.bss` may only contain `.zero`"),
+        }
+    }
+}
+
+/// A symbol of an object: a `.globl` label, or a symbol referenced but not
+/// defined in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// 定义符号的段，未定义时为None
+    pub section: Option<Section>,
+    /// 在段中的偏移
+    pub offset: u32,
+    /// 到同一段中下一个符号或段末尾的字节数
+    pub size: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    /// An `auipc ra` and `jalr ra` pair calling the symbol.
+    Call,
+    /// The upper 20 bits of the address of the symbol, in a `lui`.
+    Hi20,
+    /// The lower 12 bits of the address of the symbol, in an I-type
+    /// instruction.
+    Lo12I,
+}
+
+impl RelocKind {
+    /// Patch the instructions at `offset` in `text`, loaded at address `pc`,
+    /// to refer to the symbol at address `addr`.
+    pub fn apply(self, text: &mut [u8], offset: usize, pc: u32, addr: u32) {
+        let mut patch = |offset: usize, mask: u32, bits: u32| {
+            let bytes: &mut [u8; 4] = (&mut text[offset..offset + 4]).try_into().unwrap();
+            let word = u32::from_le_bytes(*bytes) & !mask | bits & mask;
+            *bytes = word.to_le_bytes();
+        };
+        match self {
+            RelocKind::Call => {
+                let (hi, lo) = hi_lo(addr.wrapping_sub(pc) as i32);
+                patch(offset, 0xfffff000, hi << 12);
+                patch(offset + 4, 0xfff00000, (lo as u32) << 20);
+            }
+            RelocKind::Hi20 => patch(offset, 0xfffff000, hi_lo(addr as i32).0 << 12),
+            RelocKind::Lo12I => patch(offset, 0xfff00000, (hi_lo(addr as i32).1 as u32) << 20),
+        }
+    }
+}
+
+/// A place in `.text` to patch with the address of a symbol when linking.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    pub offset: u32,
+    pub kind: RelocKind,
+    /// 符号在[`Object::symbols`]中的序号
+    pub symbol: usize,
+}
+
+/// Assembled code and data, before linking.
+#[derive(Debug, Clone, Default)]
+pub struct Object {
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    /// .bss段的大小
+    pub bss: u32,
+    pub symbols: Vec<Symbol>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// Split `value` into the parts loaded by `lui` and added by a following
+/// 12-bit immediate, which is sign-extended.
+pub fn hi_lo(value: i32) -> (u32, i32) {
+    let lo = (value << 20) >> 20;
+    let hi = (value.wrapping_sub(lo) as u32) >> 12;
+    (hi, lo)
+}
+
+fn r_type(funct7: u32, rs2: Reg, rs1: Reg, funct3: u32, rd: Reg, opcode: u32) -> u32 {
+    funct7 << 25 | rs2.index() << 20 | rs1.index() << 15 | funct3 << 12 | rd.index() << 7 | opcode
+}
+
+fn i_type(imm: i32, rs1: Reg, funct3: u32, rd: Reg, opcode: u32) -> u32 {
+    ((imm as u32) & 0xfff) << 20 | rs1.index() << 15 | funct3 << 12 | rd.index() << 7 | opcode
+}
+
+fn s_type(imm: i32, rs2: Reg, rs1: Reg, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 5 & 0x7f) << 25
+        | rs2.index() << 20
+        | rs1.index() << 15
+        | funct3 << 12
+        | (imm & 0x1f) << 7
+        | 0x23
+}
+
+fn b_type(imm: i32, rs2: Reg, rs1: Reg, funct3: u32) -> u32 {
+    let imm = imm as u32;
+    (imm >> 12 & 1) << 31
+        | (imm >> 5 & 0x3f) << 25
+        | rs2.index() << 20
+        | rs1.index() << 15
+        | funct3 << 12
+        | (imm >> 1 & 0xf) << 8
+        | (imm >> 11 & 1) << 7
+        | 0x63
+}
+
+fn u_type(imm20: u32, rd: Reg, opcode: u32) -> u32 {
+    (imm20 & 0xfffff) << 12 | rd.index() << 7 | opcode
+}
+
+fn j_type(imm: i32, rd: Reg) -> u32 {
+    let imm = imm as u32;
+    (imm >> 20 & 1) << 31
+        | (imm >> 1 & 0x3ff) << 21
+        | (imm >> 11 & 1) << 20
+        | (imm >> 12 & 0xff) << 12
+        | rd.index() << 7
+        | 0x6f
+}
+
+/// `funct7` and `funct3` of an R-type operation.
+fn binary_funct(op: BinOp) -> (u32, u32) {
+    match op {
+        BinOp::Add => (0x00, 0),
+        BinOp::Sub => (0x20, 0),
+        BinOp::Slt => (0x00, 2),
+        BinOp::Sltu => (0x00, 3),
+        BinOp::Xor => (0x00, 4),
+        BinOp::Or => (0x00, 6),
+        BinOp::And => (0x00, 7),
+        BinOp::Mul => (0x01, 0),
+        BinOp::Div => (0x01, 4),
+        BinOp::Rem => (0x01, 6),
+    }
+}
+
+fn imm_funct(op: ImmOp) -> u32 {
+    match op {
+        ImmOp::Addi => 0,
+        ImmOp::Slti => 2,
+        ImmOp::Sltiu => 3,
+        ImmOp::Xori => 4,
+        ImmOp::Ori => 6,
+        ImmOp::Andi => 7,
+    }
+}
+
+/// Number of bytes `inst` takes, with branches in their long form if `long`.
+fn size(inst: &RvInst, long: bool) -> u64 {
+    match inst {
+        RvInst::Text | RvInst::Data | RvInst::Bss | RvInst::Globl(_) | RvInst::Label(_) => 0,
+        RvInst::Zero(bytes) => *bytes as u64,
+        RvInst::Li(_, imm) if fits_imm12(*imm) || hi_lo(*imm).1 == 0 => 4,
+        RvInst::Li(..) | RvInst::La(..) | RvInst::Call(_) => 8,
+        RvInst::Bnez(..) if long => 8,
+        _ => 4,
+    }
+}
+
+/// The section and offset of every label.
+type Labels<'a> = HashMap<&'a str, (Section, u32)>;
+
+/// Offset of every instruction in its section, and the section and offset
+/// of every label.
+fn layout<'a>(
+    insts: &'a [RvInst],
+    long: &HashSet<usize>,
+) -> Result<(Vec<u32>, Labels<'a>), AssembleError> {
+    let mut offsets = Vec::new();
+    let mut labels = HashMap::new();
+    let mut section = Section::Text;
+    let (mut text, mut data, mut bss) = (0, 0, 0);
+    for (i, inst) in insts.iter().enumerate() {
+        match inst {
+            RvInst::Text => section = Section::Text,
+            RvInst::Data => section = Section::Data,
+            RvInst::Bss => section = Section::Bss,
+            _ => {}
+        }
+        let offset = match section {
+            Section::Text => &mut text,
+            Section::Data => &mut data,
+            Section::Bss => &mut bss,
+        };
+        if let RvInst::Label(label) = inst {
+            if labels.insert(label.as_str(), (section, *offset)).is_some() {
+                return Err(AssembleError::DuplicateLabel(label.clone()));
+            }
+        }
+        offsets.push(*offset);
+        *offset = u32::try_from(*offset as u64 + size(inst, long.contains(&i)))
+            .map_err(|_| AssembleError::SectionTooLarge(section))?;
+    }
+    Ok((offsets, labels))
+}
+
+/// The section and offset of the local label `label`.
+fn resolve(labels: &Labels, label: &str) -> Result<(Section, u32), AssembleError> {
+    labels
+        .get(label)
+        .copied()
+        .ok_or_else(|| AssembleError::UndefinedLabel(label.to_string()))
+}
+
+/// Assemble `insts` into an object.
+pub fn assemble(insts: &[RvInst]) -> Result<Object, AssembleError> {
+    // 超出范围的分支改用长形式，这会使其后的代码后移，因此反复检查直到不再变化
+    let mut long = HashSet::new();
+    let (offsets, labels) = loop {
+        let (offsets, labels) = layout(insts, &long)?;
+        let mut changed = false;
+        for (i, inst) in insts.iter().enumerate() {
+            if let RvInst::Bnez(_, label) = inst {
+                let distance = resolve(&labels, label)?.1 as i64 - offsets[i] as i64;
+                if !long.contains(&i) && !(-4096..4096).contains(&distance) {
+                    long.insert(i);
+                    changed = true;
+                }
+            }
+        }
+        if !changed {
+            break (offsets, labels);
+        }
+    };
+
+    let mut obj = Object::default();
+    let mut symbol_ids: HashMap<String, usize> = HashMap::new();
+    let mut symbol = |obj: &mut Object, name: &str| -> usize {
+        *symbol_ids.entry(name.to_string()).or_insert_with(|| {
+            let (section, offset) = match labels.get(name) {
+                Some(&(section, offset)) => (Some(section), offset),
+                None => (None, 0),
+            };
+            obj.symbols.push(Symbol {
+                name: name.to_string(),
+                section,
+                offset,
+                size: 0,
+            });
+            obj.symbols.len() - 1
+        })
+    };
+    let mut section = Section::Text;
+    for (i, inst) in insts.iter().enumerate() {
+        let pc = offsets[i];
+        let target = |label: &str| Ok(resolve(&labels, label)?.1 as i32 - pc as i32);
+        let mut words = Vec::new();
+        let mut zeros = 0;
+        match inst {
+            RvInst::Text => section = Section::Text,
+            RvInst::Data => section = Section::Data,
+            RvInst::Bss => section = Section::Bss,
+            RvInst::Globl(name) => {
+                symbol(&mut obj, name);
+            }
+            RvInst::Label(_) => {}
+            RvInst::Word(value) => words.push(*value as u32),
+            RvInst::Zero(bytes) => zeros = *bytes,
+            &RvInst::Binary(op, rd, rs1, rs2) => {
+                let (funct7, funct3) = binary_funct(op);
+                words.push(r_type(funct7, rs2, rs1, funct3, rd, 0x33));
+            }
+            &RvInst::BinaryImm(op, rd, rs, imm) => {
+                words.push(i_type(imm, rs, imm_funct(op), rd, 0x13));
+            }
+            &RvInst::Lw(rd, base, offset) => words.push(i_type(offset, base, 2, rd, 0x03)),
+            &RvInst::Sw(rs, base, offset) => words.push(s_type(offset, rs, base, 2)),
+            &RvInst::Li(rd, imm) => {
+                let (hi, lo) = hi_lo(imm);
+                if fits_imm12(imm) {
+                    words.push(i_type(imm, Reg::Zero, 0, rd, 0x13));
+                } else {
+                    words.push(u_type(hi, rd, 0x37));
+                    if lo != 0 {
+                        words.push(i_type(lo, rd, 0, rd, 0x13));
+                    }
+                }
+            }
+            RvInst::La(rd, name) => {
+                let id = symbol(&mut obj, name);
+                obj.relocations.push(Relocation {
+                    offset: pc,
+                    kind: RelocKind::Hi20,
+                    symbol: id,
+                });
+                obj.relocations.push(Relocation {
+                    offset: pc + 4,
+                    kind: RelocKind::Lo12I,
+                    symbol: id,
+                });
+                words.push(u_type(0, *rd, 0x37));
+                words.push(i_type(0, *rd, 0, *rd, 0x13));
+            }
+            &RvInst::Mv(rd, rs) => words.push(i_type(0, rs, 0, rd, 0x13)),
+            // seqz即sltiu rd, rs, 1；snez即sltu rd, zero, rs
+            &RvInst::Seqz(rd, rs) => words.push(i_type(1, rs, 3, rd, 0x13)),
+            &RvInst::Snez(rd, rs) => words.push(r_type(0, rs, Reg::Zero, 3, rd, 0x33)),
+            RvInst::Bnez(rs, label) => {
+                if long.contains(&i) {
+                    words.push(b_type(8, Reg::Zero, *rs, 0));
+                    words.push(j_type(target(label)? - 4, Reg::Zero));
+                } else {
+                    words.push(b_type(target(label)?, Reg::Zero, *rs, 1));
+                }
+            }
+            RvInst::J(label) => words.push(j_type(target(label)?, Reg::Zero)),
+            RvInst::Call(name) => {
+                let id = symbol(&mut obj, name);
+                obj.relocations.push(Relocation {
+                    offset: pc,
+                    kind: RelocKind::Call,
+                    symbol: id,
+                });
+                words.push(u_type(0, Reg::Ra, 0x17));
+                words.push(i_type(0, Reg::Ra, 0, Reg::Ra, 0x67));
+            }
+            RvInst::Ret => words.push(i_type(0, Reg::Ra, 0, Reg::Zero, 0x67)),
+        }
+        let bytes = match section {
+            Section::Text => &mut obj.text,
+            Section::Data => &mut obj.data,
+            Section::Bss if words.is_empty() => {
+                obj.bss += zeros as u32;
+                continue;
+            }
+            Section::Bss => return Err(AssembleError::InitializedBss),
+        };
+        for word in words {
+            bytes.extend(word.to_le_bytes());
+        }
+        bytes.resize(bytes.len() + zeros, 0);
+    }
+
+    // 符号的大小延伸到同一段中的下一个符号
+    let mut defined: Vec<usize> = (0..obj.symbols.len())
+        .filter(|&id| obj.symbols[id].section.is_some())
+        .collect();
+    defined.sort_by_key(|&id| (obj.symbols[id].section, obj.symbols[id].offset));
+    for (k, &id) in defined.iter().enumerate() {
+        let section = obj.symbols[id].section;
+        let end = match defined.get(k + 1) {
+            Some(&next) if obj.symbols[next].section == section => obj.symbols[next].offset,
+            _ => match section {
+                Some(Section::Text) => obj.text.len() as u32,
+                Some(Section::Data) => obj.data.len() as u32,
+                _ => obj.bss,
+            },
+        };
+        obj.symbols[id].size = end - obj.symbols[id].offset;
+    }
+    Ok(obj)
+}
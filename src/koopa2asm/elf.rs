@@ -0,0 +1,221 @@
+//! Writing an [`Object`] as a relocatable ELF file for RV32.
+//!
+//! The file has the sections `.text`, `.data`, `.bss`, `.rela.text`,
+//! `.symtab`, `.strtab` and `.shstrtab`, in this order, followed by the
+//! section header table. Functions become `STT_FUNC` symbols and global variables
+//! `STT_OBJECT` symbols; symbols referenced but not defined, such as the
+//! functions of the runtime library, are left for the linker.
+
+use super::assemble::{Object, RelocKind, Section};
+
+const EM_RISCV: u16 = 243;
+const ET_REL: u16 = 1;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+const R_RISCV_CALL: u32 = 18;
+const R_RISCV_HI20: u32 = 26;
+const R_RISCV_LO12_I: u32 = 27;
+
+/// 各段在节头表中的序号
+const TEXT_INDEX: u16 = 1;
+const DATA_INDEX: u16 = 2;
+const BSS_INDEX: u16 = 3;
+const SYMTAB_INDEX: u32 = 5;
+const STRTAB_INDEX: u32 = 6;
+const SHSTRTAB_INDEX: u16 = 7;
+
+const EHDR_SIZE: usize = 52;
+const SHDR_SIZE: usize = 40;
+const SYM_SIZE: u32 = 16;
+const RELA_SIZE: u32 = 12;
+
+/// A string table under construction.
+struct StrTab(Vec<u8>);
+
+impl StrTab {
+    fn new() -> Self {
+        StrTab(vec![0])
+    }
+
+    /// Append `s` and return its offset.
+    fn add(&mut self, s: &str) -> u32 {
+        let offset = self.0.len() as u32;
+        self.0.extend(s.as_bytes());
+        self.0.push(0);
+        offset
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+fn push_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend(value.to_le_bytes());
+}
+
+fn push_u32(buf: &mut Vec<u8>, value: u32) {
+    buf.extend(value.to_le_bytes());
+}
+
+/// Encode `obj` as an ELF relocatable object.
+pub fn write_elf(obj: &Object) -> Vec<u8> {
+    // 符号表中所有符号都是全局的，第0项为空符号
+    let mut strtab = StrTab::new();
+    let mut symtab = vec![0; SYM_SIZE as usize];
+    for symbol in &obj.symbols {
+        let (kind, shndx) = match symbol.section {
+            Some(Section::Text) => (STT_FUNC, TEXT_INDEX),
+            Some(Section::Data) => (STT_OBJECT, DATA_INDEX),
+            Some(Section::Bss) => (STT_OBJECT, BSS_INDEX),
+            None => (STT_NOTYPE, 0),
+        };
+        push_u32(&mut symtab, strtab.add(&symbol.name));
+        push_u32(&mut symtab, symbol.offset);
+        push_u32(&mut symtab, symbol.size);
+        symtab.push(STB_GLOBAL << 4 | kind);
+        symtab.push(0);
+        push_u16(&mut symtab, shndx);
+    }
+
+    let mut rela = Vec::new();
+    for reloc in &obj.relocations {
+        let kind = match reloc.kind {
+            RelocKind::Call => R_RISCV_CALL,
+            RelocKind::Hi20 => R_RISCV_HI20,
+            RelocKind::Lo12I => R_RISCV_LO12_I,
+        };
+        push_u32(&mut rela, reloc.offset);
+        push_u32(&mut rela, (reloc.symbol as u32 + 1) << 8 | kind);
+        push_u32(&mut rela, 0);
+    }
+
+    let mut shstrtab = StrTab::new();
+    let names = [
+        shstrtab.add(".text"),
+        shstrtab.add(".data"),
+        shstrtab.add(".bss"),
+        shstrtab.add(".rela.text"),
+        shstrtab.add(".symtab"),
+        shstrtab.add(".strtab"),
+        shstrtab.add(".shstrtab"),
+    ];
+
+    // 各段的内容依次排在文件头之后，均按4字节对齐；.bss在文件中不占空间
+    let bss = Vec::new();
+    let contents = [
+        &obj.text,
+        &obj.data,
+        &bss,
+        &rela,
+        &symtab,
+        &strtab.0,
+        &shstrtab.0,
+    ];
+    let mut offsets = Vec::new();
+    let mut offset = EHDR_SIZE;
+    for content in contents {
+        offsets.push(offset as u32);
+        offset = (offset + content.len() + 3) & !3;
+    }
+    let shoff = offset;
+    let section = |i: usize, kind, flags, link, info, align, entsize| SectionHeader {
+        name: names[i],
+        kind,
+        flags,
+        offset: offsets[i],
+        size: match kind {
+            SHT_NOBITS => obj.bss,
+            _ => contents[i].len() as u32,
+        },
+        link,
+        info,
+        align,
+        entsize,
+    };
+    let headers = [
+        section(0, SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, 0, 0, 4, 0),
+        section(1, SHT_PROGBITS, SHF_ALLOC | SHF_WRITE, 0, 0, 4, 0),
+        section(2, SHT_NOBITS, SHF_ALLOC | SHF_WRITE, 0, 0, 4, 0),
+        section(
+            3,
+            SHT_RELA,
+            SHF_INFO_LINK,
+            SYMTAB_INDEX,
+            TEXT_INDEX as u32,
+            4,
+            RELA_SIZE,
+        ),
+        // info为第一个全局符号的序号
+        section(4, SHT_SYMTAB, 0, STRTAB_INDEX, 1, 4, SYM_SIZE),
+        section(5, SHT_STRTAB, 0, 0, 0, 1, 0),
+        section(6, SHT_STRTAB, 0, 0, 0, 1, 0),
+    ];
+
+    let mut elf = Vec::new();
+    elf.extend(b"\x7fELF");
+    // 32位、小端、版本1
+    elf.extend([1, 1, 1, 0]);
+    elf.extend([0; 8]);
+    push_u16(&mut elf, ET_REL);
+    push_u16(&mut elf, EM_RISCV);
+    push_u32(&mut elf, 1);
+    // 入口、程序头表偏移
+    push_u32(&mut elf, 0);
+    push_u32(&mut elf, 0);
+    push_u32(&mut elf, shoff as u32);
+    // 标志为0：软件浮点ABI，不含压缩指令
+    push_u32(&mut elf, 0);
+    push_u16(&mut elf, EHDR_SIZE as u16);
+    push_u16(&mut elf, 0);
+    push_u16(&mut elf, 0);
+    push_u16(&mut elf, SHDR_SIZE as u16);
+    push_u16(&mut elf, headers.len() as u16 + 1);
+    push_u16(&mut elf, SHSTRTAB_INDEX);
+
+    for content in contents {
+        elf.extend(content.iter());
+        elf.resize((elf.len() + 3) & !3, 0);
+    }
+    elf.extend([0; SHDR_SIZE]);
+    for header in headers {
+        for field in [
+            header.name,
+            header.kind,
+            header.flags,
+            0,
+            header.offset,
+            header.size,
+            header.link,
+            header.info,
+            header.align,
+            header.entsize,
+        ] {
+            push_u32(&mut elf, field);
+        }
+    }
+    elf
+}
@@ -0,0 +1,780 @@
+use koopa::ir::{
+    entities::{Value, ValueData},
+    BasicBlock, BinaryOp, FunctionData, Program, Type, TypeKind, ValueKind,
+};
+use riscv::{fits_imm12, BinOp, ImmOp, Reg, RvInst};
+use std::collections::HashMap;
+
+pub mod assemble;
+pub mod elf;
+mod peephole;
+mod regalloc;
+pub mod riscv;
+
+/// 通过寄存器传递的参数个数（a0-a7）
+const ARG_REGS: usize = 8;
+
+/// 传递参数的寄存器
+const ARG_REG_NAMES: [Reg; ARG_REGS] = [
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+];
+
+enum Res {
+    Nothing,
+    Imm(i32),
+    /// 结果暂存在该寄存器中，由函数体负责放入值所在的位置
+    Temp(Reg),
+    Return,
+}
+
+/// 一个koopa值在汇编中的位置
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Location {
+    /// 值保存在第id个栈槽中
+    Slot(i32),
+    /// 局部alloc的内存从第id个栈槽开始，值为其地址
+    Stack(i32),
+    /// 第id个全局变量，值为其地址
+    Global(i32),
+    /// 值保存在寄存器中
+    Reg(Reg),
+}
+
+/// Access the stack at `offset` bytes above `sp`, going through `t3` when the
+/// offset does not fit in a 12-bit immediate.
+fn stack_access(store: bool, reg: Reg, offset: i32) -> Vec<RvInst> {
+    let (base, offset, mut insts) = if fits_imm12(offset) {
+        (Reg::Sp, offset, vec![])
+    } else {
+        let insts = vec![
+            RvInst::Li(Reg::T3, offset),
+            RvInst::Binary(BinOp::Add, Reg::T3, Reg::Sp, Reg::T3),
+        ];
+        (Reg::T3, 0, insts)
+    };
+    insts.push(if store {
+        RvInst::Sw(reg, base, offset)
+    } else {
+        RvInst::Lw(reg, base, offset)
+    });
+    insts
+}
+
+/// Load the value kept in slot `register_id` into `reg`.
+fn load_value(reg: Reg, register_id: &i32) -> Vec<RvInst> {
+    stack_access(false, reg, register_id * 4)
+}
+
+/// Store `reg` into slot `register_id`.
+fn store_value(reg: Reg, register_id: &i32) -> Vec<RvInst> {
+    stack_access(true, reg, register_id * 4)
+}
+
+/// Add `imm` to `sp`.
+fn adjust_sp(imm: i32) -> Vec<RvInst> {
+    if fits_imm12(imm) {
+        vec![RvInst::BinaryImm(ImmOp::Addi, Reg::Sp, Reg::Sp, imm)]
+    } else {
+        vec![
+            RvInst::Li(Reg::T5, imm),
+            RvInst::Binary(BinOp::Add, Reg::Sp, Reg::Sp, Reg::T5),
+        ]
+    }
+}
+
+/// Name of the `id`-th global variable.
+fn global_label(id: i32) -> String {
+    format!("global_var_{0}", id)
+}
+
+/// Put the value at `loc` into `reg`; for allocations this is their address.
+fn load_location(reg: Reg, loc: &Location) -> Vec<RvInst> {
+    match *loc {
+        Location::Slot(id) => load_value(reg, &id),
+        Location::Stack(id) => {
+            if fits_imm12(id * 4) {
+                vec![RvInst::BinaryImm(ImmOp::Addi, reg, Reg::Sp, id * 4)]
+            } else {
+                vec![
+                    RvInst::Li(reg, id * 4),
+                    RvInst::Binary(BinOp::Add, reg, Reg::Sp, reg),
+                ]
+            }
+        }
+        Location::Global(id) => vec![RvInst::La(reg, global_label(id))],
+        Location::Reg(src) => {
+            if src == reg {
+                vec![]
+            } else {
+                vec![RvInst::Mv(reg, src)]
+            }
+        }
+    }
+}
+
+/// Load (`lw`) or store (`sw`) `reg` through the pointer at `loc`, using `t6`
+/// to hold computed addresses.
+fn memory_access(store: bool, reg: Reg, loc: &Location) -> Vec<RvInst> {
+    let access = |base| {
+        if store {
+            RvInst::Sw(reg, base, 0)
+        } else {
+            RvInst::Lw(reg, base, 0)
+        }
+    };
+    match *loc {
+        Location::Stack(id) => stack_access(store, reg, id * 4),
+        Location::Global(id) => vec![RvInst::La(Reg::T6, global_label(id)), access(Reg::T6)],
+        Location::Slot(id) => {
+            let mut insts = load_value(Reg::T6, &id);
+            insts.push(access(Reg::T6));
+            insts
+        }
+        Location::Reg(ptr) => vec![access(ptr)],
+    }
+}
+
+/// Size in bytes of the type a pointer type points to.
+fn pointee_size(ty: &Type) -> usize {
+    match ty.kind() {
+        TypeKind::Pointer(base) => base.size(),
+        _ => unreachable!(),
+    }
+}
+
+/// Compute `src + index * size` into `t5`. A constant index is scaled here
+/// instead of at run time.
+fn offset_address(
+    src: Reg,
+    index: Value,
+    size: usize,
+    func: &FunctionData,
+    value_reg_map: &HashMap<Value, Location>,
+) -> Vec<RvInst> {
+    if let ValueKind::Integer(int) = func.dfg().value(index).kind() {
+        let offset = int.value().wrapping_mul(size as i32);
+        return vec![
+            RvInst::Li(Reg::T4, offset),
+            RvInst::Binary(BinOp::Add, Reg::T5, src, Reg::T4),
+        ];
+    }
+    let (mut insts, index) = operand(Reg::T6, index, func, value_reg_map);
+    insts.push(RvInst::Li(Reg::T4, size as i32));
+    insts.push(RvInst::Binary(BinOp::Mul, Reg::T6, index, Reg::T4));
+    insts.push(RvInst::Binary(BinOp::Add, Reg::T5, src, Reg::T6));
+    insts
+}
+
+/// Label of a basic block: `.L{func}.{index}`, where `index` is the block's
+/// position in the layout. Function names never contain `.`, so these labels
+/// can clash neither with each other nor with function and global symbols.
+fn bb_label(func: &FunctionData, bb: BasicBlock) -> String {
+    let position = func
+        .layout()
+        .bbs()
+        .keys()
+        .position(|&other| other == bb)
+        .unwrap();
+    format!(".L{0}.{1}", &func.name()[1..], position)
+}
+
+/// Label of the epilogue of `func`.
+fn end_label(func: &FunctionData) -> String {
+    format!(".L{0}.end", &func.name()[1..])
+}
+
+/// Load an operand into `reg`, materializing immediates with `li`.
+fn load_operand(
+    reg: Reg,
+    value: Value,
+    func: &FunctionData,
+    value_reg_map: &HashMap<Value, Location>,
+) -> Vec<RvInst> {
+    match value_reg_map.get(&value) {
+        Some(loc) => load_location(reg, loc),
+        None => match func.dfg().value(value).kind() {
+            ValueKind::Integer(int) => vec![RvInst::Li(reg, int.value())],
+            // 未定义的值可以取任意值，这里取0
+            ValueKind::Undef(_) => vec![RvInst::Li(reg, 0)],
+            _ => unreachable!("values of instructions get a location before any code"),
+        },
+    }
+}
+
+/// Get a register holding `value`: its own register when it has one,
+/// otherwise `scratch` after loading the operand into it.
+fn operand(
+    scratch: Reg,
+    value: Value,
+    func: &FunctionData,
+    value_reg_map: &HashMap<Value, Location>,
+) -> (Vec<RvInst>, Reg) {
+    match value_reg_map.get(&value) {
+        Some(&Location::Reg(reg)) => (vec![], reg),
+        _ => (load_operand(scratch, value, func, value_reg_map), scratch),
+    }
+}
+
+/// Emit the moves `dst <- src` between registers and stack slots as if they
+/// happened simultaneously, breaking cycles through `t4`.
+fn parallel_move(mut moves: Vec<(Location, Location)>) -> Vec<RvInst> {
+    let mut insts = vec![];
+    moves.retain(|(dst, src)| dst != src);
+    while !moves.is_empty() {
+        // 先处理目标不再被其他移动读取的移动
+        match moves
+            .iter()
+            .position(|&(dst, _)| moves.iter().all(|&(_, src)| src != dst))
+        {
+            Some(idx) => match moves.remove(idx) {
+                (Location::Reg(dst), src) => insts.extend(load_location(dst, &src)),
+                (Location::Slot(dst), Location::Reg(src)) => {
+                    insts.extend(store_value(src, &dst));
+                }
+                (Location::Slot(dst), src) => {
+                    insts.extend(load_location(Reg::T5, &src));
+                    insts.extend(store_value(Reg::T5, &dst));
+                }
+                _ => unreachable!(),
+            },
+            None => {
+                // 剩下的移动构成环，将其中一个目标暂存到t4
+                let dst = moves[0].0;
+                insts.extend(load_location(Reg::T4, &dst));
+                for (_, src) in moves.iter_mut() {
+                    if *src == dst {
+                        *src = Location::Reg(Reg::T4);
+                    }
+                }
+            }
+        }
+    }
+    insts
+}
+
+/// Pass `args` to the parameters of `target`. Arguments held in registers or
+/// stack slots are moved in parallel; constants and addresses, which cannot be
+/// overwritten by these moves, are materialized afterwards.
+fn block_args(
+    target: BasicBlock,
+    args: &[Value],
+    func: &FunctionData,
+    value_reg_map: &HashMap<Value, Location>,
+) -> Vec<RvInst> {
+    let mut moves = Vec::new();
+    let mut rest = Vec::new();
+    for (param, &arg) in func.dfg().bb(target).params().iter().zip(args) {
+        let dst = value_reg_map[param];
+        match value_reg_map.get(&arg) {
+            Some(&src @ (Location::Reg(_) | Location::Slot(_))) => moves.push((dst, src)),
+            _ => rest.push((dst, arg)),
+        }
+    }
+    let mut insts = parallel_move(moves);
+    for (dst, arg) in rest {
+        match dst {
+            Location::Reg(reg) => {
+                insts.extend(load_operand(reg, arg, func, value_reg_map));
+            }
+            Location::Slot(slot) => {
+                insts.extend(load_operand(Reg::T5, arg, func, value_reg_map));
+                insts.extend(store_value(Reg::T5, &slot));
+            }
+            _ => unreachable!(),
+        }
+    }
+    insts
+}
+
+/// Label of the code that passes the true arguments of `branch` before
+/// jumping to its true target.
+fn true_edge_label(func: &FunctionData, branch: &ValueData, target: BasicBlock) -> String {
+    // 在目标块的使用者中找到该分支指令本身，以得到其所在的基本块
+    let inst = func
+        .dfg()
+        .bb(target)
+        .used_by()
+        .iter()
+        .copied()
+        .find(|&user| std::ptr::eq(func.dfg().value(user), branch))
+        .unwrap();
+    let bb = func.layout().parent_bb(inst).unwrap();
+    format!("{0}.true", bb_label(func, bb))
+}
+
+trait GenerateAsm {
+    fn generate(
+        &self,
+        func: Option<&FunctionData>,
+        register_id: &mut i32,
+        value_reg_map: &mut HashMap<Value, Location>,
+        source_prog: &koopa::ir::Program,
+    ) -> (Vec<RvInst>, Res);
+}
+
+impl GenerateAsm for koopa::ir::Program {
+    fn generate(
+        &self,
+        _func: Option<&FunctionData>,
+        register_id: &mut i32,
+        value_reg_map: &mut HashMap<Value, Location>,
+        source_prog: &koopa::ir::Program,
+    ) -> (Vec<RvInst>, Res) {
+        let mut insts = vec![];
+        for (global_id, &value) in (1..).zip(self.inst_layout()) {
+            let data = self.borrow_value(value);
+            // 全零的全局变量放入.bss，不占目标文件的空间
+            let ValueKind::GlobalAlloc(alloc) = data.kind() else {
+                unreachable!("global values are all `global alloc`s")
+            };
+            match self.borrow_value(alloc.init()).kind() {
+                ValueKind::ZeroInit(_) => insts.push(RvInst::Bss),
+                _ => insts.push(RvInst::Data),
+            }
+            insts.push(RvInst::Globl(global_label(global_id)));
+            insts.push(RvInst::Label(global_label(global_id)));
+            insts.extend(
+                data.generate(None, register_id, value_reg_map, source_prog)
+                    .0,
+            );
+            value_reg_map.insert(value, Location::Global(global_id));
+        }
+        for &func in self.func_layout() {
+            let func_data = self.func(func);
+            if func_data.layout().entry_bb().is_none() {
+                continue;
+            }
+            insts.extend(
+                func_data
+                    .generate(
+                        None,
+                        &mut register_id.clone(),
+                        &mut value_reg_map.clone(),
+                        source_prog,
+                    )
+                    .0,
+            );
+        }
+        (insts, Res::Nothing)
+    }
+}
+
+impl GenerateAsm for koopa::ir::FunctionData {
+    fn generate(
+        &self,
+        _func: Option<&FunctionData>,
+        register_id: &mut i32,
+        value_reg_map: &mut HashMap<Value, Location>,
+        source_prog: &koopa::ir::Program,
+    ) -> (Vec<RvInst>, Res) {
+        let name = &self.name()[1..];
+        let end_label = end_label(self);
+        let mut pre = vec![
+            RvInst::Text,
+            RvInst::Globl(name.to_string()),
+            RvInst::Label(name.to_string()),
+        ];
+
+        // 栈帧底部预留给超过8个的调用参数，其上依次为参数、局部变量，最顶部保存ra
+        let mut has_call = false;
+        let mut max_args = 0;
+        for (_, node) in self.layout().bbs() {
+            for &inst in node.insts().keys() {
+                if let ValueKind::Call(call) = self.dfg().value(inst).kind() {
+                    has_call = true;
+                    max_args = max_args.max(call.args().len());
+                }
+            }
+        }
+        *register_id = max_args.saturating_sub(ARG_REGS) as i32;
+        // 未分配到寄存器的函数参数与基本块参数存入栈槽
+        let bb_params = self
+            .layout()
+            .bbs()
+            .keys()
+            .flat_map(|&bb| self.dfg().bb(bb).params());
+        for &param in self.params().iter().chain(bb_params) {
+            if !matches!(value_reg_map.get(&param), Some(Location::Reg(_))) {
+                value_reg_map.insert(param, Location::Slot(*register_id));
+                *register_id += 1;
+            }
+        }
+        // 指令的结果同样在生成代码之前确定位置，使用者因此不依赖定义在布局中的先后：
+        // 局部alloc按被分配类型的大小占用连续的栈槽，其余未分配到寄存器的值各占一个栈槽
+        for (_, node) in self.layout().bbs() {
+            for &inst in node.insts().keys() {
+                let data = self.dfg().value(inst);
+                if let ValueKind::Alloc(_) = data.kind() {
+                    let size = pointee_size(data.ty()).max(4) as i32;
+                    value_reg_map.insert(inst, Location::Stack(*register_id));
+                    *register_id += (size + 3) / 4;
+                } else if !data.ty().is_unit()
+                    && !matches!(value_reg_map.get(&inst), Some(Location::Reg(_)))
+                {
+                    value_reg_map.insert(inst, Location::Slot(*register_id));
+                    *register_id += 1;
+                }
+            }
+        }
+
+        let mut body = vec![];
+        for (&bb, node) in self.layout().bbs() {
+            body.push(RvInst::Label(bb_label(self, bb)));
+            for &inst in node.insts().keys() {
+                let value_data = self.dfg().value(inst);
+                let (insts, ret_res) =
+                    value_data.generate(Some(self), register_id, value_reg_map, source_prog);
+                body.extend(insts);
+                match ret_res {
+                    Res::Nothing => {}
+                    Res::Imm(_) => {}
+                    Res::Temp(src) => match value_reg_map[&inst] {
+                        Location::Reg(reg) => body.extend(load_location(reg, &Location::Reg(src))),
+                        Location::Slot(slot) => body.extend(store_value(src, &slot)),
+                        _ => unreachable!("only allocations live on the stack directly"),
+                    },
+                    Res::Return => body.push(RvInst::J(end_label.clone())),
+                }
+            }
+        }
+
+        // 本函数用到的被调用者保存寄存器，保存在局部变量之上
+        let saved_regs: Vec<Reg> = regalloc::CALLEE_SAVED
+            .iter()
+            .copied()
+            .filter(|reg| {
+                self.dfg().values().keys().any(
+                    |value| matches!(value_reg_map.get(value), Some(Location::Reg(r)) if r == reg),
+                )
+            })
+            .collect();
+        let saved_offset = *register_id * 4;
+        let ra_offset = saved_offset + saved_regs.len() as i32 * 4;
+        let mut stack_len = ra_offset + if has_call { 4 } else { 0 };
+        if stack_len % 16 != 0 {
+            stack_len += 16 - stack_len % 16;
+        }
+        if stack_len != 0 {
+            pre.extend(adjust_sp(-stack_len));
+        }
+        if has_call {
+            pre.extend(stack_access(true, Reg::Ra, ra_offset));
+        }
+        for (i, &reg) in saved_regs.iter().enumerate() {
+            pre.extend(stack_access(true, reg, saved_offset + i as i32 * 4));
+        }
+        // 参数放入各自的位置：先将寄存器参数存入栈槽，再做寄存器间的并行移动，
+        // 最后读取位于调用者栈帧底部的第9个及以后的参数
+        let mut moves = Vec::new();
+        for (i, param) in self.params().iter().enumerate().take(ARG_REGS) {
+            let arg_reg = ARG_REG_NAMES[i];
+            match value_reg_map[param] {
+                Location::Reg(reg) => moves.push((Location::Reg(reg), Location::Reg(arg_reg))),
+                Location::Slot(slot) => pre.extend(store_value(arg_reg, &slot)),
+                _ => unreachable!(),
+            }
+        }
+        pre.extend(parallel_move(moves));
+        for (i, param) in self.params().iter().enumerate().skip(ARG_REGS) {
+            let caller_offset = stack_len + (i - ARG_REGS) as i32 * 4;
+            match value_reg_map[param] {
+                Location::Reg(reg) => pre.extend(stack_access(false, reg, caller_offset)),
+                Location::Slot(slot) => {
+                    pre.extend(stack_access(false, Reg::T5, caller_offset));
+                    pre.extend(store_value(Reg::T5, &slot));
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        let mut end = vec![RvInst::Label(end_label)];
+        if has_call {
+            end.extend(stack_access(false, Reg::Ra, ra_offset));
+        }
+        for (i, &reg) in saved_regs.iter().enumerate() {
+            end.extend(stack_access(false, reg, saved_offset + i as i32 * 4));
+        }
+        if stack_len != 0 {
+            end.extend(adjust_sp(stack_len));
+        }
+        end.push(RvInst::Ret);
+        pre.extend(body);
+        pre.extend(end);
+        (pre, Res::Nothing)
+    }
+}
+
+impl GenerateAsm for koopa::ir::entities::ValueData {
+    fn generate(
+        &self,
+        func: Option<&FunctionData>,
+        _register_id: &mut i32,
+        value_reg_map: &mut HashMap<Value, Location>,
+        source_prog: &koopa::ir::Program,
+    ) -> (Vec<RvInst>, Res) {
+        let mut insts = vec![];
+        let mut res = Res::Nothing;
+        match self.kind() {
+            ValueKind::Integer(int) => res = Res::Imm(int.value()),
+            // 未定义的值可以取任意值，这里取0
+            ValueKind::Undef(_) => res = Res::Imm(0),
+            ValueKind::Return(ret) => {
+                if let Some(ret_val) = ret.value() {
+                    insts.extend(load_operand(Reg::A0, ret_val, func.unwrap(), value_reg_map));
+                }
+                res = Res::Return;
+            }
+            ValueKind::Binary(exp) => {
+                let func = func.unwrap();
+                let op = exp.op();
+                // 不在寄存器中的左右操作数分别读入t5、t6，结果写入t5
+                let (lhs_insts, l) = operand(Reg::T5, exp.lhs(), func, value_reg_map);
+                let (rhs_insts, r) = operand(Reg::T6, exp.rhs(), func, value_reg_map);
+                insts.extend(lhs_insts);
+                insts.extend(rhs_insts);
+
+                // 找出对应操作
+                let t5 = Reg::T5;
+                match op {
+                    BinaryOp::Add => insts.push(RvInst::Binary(BinOp::Add, t5, l, r)),
+                    BinaryOp::Sub => insts.push(RvInst::Binary(BinOp::Sub, t5, l, r)),
+                    BinaryOp::Mul => insts.push(RvInst::Binary(BinOp::Mul, t5, l, r)),
+                    BinaryOp::Div => insts.push(RvInst::Binary(BinOp::Div, t5, l, r)),
+                    BinaryOp::Mod => insts.push(RvInst::Binary(BinOp::Rem, t5, l, r)),
+                    BinaryOp::And => insts.push(RvInst::Binary(BinOp::And, t5, l, r)),
+                    BinaryOp::Or => insts.push(RvInst::Binary(BinOp::Or, t5, l, r)),
+                    BinaryOp::Xor => insts.push(RvInst::Binary(BinOp::Xor, t5, l, r)),
+                    BinaryOp::Eq => {
+                        // a == b <==> (a xor b) == 0
+                        insts.push(RvInst::Binary(BinOp::Xor, t5, l, r));
+                        insts.push(RvInst::Seqz(t5, t5));
+                    }
+                    BinaryOp::NotEq => {
+                        // a != b <==> (a xor b) != 0
+                        insts.push(RvInst::Binary(BinOp::Xor, t5, l, r));
+                        insts.push(RvInst::Snez(t5, t5));
+                    }
+                    BinaryOp::Lt => insts.push(RvInst::Binary(BinOp::Slt, t5, l, r)),
+                    // a > b <==> b < a
+                    BinaryOp::Gt => insts.push(RvInst::Binary(BinOp::Slt, t5, r, l)),
+                    BinaryOp::Le => {
+                        // a <= b <==> !(b < a)
+                        insts.push(RvInst::Binary(BinOp::Slt, t5, r, l));
+                        insts.push(RvInst::Seqz(t5, t5));
+                    }
+                    BinaryOp::Ge => {
+                        // a >= b <==> !(a < b)
+                        insts.push(RvInst::Binary(BinOp::Slt, t5, l, r));
+                        insts.push(RvInst::Seqz(t5, t5));
+                    }
+                    // 前端与优化都不生成移位
+                    BinaryOp::Shl | BinaryOp::Shr | BinaryOp::Sar => {
+                        unreachable!("shifts are never generated")
+                    }
+                }
+                res = Res::Temp(t5);
+            }
+            // 局部alloc的栈槽在生成函数体之前已经预留
+            ValueKind::Alloc(_alloc) => {}
+            ValueKind::Load(load) => {
+                match value_reg_map.get(&load.src()) {
+                    None => unreachable!("pointers get a location before they are loaded from"),
+                    Some(loc) => insts.extend(memory_access(false, Reg::T5, loc)),
+                }
+                res = Res::Temp(Reg::T5);
+            }
+            ValueKind::Store(store) => {
+                let (value_insts, reg) =
+                    operand(Reg::T5, store.value(), func.unwrap(), value_reg_map);
+                insts.extend(value_insts);
+                match value_reg_map.get(&store.dest()) {
+                    Some(loc) => insts.extend(memory_access(true, reg, loc)),
+                    None => unreachable!("pointers get a location before they are stored to"),
+                }
+            }
+            ValueKind::GetElemPtr(gep) => {
+                // 数组第index个元素的地址: src + index * 元素大小
+                let func = func.unwrap();
+                let elem_size = pointee_size(self.ty());
+                let (src_insts, src) = operand(Reg::T5, gep.src(), func, value_reg_map);
+                insts.extend(src_insts);
+                insts.extend(offset_address(
+                    src,
+                    gep.index(),
+                    elem_size,
+                    func,
+                    value_reg_map,
+                ));
+                res = Res::Temp(Reg::T5);
+            }
+            ValueKind::GetPtr(get_ptr) => {
+                // 指针偏移index个其所指类型的大小: src + index * sizeof(*src)
+                let func = func.unwrap();
+                let stride = pointee_size(self.ty());
+                let (src_insts, src) = operand(Reg::T5, get_ptr.src(), func, value_reg_map);
+                insts.extend(src_insts);
+                insts.extend(offset_address(
+                    src,
+                    get_ptr.index(),
+                    stride,
+                    func,
+                    value_reg_map,
+                ));
+                res = Res::Temp(Reg::T5);
+            }
+            ValueKind::Jump(jump) => {
+                let func = func.unwrap();
+                insts.extend(block_args(jump.target(), jump.args(), func, value_reg_map));
+                insts.push(RvInst::J(bb_label(func, jump.target())));
+            }
+            ValueKind::Branch(branch) => {
+                let func = func.unwrap();
+                let (cond_insts, cond) = operand(Reg::T5, branch.cond(), func, value_reg_map);
+                insts.extend(cond_insts);
+                // 有实参的边先跳到传递实参的代码
+                let true_label = bb_label(func, branch.true_bb());
+                let true_edge = if branch.true_args().is_empty() {
+                    None
+                } else {
+                    Some(true_edge_label(func, self, branch.true_bb()))
+                };
+                insts.push(RvInst::Bnez(
+                    cond,
+                    true_edge.clone().unwrap_or(true_label.clone()),
+                ));
+                insts.extend(block_args(
+                    branch.false_bb(),
+                    branch.false_args(),
+                    func,
+                    value_reg_map,
+                ));
+                insts.push(RvInst::J(bb_label(func, branch.false_bb())));
+                if let Some(true_edge) = true_edge {
+                    insts.push(RvInst::Label(true_edge));
+                    insts.extend(block_args(
+                        branch.true_bb(),
+                        branch.true_args(),
+                        func,
+                        value_reg_map,
+                    ));
+                    insts.push(RvInst::J(true_label));
+                }
+            }
+            ValueKind::Call(call) => {
+                let func = func.unwrap();
+                // 前8个参数放入a0-a7，其余参数依次存入栈帧底部。
+                // 参数可能位于a0-a7中，因此先存栈上的参数，再做寄存器间的并行移动，
+                // 最后读入不在寄存器中的参数
+                for (i, &arg) in call.args().iter().enumerate().skip(ARG_REGS) {
+                    let (arg_insts, reg) = operand(Reg::T5, arg, func, value_reg_map);
+                    insts.extend(arg_insts);
+                    insts.extend(stack_access(true, reg, (i - ARG_REGS) as i32 * 4));
+                }
+                let mut moves = Vec::new();
+                for (i, arg) in call.args().iter().enumerate().take(ARG_REGS) {
+                    if let Some(&Location::Reg(reg)) = value_reg_map.get(arg) {
+                        moves.push((Location::Reg(ARG_REG_NAMES[i]), Location::Reg(reg)));
+                    }
+                }
+                insts.extend(parallel_move(moves));
+                for (i, &arg) in call.args().iter().enumerate().take(ARG_REGS) {
+                    if !matches!(value_reg_map.get(&arg), Some(Location::Reg(_))) {
+                        insts.extend(load_operand(ARG_REG_NAMES[i], arg, func, value_reg_map));
+                    }
+                }
+                let callee = &source_prog.func(call.callee()).name()[1..];
+                insts.push(RvInst::Call(callee.to_string()));
+                if !self.ty().is_unit() {
+                    res = Res::Temp(Reg::A0);
+                }
+            }
+            // 函数参数已在序言中放入各自的位置
+            ValueKind::FuncArgRef(_arg_val) => unreachable!(),
+            ValueKind::GlobalAlloc(globl_alloc) => {
+                let init_val = source_prog.borrow_value(globl_alloc.init());
+                let (init_insts, init_res) =
+                    init_val.generate(func, _register_id, value_reg_map, source_prog);
+                match init_res {
+                    Res::Imm(imm) => insts.push(RvInst::Word(imm)),
+                    _ => insts.extend(init_insts),
+                }
+            }
+            ValueKind::ZeroInit(_zero_init) => insts.push(RvInst::Zero(self.ty().size())),
+            ValueKind::Aggregate(aggregate) => {
+                // 连续的0合并为一条.zero
+                let mut zeros = 0;
+                for &elem in aggregate.elems() {
+                    let elem_data = source_prog.borrow_value(elem);
+                    match elem_data.kind() {
+                        ValueKind::Integer(int) if int.value() == 0 => zeros += 4,
+                        _ => {
+                            if zeros > 0 {
+                                insts.push(RvInst::Zero(zeros));
+                                zeros = 0;
+                            }
+                            let (elem_insts, elem_res) =
+                                elem_data.generate(func, _register_id, value_reg_map, source_prog);
+                            match elem_res {
+                                Res::Imm(imm) => insts.push(RvInst::Word(imm)),
+                                _ => insts.extend(elem_insts),
+                            }
+                        }
+                    }
+                }
+                if zeros > 0 {
+                    insts.push(RvInst::Zero(zeros));
+                }
+            }
+            // 基本块参数在其位置中读取，不生成指令
+            ValueKind::BlockArgRef(_) => unreachable!("block arguments are not instructions"),
+        }
+        (insts, res)
+    }
+}
+
+/// Generate RV32IM code for `program`. With `optimize` on values are
+/// allocated to registers and the code goes through the peephole optimizer;
+/// otherwise every value is spilled to its own stack slot (`-O0`).
+pub fn generate(program: &Program, optimize: bool) -> Vec<RvInst> {
+    // RV32 下指针占4字节
+    Type::set_ptr_size(4);
+    let mut register_recorder = 0;
+    let mut value_reg_map: HashMap<Value, Location> = HashMap::new();
+    if optimize {
+        // 各函数中的值互不相同，可以预先放入同一张表中
+        for &func in program.func_layout() {
+            let func_data = program.func(func);
+            if func_data.layout().entry_bb().is_some() {
+                for (value, reg) in regalloc::allocate(func_data) {
+                    value_reg_map.insert(value, Location::Reg(reg));
+                }
+            }
+        }
+    }
+    let mut insts = program
+        .generate(None, &mut register_recorder, &mut value_reg_map, program)
+        .0;
+    if optimize {
+        peephole::optimize(&mut insts);
+    }
+    insts
+}
+
+/// Generate RISC-V assembly for `program`, see [`generate`].
+pub fn koopa2asm(program: &Program, optimize: bool) -> String {
+    riscv::render(&generate(program, optimize))
+}
+
+/// Generate a relocatable ELF object for `program`, see [`generate`].
+pub fn koopa2obj(program: &Program, optimize: bool) -> Result<Vec<u8>, assemble::AssembleError> {
+    Ok(elf::write_elf(&assemble::assemble(&generate(
+        program, optimize,
+    ))?))
+}
@@ -0,0 +1,262 @@
+//! Peephole optimization of the generated code.
+//!
+//! The code generator handles one Koopa instruction at a time and passes
+//! every result through a scratch register. Adjacent instructions are
+//! rewritten until no rule applies:
+//!
+//! - an `sw` followed by an `lw` of the same address reuses the stored
+//!   register, and an `lw` followed by an `sw` of the same address drops the
+//!   store;
+//! - `li` followed by an instruction reading the loaded register becomes the
+//!   immediate form (`addi`, `slti`, `xori`, ...) when the value fits in 12
+//!   bits;
+//! - a result computed into a scratch register and then moved elsewhere is
+//!   computed into its destination directly;
+//! - `addi` of zero becomes a move, and a move to the same register is
+//!   removed;
+//! - a jump to the label right after it is removed.
+
+use super::riscv::{fits_imm12, BinOp, ImmOp, Reg, RvInst};
+
+/// Registers only used within the code of a single Koopa instruction, never
+/// live across a label or a jump.
+const SCRATCH: [Reg; 4] = [Reg::T3, Reg::T4, Reg::T5, Reg::T6];
+
+/// Optimize `insts` in place.
+pub fn optimize(insts: &mut Vec<RvInst>) {
+    // 改写后回退一条，新形成的相邻指令可能再次匹配
+    let mut i = 0;
+    while i + 1 < insts.len() {
+        if rewrite(insts, i) {
+            i = i.saturating_sub(1);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Whether the value of `reg` after instruction `index` is never read.
+fn dead_after(insts: &[RvInst], index: usize, reg: Reg) -> bool {
+    for inst in &insts[index + 1..] {
+        if matches!(
+            inst,
+            RvInst::Label(_)
+                | RvInst::Text
+                | RvInst::Data
+                | RvInst::Bss
+                | RvInst::Globl(_)
+                | RvInst::Word(_)
+                | RvInst::Zero(_)
+        ) {
+            break;
+        }
+        if inst.uses().contains(&reg) {
+            return false;
+        }
+        if inst.def() == Some(reg) {
+            return true;
+        }
+        if inst.ends_block() {
+            break;
+        }
+    }
+    // 临时寄存器不会跨越标号或跳转存活
+    SCRATCH.contains(&reg)
+}
+
+/// The immediate form of `op` reading `imm` in place of its second operand,
+/// if any.
+fn immediate_form(op: BinOp, imm: i32) -> Option<(ImmOp, i32)> {
+    let form = match op {
+        BinOp::Add => (ImmOp::Addi, imm),
+        BinOp::Sub => (ImmOp::Addi, imm.checked_neg()?),
+        BinOp::Slt => (ImmOp::Slti, imm),
+        BinOp::Sltu => (ImmOp::Sltiu, imm),
+        BinOp::Xor => (ImmOp::Xori, imm),
+        BinOp::And => (ImmOp::Andi, imm),
+        BinOp::Or => (ImmOp::Ori, imm),
+        _ => return None,
+    };
+    fits_imm12(form.1).then_some(form)
+}
+
+/// Apply a rule to instructions `i` and `i + 1`, if one matches.
+fn rewrite(insts: &mut Vec<RvInst>, i: usize) -> bool {
+    // 加0即复制
+    if let RvInst::BinaryImm(ImmOp::Addi, rd, rs, 0) = insts[i] {
+        insts[i] = RvInst::Mv(rd, rs);
+        return true;
+    }
+    if let RvInst::Mv(rd, rs) = insts[i] {
+        if rd == rs {
+            insts.remove(i);
+            return true;
+        }
+    }
+    match (&insts[i], &insts[i + 1]) {
+        // 跳转到紧随其后的标号
+        (RvInst::J(target), RvInst::Label(label)) if target == label => {
+            insts.remove(i);
+            true
+        }
+        // 刚存入内存的值仍在寄存器中
+        (&RvInst::Sw(rs, base, offset), &RvInst::Lw(rd, next_base, next_offset))
+            if (base, offset) == (next_base, next_offset) =>
+        {
+            if rs == rd {
+                insts.remove(i + 1);
+            } else {
+                insts[i + 1] = RvInst::Mv(rd, rs);
+            }
+            true
+        }
+        (&RvInst::Lw(rd, base, offset), &RvInst::Sw(rs, next_base, next_offset))
+            if (rd, base, offset) == (rs, next_base, next_offset) && base != rd =>
+        {
+            insts.remove(i + 1);
+            true
+        }
+        (&RvInst::Li(reg, imm), &RvInst::Binary(op, rd, rs1, rs2)) => {
+            let src = if rs2 == reg && rs1 != reg {
+                rs1
+            } else if op.is_commutative() && rs1 == reg && rs2 != reg {
+                rs2
+            } else {
+                return false;
+            };
+            let Some((imm_op, imm)) = immediate_form(op, imm) else {
+                return false;
+            };
+            if rd != reg && !dead_after(insts, i + 1, reg) {
+                return false;
+            }
+            insts[i + 1] = RvInst::BinaryImm(imm_op, rd, src, imm);
+            insts.remove(i);
+            true
+        }
+        // 结果直接写入目标寄存器
+        (inst, &RvInst::Mv(rd, rs))
+            if inst.def() == Some(rs) && SCRATCH.contains(&rs) && dead_after(insts, i + 1, rs) =>
+        {
+            insts[i].set_def(rd);
+            insts.remove(i + 1);
+            true
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn optimized(mut insts: Vec<RvInst>) -> Vec<RvInst> {
+        optimize(&mut insts);
+        insts
+    }
+
+    #[test]
+    fn store_then_load() {
+        let store = RvInst::Sw(Reg::T0, Reg::Sp, 4);
+        assert_eq!(
+            optimized(vec![store.clone(), RvInst::Lw(Reg::T1, Reg::Sp, 4)]),
+            [store.clone(), RvInst::Mv(Reg::T1, Reg::T0)]
+        );
+        assert_eq!(
+            optimized(vec![store.clone(), RvInst::Lw(Reg::T0, Reg::Sp, 4)]),
+            std::slice::from_ref(&store)
+        );
+        // 读出后原样写回的存储可以删去
+        let load = RvInst::Lw(Reg::T0, Reg::Sp, 8);
+        assert_eq!(
+            optimized(vec![load.clone(), RvInst::Sw(Reg::T0, Reg::Sp, 8)]),
+            [load]
+        );
+        // 不同地址之间不能互相替代
+        let insts = vec![store, RvInst::Lw(Reg::T1, Reg::Sp, 8)];
+        assert_eq!(optimized(insts.clone()), insts);
+    }
+
+    #[test]
+    fn immediate_operands() {
+        let insts = vec![
+            RvInst::Li(Reg::T5, 5),
+            RvInst::Binary(BinOp::Add, Reg::T0, Reg::T1, Reg::T5),
+            RvInst::Li(Reg::T5, 3),
+            RvInst::Binary(BinOp::Sub, Reg::T0, Reg::T0, Reg::T5),
+            RvInst::Ret,
+        ];
+        assert_eq!(
+            optimized(insts),
+            [
+                RvInst::BinaryImm(ImmOp::Addi, Reg::T0, Reg::T1, 5),
+                RvInst::BinaryImm(ImmOp::Addi, Reg::T0, Reg::T0, -3),
+                RvInst::Ret,
+            ]
+        );
+        // 超过12位的立即数，以及之后仍被读取的寄存器都要保留li
+        let insts = vec![
+            RvInst::Li(Reg::T5, 4096),
+            RvInst::Binary(BinOp::Add, Reg::T0, Reg::T1, Reg::T5),
+            RvInst::Li(Reg::T1, 7),
+            RvInst::Binary(BinOp::Add, Reg::T0, Reg::T0, Reg::T1),
+            RvInst::Mv(Reg::A0, Reg::T1),
+            RvInst::Ret,
+        ];
+        assert_eq!(optimized(insts.clone()), insts);
+    }
+
+    #[test]
+    fn fallthrough_jump() {
+        let label = ".Lmain.1".to_string();
+        assert_eq!(
+            optimized(vec![RvInst::J(label.clone()), RvInst::Label(label.clone())]),
+            [RvInst::Label(label.clone())]
+        );
+        let insts = vec![RvInst::J(label), RvInst::Label(".Lmain.2".to_string())];
+        assert_eq!(optimized(insts.clone()), insts);
+    }
+
+    #[test]
+    fn stack_frame() {
+        let program = |alloc: &str| {
+            let text = format!(
+                "decl @putint(i32)
+
+fun @main(): i32 {{
+%entry:
+  @a = alloc {alloc}
+  call @putint(1)
+  ret 0
+}}
+"
+            );
+            koopa::front::Driver::from(text).generate_program().unwrap()
+        };
+        let grow = |insts: &[RvInst]| {
+            let start = insts
+                .iter()
+                .position(|inst| *inst == RvInst::Label("main".to_string()));
+            insts[start.unwrap() + 1..].to_vec()
+        };
+        // 小的栈帧直接用addi调整sp，不经过t5
+        for optimize in [false, true] {
+            let insts = super::super::generate(&program("i32"), optimize);
+            assert_eq!(
+                grow(&insts)[0],
+                RvInst::BinaryImm(ImmOp::Addi, Reg::Sp, Reg::Sp, -16)
+            );
+            assert!(insts.contains(&RvInst::BinaryImm(ImmOp::Addi, Reg::Sp, Reg::Sp, 16)));
+            assert!(!insts.contains(&RvInst::Binary(BinOp::Add, Reg::Sp, Reg::Sp, Reg::T5)));
+        }
+        // 超过12位的栈帧大小先读入t5
+        let insts = super::super::generate(&program("[i32, 1000]"), true);
+        assert_eq!(
+            grow(&insts)[..2],
+            [
+                RvInst::Li(Reg::T5, -4016),
+                RvInst::Binary(BinOp::Add, Reg::Sp, Reg::Sp, Reg::T5),
+            ]
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use super::riscv::Reg;
+use koopa::ir::{BasicBlock, FunctionData, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+/// 调用者保存的可分配寄存器，t3-t6留作生成代码时的临时寄存器
+pub const CALLER_SAVED: [Reg; 11] = [
+    Reg::T0,
+    Reg::T1,
+    Reg::T2,
+    Reg::A0,
+    Reg::A1,
+    Reg::A2,
+    Reg::A3,
+    Reg::A4,
+    Reg::A5,
+    Reg::A6,
+    Reg::A7,
+];
+
+/// 被调用者保存的可分配寄存器
+pub const CALLEE_SAVED: [Reg; 12] = [
+    Reg::S0,
+    Reg::S1,
+    Reg::S2,
+    Reg::S3,
+    Reg::S4,
+    Reg::S5,
+    Reg::S6,
+    Reg::S7,
+    Reg::S8,
+    Reg::S9,
+    Reg::S10,
+    Reg::S11,
+];
+
+/// A live interval `[start, end]` of a value over the linearized function.
+struct Interval {
+    value: Value,
+    start: usize,
+    end: usize,
+    /// 区间内跨越了函数调用，只能放在被调用者保存的寄存器中
+    across_call: bool,
+}
+
+/// Whether a value is kept in a register: every non-unit value computed by an
+/// instruction, plus function and basic block parameters. Allocations are
+/// addressed relative to `sp` and constants are materialized on use.
+fn is_allocatable(func: &FunctionData, value: Value) -> bool {
+    if value.is_global() {
+        return false;
+    }
+    let data = func.dfg().value(value);
+    match data.kind() {
+        ValueKind::Binary(_)
+        | ValueKind::Load(_)
+        | ValueKind::GetPtr(_)
+        | ValueKind::GetElemPtr(_)
+        | ValueKind::FuncArgRef(_)
+        | ValueKind::BlockArgRef(_) => true,
+        ValueKind::Call(_) => !data.ty().is_unit(),
+        _ => false,
+    }
+}
+
+fn successors(func: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+    match func.layout().bbs().node(&bb).unwrap().insts().back_key() {
+        Some(&last) => func.dfg().value(last).kind().bb_uses().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Compute live intervals of every allocatable value. Uses of an instruction
+/// are placed at an even position and its definition right after, so that a
+/// result may reuse the register of an operand that dies there.
+fn live_intervals(func: &FunctionData) -> Vec<Interval> {
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+
+    // 每个基本块的 use/def 集合
+    let mut uses: HashMap<BasicBlock, HashSet<Value>> = HashMap::new();
+    let mut defs: HashMap<BasicBlock, HashSet<Value>> = HashMap::new();
+    for &bb in &bbs {
+        let mut bb_uses = HashSet::new();
+        let mut bb_defs: HashSet<Value> = func.dfg().bb(bb).params().iter().copied().collect();
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            for operand in func.dfg().value(inst).kind().value_uses() {
+                if is_allocatable(func, operand) && !bb_defs.contains(&operand) {
+                    bb_uses.insert(operand);
+                }
+            }
+            if is_allocatable(func, inst) {
+                bb_defs.insert(inst);
+            }
+        }
+        uses.insert(bb, bb_uses);
+        defs.insert(bb, bb_defs);
+    }
+
+    // 迭代求解活跃变量
+    let mut live_in: HashMap<BasicBlock, HashSet<Value>> =
+        bbs.iter().map(|&bb| (bb, HashSet::new())).collect();
+    let mut live_out = live_in.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &bb in bbs.iter().rev() {
+            let mut out = HashSet::new();
+            for succ in successors(func, bb) {
+                out.extend(live_in[&succ].iter().copied());
+            }
+            let mut new_in = uses[&bb].clone();
+            new_in.extend(out.difference(&defs[&bb]).copied());
+            if new_in.len() != live_in[&bb].len() {
+                changed = true;
+            }
+            live_in.insert(bb, new_in);
+            live_out.insert(bb, out);
+        }
+    }
+
+    // 按布局顺序编号，求每个值的活跃区间（取覆盖所有活跃点的最小区间）
+    let mut ranges: HashMap<Value, (usize, usize)> = HashMap::new();
+    let mut extend = |value: Value, pos: usize| {
+        let range = ranges.entry(value).or_insert((pos, pos));
+        range.0 = range.0.min(pos);
+        range.1 = range.1.max(pos);
+    };
+    for &param in func.params() {
+        extend(param, 1);
+    }
+    let mut calls = Vec::new();
+    let mut pos = 2;
+    for &bb in &bbs {
+        let label_pos = pos + 1;
+        for &param in func.dfg().bb(bb).params() {
+            extend(param, label_pos);
+        }
+        for &value in &live_in[&bb] {
+            extend(value, label_pos);
+        }
+        pos += 2;
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            let data = func.dfg().value(inst);
+            for operand in data.kind().value_uses() {
+                if is_allocatable(func, operand) {
+                    extend(operand, pos);
+                }
+            }
+            if is_allocatable(func, inst) {
+                extend(inst, pos + 1);
+            }
+            if let ValueKind::Call(_) = data.kind() {
+                calls.push(pos);
+            }
+            pos += 2;
+        }
+        for &value in &live_out[&bb] {
+            extend(value, pos - 1);
+        }
+    }
+
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(value, (start, end))| Interval {
+            value,
+            start,
+            end,
+            across_call: calls.iter().any(|&c| start < c && c < end),
+        })
+        .collect();
+    intervals.sort_by_key(|i| (i.start, i.end));
+    intervals
+}
+
+/// Assign registers to the values of `func` by linear scan over their live
+/// intervals. Values missing from the returned map are spilled to the stack.
+pub fn allocate(func: &FunctionData) -> HashMap<Value, Reg> {
+    let intervals = live_intervals(func);
+    let mut assigned: HashMap<Value, Reg> = HashMap::new();
+    // 正在占用寄存器的区间：(结束位置, 值, 寄存器)
+    let mut active: Vec<(usize, Value, Reg)> = Vec::new();
+    let mut free_caller: Vec<Reg> = CALLER_SAVED.iter().rev().copied().collect();
+    let mut free_callee: Vec<Reg> = CALLEE_SAVED.iter().rev().copied().collect();
+
+    for interval in &intervals {
+        // 释放已经结束的区间
+        active.retain(|&(end, _, reg)| {
+            if end < interval.start {
+                if CALLEE_SAVED.contains(&reg) {
+                    free_callee.push(reg);
+                } else {
+                    free_caller.push(reg);
+                }
+                false
+            } else {
+                true
+            }
+        });
+
+        let reg = if interval.across_call {
+            free_callee.pop()
+        } else {
+            free_caller.pop().or_else(|| free_callee.pop())
+        };
+        match reg {
+            Some(reg) => {
+                assigned.insert(interval.value, reg);
+                active.push((interval.end, interval.value, reg));
+            }
+            None => {
+                // 寄存器不足：溢出结束位置最远的区间
+                let victim = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, &(_, _, reg))| {
+                        !interval.across_call || CALLEE_SAVED.contains(&reg)
+                    })
+                    .max_by_key(|(_, &(end, _, _))| end)
+                    .map(|(idx, &entry)| (idx, entry));
+                if let Some((idx, (end, value, reg))) = victim {
+                    if end > interval.end {
+                        assigned.remove(&value);
+                        active.remove(idx);
+                        assigned.insert(interval.value, reg);
+                        active.push((interval.end, interval.value, reg));
+                    }
+                }
+            }
+        }
+    }
+    assigned
+}
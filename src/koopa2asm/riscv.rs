@@ -0,0 +1,281 @@
+//! RV32IM instructions as produced by the code generator.
+//!
+//! The backend builds a list of [`RvInst`]s and renders it to assembly text
+//! only at the end, so that later stages can inspect and rewrite the code.
+//! Only the instructions the generator needs are representable, which rules
+//! out mnemonics the assembler does not know.
+
+use std::fmt;
+
+/// An integer register, in the order of its number (`x0` to `x31`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Zero,
+    Ra,
+    Sp,
+    Gp,
+    Tp,
+    T0,
+    T1,
+    T2,
+    S0,
+    S1,
+    A0,
+    A1,
+    A2,
+    A3,
+    A4,
+    A5,
+    A6,
+    A7,
+    S2,
+    S3,
+    S4,
+    S5,
+    S6,
+    S7,
+    S8,
+    S9,
+    S10,
+    S11,
+    T3,
+    T4,
+    T5,
+    T6,
+}
+
+impl Reg {
+    /// ABI names, indexed by register number.
+    const NAMES: [&'static str; 32] = [
+        "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "s0", "s1", "a0", "a1", "a2", "a3", "a4",
+        "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+        "t5", "t6",
+    ];
+
+    /// The register number.
+    pub fn index(self) -> u32 {
+        self as u32
+    }
+
+    pub fn name(self) -> &'static str {
+        Reg::NAMES[self as usize]
+    }
+}
+
+impl fmt::Display for Reg {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{0}", self.name())
+    }
+}
+
+/// Operations on two registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Slt,
+    Sltu,
+}
+
+impl BinOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            BinOp::Add => "add",
+            BinOp::Sub => "sub",
+            BinOp::Mul => "mul",
+            BinOp::Div => "div",
+            BinOp::Rem => "rem",
+            BinOp::And => "and",
+            BinOp::Or => "or",
+            BinOp::Xor => "xor",
+            BinOp::Slt => "slt",
+            BinOp::Sltu => "sltu",
+        }
+    }
+
+    pub fn is_commutative(self) -> bool {
+        matches!(
+            self,
+            BinOp::Add | BinOp::Mul | BinOp::And | BinOp::Or | BinOp::Xor
+        )
+    }
+}
+
+/// Operations on a register and a 12-bit immediate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImmOp {
+    Addi,
+    Andi,
+    Ori,
+    Xori,
+    Slti,
+    Sltiu,
+}
+
+impl ImmOp {
+    pub fn mnemonic(self) -> &'static str {
+        match self {
+            ImmOp::Addi => "addi",
+            ImmOp::Andi => "andi",
+            ImmOp::Ori => "ori",
+            ImmOp::Xori => "xori",
+            ImmOp::Slti => "slti",
+            ImmOp::Sltiu => "sltiu",
+        }
+    }
+}
+
+/// Whether `imm` fits in the 12-bit signed immediate of an instruction.
+pub fn fits_imm12(imm: i32) -> bool {
+    (-2048..2048).contains(&imm)
+}
+
+/// An instruction, pseudo-instruction, label or directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RvInst {
+    /// `.text`
+    Text,
+    /// `.data`
+    Data,
+    /// `.bss`
+    Bss,
+    /// `.globl symbol`
+    Globl(String),
+    Label(String),
+    /// `.word value`
+    Word(i32),
+    /// `.zero bytes`
+    Zero(usize),
+    /// `op rd, rs1, rs2`
+    Binary(BinOp, Reg, Reg, Reg),
+    /// `op rd, rs1, imm`
+    BinaryImm(ImmOp, Reg, Reg, i32),
+    /// `lw rd, offset(base)`
+    Lw(Reg, Reg, i32),
+    /// `sw rs, offset(base)`
+    Sw(Reg, Reg, i32),
+    Li(Reg, i32),
+    /// `la rd, symbol`
+    La(Reg, String),
+    Mv(Reg, Reg),
+    Seqz(Reg, Reg),
+    Snez(Reg, Reg),
+    /// `bnez rs, label`
+    Bnez(Reg, String),
+    J(String),
+    Call(String),
+    Ret,
+}
+
+impl RvInst {
+    /// The register written, if any. Calls clobber every caller-saved
+    /// register and have none.
+    pub fn def(&self) -> Option<Reg> {
+        match *self {
+            RvInst::Binary(_, rd, _, _)
+            | RvInst::BinaryImm(_, rd, _, _)
+            | RvInst::Lw(rd, _, _)
+            | RvInst::Li(rd, _)
+            | RvInst::La(rd, _)
+            | RvInst::Mv(rd, _)
+            | RvInst::Seqz(rd, _)
+            | RvInst::Snez(rd, _) => Some(rd),
+            _ => None,
+        }
+    }
+
+    /// Replace the register written.
+    pub fn set_def(&mut self, reg: Reg) {
+        match self {
+            RvInst::Binary(_, rd, _, _)
+            | RvInst::BinaryImm(_, rd, _, _)
+            | RvInst::Lw(rd, _, _)
+            | RvInst::Li(rd, _)
+            | RvInst::La(rd, _)
+            | RvInst::Mv(rd, _)
+            | RvInst::Seqz(rd, _)
+            | RvInst::Snez(rd, _) => *rd = reg,
+            _ => unreachable!(),
+        }
+    }
+
+    /// The registers read.
+    pub fn uses(&self) -> Vec<Reg> {
+        match *self {
+            RvInst::Binary(_, _, rs1, rs2) => vec![rs1, rs2],
+            RvInst::BinaryImm(_, _, rs, _)
+            | RvInst::Lw(_, rs, _)
+            | RvInst::Mv(_, rs)
+            | RvInst::Seqz(_, rs)
+            | RvInst::Snez(_, rs)
+            | RvInst::Bnez(rs, _) => vec![rs],
+            RvInst::Sw(rs, base, _) => vec![rs, base],
+            RvInst::Call(_) => vec![
+                Reg::A0,
+                Reg::A1,
+                Reg::A2,
+                Reg::A3,
+                Reg::A4,
+                Reg::A5,
+                Reg::A6,
+                Reg::A7,
+            ],
+            RvInst::Ret => vec![Reg::A0],
+            _ => vec![],
+        }
+    }
+
+    /// Whether control may leave the straight-line code here.
+    pub fn ends_block(&self) -> bool {
+        matches!(
+            self,
+            RvInst::Bnez(..) | RvInst::J(_) | RvInst::Call(_) | RvInst::Ret
+        )
+    }
+}
+
+impl fmt::Display for RvInst {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RvInst::Text => write!(f, "\n\t.text"),
+            RvInst::Data => write!(f, "\t.data"),
+            RvInst::Bss => write!(f, "\t.bss"),
+            RvInst::Globl(symbol) => write!(f, "\t.globl {0}", symbol),
+            RvInst::Label(label) => write!(f, "{0}:", label),
+            RvInst::Word(value) => write!(f, "\t.word {0}", value),
+            RvInst::Zero(bytes) => write!(f, "\t.zero {0}", bytes),
+            RvInst::Binary(op, rd, rs1, rs2) => {
+                write!(f, "\t{0} {1}, {2}, {3}", op.mnemonic(), rd, rs1, rs2)
+            }
+            RvInst::BinaryImm(op, rd, rs, imm) => {
+                write!(f, "\t{0} {1}, {2}, {3}", op.mnemonic(), rd, rs, imm)
+            }
+            RvInst::Lw(rd, base, offset) => write!(f, "\tlw {0}, {1}({2})", rd, offset, base),
+            RvInst::Sw(rs, base, offset) => write!(f, "\tsw {0}, {1}({2})", rs, offset, base),
+            RvInst::Li(rd, imm) => write!(f, "\tli {0}, {1}", rd, imm),
+            RvInst::La(rd, symbol) => write!(f, "\tla {0}, {1}", rd, symbol),
+            RvInst::Mv(rd, rs) => write!(f, "\tmv {0}, {1}", rd, rs),
+            RvInst::Seqz(rd, rs) => write!(f, "\tseqz {0}, {1}", rd, rs),
+            RvInst::Snez(rd, rs) => write!(f, "\tsnez {0}, {1}", rd, rs),
+            RvInst::Bnez(rs, label) => write!(f, "\tbnez {0}, {1}", rs, label),
+            RvInst::J(label) => write!(f, "\tj {0}", label),
+            RvInst::Call(symbol) => write!(f, "\tcall {0}", symbol),
+            RvInst::Ret => write!(f, "\tret"),
+        }
+    }
+}
+
+/// Render instructions as assembly text, one per line.
+pub fn render(insts: &[RvInst]) -> String {
+    let mut s = String::new();
+    for inst in insts {
+        s += &format!("{0}\n", inst);
+    }
+    s
+}
@@ -0,0 +1,15 @@
+use lalrpop_util::lalrpop_mod;
+
+lalrpop_mod!(
+    #[allow(clippy::all)]
+    pub sysy
+);
+pub mod cli;
+pub mod diagnostics;
+pub mod difftest;
+pub mod emulator;
+pub mod fuzz;
+pub mod interpreter;
+pub mod ir_gen;
+pub mod koopa2asm;
+pub mod opt;
@@ -1,42 +1,249 @@
-#![allow(unused_variables)]
-
-use lalrpop_util::lalrpop_mod;
+use course_lab::cli::{self, Command, Emit, FormatOptions, FuzzOptions, InputFormat, Options};
+use course_lab::diagnostics::Diagnostic;
+use course_lab::difftest;
+use course_lab::fuzz::{self, minimize::minimize, random::random_program};
+use course_lab::ir_gen::ast::CompUnit;
+use course_lab::koopa2asm::{assemble::assemble, generate, koopa2asm, koopa2obj};
+use course_lab::{emulator, interpreter, opt, sysy};
+use koopa::back::KoopaGenerator;
 use std::env::args;
 use std::fs::{read_to_string, File};
-use std::io::{Result, Write};
-
-lalrpop_mod!(sysy);
-mod ir_gen;
-mod koopa2asm;
-use koopa2asm::koopa2asm;
-
-fn main() -> Result<()> {
-    let mut args = args();
-    args.next();
-    let mode = args.next().unwrap();
-    let input = args.next().unwrap();
-    args.next();
-    let output = args.next().unwrap();
-
-    let input = read_to_string(input)?;
-    let ast = sysy::CompUnitParser::new().parse(&input).unwrap();
-    let mut file = File::create(output)?;
-    let koopa_str = ast.generate_koopa();
-    if mode == "-koopa" {
-        let _err = write!(file, "{}", koopa_str);
-    } else if mode == "-riscv" {
-        let driver = koopa::front::Driver::from(koopa_str);
-        let program = driver.generate_program().unwrap();
-        let asm_str = koopa2asm(&program);
-        let _err = write!(file, "{}", asm_str);
-    } else if mode == "-all" {
-        let _err = write!(file, "{}", koopa_str);
-        let asm_output = args.next().unwrap();
-        file = File::create(asm_output)?;
-        let driver = koopa::front::Driver::from(koopa_str);
-        let program = driver.generate_program().unwrap();
-        let asm_str = koopa2asm(&program);
-        let _err1 = write!(file, "{}", asm_str);
-    }
-    Ok(())
+use std::io::{stderr, stdin, stdout, BufWriter, Read, Write};
+use std::panic;
+use std::process::exit;
+
+/// Print an error about the invocation itself and exit.
+fn fail(message: String) -> ! {
+    eprintln!("error: {0}", message);
+    exit(1)
+}
+
+fn main() {
+    let options = match cli::parse_args(args().skip(1)) {
+        Ok(Command::Compile(options)) => options,
+        Ok(Command::Format(options)) => {
+            format(&options);
+            return;
+        }
+        Ok(Command::Fuzz(options)) => {
+            fuzz(&options);
+            return;
+        }
+        Ok(Command::Help) => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(Command::Version) => {
+            println!("{0} {1}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+            return;
+        }
+        Err(message) => {
+            eprintln!("error: {0}\n\nFor more information, try `--help`.", message);
+            exit(2)
+        }
+    };
+    compile(&options);
+}
+
+/// Read the source file at `path`, or stdin for `-`.
+fn read_input(path: &str) -> String {
+    if path == "-" {
+        let mut input = String::new();
+        if let Err(err) = stdin().read_to_string(&mut input) {
+            fail(format!("cannot read stdin: {0}", err));
+        }
+        input
+    } else {
+        match read_to_string(path) {
+            Ok(input) => input,
+            Err(err) => fail(format!("cannot read `{0}`: {1}", path, err)),
+        }
+    }
+}
+
+/// Read and parse the file at `path`, written in `format`, reporting
+/// syntax errors and exiting if there are any.
+fn parse_input(path: &str, format: InputFormat) -> (String, CompUnit) {
+    let input = read_input(path);
+    let result = match format {
+        InputFormat::Sysy => sysy::CompUnitParser::new()
+            .parse(&input)
+            .map_err(|err| Diagnostic::from_parse_error(err, &input)),
+        InputFormat::AstJson => CompUnit::from_json(&input),
+        InputFormat::AstSexp => CompUnit::from_sexp(&input),
+    };
+    match result {
+        Ok(ast) => (input, ast),
+        Err(diagnostic) => {
+            eprint!("{}", diagnostic.render(input_name(path), &input));
+            exit(1)
+        }
+    }
+}
+
+fn input_name(path: &str) -> &str {
+    if path == "-" {
+        "<stdin>"
+    } else {
+        path
+    }
+}
+
+fn compile(options: &Options) {
+    let (input, mut ast) = parse_input(&options.input, options.input_format);
+
+    // 语义错误时打印所有诊断信息并以非零状态退出；
+    // 从AST读入时没有源码，只能给出源码中的字节范围
+    let report = |diagnostics: Vec<Diagnostic>| -> ! {
+        for diagnostic in &diagnostics {
+            match options.input_format {
+                InputFormat::Sysy => {
+                    eprint!("{}", diagnostic.render(input_name(&options.input), &input))
+                }
+                InputFormat::AstJson | InputFormat::AstSexp => eprintln!(
+                    "error[{0}]: {1} at bytes {2}..{3} of the source",
+                    diagnostic.code, diagnostic.message, diagnostic.span.start, diagnostic.span.end
+                ),
+            }
+        }
+        exit(1)
+    };
+    let symbols = match ast.analyze() {
+        Ok(symbols) => symbols,
+        Err(diagnostics) => report(diagnostics),
+    };
+    let mut program = match ast.generate_koopa(&symbols) {
+        Ok(program) => program,
+        Err(diagnostics) => report(diagnostics),
+    };
+    // -difftest以未优化的IR在解释器上的运行结果为参照
+    let reference = options
+        .difftest
+        .then(|| match ast.generate_koopa(&symbols) {
+            Ok(program) => program,
+            Err(diagnostics) => report(diagnostics),
+        });
+    let mut passes = match &options.passes {
+        Some(passes) => passes.clone(),
+        None => opt::pipeline(options.opt_level)
+            .into_iter()
+            .map(String::from)
+            .collect(),
+    };
+    if options.no_inline {
+        passes.retain(|pass| pass != "inline");
+    }
+    let mut passman = opt::PassManager::new(&passes).unwrap_or_else(|message| fail(message));
+    passman.print_after = options.print_after.clone();
+    passman.time_passes = options.time_passes;
+    if let Err(err) = passman.run(&mut program, &mut stderr().lock()) {
+        fail(format!("cannot write to stderr: {0}", err));
+    }
+
+    for (emit, path) in &options.emits {
+        let bytes = match emit {
+            Emit::Ast => format!("{:#?}\n", ast).into_bytes(),
+            Emit::AstJson => ast.to_json().into_bytes(),
+            Emit::AstSexp => ast.to_sexp().into_bytes(),
+            Emit::Koopa => {
+                let mut generator = KoopaGenerator::new(Vec::new());
+                generator.generate_on(&program).unwrap();
+                generator.writer()
+            }
+            Emit::Riscv => koopa2asm(&program, options.opt_level > 0).into_bytes(),
+            Emit::Obj => koopa2obj(&program, options.opt_level > 0)
+                .unwrap_or_else(|err| fail(format!("cannot assemble: {0}", err))),
+        };
+        write_output(path, &bytes);
+    }
+
+    if options.interpret {
+        let output = BufWriter::new(stdout().lock());
+        match interpreter::interpret(&program, stdin().lock(), output) {
+            Ok(ret) => exit(ret),
+            Err(err) => fail(format!("runtime error: {0}", err)),
+        }
+    }
+
+    if options.run_riscv {
+        let obj = assemble(&generate(&program, options.opt_level > 0))
+            .unwrap_or_else(|err| fail(format!("cannot assemble: {0}", err)));
+        let output = BufWriter::new(stdout().lock());
+        match emulator::run(&obj, stdin().lock(), output, None) {
+            Ok(result) => {
+                eprintln!(
+                    "exited with code {0} after {1} instructions",
+                    result.code, result.steps
+                );
+                exit(result.code)
+            }
+            Err(err) => fail(format!("runtime error: {0}", err)),
+        }
+    }
+
+    if let Some(reference) = &reference {
+        let mut stdin_bytes = Vec::new();
+        if let Err(err) = stdin().read_to_end(&mut stdin_bytes) {
+            fail(format!("cannot read stdin: {0}", err));
+        }
+        match difftest::compare(
+            reference,
+            &program,
+            &stdin_bytes,
+            options.opt_level > 0,
+            None,
+        ) {
+            Ok(ret) => eprintln!("both runs agree, `main` returned {0}", ret),
+            Err(divergence) => fail(divergence.to_string()),
+        }
+    }
+}
+
+/// Reformat the source file of `options`.
+fn format(options: &FormatOptions) {
+    let (input, unit) = parse_input(&options.input, options.input_format);
+    // SysY没有字符串字面量，源码中出现注释的开头就一定是注释
+    if options.in_place && (input.contains("//") || input.contains("/*")) {
+        fail(format!(
+            "`{0}` has comments, which formatting would remove",
+            options.input
+        ));
+    }
+    write_output(&options.output, format!("{0}\n", unit).as_bytes());
+}
+
+/// Check the random programs of `options`, printing the first one that
+/// fails after minimizing it.
+fn fuzz(options: &FuzzOptions) {
+    // 编译器的panic会作为失败报告，不必打印
+    panic::set_hook(Box::new(|_| {}));
+    for seed in options.seed..options.seed + options.count {
+        let unit = random_program(seed);
+        let failure = match fuzz::check(&unit, options.opt_level) {
+            Ok(()) => continue,
+            Err(failure) => failure,
+        };
+        eprintln!("error: seed {0}: {1}", seed, failure);
+        eprintln!("minimizing the program...");
+        let unit = minimize(unit, |unit| {
+            fuzz::check(unit, options.opt_level).is_err_and(|f| f.same_kind(&failure))
+        });
+        println!("{0}", unit);
+        let failure = fuzz::check(&unit, options.opt_level).unwrap_err();
+        fail(format!("minimized program: {0}", failure));
+    }
+    drop(panic::take_hook());
+    eprintln!("{0} random programs passed", options.count);
+}
+
+/// Write `bytes` to the file at `path`, or to stdout for `-`.
+fn write_output(path: &str, bytes: &[u8]) {
+    let result = if path == "-" {
+        stdout().lock().write_all(bytes)
+    } else {
+        File::create(path).and_then(|mut file| file.write_all(bytes))
+    };
+    if let Err(err) = result {
+        fail(format!("cannot write `{0}`: {1}", path, err));
+    }
 }
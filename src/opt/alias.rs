@@ -0,0 +1,176 @@
+//! Which memory accesses may overlap.
+//!
+//! SysY has no pointer variables: a pointer is either the address of an
+//! allocation or global, an element of one, or an array parameter, which
+//! points into memory of a caller or into a global. Each pointer is traced
+//! back to such a [`Root`], and accesses with different roots never overlap
+//! unless one of them may point anywhere.
+
+use koopa::ir::{FunctionData, TypeKind, Value, ValueKind};
+use std::collections::{HashMap, HashSet};
+
+/// What a pointer points into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Root {
+    /// 局部分配或全局变量
+    Object(Value),
+    /// 由函数的数组参数得到
+    Param,
+    Unknown,
+}
+
+pub struct AliasAnalysis {
+    /// 指针类型的基本块参数指向的对象
+    params: HashMap<Value, Root>,
+    /// 地址被传给函数调用的局部分配
+    escaped: HashSet<Value>,
+}
+
+impl AliasAnalysis {
+    pub fn new(func: &FunctionData) -> Self {
+        let mut alias = AliasAnalysis {
+            params: HashMap::new(),
+            escaped: HashSet::new(),
+        };
+        // 每个指针参数在各条入边上的实参
+        let mut incoming: HashMap<Value, Vec<Value>> = HashMap::new();
+        for (_, node) in func.layout().bbs() {
+            for &inst in node.insts().keys() {
+                let edges = match func.dfg().value(inst).kind() {
+                    ValueKind::Jump(jump) => vec![(jump.target(), jump.args())],
+                    ValueKind::Branch(branch) => vec![
+                        (branch.true_bb(), branch.true_args()),
+                        (branch.false_bb(), branch.false_args()),
+                    ],
+                    _ => vec![],
+                };
+                for (target, args) in edges {
+                    for (&param, &arg) in func.dfg().bb(target).params().iter().zip(args) {
+                        if is_pointer(func, param) {
+                            incoming.entry(param).or_default().push(arg);
+                        }
+                    }
+                }
+            }
+        }
+        // 参数的根是所有实参共同的根；从未确定开始迭代，循环中传回自身的实参不影响结果
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (&param, args) in &incoming {
+                let mut root = None;
+                for &arg in args {
+                    let arg_root = match alias.trace(func, arg) {
+                        Some(arg_root) => arg_root,
+                        None => continue,
+                    };
+                    root = match root {
+                        None => Some(arg_root),
+                        Some(root) if root == arg_root => Some(root),
+                        Some(_) => Some(Root::Unknown),
+                    };
+                }
+                if let Some(root) = root {
+                    if alias.params.insert(param, root) != Some(root) {
+                        changed = true;
+                    }
+                }
+            }
+        }
+        for (_, node) in func.layout().bbs() {
+            for &inst in node.insts().keys() {
+                if let ValueKind::Call(call) = func.dfg().value(inst).kind() {
+                    for &arg in call.args() {
+                        if let Root::Object(object) = alias.root(func, arg) {
+                            alias.escaped.insert(object);
+                        }
+                    }
+                }
+            }
+        }
+        alias
+    }
+
+    /// What `ptr` points into.
+    pub fn root(&self, func: &FunctionData, ptr: Value) -> Root {
+        self.trace(func, ptr).unwrap_or(Root::Unknown)
+    }
+
+    /// Like [`root`](Self::root), but `None` for block parameters not
+    /// determined yet.
+    fn trace(&self, func: &FunctionData, ptr: Value) -> Option<Root> {
+        if ptr.is_global() {
+            return Some(Root::Object(ptr));
+        }
+        match func.dfg().value(ptr).kind() {
+            ValueKind::Alloc(_) => Some(Root::Object(ptr)),
+            ValueKind::GetPtr(get_ptr) => self.trace(func, get_ptr.src()),
+            ValueKind::GetElemPtr(gep) => self.trace(func, gep.src()),
+            ValueKind::FuncArgRef(_) => Some(Root::Param),
+            ValueKind::BlockArgRef(_) => self.params.get(&ptr).copied(),
+            _ => Some(Root::Unknown),
+        }
+    }
+
+    /// Whether accesses through pointers with roots `a` and `b` may overlap.
+    pub fn may_alias(&self, a: Root, b: Root) -> bool {
+        match (a, b) {
+            (Root::Object(a), Root::Object(b)) => a == b,
+            // 数组参数不会指向本函数的局部分配
+            (Root::Param, Root::Object(object)) | (Root::Object(object), Root::Param) => {
+                object.is_global()
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether the pointers `a` and `b` may point to overlapping memory.
+    /// Beyond their roots, elements at different constant indices of the
+    /// same array are told apart.
+    pub fn may_alias_ptrs(&self, func: &FunctionData, a: Value, b: Value) -> bool {
+        if a == b {
+            return true;
+        }
+        if let (Some((a_elem, a_src, a_index)), Some((b_elem, b_src, b_index))) =
+            (element(func, a), element(func, b))
+        {
+            if a_elem == b_elem
+                && a_src == b_src
+                && matches!((a_index, b_index), (Some(x), Some(y)) if x != y)
+            {
+                return false;
+            }
+        }
+        self.may_alias(self.root(func, a), self.root(func, b))
+    }
+
+    /// Whether a call may read or write memory with root `root`.
+    pub fn call_may_access(&self, root: Root) -> bool {
+        match root {
+            Root::Object(object) => object.is_global() || self.escaped.contains(&object),
+            _ => true,
+        }
+    }
+}
+
+fn is_pointer(func: &FunctionData, value: Value) -> bool {
+    matches!(func.dfg().value(value).ty().kind(), TypeKind::Pointer(_))
+}
+
+/// For an element address, whether it comes from `getelemptr`, the array
+/// pointer and the index if constant.
+fn element(func: &FunctionData, ptr: Value) -> Option<(bool, Value, Option<i32>)> {
+    if ptr.is_global() {
+        return None;
+    }
+    let (elem, src, index) = match func.dfg().value(ptr).kind() {
+        ValueKind::GetElemPtr(gep) => (true, gep.src(), gep.index()),
+        ValueKind::GetPtr(get_ptr) => (false, get_ptr.src(), get_ptr.index()),
+        _ => return None,
+    };
+    let index = match func.dfg().value(index).kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    };
+    Some((elem, src, index))
+}
@@ -0,0 +1,131 @@
+//! Dead code elimination.
+//!
+//! Starting from the instructions with side effects, everything they use is
+//! marked live; whatever is left unmarked is removed. A block parameter is
+//! only live when an instruction uses it, so parameters that merely pass
+//! their value around a loop are removed together with their arguments.
+
+use super::{remove_insts, terminator, update_edges};
+use koopa::ir::{BasicBlock, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+
+pub struct DeadCodeElim;
+
+impl FunctionPass for DeadCodeElim {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_some() {
+            eliminate(data);
+        }
+    }
+}
+
+/// Whether an instruction must be kept even if its result is unused.
+fn has_side_effect(kind: &ValueKind) -> bool {
+    matches!(
+        kind,
+        ValueKind::Store(_)
+            | ValueKind::Call(_)
+            | ValueKind::Return(_)
+            | ValueKind::Branch(_)
+            | ValueKind::Jump(_)
+    )
+}
+
+fn eliminate(func: &mut FunctionData) {
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    // 基本块参数所在的块及其序号
+    let mut params: HashMap<Value, (BasicBlock, usize)> = HashMap::new();
+    for &bb in &bbs {
+        for (i, &param) in func.dfg().bb(bb).params().iter().enumerate() {
+            params.insert(param, (bb, i));
+        }
+    }
+    // 每条边传给目标块第i个参数的实参
+    let mut edge_args: HashMap<(BasicBlock, usize), Vec<Value>> = HashMap::new();
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    for (_, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            let kind = func.dfg().value(inst).kind();
+            let edges = match kind {
+                ValueKind::Jump(jump) => vec![(jump.target(), jump.args())],
+                ValueKind::Branch(branch) => vec![
+                    (branch.true_bb(), branch.true_args()),
+                    (branch.false_bb(), branch.false_args()),
+                ],
+                _ => vec![],
+            };
+            for (target, args) in edges {
+                for (i, &arg) in args.iter().enumerate() {
+                    edge_args.entry((target, i)).or_default().push(arg);
+                }
+            }
+            if has_side_effect(kind) {
+                live.insert(inst);
+                worklist.push(inst);
+            }
+        }
+    }
+
+    while let Some(value) = worklist.pop() {
+        // 参数活跃时，传给它的实参也活跃；跳转本身不使用实参
+        let uses: Vec<Value> = match params.get(&value) {
+            Some(edge) => edge_args.get(edge).cloned().unwrap_or_default(),
+            None => match func.dfg().value(value).kind() {
+                ValueKind::Jump(_) => vec![],
+                ValueKind::Branch(branch) => vec![branch.cond()],
+                kind => kind.value_uses().collect(),
+            },
+        };
+        for used in uses {
+            if !used.is_global() && live.insert(used) {
+                worklist.push(used);
+            }
+        }
+    }
+
+    // 删除死参数及其在每条入边上的实参
+    let mut dead_params: HashMap<BasicBlock, Vec<usize>> = HashMap::new();
+    for (&param, &(bb, i)) in &params {
+        if !live.contains(&param) {
+            dead_params.entry(bb).or_default().push(i);
+        }
+    }
+    let mut dead: Vec<Value> = Vec::new();
+    for &bb in &bbs {
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            if !live.contains(&inst) {
+                dead.push(inst);
+            }
+        }
+    }
+    if !dead_params.is_empty() {
+        for &bb in &bbs {
+            let term = terminator(func, bb);
+            update_edges(func, term, |target, args| {
+                if let Some(indices) = dead_params.get(target) {
+                    *args = without(args, indices);
+                }
+            });
+        }
+    }
+    remove_insts(func, dead);
+    for (bb, indices) in dead_params {
+        let params = func.dfg().bb(bb).params().to_vec();
+        *func.dfg_mut().bb_mut(bb).params_mut() = without(&params, &indices);
+        for i in indices {
+            func.dfg_mut().remove_value(params[i]);
+        }
+    }
+}
+
+/// `values` without the elements at `indices`.
+fn without(values: &[Value], indices: &[usize]) -> Vec<Value> {
+    values
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !indices.contains(i))
+        .map(|(_, &value)| value)
+        .collect()
+}
@@ -0,0 +1,147 @@
+use super::successors;
+use koopa::ir::{BasicBlock, FunctionData};
+use std::collections::{HashMap, HashSet};
+
+/// Dominator tree of the blocks reachable from the entry, computed with the
+/// iterative algorithm of Cooper, Harvey and Kennedy.
+pub struct DomTree {
+    /// 可达基本块的逆后序，第一个为入口
+    rpo: Vec<BasicBlock>,
+    /// 除入口外每个可达基本块的直接支配者
+    idom: HashMap<BasicBlock, BasicBlock>,
+    children: HashMap<BasicBlock, Vec<BasicBlock>>,
+}
+
+impl DomTree {
+    pub fn new(func: &FunctionData, preds: &HashMap<BasicBlock, Vec<BasicBlock>>) -> Self {
+        let entry = func.layout().entry_bb().unwrap();
+        let rpo = reverse_postorder(func, entry);
+        let order: HashMap<BasicBlock, usize> =
+            rpo.iter().enumerate().map(|(i, &bb)| (bb, i)).collect();
+
+        // 迭代求直接支配者，入口暂时视为自身的支配者
+        let mut idom: HashMap<BasicBlock, BasicBlock> = HashMap::from([(entry, entry)]);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &bb in &rpo[1..] {
+                let mut new_idom = None;
+                for &pred in &preds[&bb] {
+                    if !idom.contains_key(&pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => intersect(&idom, &order, pred, other),
+                    });
+                }
+                let new_idom = new_idom.unwrap();
+                if idom.insert(bb, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+        idom.remove(&entry);
+
+        let mut children: HashMap<BasicBlock, Vec<BasicBlock>> = HashMap::new();
+        for &bb in &rpo[1..] {
+            children.entry(idom[&bb]).or_default().push(bb);
+        }
+        DomTree {
+            rpo,
+            idom,
+            children,
+        }
+    }
+
+    pub fn is_reachable(&self, bb: BasicBlock) -> bool {
+        bb == self.rpo[0] || self.idom.contains_key(&bb)
+    }
+
+    /// The reachable blocks in reverse postorder, starting with the entry.
+    pub fn rpo(&self) -> &[BasicBlock] {
+        &self.rpo
+    }
+
+    /// Whether `a` dominates `b`, which must be reachable.
+    pub fn dominates(&self, a: BasicBlock, mut b: BasicBlock) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idom.get(&b) {
+                Some(&idom) => b = idom,
+                None => return false,
+            }
+        }
+    }
+
+    /// Blocks immediately dominated by `bb`, in reverse postorder.
+    pub fn children(&self, bb: BasicBlock) -> &[BasicBlock] {
+        self.children.get(&bb).map_or(&[], |children| children)
+    }
+
+    /// Dominance frontier of every reachable block.
+    pub fn frontiers(
+        &self,
+        preds: &HashMap<BasicBlock, Vec<BasicBlock>>,
+    ) -> HashMap<BasicBlock, HashSet<BasicBlock>> {
+        let mut frontiers: HashMap<BasicBlock, HashSet<BasicBlock>> = HashMap::new();
+        for &bb in &self.rpo[1..] {
+            let reachable: Vec<BasicBlock> = preds[&bb]
+                .iter()
+                .copied()
+                .filter(|&pred| self.is_reachable(pred))
+                .collect();
+            if reachable.len() < 2 {
+                continue;
+            }
+            // 从每个前驱沿支配树上行到bb的直接支配者，途经的块的支配边界都包含bb
+            for mut runner in reachable {
+                while runner != self.idom[&bb] {
+                    frontiers.entry(runner).or_default().insert(bb);
+                    runner = self.idom[&runner];
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+/// Nearest common dominator of `a` and `b`.
+fn intersect(
+    idom: &HashMap<BasicBlock, BasicBlock>,
+    order: &HashMap<BasicBlock, usize>,
+    mut a: BasicBlock,
+    mut b: BasicBlock,
+) -> BasicBlock {
+    while a != b {
+        while order[&a] > order[&b] {
+            a = idom[&a];
+        }
+        while order[&b] > order[&a] {
+            b = idom[&b];
+        }
+    }
+    a
+}
+
+/// Blocks reachable from `entry`, in reverse postorder.
+pub fn reverse_postorder(func: &FunctionData, entry: BasicBlock) -> Vec<BasicBlock> {
+    let mut postorder = Vec::new();
+    let mut visited = HashSet::from([entry]);
+    // 栈中保存基本块及其尚未访问的后继
+    let mut stack = vec![(entry, successors(func, entry))];
+    while let Some((_, succs)) = stack.last_mut() {
+        match succs.pop() {
+            Some(succ) => {
+                if visited.insert(succ) {
+                    stack.push((succ, successors(func, succ)));
+                }
+            }
+            None => postorder.push(stack.pop().unwrap().0),
+        }
+    }
+    postorder.reverse();
+    postorder
+}
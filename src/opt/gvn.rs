@@ -0,0 +1,172 @@
+//! Global value numbering.
+//!
+//! The blocks are visited along the dominator tree with a table of the pure
+//! expressions computed on the way. An instruction computing an expression
+//! already in the table is replaced by the earlier instruction, which
+//! dominates it. Integer constants are compared by value, and the operands of
+//! commutative operators in either order.
+//!
+//! Loads are numbered by their address, and a store makes the stored value
+//! known to later loads of its address. A store or call forgets what it may
+//! overwrite according to the alias model. What is known about memory only
+//! flows into a block whose single predecessor is its immediate dominator,
+//! since other paths may write memory in between.
+
+use super::alias::AliasAnalysis;
+use super::dominators::DomTree;
+use super::{predecessors, remove_insts, replace_operands};
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::HashMap;
+
+pub struct Gvn;
+
+impl FunctionPass for Gvn {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_some() {
+            number(data);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Operand {
+    Int(i32),
+    Value(Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Expr {
+    Binary(BinaryOp, Operand, Operand),
+    GetElemPtr(Operand, Operand),
+    GetPtr(Operand, Operand),
+}
+
+fn operand(func: &FunctionData, value: Value) -> Operand {
+    if !value.is_global() {
+        if let ValueKind::Integer(int) = func.dfg().value(value).kind() {
+            return Operand::Int(int.value());
+        }
+    }
+    Operand::Value(value)
+}
+
+fn is_commutative(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Add
+            | BinaryOp::Mul
+            | BinaryOp::And
+            | BinaryOp::Or
+            | BinaryOp::Xor
+            | BinaryOp::Eq
+            | BinaryOp::NotEq
+    )
+}
+
+fn number(func: &mut FunctionData) {
+    let preds = predecessors(func);
+    let dom = DomTree::new(func, &preds);
+    let alias = AliasAnalysis::new(func);
+    let mut replace: HashMap<Value, Value> = HashMap::new();
+    let mut removed = Vec::new();
+
+    // 栈中保存基本块、入口处可用的表达式，以及已知的各地址的内容
+    let entry = func.layout().entry_bb().unwrap();
+    let mut stack = vec![(entry, HashMap::new(), HashMap::new())];
+    while let Some((bb, mut exprs, mut memory)) = stack.pop() {
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for inst in insts {
+            replace_operands(func, inst, &replace);
+            let (key, swapped) = match func.dfg().value(inst).kind() {
+                ValueKind::Binary(exp) => {
+                    let (lhs, rhs) = (operand(func, exp.lhs()), operand(func, exp.rhs()));
+                    let swapped =
+                        is_commutative(exp.op()).then_some(Expr::Binary(exp.op(), rhs, lhs));
+                    (Expr::Binary(exp.op(), lhs, rhs), swapped)
+                }
+                ValueKind::GetElemPtr(gep) => {
+                    let (src, index) = (operand(func, gep.src()), operand(func, gep.index()));
+                    (Expr::GetElemPtr(src, index), None)
+                }
+                ValueKind::GetPtr(get_ptr) => {
+                    let (src, index) =
+                        (operand(func, get_ptr.src()), operand(func, get_ptr.index()));
+                    (Expr::GetPtr(src, index), None)
+                }
+                ValueKind::Load(load) => {
+                    match memory.get(&load.src()) {
+                        Some(&known) => {
+                            replace.insert(inst, known);
+                            removed.push(inst);
+                        }
+                        None => {
+                            memory.insert(load.src(), inst);
+                        }
+                    }
+                    continue;
+                }
+                ValueKind::Store(store) => {
+                    let dest = store.dest();
+                    memory.retain(|&addr, _| !alias.may_alias_ptrs(func, addr, dest));
+                    memory.insert(dest, store.value());
+                    continue;
+                }
+                ValueKind::Call(_) => {
+                    memory.retain(|&addr, _| !alias.call_may_access(alias.root(func, addr)));
+                    continue;
+                }
+                _ => continue,
+            };
+            let leader = exprs
+                .get(&key)
+                .or_else(|| swapped.and_then(|swapped| exprs.get(&swapped)));
+            match leader {
+                Some(&leader) => {
+                    replace.insert(inst, leader);
+                    removed.push(inst);
+                }
+                None => {
+                    exprs.insert(key, inst);
+                }
+            }
+        }
+        for &child in dom.children(bb) {
+            let memory = if preds[&child] == [bb] {
+                memory.clone()
+            } else {
+                HashMap::new()
+            };
+            stack.push((child, exprs.clone(), memory));
+        }
+    }
+
+    // 不可达的块也可能使用被替换的值
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        if dom.is_reachable(bb) {
+            continue;
+        }
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for inst in insts {
+            replace_operands(func, inst, &replace);
+        }
+    }
+    remove_insts(func, removed);
+}
@@ -0,0 +1,409 @@
+//! Function inlining.
+//!
+//! Calls to small functions are replaced by a copy of the callee's body: the
+//! calling block is split after the call, the arguments take the place of the
+//! parameters, and every `ret` becomes a jump to the rest of the calling block,
+//! which receives the returned value as a parameter. Array parameters are
+//! plain pointers, so the pointer passed by the caller is used directly.
+//!
+//! Functions are visited callees first, so a small function that became
+//! larger by inlining is measured at its new size. Functions on a cycle of the
+//! call graph are never inlined.
+
+use super::dominators::reverse_postorder;
+use super::{remove_insts, replace_operands};
+use koopa::ir::builder::{BasicBlockBuilder, LocalInstBuilder, ValueBuilder};
+use koopa::ir::entities::ValueData;
+use koopa::ir::{BasicBlock, Function, FunctionData, Program, TypeKind, Value, ValueKind};
+use koopa::opt::ModulePass;
+use std::collections::{HashMap, HashSet};
+
+/// Functions with more instructions than this are not inlined.
+const CALLEE_SIZE_LIMIT: usize = 40;
+/// No more calls are inlined into a function once it has this many
+/// instructions.
+const CALLER_SIZE_LIMIT: usize = 2000;
+
+pub struct Inline;
+
+impl ModulePass for Inline {
+    fn run_on(&mut self, program: &mut Program) {
+        let calls: HashMap<Function, Vec<Function>> = program
+            .func_layout()
+            .iter()
+            .map(|&func| (func, callees(program.func(func))))
+            .collect();
+        let recursive = recursive_funcs(program, &calls);
+        let mut sizes: HashMap<Function, usize> = program
+            .func_layout()
+            .iter()
+            .map(|&func| (func, size(program.func(func))))
+            .collect();
+        for caller in post_order(program, &calls) {
+            for (call, callee) in call_sites(program.func(caller)) {
+                let data = program.func(callee);
+                if callee == caller
+                    || recursive.contains(&callee)
+                    || data.layout().entry_bb().is_none()
+                    || sizes[&callee] > CALLEE_SIZE_LIMIT
+                    || sizes[&caller] >= CALLER_SIZE_LIMIT
+                {
+                    continue;
+                }
+                let body = Body::new(data);
+                inline_call(program.func_mut(caller), call, &body);
+                *sizes.get_mut(&caller).unwrap() += sizes[&callee];
+            }
+        }
+    }
+}
+
+/// Number of instructions in `func`.
+fn size(func: &FunctionData) -> usize {
+    func.layout()
+        .bbs()
+        .nodes()
+        .map(|node| node.insts().len())
+        .sum()
+}
+
+/// Calls in `func` and the functions they call, in layout order.
+fn call_sites(func: &FunctionData) -> Vec<(Value, Function)> {
+    let mut sites = Vec::new();
+    for (_, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            if let ValueKind::Call(call) = func.dfg().value(inst).kind() {
+                sites.push((inst, call.callee()));
+            }
+        }
+    }
+    sites
+}
+
+/// Functions called by `func`, each listed once.
+fn callees(func: &FunctionData) -> Vec<Function> {
+    let mut callees = Vec::new();
+    for (_, callee) in call_sites(func) {
+        if !callees.contains(&callee) {
+            callees.push(callee);
+        }
+    }
+    callees
+}
+
+/// Functions that can reach themselves in the call graph.
+fn recursive_funcs(
+    program: &Program,
+    calls: &HashMap<Function, Vec<Function>>,
+) -> HashSet<Function> {
+    let mut recursive = HashSet::new();
+    for &func in program.func_layout() {
+        let mut visited = HashSet::new();
+        let mut worklist = calls[&func].clone();
+        while let Some(callee) = worklist.pop() {
+            if callee == func {
+                recursive.insert(func);
+                break;
+            }
+            if visited.insert(callee) {
+                worklist.extend(&calls[&callee]);
+            }
+        }
+    }
+    recursive
+}
+
+/// Every function after the functions it calls, apart from calls on cycles.
+fn post_order(program: &Program, calls: &HashMap<Function, Vec<Function>>) -> Vec<Function> {
+    fn visit(
+        func: Function,
+        calls: &HashMap<Function, Vec<Function>>,
+        visited: &mut HashSet<Function>,
+        order: &mut Vec<Function>,
+    ) {
+        if visited.insert(func) {
+            for &callee in &calls[&func] {
+                visit(callee, calls, visited, order);
+            }
+            order.push(func);
+        }
+    }
+    let mut visited = HashSet::new();
+    let mut order = Vec::new();
+    for &func in program.func_layout() {
+        visit(func, calls, &mut visited, &mut order);
+    }
+    order
+}
+
+/// What inlining needs from the callee, copied out of the program so that the
+/// caller can be changed meanwhile.
+struct Body {
+    name: String,
+    params: Vec<Value>,
+    /// 可达的基本块，按逆后序排列，使定义先于使用被复制
+    bbs: Vec<BasicBlock>,
+    bb_names: HashMap<BasicBlock, Option<String>>,
+    bb_params: HashMap<BasicBlock, Vec<Value>>,
+    insts: HashMap<BasicBlock, Vec<Value>>,
+    values: HashMap<Value, ValueData>,
+}
+
+impl Body {
+    fn new(func: &FunctionData) -> Self {
+        let entry = func.layout().entry_bb().unwrap();
+        let bbs = reverse_postorder(func, entry);
+        let mut body = Body {
+            name: func.name().to_string(),
+            params: func.params().to_vec(),
+            bbs: bbs.clone(),
+            bb_names: HashMap::new(),
+            bb_params: HashMap::new(),
+            insts: HashMap::new(),
+            values: func.dfg().values().clone(),
+        };
+        for bb in bbs {
+            let data = func.dfg().bb(bb);
+            body.bb_names.insert(bb, data.name().clone());
+            body.bb_params.insert(bb, data.params().to_vec());
+            let node = func.layout().bbs().node(&bb).unwrap();
+            body.insts
+                .insert(bb, node.insts().keys().copied().collect());
+        }
+        body
+    }
+}
+
+/// Copies values of a [`Body`] into the caller.
+struct Copier<'a> {
+    func: &'a mut FunctionData,
+    body: &'a Body,
+    /// 被调用者中的值、基本块及其在调用者中的副本
+    values: HashMap<Value, Value>,
+    bbs: HashMap<BasicBlock, BasicBlock>,
+}
+
+impl Copier<'_> {
+    /// The copy of `value`. Constants are copied when first used, globals are
+    /// shared by both functions.
+    fn value(&mut self, value: Value) -> Value {
+        if value.is_global() {
+            return value;
+        }
+        if let Some(&copy) = self.values.get(&value) {
+            return copy;
+        }
+        let copy = self.copy(value);
+        self.values.insert(value, copy);
+        copy
+    }
+
+    /// Build a copy of `value` in the caller, with its operands replaced by
+    /// their copies. koopa has no way to insert cloned value data, so the
+    /// value is rebuilt from its kind.
+    fn copy(&mut self, value: Value) -> Value {
+        let data = &self.body.values[&value];
+        let copy = match data.kind() {
+            ValueKind::Integer(int) => self.func.dfg_mut().new_value().integer(int.value()),
+            ValueKind::ZeroInit(_) => self.func.dfg_mut().new_value().zero_init(data.ty().clone()),
+            ValueKind::Undef(_) => self.func.dfg_mut().new_value().undef(data.ty().clone()),
+            ValueKind::Aggregate(agg) => {
+                let elems = agg.elems().iter().map(|&elem| self.value(elem)).collect();
+                self.func.dfg_mut().new_value().aggregate(elems)
+            }
+            ValueKind::Alloc(_) => {
+                let TypeKind::Pointer(base) = data.ty().kind() else {
+                    unreachable!()
+                };
+                self.func.dfg_mut().new_value().alloc(base.clone())
+            }
+            ValueKind::Load(load) => {
+                let src = self.value(load.src());
+                self.func.dfg_mut().new_value().load(src)
+            }
+            ValueKind::Store(store) => {
+                let (value, dest) = (self.value(store.value()), self.value(store.dest()));
+                self.func.dfg_mut().new_value().store(value, dest)
+            }
+            ValueKind::GetPtr(get_ptr) => {
+                let (src, index) = (self.value(get_ptr.src()), self.value(get_ptr.index()));
+                self.func.dfg_mut().new_value().get_ptr(src, index)
+            }
+            ValueKind::GetElemPtr(gep) => {
+                let (src, index) = (self.value(gep.src()), self.value(gep.index()));
+                self.func.dfg_mut().new_value().get_elem_ptr(src, index)
+            }
+            ValueKind::Binary(exp) => {
+                let (lhs, rhs) = (self.value(exp.lhs()), self.value(exp.rhs()));
+                self.func.dfg_mut().new_value().binary(exp.op(), lhs, rhs)
+            }
+            ValueKind::Branch(branch) => {
+                let cond = self.value(branch.cond());
+                let true_args = self.values(branch.true_args());
+                let false_args = self.values(branch.false_args());
+                let (true_bb, false_bb) =
+                    (self.bbs[&branch.true_bb()], self.bbs[&branch.false_bb()]);
+                self.func
+                    .dfg_mut()
+                    .new_value()
+                    .branch_with_args(cond, true_bb, false_bb, true_args, false_args)
+            }
+            ValueKind::Jump(jump) => {
+                let args = self.values(jump.args());
+                let target = self.bbs[&jump.target()];
+                self.func.dfg_mut().new_value().jump_with_args(target, args)
+            }
+            ValueKind::Call(call) => {
+                let args = self.values(call.args());
+                self.func.dfg_mut().new_value().call(call.callee(), args)
+            }
+            // 参数在复制之前已被映射，ret由调用者处理
+            _ => unreachable!(),
+        };
+        if data.name().is_some() {
+            self.func
+                .dfg_mut()
+                .set_value_name(copy, data.name().clone());
+        }
+        copy
+    }
+
+    fn values(&mut self, values: &[Value]) -> Vec<Value> {
+        values.iter().map(|&value| self.value(value)).collect()
+    }
+}
+
+/// Replace `call` in `func` by a copy of `body`.
+fn inline_call(func: &mut FunctionData, call: Value, body: &Body) {
+    let bb = func.layout().parent_bb(call).unwrap();
+    let args = match func.dfg().value(call).kind() {
+        ValueKind::Call(call) => call.args().to_vec(),
+        _ => unreachable!(),
+    };
+    let ret_ty = func.dfg().value(call).ty().clone();
+    let callee_name = &body.name[1..];
+
+    let mut copier = Copier {
+        func,
+        body,
+        values: body.params.iter().copied().zip(args).collect(),
+        bbs: HashMap::new(),
+    };
+    let mut new_bbs = Vec::new();
+    for &callee_bb in &body.bbs {
+        let name = body.bb_names[&callee_bb]
+            .as_ref()
+            .map(|name| format!("%{0}_{1}", callee_name, &name[1..]));
+        let params = &body.bb_params[&callee_bb];
+        let tys = params
+            .iter()
+            .map(|param| body.values[param].ty().clone())
+            .collect();
+        let new_bb = copier
+            .func
+            .dfg_mut()
+            .new_bb()
+            .basic_block_with_params(name, tys);
+        let new_params = copier.func.dfg().bb(new_bb).params().to_vec();
+        copier.values.extend(params.iter().copied().zip(new_params));
+        copier.bbs.insert(callee_bb, new_bb);
+        new_bbs.push(new_bb);
+    }
+    // 调用之后的指令移入新的块，返回值作为它的参数
+    let ret_params = if ret_ty.is_unit() {
+        vec![]
+    } else {
+        vec![ret_ty]
+    };
+    let rest = copier
+        .func
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_params(Some(format!("%{0}_end", callee_name)), ret_params);
+    new_bbs.push(rest);
+    // 新的块排在调用所在的块之后
+    let mut cursor = copier.func.layout_mut().bbs_mut().cursor_mut(bb);
+    for &new_bb in &new_bbs {
+        cursor.insert_key_after(new_bb).unwrap();
+        cursor.move_next();
+    }
+    let mut after = Vec::new();
+    let mut cursor = copier
+        .func
+        .layout()
+        .bbs()
+        .node(&bb)
+        .unwrap()
+        .insts()
+        .cursor(call);
+    cursor.move_next();
+    while let Some(&inst) = cursor.key() {
+        after.push(inst);
+        cursor.move_next();
+    }
+    for inst in after {
+        let layout = copier.func.layout_mut();
+        layout.bb_mut(bb).insts_mut().remove(&inst);
+        layout.bb_mut(rest).insts_mut().push_key_back(inst).unwrap();
+    }
+
+    // 局部数组等分配放在调用者的入口，避免在循环中重复分配
+    let mut allocs = Vec::new();
+    for &callee_bb in &body.bbs {
+        let new_bb = copier.bbs[&callee_bb];
+        for inst in &body.insts[&callee_bb] {
+            let copy = match body.values[inst].kind() {
+                ValueKind::Return(ret) => {
+                    let args = ret
+                        .value()
+                        .map(|value| copier.value(value))
+                        .into_iter()
+                        .collect();
+                    copier.func.dfg_mut().new_value().jump_with_args(rest, args)
+                }
+                _ => copier.value(*inst),
+            };
+            if matches!(body.values[inst].kind(), ValueKind::Alloc(_)) {
+                allocs.push(copy);
+            } else {
+                copier
+                    .func
+                    .layout_mut()
+                    .bb_mut(new_bb)
+                    .insts_mut()
+                    .push_key_back(copy)
+                    .unwrap();
+            }
+        }
+    }
+    let entry_bb = copier.bbs[&body.bbs[0]];
+    let jump = func.dfg_mut().new_value().jump(entry_bb);
+    func.layout_mut()
+        .bb_mut(bb)
+        .insts_mut()
+        .push_key_back(jump)
+        .unwrap();
+    let entry = func.layout().entry_bb().unwrap();
+    for alloc in allocs.into_iter().rev() {
+        func.layout_mut()
+            .bb_mut(entry)
+            .insts_mut()
+            .push_key_front(alloc)
+            .unwrap();
+    }
+
+    // 调用的结果由返回块的参数代替
+    if let Some(&ret) = func.dfg().bb(rest).params().first() {
+        let map = HashMap::from([(call, ret)]);
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .nodes()
+            .flat_map(|node| node.insts().keys().copied())
+            .collect();
+        for inst in insts {
+            replace_operands(func, inst, &map);
+        }
+    }
+    remove_insts(func, vec![call]);
+}
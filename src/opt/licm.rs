@@ -0,0 +1,105 @@
+//! Loop-invariant code motion.
+//!
+//! Instructions of a loop whose operands are all defined outside of it
+//! compute the same value on every iteration and are moved into the loop's
+//! preheader, inner loops first so that they can keep moving outwards.
+//!
+//! The preheader runs even when the loop body does not, so only instructions
+//! that cannot fault are moved: arithmetic other than division by a possibly
+//! zero value, address computations, and loads that nothing in the loop may
+//! overwrite, provided that the loaded address is known to be valid or that
+//! the load is in the header, which runs whenever the loop is entered.
+
+use super::alias::AliasAnalysis;
+use super::loops::{find_loops, insert_before_terminator, preheader, Loop};
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::HashSet;
+
+pub struct Licm;
+
+impl FunctionPass for Licm {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_none() {
+            return;
+        }
+        // 移动指令可能加入新的块，因此每个循环都重新分析
+        let headers: Vec<BasicBlock> = find_loops(data).iter().map(|lp| lp.header).collect();
+        for header in headers {
+            let loops = find_loops(data);
+            if let Some(lp) = loops.iter().find(|lp| lp.header == header) {
+                hoist(data, lp);
+            }
+        }
+    }
+}
+
+fn hoist(func: &mut FunctionData, lp: &Loop) {
+    let alias = AliasAnalysis::new(func);
+    // 循环中定义的值，以及循环中写入的位置
+    let mut defined: HashSet<Value> = HashSet::new();
+    let mut stores = Vec::new();
+    let mut has_call = false;
+    for &bb in &lp.blocks {
+        defined.extend(func.dfg().bb(bb).params());
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            defined.insert(inst);
+            match func.dfg().value(inst).kind() {
+                ValueKind::Store(store) => stores.push(alias.root(func, store.dest())),
+                ValueKind::Call(_) => has_call = true,
+                _ => {}
+            }
+        }
+    }
+
+    let mut hoisted = Vec::new();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for &bb in &lp.blocks {
+            for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+                let kind = func.dfg().value(inst).kind();
+                if !defined.contains(&inst) || kind.value_uses().any(|v| defined.contains(&v)) {
+                    continue;
+                }
+                let movable = match kind {
+                    ValueKind::Binary(exp) => match exp.op() {
+                        BinaryOp::Div | BinaryOp::Mod => matches!(
+                            func.dfg().value(exp.rhs()).kind(),
+                            ValueKind::Integer(int) if int.value() != 0
+                        ),
+                        _ => true,
+                    },
+                    ValueKind::GetPtr(_) | ValueKind::GetElemPtr(_) => true,
+                    ValueKind::Load(load) => {
+                        let root = alias.root(func, load.src());
+                        let valid = bb == lp.header
+                            || load.src().is_global()
+                            || matches!(func.dfg().value(load.src()).kind(), ValueKind::Alloc(_));
+                        valid
+                            && !stores.iter().any(|&store| alias.may_alias(root, store))
+                            && !(has_call && alias.call_may_access(root))
+                    }
+                    _ => false,
+                };
+                if movable {
+                    defined.remove(&inst);
+                    hoisted.push(inst);
+                    changed = true;
+                }
+            }
+        }
+    }
+    if hoisted.is_empty() {
+        return;
+    }
+    let Some(pre) = preheader(func, lp) else {
+        return;
+    };
+    // 指令在其操作数之后被选中，按选中的顺序移动即可
+    for inst in hoisted {
+        let bb = func.layout().parent_bb(inst).unwrap();
+        func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+        insert_before_terminator(func, pre, inst);
+    }
+}
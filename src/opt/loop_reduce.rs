@@ -0,0 +1,181 @@
+//! Strength reduction of array indexing in loops.
+//!
+//! An induction variable is a parameter of the loop header that every back
+//! edge passes either unchanged or advanced by a constant. The address of
+//! element `i` of a loop-invariant array, `getelemptr base, i` or
+//! `getptr base, i`, then moves by the same number of elements as `i` does,
+//! so it becomes a header parameter of its own: the preheader computes its
+//! first value and each back edge advances it with `getptr p, step`, without
+//! multiplying `i` by the element size.
+
+use super::loops::{find_loops, insert_before_terminator, preheader, Loop};
+use super::{add_block_params, remove_insts, replace_operands, terminator, update_edges};
+use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use koopa::ir::{BasicBlock, BinaryOp, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+
+pub struct LoopReduce;
+
+impl FunctionPass for LoopReduce {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_none() {
+            return;
+        }
+        let headers: Vec<BasicBlock> = find_loops(data).iter().map(|lp| lp.header).collect();
+        for header in headers {
+            let loops = find_loops(data);
+            if let Some(lp) = loops.iter().find(|lp| lp.header == header) {
+                reduce(data, lp);
+            }
+        }
+    }
+}
+
+/// Arguments of the edges from the terminator `term` to `target`, in the
+/// order [`update_edges`] visits them.
+fn edges_to(func: &FunctionData, term: Value, target: BasicBlock) -> Vec<Vec<Value>> {
+    match func.dfg().value(term).kind() {
+        ValueKind::Jump(jump) if jump.target() == target => vec![jump.args().to_vec()],
+        ValueKind::Branch(branch) => {
+            let mut edges = Vec::new();
+            if branch.true_bb() == target {
+                edges.push(branch.true_args().to_vec());
+            }
+            if branch.false_bb() == target {
+                edges.push(branch.false_args().to_vec());
+            }
+            edges
+        }
+        _ => vec![],
+    }
+}
+
+/// How much `next` advances `param` by, if it is `param` plus a constant.
+fn step(func: &FunctionData, param: Value, next: Value) -> Option<i32> {
+    if next == param {
+        return Some(0);
+    }
+    let ValueKind::Binary(exp) = func.dfg().value(next).kind() else {
+        return None;
+    };
+    let constant = |value: Value| match func.dfg().value(value).kind() {
+        ValueKind::Integer(int) => Some(int.value()),
+        _ => None,
+    };
+    match exp.op() {
+        BinaryOp::Add if exp.lhs() == param => constant(exp.rhs()),
+        BinaryOp::Add if exp.rhs() == param => constant(exp.lhs()),
+        BinaryOp::Sub if exp.lhs() == param => constant(exp.rhs()).map(i32::wrapping_neg),
+        _ => None,
+    }
+}
+
+fn reduce(func: &mut FunctionData, lp: &Loop) {
+    let header = lp.header;
+    // 归纳变量在头部参数中的序号
+    let params = func.dfg().bb(header).params().to_vec();
+    let inductions: HashMap<Value, usize> = params
+        .iter()
+        .enumerate()
+        .filter(|&(i, &param)| {
+            lp.latches.iter().all(|&latch| {
+                edges_to(func, terminator(func, latch), header)
+                    .iter()
+                    .all(|args| step(func, param, args[i]).is_some())
+            })
+        })
+        .map(|(i, &param)| (param, i))
+        .collect();
+    if inductions.is_empty() {
+        return;
+    }
+
+    // 以循环外的数组和归纳变量为下标的地址，相同的计算归为一组
+    let mut defined: HashSet<Value> = HashSet::new();
+    for &bb in &lp.blocks {
+        defined.extend(func.dfg().bb(bb).params());
+        defined.extend(func.layout().bbs().node(&bb).unwrap().insts().keys());
+    }
+    let mut groups: Vec<((bool, Value, Value), Vec<Value>)> = Vec::new();
+    for &bb in &lp.blocks {
+        for &inst in func.layout().bbs().node(&bb).unwrap().insts().keys() {
+            let key = match func.dfg().value(inst).kind() {
+                ValueKind::GetElemPtr(gep) => (true, gep.src(), gep.index()),
+                ValueKind::GetPtr(get_ptr) => (false, get_ptr.src(), get_ptr.index()),
+                _ => continue,
+            };
+            if defined.contains(&key.1) || !inductions.contains_key(&key.2) {
+                continue;
+            }
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, insts)) => insts.push(inst),
+                None => groups.push((key, vec![inst])),
+            }
+        }
+    }
+    if groups.is_empty() {
+        return;
+    }
+    let Some(pre) = preheader(func, lp) else {
+        return;
+    };
+
+    let mut replace = HashMap::new();
+    let mut removed = Vec::new();
+    for ((elem, src, param), insts) in groups {
+        let i = inductions[&param];
+        let ty = func.dfg().value(insts[0]).ty().clone();
+        let ptr = add_block_params(func, header, vec![ty])[0];
+        // 进入循环时的地址
+        let pre_term = terminator(func, pre);
+        let init = edges_to(func, pre_term, header)[0][i];
+        let first = if elem {
+            func.dfg_mut().new_value().get_elem_ptr(src, init)
+        } else {
+            func.dfg_mut().new_value().get_ptr(src, init)
+        };
+        insert_before_terminator(func, pre, first);
+        update_edges(func, pre_term, |target, args| {
+            if *target == header {
+                args.push(first);
+            }
+        });
+        // 每条回边上前进相应的元素个数
+        for &latch in &lp.latches {
+            let term = terminator(func, latch);
+            let mut next = Vec::new();
+            for args in edges_to(func, term, header) {
+                let step = step(func, param, args[i]).unwrap();
+                if step == 0 {
+                    next.push(ptr);
+                    continue;
+                }
+                let step = func.dfg_mut().new_value().integer(step);
+                let advanced = func.dfg_mut().new_value().get_ptr(ptr, step);
+                insert_before_terminator(func, latch, advanced);
+                next.push(advanced);
+            }
+            let mut next = next.into_iter();
+            update_edges(func, term, |target, args| {
+                if *target == header {
+                    args.push(next.next().unwrap());
+                }
+            });
+        }
+        for inst in insts {
+            replace.insert(inst, ptr);
+            removed.push(inst);
+        }
+    }
+    let insts: Vec<Value> = func
+        .layout()
+        .bbs()
+        .nodes()
+        .flat_map(|node| node.insts().keys().copied())
+        .collect();
+    for inst in insts {
+        replace_operands(func, inst, &replace);
+    }
+    remove_insts(func, removed);
+}
@@ -0,0 +1,135 @@
+use super::dominators::DomTree;
+use super::{predecessors, successors, terminator, update_edges};
+use koopa::ir::builder::{BasicBlockBuilder, LocalInstBuilder};
+use koopa::ir::{BasicBlock, FunctionData, Type, Value, ValueKind};
+use std::collections::HashSet;
+
+/// A natural loop: the blocks that reach a back edge into `header` without
+/// passing through the header. Loops sharing a header are merged.
+pub struct Loop {
+    pub header: BasicBlock,
+    /// 循环中的基本块（包括内层循环的），按逆后序排列
+    pub blocks: Vec<BasicBlock>,
+    block_set: HashSet<BasicBlock>,
+    /// 回边的起点
+    pub latches: Vec<BasicBlock>,
+}
+
+impl Loop {
+    pub fn contains(&self, bb: BasicBlock) -> bool {
+        self.block_set.contains(&bb)
+    }
+}
+
+/// The natural loops of `func`, inner loops before the loops containing them.
+pub fn find_loops(func: &FunctionData) -> Vec<Loop> {
+    let preds = predecessors(func);
+    let dom = DomTree::new(func, &preds);
+    let rpo = dom.rpo();
+    // 回边的终点支配起点
+    let mut loops: Vec<Loop> = Vec::new();
+    for &bb in rpo {
+        for succ in successors(func, bb) {
+            if !dom.dominates(succ, bb) {
+                continue;
+            }
+            match loops.iter_mut().find(|lp| lp.header == succ) {
+                Some(lp) => lp.latches.push(bb),
+                None => loops.push(Loop {
+                    header: succ,
+                    blocks: Vec::new(),
+                    block_set: HashSet::new(),
+                    latches: vec![bb],
+                }),
+            }
+        }
+    }
+    for lp in &mut loops {
+        lp.block_set.insert(lp.header);
+        let mut worklist = lp.latches.clone();
+        while let Some(bb) = worklist.pop() {
+            if lp.block_set.insert(bb) {
+                worklist.extend(preds[&bb].iter().filter(|&&pred| dom.is_reachable(pred)));
+            }
+        }
+        lp.blocks = rpo
+            .iter()
+            .copied()
+            .filter(|bb| lp.block_set.contains(bb))
+            .collect();
+    }
+    loops.sort_by_key(|lp| lp.blocks.len());
+    loops
+}
+
+/// The block through which control enters `lp`: a block outside the loop
+/// whose only successor is the header, reached by every edge into the header
+/// from outside. One is inserted if there is none. A loop headed by the entry
+/// has no preheader. A reused block may come after the loop in the layout,
+/// which neither the interpreter nor the backend depends on.
+pub fn preheader(func: &mut FunctionData, lp: &Loop) -> Option<BasicBlock> {
+    let header = lp.header;
+    if func.layout().entry_bb() == Some(header) {
+        return None;
+    }
+    let preds = predecessors(func);
+    let outside: Vec<BasicBlock> = preds[&header]
+        .iter()
+        .copied()
+        .filter(|&pred| !lp.contains(pred))
+        .collect();
+    if let [pred] = outside[..] {
+        if matches!(
+            func.dfg().value(terminator(func, pred)).kind(),
+            ValueKind::Jump(_)
+        ) {
+            return Some(pred);
+        }
+    }
+
+    // 新的块接收头部的参数并原样传给头部
+    let tys: Vec<Type> = func
+        .dfg()
+        .bb(header)
+        .params()
+        .iter()
+        .map(|&param| func.dfg().value(param).ty().clone())
+        .collect();
+    let pre = func
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_params(Some("%preheader".to_string()), tys);
+    func.layout_mut()
+        .bbs_mut()
+        .cursor_mut(header)
+        .insert_key_before(pre)
+        .unwrap();
+    let params = func.dfg().bb(pre).params().to_vec();
+    let jump = func.dfg_mut().new_value().jump_with_args(header, params);
+    func.layout_mut()
+        .bb_mut(pre)
+        .insts_mut()
+        .push_key_back(jump)
+        .unwrap();
+    for pred in outside {
+        let term = terminator(func, pred);
+        update_edges(func, term, |target, _| {
+            if *target == header {
+                *target = pre;
+            }
+        });
+    }
+    Some(pre)
+}
+
+/// Place `inst`, which is not in the layout, right before the terminator of
+/// `bb`.
+pub fn insert_before_terminator(func: &mut FunctionData, bb: BasicBlock, inst: Value) {
+    let term = terminator(func, bb);
+    func.layout_mut()
+        .bb_mut(bb)
+        .insts_mut()
+        .cursor_mut(term)
+        .insert_key_before(inst)
+        .unwrap();
+}
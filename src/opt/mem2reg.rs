@@ -0,0 +1,256 @@
+//! Promote scalar stack variables to SSA values.
+//!
+//! Every SysY local lives in an `alloc` that is read and written through
+//! `load` and `store`. When the address of an allocation is only ever loaded
+//! from or stored to, the allocation can be removed: each load is replaced by
+//! the value last stored along the way, and where different values meet the
+//! block receives a parameter. Parameters are placed on the iterated dominance
+//! frontier of the stores, limited to the blocks where the variable is live.
+
+use super::dominators::DomTree;
+use super::{add_block_params, predecessors, remove_insts, replace_operands};
+use koopa::ir::builder::ValueBuilder;
+use koopa::ir::{BasicBlock, Function, FunctionData, Type, TypeKind, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+
+pub struct Mem2Reg;
+
+impl FunctionPass for Mem2Reg {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_none() {
+            return;
+        }
+        let allocs = promotable_allocs(data);
+        if !allocs.is_empty() {
+            promote(data, &allocs);
+        }
+    }
+}
+
+/// The type an allocation holds.
+fn allocated_type(func: &FunctionData, alloc: Value) -> Type {
+    match func.dfg().value(alloc).ty().kind() {
+        TypeKind::Pointer(base) => base.clone(),
+        _ => unreachable!(),
+    }
+}
+
+/// Non-array allocations whose address is only used by loads and as the
+/// destination of stores.
+fn promotable_allocs(func: &FunctionData) -> Vec<Value> {
+    let mut allocs = Vec::new();
+    let mut escaped = HashSet::new();
+    for (_, node) in func.layout().bbs() {
+        for &inst in node.insts().keys() {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Alloc(_) => {
+                    if !matches!(allocated_type(func, inst).kind(), TypeKind::Array(..)) {
+                        allocs.push(inst);
+                    }
+                }
+                ValueKind::Load(_) => {}
+                ValueKind::Store(store) => {
+                    escaped.insert(store.value());
+                }
+                kind => escaped.extend(kind.value_uses()),
+            }
+        }
+    }
+    allocs.retain(|alloc| !escaped.contains(alloc));
+    allocs
+}
+
+/// State of the renaming walk over the dominator tree.
+struct Renamer<'a> {
+    /// 被提升的alloc及其序号
+    index: &'a HashMap<Value, usize>,
+    /// 每个基本块新增的参数对应的变量
+    phis: &'a HashMap<BasicBlock, Vec<usize>>,
+    params: &'a HashMap<BasicBlock, Vec<Value>>,
+    /// 被删除的load及替代它的值
+    replace: HashMap<Value, Value>,
+    removed: Vec<Value>,
+}
+
+impl Renamer<'_> {
+    /// Rename the loads and stores of `bb`, where `defs` holds the value of
+    /// each variable on entry, and pass the values at its end to the new
+    /// parameters of its successors.
+    fn rename_block(&mut self, func: &mut FunctionData, bb: BasicBlock, defs: &mut [Value]) {
+        if let Some(vars) = self.phis.get(&bb) {
+            for (&var, &param) in vars.iter().zip(&self.params[&bb]) {
+                defs[var] = param;
+            }
+        }
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for &inst in &insts {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Load(load) => {
+                    if let Some(&var) = self.index.get(&load.src()) {
+                        self.replace.insert(inst, defs[var]);
+                        self.removed.push(inst);
+                    }
+                }
+                ValueKind::Store(store) => {
+                    if let Some(&var) = self.index.get(&store.dest()) {
+                        let value = store.value();
+                        defs[var] = *self.replace.get(&value).unwrap_or(&value);
+                        self.removed.push(inst);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let term = *insts.last().unwrap();
+        let args = |target: BasicBlock| -> Vec<Value> {
+            self.phis.get(&target).map_or(Vec::new(), |vars| {
+                vars.iter().map(|&var| defs[var]).collect()
+            })
+        };
+        let mut data = func.dfg().value(term).clone();
+        match data.kind_mut() {
+            ValueKind::Jump(jump) => {
+                let args = args(jump.target());
+                jump.args_mut().extend(args);
+            }
+            ValueKind::Branch(branch) => {
+                let true_args = args(branch.true_bb());
+                let false_args = args(branch.false_bb());
+                branch.true_args_mut().extend(true_args);
+                branch.false_args_mut().extend(false_args);
+            }
+            _ => return,
+        }
+        func.dfg_mut().replace_value_with(term).raw(data);
+    }
+}
+
+fn promote(func: &mut FunctionData, allocs: &[Value]) {
+    let index: HashMap<Value, usize> = allocs.iter().enumerate().map(|(i, &a)| (a, i)).collect();
+    let tys: Vec<Type> = allocs.iter().map(|&a| allocated_type(func, a)).collect();
+    let preds = predecessors(func);
+    let dom = DomTree::new(func, &preds);
+    let frontiers = dom.frontiers(&preds);
+
+    // 每个变量被存储的基本块，以及入口处活跃（块内先读后写）的基本块
+    let mut def_blocks = vec![HashSet::new(); allocs.len()];
+    let mut live_in = vec![HashSet::new(); allocs.len()];
+    for (&bb, node) in func.layout().bbs() {
+        let mut stored = HashSet::new();
+        for &inst in node.insts().keys() {
+            match func.dfg().value(inst).kind() {
+                ValueKind::Store(store) => {
+                    if let Some(&var) = index.get(&store.dest()) {
+                        stored.insert(var);
+                        def_blocks[var].insert(bb);
+                    }
+                }
+                ValueKind::Load(load) => {
+                    if let Some(&var) = index.get(&load.src()) {
+                        if !stored.contains(&var) {
+                            live_in[var].insert(bb);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    // 活跃性沿前驱向上传播，直到遇到存储该变量的块
+    for (live_in, def_blocks) in live_in.iter_mut().zip(&def_blocks) {
+        let mut worklist: Vec<BasicBlock> = live_in.iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            for &pred in &preds[&bb] {
+                if !def_blocks.contains(&pred) && live_in.insert(pred) {
+                    worklist.push(pred);
+                }
+            }
+        }
+    }
+
+    // 在存储的迭代支配边界上、变量活跃的块中插入参数
+    let mut phis: HashMap<BasicBlock, Vec<usize>> = HashMap::new();
+    for var in 0..allocs.len() {
+        let mut has_phi = HashSet::new();
+        let mut worklist: Vec<BasicBlock> = def_blocks[var].iter().copied().collect();
+        while let Some(bb) = worklist.pop() {
+            for &frontier in frontiers.get(&bb).into_iter().flatten() {
+                if live_in[var].contains(&frontier) && has_phi.insert(frontier) {
+                    phis.entry(frontier).or_default().push(var);
+                    if !def_blocks[var].contains(&frontier) {
+                        worklist.push(frontier);
+                    }
+                }
+            }
+        }
+    }
+    let mut params = HashMap::new();
+    for (&bb, vars) in &phis {
+        let param_tys = vars.iter().map(|&var| tys[var].clone()).collect();
+        params.insert(bb, add_block_params(func, bb, param_tys));
+    }
+
+    // 沿支配树重命名，变量在定义之前取未定义值
+    let undefs: Vec<Value> = tys
+        .iter()
+        .map(|ty| func.dfg_mut().new_value().undef(ty.clone()))
+        .collect();
+    let mut renamer = Renamer {
+        index: &index,
+        phis: &phis,
+        params: &params,
+        replace: HashMap::new(),
+        removed: Vec::new(),
+    };
+    let entry = func.layout().entry_bb().unwrap();
+    let mut stack = vec![(entry, undefs.clone())];
+    while let Some((bb, mut defs)) = stack.pop() {
+        renamer.rename_block(func, bb, &mut defs);
+        for &child in dom.children(bb) {
+            stack.push((child, defs.clone()));
+        }
+    }
+    // 不可达的块不会被执行，其中的变量同样取未定义值
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for &bb in &bbs {
+        if !dom.is_reachable(bb) {
+            renamer.rename_block(func, bb, &mut undefs.clone());
+        }
+    }
+
+    let removed: HashSet<Value> = renamer.removed.iter().copied().collect();
+    for &bb in &bbs {
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for inst in insts {
+            if !removed.contains(&inst) {
+                replace_operands(func, inst, &renamer.replace);
+            }
+        }
+    }
+    let mut removed = renamer.removed;
+    removed.extend(allocs);
+    remove_insts(func, removed);
+    for undef in undefs {
+        if func.dfg().value(undef).used_by().is_empty() {
+            func.dfg_mut().remove_value(undef);
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Optimization passes over the in-memory Koopa IR program, and the
+//! [`PassManager`] that runs them in the order given by an optimization level
+//! or by `--passes`.
+//!
+//! koopa forgets the users of a value that is rebuilt with
+//! `replace_value_with`, so the passes find uses by walking the layout instead
+//! of trusting `used_by`.
+
+use koopa::ir::builder::{BasicBlockBuilder, ValueBuilder};
+use koopa::ir::{BasicBlock, FunctionData, Program, Type, Value, ValueKind};
+use std::collections::HashMap;
+
+mod alias;
+pub mod dce;
+mod dominators;
+pub mod gvn;
+pub mod inline;
+pub mod licm;
+pub mod loop_reduce;
+mod loops;
+pub mod mem2reg;
+mod passman;
+pub mod sccp;
+pub mod simplify_cfg;
+
+pub use dce::DeadCodeElim;
+pub use gvn::Gvn;
+pub use inline::Inline;
+pub use licm::Licm;
+pub use loop_reduce::LoopReduce;
+pub use mem2reg::Mem2Reg;
+pub use passman::{pass_name, pass_names, pipeline, PassManager};
+pub use sccp::Sccp;
+pub use simplify_cfg::SimplifyCfg;
+
+/// Run the pipeline of `opt_level` on `program`.
+pub fn optimize(program: &mut Program, opt_level: u32) {
+    PassManager::for_level(opt_level)
+        .run(program, &mut std::io::sink())
+        .unwrap();
+}
+
+/// The last instruction of `bb`.
+fn terminator(func: &FunctionData, bb: BasicBlock) -> Value {
+    *func
+        .layout()
+        .bbs()
+        .node(&bb)
+        .unwrap()
+        .insts()
+        .back_key()
+        .unwrap()
+}
+
+/// Blocks the terminator of `bb` may jump to.
+fn successors(func: &FunctionData, bb: BasicBlock) -> Vec<BasicBlock> {
+    match func.layout().bbs().node(&bb).unwrap().insts().back_key() {
+        Some(&last) => func.dfg().value(last).kind().bb_uses().collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Predecessors of every block of `func`, each listed once.
+fn predecessors(func: &FunctionData) -> HashMap<BasicBlock, Vec<BasicBlock>> {
+    let mut preds: HashMap<BasicBlock, Vec<BasicBlock>> = func
+        .layout()
+        .bbs()
+        .keys()
+        .map(|&bb| (bb, Vec::new()))
+        .collect();
+    for &bb in func.layout().bbs().keys() {
+        for succ in successors(func, bb) {
+            let succ_preds = preds.get_mut(&succ).unwrap();
+            if !succ_preds.contains(&bb) {
+                succ_preds.push(bb);
+            }
+        }
+    }
+    preds
+}
+
+/// Rewrite the operands of `inst` that appear in `map`. The instruction is
+/// rebuilt in place, so its handle stays valid.
+fn replace_operands(func: &mut FunctionData, inst: Value, map: &HashMap<Value, Value>) {
+    let data = func.dfg().value(inst);
+    if !data
+        .kind()
+        .value_uses()
+        .any(|value| map.contains_key(&value))
+    {
+        return;
+    }
+    let mut data = data.clone();
+    let replace = |value: &mut Value| {
+        if let Some(&new) = map.get(value) {
+            *value = new;
+        }
+    };
+    match data.kind_mut() {
+        ValueKind::Load(load) => replace(load.src_mut()),
+        ValueKind::Store(store) => {
+            replace(store.value_mut());
+            replace(store.dest_mut());
+        }
+        ValueKind::GetPtr(get_ptr) => {
+            replace(get_ptr.src_mut());
+            replace(get_ptr.index_mut());
+        }
+        ValueKind::GetElemPtr(gep) => {
+            replace(gep.src_mut());
+            replace(gep.index_mut());
+        }
+        ValueKind::Binary(exp) => {
+            replace(exp.lhs_mut());
+            replace(exp.rhs_mut());
+        }
+        ValueKind::Branch(branch) => {
+            replace(branch.cond_mut());
+            branch.true_args_mut().iter_mut().for_each(replace);
+            branch.false_args_mut().iter_mut().for_each(replace);
+        }
+        ValueKind::Jump(jump) => jump.args_mut().iter_mut().for_each(replace),
+        ValueKind::Call(call) => call.args_mut().iter_mut().for_each(replace),
+        ValueKind::Return(ret) => {
+            if let Some(value) = ret.value_mut() {
+                replace(value);
+            }
+        }
+        _ => unreachable!(),
+    }
+    func.dfg_mut().replace_value_with(inst).raw(data);
+}
+
+/// Append parameters of types `tys` to `bb` and return them. koopa only
+/// creates block parameters together with their block, so they are taken from
+/// a scratch block.
+fn add_block_params(func: &mut FunctionData, bb: BasicBlock, tys: Vec<Type>) -> Vec<Value> {
+    let count = func.dfg().bb(bb).params().len();
+    // 参数记录了自己的序号，先用占位参数补齐已有的参数
+    let mut all_tys = vec![Type::get_i32(); count];
+    all_tys.extend(tys);
+    let scratch = func
+        .dfg_mut()
+        .new_bb()
+        .basic_block_with_params(None, all_tys);
+    let mut params = std::mem::take(func.dfg_mut().bb_mut(scratch).params_mut());
+    func.dfg_mut().remove_bb(scratch);
+    let new_params = params.split_off(count);
+    for placeholder in params {
+        func.dfg_mut().remove_value(placeholder);
+    }
+    func.dfg_mut()
+        .bb_mut(bb)
+        .params_mut()
+        .extend(new_params.iter().copied());
+    new_params
+}
+
+/// Remove the instructions `insts`, which may only be used by each other.
+fn remove_insts(func: &mut FunctionData, insts: Vec<Value>) {
+    for &inst in &insts {
+        let bb = func.layout().parent_bb(inst).unwrap();
+        func.layout_mut().bb_mut(bb).insts_mut().remove(&inst);
+    }
+    // 使用者必须先于被使用的值删除。`used_by`可能不全，因此自行统计
+    let mut users: HashMap<Value, usize> = insts.iter().map(|&inst| (inst, 0)).collect();
+    for &inst in &insts {
+        for value in func.dfg().value(inst).kind().value_uses() {
+            if let Some(count) = users.get_mut(&value) {
+                *count += 1;
+            }
+        }
+    }
+    let mut worklist: Vec<Value> = insts.into_iter().filter(|inst| users[inst] == 0).collect();
+    while let Some(inst) = worklist.pop() {
+        for value in func.dfg_mut().remove_value(inst).kind().value_uses() {
+            if let Some(count) = users.get_mut(&value) {
+                *count -= 1;
+                if *count == 0 {
+                    worklist.push(value);
+                }
+            }
+        }
+    }
+}
+
+/// Rewrite the control flow edges of the terminator `term`: `update` is
+/// called with the target and arguments of each edge and may change both.
+fn update_edges(
+    func: &mut FunctionData,
+    term: Value,
+    mut update: impl FnMut(&mut BasicBlock, &mut Vec<Value>),
+) {
+    let mut data = func.dfg().value(term).clone();
+    match data.kind_mut() {
+        ValueKind::Jump(jump) => {
+            let (mut target, mut args) = (jump.target(), jump.args().to_vec());
+            update(&mut target, &mut args);
+            *jump.target_mut() = target;
+            *jump.args_mut() = args;
+        }
+        ValueKind::Branch(branch) => {
+            let (mut target, mut args) = (branch.true_bb(), branch.true_args().to_vec());
+            update(&mut target, &mut args);
+            *branch.true_bb_mut() = target;
+            *branch.true_args_mut() = args;
+            let (mut target, mut args) = (branch.false_bb(), branch.false_args().to_vec());
+            update(&mut target, &mut args);
+            *branch.false_bb_mut() = target;
+            *branch.false_args_mut() = args;
+        }
+        _ => return,
+    }
+    func.dfg_mut().replace_value_with(term).raw(data);
+}
@@ -0,0 +1,125 @@
+use super::{DeadCodeElim, Gvn, Inline, Licm, LoopReduce, Mem2Reg, Sccp, SimplifyCfg};
+use koopa::back::KoopaGenerator;
+use koopa::ir::Program;
+use koopa::opt::Pass;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+/// Builds a fresh instance of a pass.
+type PassConstructor = fn() -> Pass;
+
+/// Every pass that can be selected by name, with a constructor for it.
+const PASSES: &[(&str, PassConstructor)] = &[
+    ("mem2reg", || Pass::Function(Box::new(Mem2Reg))),
+    ("inline", || Pass::Module(Box::new(Inline))),
+    ("sccp", || Pass::Function(Box::new(Sccp))),
+    ("gvn", || Pass::Function(Box::new(Gvn))),
+    ("licm", || Pass::Function(Box::new(Licm))),
+    ("loop-reduce", || Pass::Function(Box::new(LoopReduce))),
+    ("dce", || Pass::Function(Box::new(DeadCodeElim))),
+    ("simplifycfg", || Pass::Function(Box::new(SimplifyCfg))),
+];
+
+/// Other names of some passes: an alias and the pass it stands for.
+const ALIASES: &[(&str, &str)] = &[("constprop", "sccp")];
+
+/// Names of the passes that can be selected, e.g. with `--passes`.
+pub fn pass_names() -> impl Iterator<Item = &'static str> {
+    PASSES.iter().map(|&(name, _)| name)
+}
+
+/// The name of the pass called `name`, resolving aliases.
+pub fn pass_name(name: &str) -> Option<&'static str> {
+    let name = ALIASES
+        .iter()
+        .find(|&&(alias, _)| alias == name)
+        .map_or(name, |&(_, pass)| pass);
+    pass_names().find(|&pass| pass == name)
+}
+
+/// The passes run at `opt_level`, in order.
+pub fn pipeline(opt_level: u32) -> Vec<&'static str> {
+    match opt_level {
+        0 => vec![],
+        1 => vec!["mem2reg", "dce", "simplifycfg"],
+        _ => vec![
+            "mem2reg",
+            "inline",
+            "sccp",
+            "gvn",
+            "licm",
+            "loop-reduce",
+            "dce",
+            "simplifycfg",
+        ],
+    }
+}
+
+/// Runs a sequence of passes over a program, optionally timing each of them
+/// and dumping the IR after some of them.
+pub struct PassManager {
+    passes: Vec<(&'static str, Pass)>,
+    /// 在这些pass之后输出Koopa IR
+    pub print_after: Vec<String>,
+    /// 是否在最后报告每个pass的用时
+    pub time_passes: bool,
+}
+
+impl PassManager {
+    /// A pass manager running the passes called `names`, in order.
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Result<Self, String> {
+        let mut passes = Vec::new();
+        for name in names {
+            let name = name.as_ref();
+            match PASSES.iter().find(|&&(n, _)| Some(n) == pass_name(name)) {
+                Some(&(name, create)) => passes.push((name, create())),
+                None => return Err(format!("unknown pass `{0}`", name)),
+            }
+        }
+        Ok(PassManager {
+            passes,
+            print_after: Vec::new(),
+            time_passes: false,
+        })
+    }
+
+    /// A pass manager running the pipeline of `opt_level`.
+    pub fn for_level(opt_level: u32) -> Self {
+        Self::new(&pipeline(opt_level)).unwrap()
+    }
+
+    /// Run the passes on `program`, writing IR dumps and the timing report
+    /// to `log`.
+    pub fn run(&mut self, program: &mut Program, log: &mut impl Write) -> io::Result<()> {
+        let mut timings: Vec<(&'static str, Duration)> = Vec::new();
+        for (name, pass) in &mut self.passes {
+            let start = Instant::now();
+            match pass {
+                Pass::Module(pass) => pass.run_on(program),
+                Pass::Function(pass) => {
+                    for (&func, data) in program.funcs_mut() {
+                        pass.run_on(func, data);
+                    }
+                }
+            }
+            timings.push((name, start.elapsed()));
+            if self.print_after.iter().any(|n| n == name) {
+                writeln!(log, "// *** IR after {0} ***", name)?;
+                KoopaGenerator::new(&mut *log).generate_on(program)?;
+            }
+        }
+        if self.time_passes {
+            let total: Duration = timings.iter().map(|&(_, time)| time).sum();
+            writeln!(log, "===== pass timing =====")?;
+            for (name, time) in timings.into_iter().chain([("total", total)]) {
+                writeln!(
+                    log,
+                    "  {0:<12} {1:>10.3} ms",
+                    name,
+                    time.as_secs_f64() * 1000.0
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+//! Sparse conditional constant propagation.
+//!
+//! Every value starts out undefined and only the entry block is executable.
+//! Instructions are evaluated as their operands change, a branch makes only
+//! the edges its condition allows executable, and a block parameter takes the
+//! meet of the arguments on its executable incoming edges. Values found to be
+//! constant are then replaced, and branches on them become jumps.
+
+use super::{remove_insts, replace_operands, terminator};
+use crate::interpreter::binary;
+use koopa::ir::builder::{LocalInstBuilder, ValueBuilder};
+use koopa::ir::{BasicBlock, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+
+pub struct Sccp;
+
+impl FunctionPass for Sccp {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_none() {
+            return;
+        }
+        let mut solver = Solver::new(data);
+        solver.solve();
+        let Solver {
+            values, executable, ..
+        } = solver;
+        rewrite(data, &values, &executable);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Lattice {
+    /// 尚未得到任何值
+    Undefined,
+    Const(i32),
+    /// 可能取不同的值
+    Overdefined,
+}
+
+impl Lattice {
+    fn meet(self, other: Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Undefined, x) | (x, Lattice::Undefined) => x,
+            (Lattice::Const(a), Lattice::Const(b)) if a == b => Lattice::Const(a),
+            _ => Lattice::Overdefined,
+        }
+    }
+}
+
+/// A control flow edge: the terminator and whether it is the false edge of a
+/// branch.
+type Edge = (Value, bool);
+
+struct Solver<'a> {
+    func: &'a FunctionData,
+    values: HashMap<Value, Lattice>,
+    executable: HashSet<BasicBlock>,
+    edges: HashSet<Edge>,
+    /// 每个值的使用者
+    users: HashMap<Value, Vec<Value>>,
+    /// 每个基本块的入边
+    incoming: HashMap<BasicBlock, Vec<Edge>>,
+    /// 新变为可执行的基本块，及值发生变化的值
+    block_worklist: Vec<BasicBlock>,
+    value_worklist: Vec<Value>,
+}
+
+/// Target and arguments of an edge.
+fn edge_target(func: &FunctionData, (term, false_edge): Edge) -> (BasicBlock, &[Value]) {
+    match func.dfg().value(term).kind() {
+        ValueKind::Jump(jump) => (jump.target(), jump.args()),
+        ValueKind::Branch(branch) if false_edge => (branch.false_bb(), branch.false_args()),
+        ValueKind::Branch(branch) => (branch.true_bb(), branch.true_args()),
+        _ => unreachable!(),
+    }
+}
+
+impl<'a> Solver<'a> {
+    fn new(func: &'a FunctionData) -> Self {
+        let mut users: HashMap<Value, Vec<Value>> = HashMap::new();
+        let mut incoming: HashMap<BasicBlock, Vec<Edge>> = HashMap::new();
+        for (_, node) in func.layout().bbs() {
+            for &inst in node.insts().keys() {
+                let kind = func.dfg().value(inst).kind();
+                for value in kind.value_uses() {
+                    users.entry(value).or_default().push(inst);
+                }
+                let edges: &[Edge] = match kind {
+                    ValueKind::Jump(_) => &[(inst, false)],
+                    ValueKind::Branch(_) => &[(inst, false), (inst, true)],
+                    _ => &[],
+                };
+                for &edge in edges {
+                    incoming
+                        .entry(edge_target(func, edge).0)
+                        .or_default()
+                        .push(edge);
+                }
+            }
+        }
+        Solver {
+            func,
+            values: HashMap::new(),
+            executable: HashSet::new(),
+            edges: HashSet::new(),
+            users,
+            incoming,
+            block_worklist: Vec::new(),
+            value_worklist: Vec::new(),
+        }
+    }
+
+    fn solve(&mut self) {
+        let entry = self.func.layout().entry_bb().unwrap();
+        self.executable.insert(entry);
+        self.block_worklist.push(entry);
+        loop {
+            if let Some(bb) = self.block_worklist.pop() {
+                let node = self.func.layout().bbs().node(&bb).unwrap();
+                for &inst in node.insts().keys() {
+                    self.visit(inst);
+                }
+            } else if let Some(value) = self.value_worklist.pop() {
+                for user in self.users.get(&value).cloned().unwrap_or_default() {
+                    let bb = self.func.layout().parent_bb(user).unwrap();
+                    if self.executable.contains(&bb) {
+                        self.visit(user);
+                    }
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn value(&self, value: Value) -> Lattice {
+        if value.is_global() {
+            return Lattice::Overdefined;
+        }
+        match self.func.dfg().value(value).kind() {
+            ValueKind::Integer(int) => Lattice::Const(int.value()),
+            // 未定义值在不同的路径上可能不同，不能当作常量
+            ValueKind::Undef(_) | ValueKind::FuncArgRef(_) => Lattice::Overdefined,
+            _ => *self.values.get(&value).unwrap_or(&Lattice::Undefined),
+        }
+    }
+
+    fn set(&mut self, value: Value, new: Lattice) {
+        let old = self.value(value);
+        let new = old.meet(new);
+        if new != old {
+            self.values.insert(value, new);
+            self.value_worklist.push(value);
+        }
+    }
+
+    fn visit(&mut self, inst: Value) {
+        let data = self.func.dfg().value(inst);
+        match data.kind() {
+            ValueKind::Binary(exp) => {
+                let new = match (self.value(exp.lhs()), self.value(exp.rhs())) {
+                    // 除以0时保留运行时的行为
+                    (Lattice::Const(lhs), Lattice::Const(rhs)) => {
+                        binary(exp.op(), lhs, rhs).map_or(Lattice::Overdefined, Lattice::Const)
+                    }
+                    (Lattice::Overdefined, _) | (_, Lattice::Overdefined) => Lattice::Overdefined,
+                    _ => Lattice::Undefined,
+                };
+                self.set(inst, new);
+            }
+            ValueKind::Jump(_) => self.mark_edge((inst, false)),
+            ValueKind::Branch(branch) => match self.value(branch.cond()) {
+                Lattice::Const(cond) => self.mark_edge((inst, cond == 0)),
+                Lattice::Overdefined => {
+                    self.mark_edge((inst, false));
+                    self.mark_edge((inst, true));
+                }
+                Lattice::Undefined => {}
+            },
+            _ if !data.ty().is_unit() => self.set(inst, Lattice::Overdefined),
+            _ => {}
+        }
+    }
+
+    /// Make `edge` executable, or pass its possibly changed arguments again.
+    fn mark_edge(&mut self, edge: Edge) {
+        let (target, _) = edge_target(self.func, edge);
+        self.edges.insert(edge);
+        if self.executable.insert(target) {
+            self.block_worklist.push(target);
+        }
+        let params = self.func.dfg().bb(target).params().to_vec();
+        for (i, param) in params.into_iter().enumerate() {
+            let mut new = Lattice::Undefined;
+            for &edge in &self.incoming[&target] {
+                if self.edges.contains(&edge) {
+                    new = new.meet(self.value(edge_target(self.func, edge).1[i]));
+                }
+            }
+            self.set(param, new);
+        }
+    }
+}
+
+/// Replace the values found to be constant and fold the branches on them.
+fn rewrite(
+    func: &mut FunctionData,
+    values: &HashMap<Value, Lattice>,
+    executable: &HashSet<BasicBlock>,
+) {
+    let mut map = HashMap::new();
+    let mut integers: HashMap<i32, Value> = HashMap::new();
+    for (&value, &lattice) in values {
+        if let Lattice::Const(int) = lattice {
+            let int = *integers
+                .entry(int)
+                .or_insert_with(|| func.dfg_mut().new_value().integer(int));
+            map.insert(value, int);
+        }
+    }
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for &bb in &bbs {
+        let insts: Vec<Value> = func
+            .layout()
+            .bbs()
+            .node(&bb)
+            .unwrap()
+            .insts()
+            .keys()
+            .copied()
+            .collect();
+        for inst in insts {
+            replace_operands(func, inst, &map);
+        }
+    }
+    for &bb in bbs.iter().filter(|bb| executable.contains(bb)) {
+        let term = terminator(func, bb);
+        let ValueKind::Branch(branch) = func.dfg().value(term).kind() else {
+            continue;
+        };
+        let ValueKind::Integer(cond) = func.dfg().value(branch.cond()).kind() else {
+            continue;
+        };
+        let (target, args) = if cond.value() != 0 {
+            (branch.true_bb(), branch.true_args().to_vec())
+        } else {
+            (branch.false_bb(), branch.false_args().to_vec())
+        };
+        func.dfg_mut()
+            .replace_value_with(term)
+            .jump_with_args(target, args);
+    }
+    // 被替换的指令已没有使用者；参数留给死代码消除
+    let folded = map
+        .into_keys()
+        .filter(|&value| func.layout().parent_bb(value).is_some())
+        .collect();
+    remove_insts(func, folded);
+}
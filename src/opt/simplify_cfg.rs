@@ -0,0 +1,202 @@
+//! Control flow graph simplification.
+//!
+//! Repeated until nothing changes:
+//!
+//! - blocks unreachable from the entry are removed;
+//! - branches on a constant, or to the same edge twice, become jumps;
+//! - edges into a block that only jumps on are redirected to its target;
+//! - a block is merged into its only predecessor when that ends in a jump.
+
+use super::dominators::reverse_postorder;
+use super::{remove_insts, replace_operands, terminator, update_edges};
+use koopa::ir::builder::LocalInstBuilder;
+use koopa::ir::{BasicBlock, Function, FunctionData, Value, ValueKind};
+use koopa::opt::FunctionPass;
+use std::collections::{HashMap, HashSet};
+
+pub struct SimplifyCfg;
+
+impl FunctionPass for SimplifyCfg {
+    fn run_on(&mut self, _func: Function, data: &mut FunctionData) {
+        if data.layout().entry_bb().is_none() {
+            return;
+        }
+        while remove_unreachable(data)
+            | fold_branches(data)
+            | thread_jumps(data)
+            | merge_blocks(data)
+        {}
+    }
+}
+
+fn remove_unreachable(func: &mut FunctionData) -> bool {
+    let entry = func.layout().entry_bb().unwrap();
+    let reachable: HashSet<BasicBlock> = reverse_postorder(func, entry).into_iter().collect();
+    let unreachable: Vec<BasicBlock> = func
+        .layout()
+        .bbs()
+        .keys()
+        .copied()
+        .filter(|bb| !reachable.contains(bb))
+        .collect();
+    let mut insts = Vec::new();
+    for &bb in &unreachable {
+        insts.extend(
+            func.layout()
+                .bbs()
+                .node(&bb)
+                .unwrap()
+                .insts()
+                .keys()
+                .copied(),
+        );
+    }
+    remove_insts(func, insts);
+    for &bb in &unreachable {
+        func.layout_mut().bbs_mut().remove(&bb);
+        func.dfg_mut().remove_bb(bb);
+    }
+    !unreachable.is_empty()
+}
+
+/// Turn branches whose target does not depend on the condition into jumps.
+fn fold_branches(func: &mut FunctionData) -> bool {
+    let mut changed = false;
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        let term = terminator(func, bb);
+        let ValueKind::Branch(branch) = func.dfg().value(term).kind() else {
+            continue;
+        };
+        let (target, args) = match func.dfg().value(branch.cond()).kind() {
+            ValueKind::Integer(int) if int.value() != 0 => (branch.true_bb(), branch.true_args()),
+            ValueKind::Integer(_) => (branch.false_bb(), branch.false_args()),
+            _ if branch.true_bb() == branch.false_bb()
+                && branch.true_args() == branch.false_args() =>
+            {
+                (branch.true_bb(), branch.true_args())
+            }
+            _ => continue,
+        };
+        let args = args.to_vec();
+        func.dfg_mut()
+            .replace_value_with(term)
+            .jump_with_args(target, args);
+        changed = true;
+    }
+    changed
+}
+
+/// Redirect the edges into blocks that consist of a single jump. Parameters
+/// of such a block may only be used by its jump, whose arguments then take the
+/// arguments of the redirected edge in their place.
+fn thread_jumps(func: &mut FunctionData) -> bool {
+    let mut changed = false;
+    let entry = func.layout().entry_bb().unwrap();
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        let node = func.layout().bbs().node(&bb).unwrap();
+        if bb == entry || node.insts().len() != 1 {
+            continue;
+        }
+        let jump = terminator(func, bb);
+        let (target, jump_args) = match func.dfg().value(jump).kind() {
+            ValueKind::Jump(jump) if jump.target() != bb => (jump.target(), jump.args().to_vec()),
+            _ => continue,
+        };
+        let params = func.dfg().bb(bb).params().to_vec();
+        if params.iter().any(|&param| used_outside(func, param, jump)) {
+            continue;
+        }
+        let preds: Vec<Value> = func.dfg().bb(bb).used_by().iter().copied().collect();
+        for pred in preds {
+            update_edges(func, pred, |edge_target, args| {
+                if *edge_target == bb {
+                    let map: HashMap<Value, Value> =
+                        params.iter().copied().zip(args.iter().copied()).collect();
+                    *edge_target = target;
+                    *args = jump_args
+                        .iter()
+                        .map(|arg| *map.get(arg).unwrap_or(arg))
+                        .collect();
+                }
+            });
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Whether `value` is used by an instruction other than `inst`.
+fn used_outside(func: &FunctionData, value: Value, inst: Value) -> bool {
+    func.layout().bbs().nodes().any(|node| {
+        node.insts().keys().any(|&user| {
+            user != inst
+                && func
+                    .dfg()
+                    .value(user)
+                    .kind()
+                    .value_uses()
+                    .any(|v| v == value)
+        })
+    })
+}
+
+/// Merge blocks into their only predecessor when it jumps to them
+/// unconditionally. The parameters of the merged block are replaced by the
+/// arguments of the jump.
+fn merge_blocks(func: &mut FunctionData) -> bool {
+    let mut changed = false;
+    let entry = func.layout().entry_bb().unwrap();
+    let bbs: Vec<BasicBlock> = func.layout().bbs().keys().copied().collect();
+    for bb in bbs {
+        if !func.layout().bbs().contains_key(&bb) {
+            continue;
+        }
+        loop {
+            let jump = terminator(func, bb);
+            let (succ, args) = match func.dfg().value(jump).kind() {
+                ValueKind::Jump(jump) => (jump.target(), jump.args().to_vec()),
+                _ => break,
+            };
+            if succ == bb || succ == entry || func.dfg().bb(succ).used_by().len() != 1 {
+                break;
+            }
+            remove_insts(func, vec![jump]);
+            let insts: Vec<Value> = func
+                .layout()
+                .bbs()
+                .node(&succ)
+                .unwrap()
+                .insts()
+                .keys()
+                .copied()
+                .collect();
+            for inst in insts {
+                func.layout_mut().bb_mut(succ).insts_mut().remove(&inst);
+                func.layout_mut()
+                    .bb_mut(bb)
+                    .insts_mut()
+                    .push_key_back(inst)
+                    .unwrap();
+            }
+            let params = func.dfg().bb(succ).params().to_vec();
+            if !params.is_empty() {
+                let map: HashMap<Value, Value> = params.iter().copied().zip(args).collect();
+                let all: Vec<Value> = func
+                    .layout()
+                    .bbs()
+                    .nodes()
+                    .flat_map(|node| node.insts().keys().copied())
+                    .collect();
+                for inst in all {
+                    replace_operands(func, inst, &map);
+                }
+            }
+            func.layout_mut().bbs_mut().remove(&succ);
+            func.dfg_mut().remove_bb(succ);
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,927 @@
+//! Runs every `tests/cases/*.sy` program and checks its output.
+//!
+//! Each case follows the usual course layout: `name.sy` is the program,
+//! `name.in` (optional) its stdin, and `name.out` the expected stdout followed
+//! by the exit code on its own line.
+
+use course_lab::diagnostics::{Diagnostic, ErrorCode, Span};
+use course_lab::difftest::{self, Divergence};
+use course_lab::emulator;
+use course_lab::fuzz::{self, minimize::minimize, random::random_program};
+use course_lab::interpreter::interpret;
+use course_lab::ir_gen::ast::{BlockItem, CompUnit, GlobalItem, Stmt};
+use course_lab::koopa2asm::assemble::{assemble, AssembleError, RelocKind, Section};
+use course_lab::koopa2asm::elf::write_elf;
+use course_lab::koopa2asm::generate;
+use course_lab::koopa2asm::riscv::RvInst;
+use course_lab::opt::{optimize, PassManager};
+use course_lab::sysy::CompUnitParser;
+use koopa::back::KoopaGenerator;
+use koopa::ir::Program;
+use std::fs;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Optimization levels every case is run at.
+const OPT_LEVELS: [u32; 3] = [0, 1, 2];
+
+/// Upper bound on the instructions a case may run on the emulator.
+const STEP_LIMIT: u64 = 100_000_000;
+
+/// Compile `source` at `opt_level` to Koopa IR.
+fn compile(source: &str, opt_level: u32) -> Result<Program, String> {
+    let mut ast = CompUnitParser::new()
+        .parse(source)
+        .map_err(|err| format!("parse error: {0:?}", err))?;
+    let render = |diagnostics: Vec<Diagnostic>| {
+        diagnostics
+            .iter()
+            .map(|d| d.render("<case>", source))
+            .collect::<String>()
+    };
+    let symbols = ast.analyze().map_err(render)?;
+    let mut program = ast.generate_koopa(&symbols).map_err(render)?;
+    optimize(&mut program, opt_level);
+    // -koopa 输出的文本必须能被重新解析
+    let mut generator = KoopaGenerator::new(Vec::new());
+    generator.generate_on(&program).unwrap();
+    let text = String::from_utf8(generator.writer()).unwrap();
+    koopa::front::Driver::from(text)
+        .generate_program()
+        .map_err(|err| format!("emitted Koopa IR does not parse: {0:?}", err))?;
+    Ok(program)
+}
+
+/// Format the output and return value of a program as a `.out` file.
+fn expected_output(output: &[u8], ret: i32) -> String {
+    let mut out = String::from_utf8_lossy(output).into_owned();
+    // 与评测脚本一致：输出不以换行结尾时补一个换行，退出码取低8位
+    if !out.is_empty() && !out.ends_with('\n') {
+        out.push('\n');
+    }
+    out += &format!("{0}\n", ret & 0xff);
+    out
+}
+
+/// Compile `source` at `opt_level` and run it on the Koopa IR interpreter,
+/// producing the text a `.out` file should contain.
+fn run(source: &str, input: &[u8], opt_level: u32) -> Result<String, String> {
+    let program = compile(source, opt_level)?;
+    let mut output = Vec::new();
+    let ret = interpret(&program, input, &mut output).map_err(|err| err.to_string())?;
+    Ok(expected_output(&output, ret))
+}
+
+/// Compile `source` at `opt_level` to RISC-V and run it on the emulator.
+fn run_riscv(source: &str, input: &[u8], opt_level: u32) -> Result<String, String> {
+    let program = compile(source, opt_level)?;
+    let obj = assemble(&generate(&program, opt_level > 0)).map_err(|err| err.to_string())?;
+    let mut output = Vec::new();
+    let exit =
+        emulator::run(&obj, input, &mut output, Some(STEP_LIMIT)).map_err(|err| err.to_string())?;
+    Ok(expected_output(&output, exit.code))
+}
+
+fn cases() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/cases");
+    let mut cases: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "sy"))
+        .collect();
+    cases.sort();
+    cases
+}
+
+#[test]
+fn testcases() {
+    let cases = cases();
+    assert!(!cases.is_empty(), "no test cases found");
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy();
+        let source = fs::read_to_string(case).unwrap();
+        let input = fs::read(case.with_extension("in")).unwrap_or_default();
+        let expected = fs::read_to_string(case.with_extension("out"))
+            .unwrap_or_else(|_| panic!("{0}: missing .out file", name));
+        for opt_level in OPT_LEVELS {
+            let runs = [
+                ("koopa", run(&source, &input, opt_level)),
+                ("riscv", run_riscv(&source, &input, opt_level)),
+            ];
+            for (target, result) in runs {
+                match result {
+                    Ok(actual) if actual.trim_end() == expected.trim_end() => {}
+                    Ok(actual) => failures.push(format!(
+                        "{0} (-O{1}, {2}): output mismatch\n--- expected\n{3}--- actual\n{4}",
+                        name, opt_level, target, expected, actual
+                    )),
+                    Err(err) => failures.push(format!(
+                        "{0} (-O{1}, {2}): {3}",
+                        name, opt_level, target, err
+                    )),
+                }
+            }
+        }
+    }
+    report(&failures, cases.len());
+}
+
+/// Fail with every collected failure, so that one broken case does not
+/// hide the others.
+fn report(failures: &[String], total: usize) {
+    assert!(
+        failures.is_empty(),
+        "{0} of {1} cases failed:\n\n{2}",
+        failures.len(),
+        total,
+        failures.join("\n")
+    );
+}
+
+/// A reader that fails, standing for input the program must not wait for.
+struct Unreadable;
+
+impl Read for Unreadable {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other(
+            "read past the input the program needs",
+        ))
+    }
+}
+
+#[test]
+fn lazy_input() {
+    // 运行时库函数只读入需要的输入
+    let program = compile(
+        "int main() { int n = getint(); putch(getch()); putint(n); return getch(); }",
+        0,
+    )
+    .unwrap();
+    let input = || BufReader::new(b"  -12 x".chain(Unreadable));
+    let mut output = Vec::new();
+    assert_eq!(interpret(&program, input(), &mut output), Ok(120));
+    assert_eq!(output, b" -12");
+
+    let obj = assemble(&generate(&program, false)).unwrap();
+    let mut output = Vec::new();
+    let exit = emulator::run(&obj, input(), &mut output, Some(STEP_LIMIT)).unwrap();
+    assert_eq!(exit.code, 120);
+    assert_eq!(output, b" -12");
+}
+
+#[test]
+fn format() {
+    // 格式化后的程序行为不变，再次格式化结果不变
+    let cases = cases();
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy();
+        let source = fs::read_to_string(case).unwrap();
+        let input = fs::read(case.with_extension("in")).unwrap_or_default();
+        let expected = fs::read_to_string(case.with_extension("out")).unwrap();
+        let formatted = CompUnitParser::new().parse(&source).unwrap().to_string();
+        let reformatted = match CompUnitParser::new().parse(&formatted) {
+            Ok(unit) => unit.to_string(),
+            Err(err) => {
+                failures.push(format!(
+                    "{0}: formatted code does not parse: {1:?}",
+                    name, err
+                ));
+                continue;
+            }
+        };
+        if formatted != reformatted {
+            failures.push(format!(
+                "{0}: formatting is not idempotent\n--- first\n{1}\n--- second\n{2}",
+                name, formatted, reformatted
+            ));
+        }
+        match run(&formatted, &input, 0) {
+            Ok(actual) if actual.trim_end() == expected.trim_end() => {}
+            Ok(actual) => failures.push(format!(
+                "{0}: output mismatch\n--- expected\n{1}--- actual\n{2}",
+                name, expected, actual
+            )),
+            Err(err) => failures.push(format!("{0}: {1}", name, err)),
+        }
+    }
+    report(&failures, cases.len());
+
+    let source = "int f(int a[][2]){if(a[0][1])if(1)return -(-1);else{}else while(!0)break;}";
+    let mut unit = CompUnitParser::new().parse(source).unwrap();
+    let expected = "\
+int f(int a[][2]) {
+  if (a[0][1])
+    if (1)
+      return -(-1);
+    else {}
+  else
+    while (!0)
+      break;
+}";
+    assert_eq!(unit.to_string(), expected);
+    // 去掉内层的else后，外层的else不能被内层的if吞掉
+    let GlobalItem::Func(func_def) = &mut unit.global_item else {
+        unreachable!()
+    };
+    let BlockItem::Stmt(Stmt::IF(outer)) = &mut func_def.block.items[0] else {
+        unreachable!()
+    };
+    let Stmt::IF(inner) = &mut outer.then_stmt else {
+        unreachable!()
+    };
+    inner.else_stmt = None;
+    let expected = "\
+int f(int a[][2]) {
+  if (a[0][1]) {
+    if (1)
+      return -(-1);
+  } else
+    while (!0)
+      break;
+}";
+    assert_eq!(unit.to_string(), expected);
+    let reparsed = CompUnitParser::new().parse(expected).unwrap();
+    assert_eq!(reparsed.to_string(), expected);
+
+    // 嵌套的一元运算符
+    let source = "int main(){return - -1+ + +2-(-3)- - -4+-+!-5;}";
+    let expected = "int main() {\n  return - -1 + + +2 - (-3) - - -4 + -+!-5;\n}";
+    let formatted = CompUnitParser::new().parse(source).unwrap().to_string();
+    assert_eq!(formatted, expected);
+    let reparsed = CompUnitParser::new().parse(&formatted).unwrap();
+    assert_eq!(reparsed.to_string(), expected);
+    assert_eq!(run(&formatted, &[], 0).unwrap(), "2\n");
+}
+
+#[test]
+fn ast_tree() {
+    // 写出的树读回后与原来的AST相同，包括源码位置
+    let cases = cases();
+    let mut failures = Vec::new();
+    for case in &cases {
+        let name = case.file_stem().unwrap().to_string_lossy();
+        let source = fs::read_to_string(case).unwrap();
+        let ast = CompUnitParser::new().parse(&source).unwrap();
+        let trees = [
+            ("json", CompUnit::from_json(&ast.to_json())),
+            ("sexp", CompUnit::from_sexp(&ast.to_sexp())),
+        ];
+        for (format, result) in trees {
+            match result {
+                Ok(tree) if tree == ast => {}
+                Ok(_) => failures.push(format!("{0} ({1}): tree read back differs", name, format)),
+                Err(err) => failures.push(format!("{0} ({1}): {2}", name, format, err.message)),
+            }
+        }
+    }
+    report(&failures, cases.len());
+
+    let ast = CompUnitParser::new()
+        .parse("int main() { return -x[1] + 2; }")
+        .unwrap();
+    let expected = "\
+(CompUnit
+  :version 1
+  :items (
+    (FuncDef
+      :span (4 8)
+      :type \"int\"
+      :name \"main\"
+      :params ()
+      :body (Block
+        :items (
+          (Return
+            :span (13 19)
+            :value (Exp
+              :span (20 29)
+              :value (Add
+                :op \"+\"
+                :lhs (Unary
+                  :op \"-\"
+                  :operand (LVal
+                    :span (21 22)
+                    :name \"x\"
+                    :indices (
+                      (Exp
+                        :span (23 24)
+                        :value (Number :value 1)))))
+                :rhs (Number :value 2)))))))))
+";
+    assert_eq!(ast.to_sexp(), expected);
+
+    // 缺省的span为空，优先级链中较低的层次要用Paren包起来
+    let json = r#"{"kind": "CompUnit", "version": 1, "items": [
+        {"kind": "FuncDef", "type": "int", "name": "main", "params": [],
+         "body": {"kind": "Block", "items": [
+            {"kind": "Return", "value": {"kind": "Exp", "value":
+                {"kind": "Mul", "op": "*", "lhs": {"kind": "Number", "value": 6},
+                 "rhs": {"kind": "Paren", "exp": {"kind": "Exp", "value":
+                    {"kind": "Add", "op": "-", "lhs": {"kind": "Number", "value": 9},
+                     "rhs": {"kind": "Number", "value": 2}}}}}}}]}}]}"#;
+    let unit = CompUnit::from_json(json).unwrap();
+    assert_eq!(unit.to_string(), "int main() {\n  return 6 * (9 - 2);\n}");
+    let invalid = [
+        (
+            json.replace(
+                r#"{"kind": "Number", "value": 6}"#,
+                r#"{"kind": "LOr", "lhs": {"kind": "Number", "value": 6}, "rhs": {"kind": "Number", "value": 0}}"#,
+            ),
+            "`LOr` cannot be an operand here without a `Paren`",
+        ),
+        (
+            json.replace(r#""version": 1"#, r#""version": 2"#),
+            "unsupported schema version 2",
+        ),
+        (
+            json.replace(r#""op": "*""#, r#""op": "+""#),
+            "must be one of",
+        ),
+        (
+            json.replace(r#""params": []"#, r#""args": []"#),
+            "has no field `args`",
+        ),
+        (json.replace("[\n", "[,"), "unexpected `,`"),
+        (
+            json.replace(r#""name": "main""#, r#""name": "ma in""#),
+            "`ma in` is not an identifier",
+        ),
+        (
+            json.replace(r#""name": "main""#, r#""name": "while""#),
+            "the keyword `while` cannot be a name",
+        ),
+        (
+            json.replace(r#""value": 6"#, r#""value": -6"#),
+            "must be an integer from 0 to 2147483647",
+        ),
+        (
+            json.replace(r#""value": 6"#, r#""value": 2147483648"#),
+            "or 2147483648 after a unary `-`",
+        ),
+        ("[".repeat(200_000), "nested more than 1000 levels deep"),
+    ];
+    for (json, message) in invalid {
+        let diagnostic = CompUnit::from_json(&json).unwrap_err();
+        assert_eq!(diagnostic.code, ErrorCode::InvalidAst);
+        assert!(
+            diagnostic.message.contains(message),
+            "{0}",
+            diagnostic.message
+        );
+    }
+    let diagnostic = CompUnit::from_sexp(&"(".repeat(200_000)).unwrap_err();
+    assert!(diagnostic
+        .message
+        .contains("nested more than 1000 levels deep"));
+
+    // -2147483648的字面量写为其无符号值，读回后不变
+    let ast = CompUnitParser::new()
+        .parse("int main() { return -2147483648; }")
+        .unwrap();
+    assert!(ast.to_json().contains(r#""value": 2147483648"#));
+    assert_eq!(CompUnit::from_json(&ast.to_json()).unwrap(), ast);
+}
+
+/// Programs rejected by semantic analysis, with the error each should report.
+const SEMANTIC_ERRORS: &[(&str, ErrorCode)] = &[
+    ("int main() { return x; }", ErrorCode::UndeclaredIdentifier),
+    (
+        "int main() { int a; int a; return 0; }",
+        ErrorCode::Redefinition,
+    ),
+    (
+        "int main() { const int c = 1; c = 2; return 0; }",
+        ErrorCode::AssignToConst,
+    ),
+    ("int main() { int f; return f(); }", ErrorCode::NotAFunction),
+    ("int main() { return main; }", ErrorCode::NotAVariable),
+    (
+        "int main() { putint(); return 0; }",
+        ErrorCode::ArgumentCount,
+    ),
+    ("int main() { break; }", ErrorCode::OutsideLoop),
+    (
+        "int main() { int x; return getarray(x); }",
+        ErrorCode::TypeMismatch,
+    ),
+    (
+        "void f(int a[][3]) {} int main() { int a[3]; f(a); return 0; }",
+        ErrorCode::TypeMismatch,
+    ),
+    (
+        "int main() { int a[2]; return a; }",
+        ErrorCode::TypeMismatch,
+    ),
+    ("int main() { return; }", ErrorCode::TypeMismatch),
+    ("int main() { return putint(1); }", ErrorCode::VoidValue),
+    (
+        "int main() { int x = 1; return x[0]; }",
+        ErrorCode::InvalidSubscript,
+    ),
+    (
+        "int main() { int a[2]; return a[0][0]; }",
+        ErrorCode::InvalidSubscript,
+    ),
+    (
+        "int main() { int a[-1]; return 0; }",
+        ErrorCode::InvalidArraySize,
+    ),
+    (
+        "int n; int a[n]; int main() { return 0; }",
+        ErrorCode::NotConstant,
+    ),
+    (
+        "int g = getint(); int main() { return g; }",
+        ErrorCode::NotConstant,
+    ),
+    (
+        "const int c = 1 / (2 - 2); int main() { return 0; }",
+        ErrorCode::DivisionByZero,
+    ),
+    (
+        "const int a[2] = {1}; const int b = a[2]; int main() { return 0; }",
+        ErrorCode::IndexOutOfBounds,
+    ),
+    (
+        "const int a[2][2] = {1, 2, 3, 4, 5}; int main() { return 0; }",
+        ErrorCode::InvalidInitializer,
+    ),
+    (
+        "int main() { int a[2][2] = {{1, 2, 3}, 4}; return 0; }",
+        ErrorCode::InvalidInitializer,
+    ),
+    (
+        "const int a[2][2] = {{1}, {2, 3, 4}}; int main() { return 0; }",
+        ErrorCode::InvalidInitializer,
+    ),
+    (
+        "const int a[2][2] = {{1}, {2}, {}}; int main() { return 0; }",
+        ErrorCode::InvalidInitializer,
+    ),
+    (
+        "const int a[3] = {1, {2}, 3}; int main() { return a[2]; }",
+        ErrorCode::InvalidInitializer,
+    ),
+    (
+        "int main() { int a[2][2] = {{1, {2}}}; return 0; }",
+        ErrorCode::InvalidInitializer,
+    ),
+];
+
+/// Random programs checked at every optimization level.
+const RANDOM_PROGRAMS: u64 = 40;
+
+#[test]
+fn fuzz() {
+    let mut failures = Vec::new();
+    for seed in 0..RANDOM_PROGRAMS {
+        let unit = random_program(seed);
+        for opt_level in OPT_LEVELS {
+            if let Err(failure) = fuzz::check(&unit, opt_level) {
+                failures.push(format!(
+                    "seed {0} (-O{1}): {2}\n{3}",
+                    seed, opt_level, failure, unit
+                ));
+            }
+        }
+    }
+    report(&failures, RANDOM_PROGRAMS as usize);
+}
+
+#[test]
+fn minimize_random() {
+    // 保留含有while循环且仍然正确的程序，结果应当只剩很少的代码
+    let interesting =
+        |unit: &CompUnit| unit.to_string().contains("while") && fuzz::check(unit, 0).is_ok();
+    let unit = (0..RANDOM_PROGRAMS)
+        .map(random_program)
+        .find(|unit| interesting(unit))
+        .unwrap();
+    let minimized = minimize(unit, interesting);
+    let source = minimized.to_string();
+    assert!(interesting(&minimized), "{0}", source);
+    assert!(source.lines().count() <= 10, "{0}", source);
+}
+
+#[test]
+fn difftest() {
+    let source = "int main() { putint(7); return 3; }";
+    let reference = compile(source, 0).unwrap();
+    let program = compile(source, 2).unwrap();
+    assert_eq!(
+        difftest::compare(&reference, &program, &[], true, None).unwrap(),
+        3
+    );
+    // 行为未定义的程序不能比较
+    let program = compile("int main() { int z = 0; return 1 / z; }", 0).unwrap();
+    assert!(matches!(
+        difftest::compare(&program, &program, &[], false, None),
+        Err(Divergence::Reference(_))
+    ));
+}
+
+#[test]
+fn semantic_errors() {
+    let mut failures = Vec::new();
+    for (source, code) in SEMANTIC_ERRORS {
+        let mut ast = CompUnitParser::new().parse(source).unwrap();
+        let codes: Vec<ErrorCode> = match ast.analyze() {
+            Ok(_) => vec![],
+            Err(diagnostics) => diagnostics.iter().map(|d| d.code).collect(),
+        };
+        if codes != [*code] {
+            failures.push(format!(
+                "{0}: expected {1:?}, got {2:?}",
+                source, code, codes
+            ));
+        }
+    }
+    report(&failures, SEMANTIC_ERRORS.len());
+
+    // 报错中的类型按SysY的写法显示
+    let source = "int f(int a[][3]) { return 0; }
+int main() { int b[2][3]; int c[3]; return f(c) + b; }";
+    let mut ast = CompUnitParser::new().parse(source).unwrap();
+    let messages: Vec<String> = ast
+        .analyze()
+        .unwrap_err()
+        .into_iter()
+        .map(|d| d.message)
+        .collect();
+    assert_eq!(
+        messages,
+        [
+            "argument 1 of `f` expects `int[][3]`, found `int[]`",
+            "expected `int`, found `int[][3]`",
+        ]
+    );
+}
+
+#[test]
+fn diagnostics() {
+    // 非ASCII字符上的错误按字符定位与标注
+    let source = "int main() {\n  // 注释\n  return 1 中 2;\n}";
+    let err = CompUnitParser::new().parse(source).unwrap_err();
+    let diagnostic = Diagnostic::from_parse_error(err, source);
+    assert_eq!(diagnostic.code, ErrorCode::InvalidToken);
+    assert_eq!(&source[diagnostic.span.start..diagnostic.span.end], "中");
+    let expected = "\
+error[E0001]: invalid token at 3:12
+ --> a.sy:3:12
+  |
+3 |   return 1 中 2;
+  |            ^
+";
+    assert_eq!(diagnostic.render("a.sy", source), expected);
+    // 落在字符中间的位置扩展到整个字符
+    let inside = Diagnostic::new(ErrorCode::InvalidToken, Span::new(37, 38), "invalid token");
+    assert_eq!(inside.render("a.sy", source), expected);
+
+    // 超过i32范围的字面量只有紧跟在负号后的2147483648可以接受
+    let literals = [
+        ("-2147483648", Some(i32::MIN)),
+        ("-0x80000000", Some(i32::MIN)),
+        ("- -2147483648", Some(i32::MIN)),
+        ("2147483647", Some(i32::MAX)),
+        ("2147483648", None),
+        ("+2147483648", None),
+        ("!2147483648", None),
+        ("-(2147483648)", None),
+        ("1 - 2147483648", None),
+        ("2 * -2147483648", Some(0)),
+        ("-2147483649", None),
+        ("3000000000", None),
+        ("4294967295", None),
+        ("037777777777", None),
+    ];
+    for (literal, value) in literals {
+        let source = format!("int main() {{ return {0}; }}", literal);
+        match (CompUnitParser::new().parse(&source), value) {
+            (Ok(_), Some(value)) => {
+                let actual = run(&source, &[], 1).unwrap();
+                assert_eq!(actual, format!("{0}\n", value & 0xff), "{0}", literal);
+            }
+            (Err(err), None) => {
+                let diagnostic = Diagnostic::from_parse_error(err, &source);
+                assert_eq!(diagnostic.code, ErrorCode::IntegerOverflow, "{0}", literal);
+                let span = &source[diagnostic.span.start..diagnostic.span.end];
+                assert!(literal.contains(span), "{0}: {1}", literal, span);
+                assert!(
+                    span.starts_with(|c: char| c.is_ascii_digit()),
+                    "{0}",
+                    literal
+                );
+            }
+            (result, _) => panic!("{0}: {1:?}", literal, result.map(|_| ())),
+        }
+    }
+}
+
+#[test]
+fn pass_manager() {
+    assert!(PassManager::new(&["mem2reg", "nonexistent"]).is_err());
+
+    let mut ast = CompUnitParser::new()
+        .parse("int main() { int x = 1; while (x < 10) x = x * 2; return x; }")
+        .unwrap();
+    let symbols = ast.analyze().unwrap();
+    let mut program = ast.generate_koopa(&symbols).unwrap();
+    let mut passman = PassManager::new(&["mem2reg"]).unwrap();
+    passman.print_after = vec!["mem2reg".to_string()];
+    passman.time_passes = true;
+    let mut log = Vec::new();
+    passman.run(&mut program, &mut log).unwrap();
+    let log = String::from_utf8(log).unwrap();
+
+    // 输出的IR中变量已被提升，且仍能被重新解析
+    let (dump, timing) = log.split_once("===== pass timing =====").unwrap();
+    let dump = dump.strip_prefix("// *** IR after mem2reg ***\n").unwrap();
+    assert!(!dump.contains("alloc"), "{0}", dump);
+    koopa::front::Driver::from(dump.to_string())
+        .generate_program()
+        .unwrap();
+    assert!(timing.contains("mem2reg") && timing.contains("total"));
+}
+
+/// Compile `source` without optimizations, run `passes` on it and return
+/// the IR printed after the last run of `print_after`.
+fn ir_after(source: &str, passes: &[&str], print_after: &str) -> String {
+    let mut program = compile(source, 0).unwrap();
+    let mut passman = PassManager::new(passes).unwrap();
+    passman.print_after = vec![print_after.to_string()];
+    let mut log = Vec::new();
+    passman.run(&mut program, &mut log).unwrap();
+    let log = String::from_utf8(log).unwrap();
+    let header = format!("// *** IR after {0} ***\n", print_after);
+    log.rsplit_once(&header).unwrap().1.to_string()
+}
+
+/// Run `passes` on the Koopa IR `text` and return the IR they leave.
+fn run_passes(text: &str, passes: &[&str]) -> String {
+    let mut program = koopa::front::Driver::from(text).generate_program().unwrap();
+    let mut passman = PassManager::new(passes).unwrap();
+    passman.run(&mut program, &mut std::io::sink()).unwrap();
+    let mut generator = KoopaGenerator::new(Vec::new());
+    generator.generate_on(&program).unwrap();
+    String::from_utf8(generator.writer()).unwrap()
+}
+
+/// The basic blocks of `func` in the Koopa IR `ir`: the name of each block
+/// with its instructions.
+fn blocks(ir: &str, func: &str) -> Vec<(String, Vec<String>)> {
+    let start = ir.find(&format!("fun @{0}(", func)).unwrap();
+    let body = &ir[start..];
+    let body = &body[..body.find("\n}").unwrap()];
+    let mut blocks: Vec<(String, Vec<String>)> = Vec::new();
+    for line in body.lines().skip(1).filter(|line| !line.is_empty()) {
+        match line.strip_prefix("  ") {
+            Some(inst) => blocks.last_mut().unwrap().1.push(inst.to_string()),
+            None => {
+                let name = line.split(['(', ':']).next().unwrap();
+                blocks.push((name.to_string(), Vec::new()));
+            }
+        }
+    }
+    blocks
+}
+
+#[test]
+fn dead_code() {
+    let text = "fun @main(): i32 {
+%entry:
+  %dead = add 1, 2
+  %live = add 3, 4
+  br 0, %unreachable, %empty
+%empty:
+  jump %exit
+%exit:
+  ret %live
+%unreachable:
+  ret 1
+}
+";
+    // 没有使用者的add被删去，用于返回值的add保留
+    let ir = run_passes(text, &["dce"]);
+    let insts: Vec<String> = blocks(&ir, "main")
+        .into_iter()
+        .flat_map(|(_, insts)| insts)
+        .collect();
+    assert_eq!(
+        insts.iter().filter(|inst| inst.contains("add")).count(),
+        1,
+        "{0}",
+        ir
+    );
+    assert!(insts.contains(&"%live = add 3, 4".to_string()), "{0}", ir);
+
+    // 常量条件的分支变为跳转，之后不可达的块被删去，只有跳转的块被合并，
+    // 最后只剩入口块
+    let ir = run_passes(text, &["simplifycfg"]);
+    let main = blocks(&ir, "main");
+    assert_eq!(main.len(), 1, "{0}", ir);
+    assert_eq!(main[0].0, "%entry");
+    assert!(!ir.contains("ret 1"), "{0}", ir);
+    assert_eq!(main[0].1.last().unwrap(), "ret %live", "{0}", ir);
+}
+
+#[test]
+fn constant_propagation() {
+    // 常量条件的分支只剩下一条跳转，两侧汇合处的值折叠进ret
+    let source = "int main() {
+  int a = 3, r;
+  if (a > 1) r = a * 2; else r = a - 4;
+  return r;
+}";
+    for pass in ["sccp", "constprop"] {
+        let ir = ir_after(source, &["mem2reg", pass], "sccp");
+        let main = blocks(&ir, "main");
+        let insts: Vec<&String> = main.iter().flat_map(|(_, insts)| insts).collect();
+        assert!(insts.iter().all(|inst| !inst.starts_with("br ")), "{0}", ir);
+        assert_eq!(main[0].1, ["jump %then"], "{0}", ir);
+        assert_eq!(main.last().unwrap().1, ["ret 6"], "{0}", ir);
+        assert!(insts.iter().all(|inst| !inst.contains("mul")), "{0}", ir);
+    }
+}
+
+#[test]
+fn inlining() {
+    // 小的非递归函数被内联，递归函数保持原样
+    let source = "int add(int a, int b) { return a + b; }
+int fact(int n) { if (n < 2) return 1; return n * fact(n - 1); }
+int main() { return add(1, 2) + fact(3); }";
+    let ir = ir_after(source, &["inline"], "inline");
+    let calls = |func| -> Vec<String> {
+        blocks(&ir, func)
+            .into_iter()
+            .flat_map(|(_, insts)| insts)
+            .filter(|inst| inst.contains("call "))
+            .collect()
+    };
+    let main = calls("main");
+    assert!(main.iter().all(|call| !call.contains("@add(")), "{0}", ir);
+    assert!(main.iter().any(|call| call.contains("@fact(")), "{0}", ir);
+    assert_eq!(calls("fact").len(), 1, "{0}", ir);
+    assert!(calls("fact")[0].contains("call @fact("), "{0}", ir);
+    assert_eq!(run(source, &[], 2).unwrap(), "9\n");
+}
+
+#[test]
+fn loop_optimizations() {
+    // 循环不变的乘法移到循环之前，循环体中只剩累加
+    let source = "int main() {
+  int n = getint(), k = getint(), i = 0, s = 0;
+  while (i < n) { s = s + k * k; i = i + 1; }
+  return s;
+}";
+    let ir = ir_after(source, &["mem2reg", "licm"], "licm");
+    let main = blocks(&ir, "main");
+    let body = |name| &main.iter().find(|(bb, _)| bb == name).unwrap().1;
+    assert!(
+        body("%entry").iter().any(|inst| inst.contains(" = mul ")),
+        "{0}",
+        ir
+    );
+    assert!(
+        body("%while_body").iter().all(|inst| !inst.contains("mul")),
+        "{0}",
+        ir
+    );
+    assert_eq!(run(source, b"3 4", 2).unwrap(), "48\n");
+    assert_eq!(run_riscv(source, b"3 4", 2).unwrap(), "48\n");
+
+    // 按下标访问数组变为每次迭代前进一个元素的指针，不再计算下标乘元素大小
+    let source = "int a[10];
+int main() {
+  int i = 0, s = 0;
+  while (i < 10) { a[i] = i; s = s + a[i]; i = i + 1; }
+  return s;
+}";
+    let ir = ir_after(source, &["mem2reg", "loop-reduce"], "loop-reduce");
+    let main = blocks(&ir, "main");
+    let body = &main.iter().find(|(bb, _)| bb == "%while_body").unwrap().1;
+    assert!(
+        body.iter().all(|inst| !inst.contains("getelemptr")),
+        "{0}",
+        ir
+    );
+    assert!(
+        body.iter()
+            .any(|inst| inst.contains("getptr") && inst.ends_with(", 1")),
+        "{0}",
+        ir
+    );
+    assert_eq!(run(source, &[], 2).unwrap(), "45\n");
+    assert_eq!(run_riscv(source, &[], 2).unwrap(), "45\n");
+}
+
+#[test]
+fn value_numbering() {
+    // 中间没有写内存时，同一全局变量的两次读取合并为一次；
+    // 可能写到该变量的调用或存储之后要重新读取
+    let source = "int g, h[2];
+void set() { g = 1; }
+int same() { int a = g; int b = g; return a + b; }
+int called() { int a = g; set(); int b = g; return a + b; }
+int stored(int p[]) { int a = h[0]; p[0] = 1; int b = h[0]; return a + b; }
+int main() { return same() + called() + stored(h); }";
+    let ir = ir_after(source, &["mem2reg", "gvn"], "gvn");
+    let loads = |func| {
+        blocks(&ir, func)
+            .into_iter()
+            .flat_map(|(_, insts)| insts)
+            .filter(|inst| inst.contains(" = load "))
+            .count()
+    };
+    assert_eq!(loads("same"), 1, "{0}", ir);
+    assert_eq!(loads("called"), 2, "{0}", ir);
+    assert_eq!(loads("stored"), 2, "{0}", ir);
+    assert_eq!(run(source, &[], 2).unwrap(), "2\n");
+}
+
+#[test]
+fn block_layout() {
+    // 定义所在的基本块排在使用之后时，值的位置不依赖布局的先后，调用也不会重复
+    let text = "decl @getint(): i32
+
+fun @main(): i32 {
+%entry:
+  jump %def
+%def:
+  %x = call @getint()
+  jump %use
+%use:
+  %y = mul %x, 10
+  ret %y
+}
+";
+    let mut program = koopa::front::Driver::from(text).generate_program().unwrap();
+    let main = *program.func_layout().last().unwrap();
+    let layout = program.func_mut(main).layout_mut();
+    let def = *layout.bbs().keys().nth(1).unwrap();
+    let insts: Vec<_> = layout.bb_mut(def).insts().keys().copied().collect();
+    layout.bbs_mut().remove(&def);
+    layout.bbs_mut().push_key_back(def).unwrap();
+    for inst in insts {
+        layout.bb_mut(def).insts_mut().push_key_back(inst).unwrap();
+    }
+    for optimize in [false, true] {
+        let obj = assemble(&generate(&program, optimize)).unwrap();
+        let mut output = Vec::new();
+        let exit = emulator::run(&obj, &b"5 6"[..], &mut output, Some(STEP_LIMIT)).unwrap();
+        assert_eq!(exit.code, 50, "optimize: {0}", optimize);
+    }
+}
+
+#[test]
+fn object_file() {
+    let mut ast = CompUnitParser::new()
+        .parse("int g = 3; int main() { putint(g); return 0; }")
+        .unwrap();
+    let symbols = ast.analyze().unwrap();
+    let mut program = ast.generate_koopa(&symbols).unwrap();
+    optimize(&mut program, 1);
+    let obj = assemble(&generate(&program, true)).unwrap();
+
+    // 函数与全局变量有定义，运行时库函数留给链接器
+    let section = |name: &str| {
+        let symbol = obj.symbols.iter().find(|s| s.name == name).unwrap();
+        symbol.section
+    };
+    assert_eq!(section("main"), Some(Section::Text));
+    assert_eq!(section("global_var_1"), Some(Section::Data));
+    assert_eq!(section("putint"), None);
+    assert_eq!(obj.data, 3i32.to_le_bytes());
+    let kinds: Vec<RelocKind> = obj.relocations.iter().map(|r| r.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![RelocKind::Hi20, RelocKind::Lo12I, RelocKind::Call]
+    );
+
+    let elf = write_elf(&obj);
+    assert_eq!(&elf[..6], b"\x7fELF\x01\x01");
+    // e_type为ET_REL，e_machine为EM_RISCV
+    assert_eq!(&elf[16..20], &[1, 0, 243, 0]);
+
+    // 全零的全局变量放入.bss，只记录大小
+    let program = compile("int a[268435456]; int main() { return a[3]; }", 1).unwrap();
+    let obj = assemble(&generate(&program, true)).unwrap();
+    let symbol = obj
+        .symbols
+        .iter()
+        .find(|s| s.name == "global_var_1")
+        .unwrap();
+    assert_eq!((symbol.section, symbol.size), (Some(Section::Bss), 1 << 30));
+    assert!(obj.data.is_empty());
+    assert!(write_elf(&obj).len() < 4096);
+
+    let label = || RvInst::Label(String::from("l"));
+    assert_eq!(
+        assemble(&[RvInst::Text, label(), RvInst::Ret, label()]).unwrap_err(),
+        AssembleError::DuplicateLabel(String::from("l"))
+    );
+    assert_eq!(
+        assemble(&[RvInst::Text, RvInst::J(String::from("l"))]).unwrap_err(),
+        AssembleError::UndefinedLabel(String::from("l"))
+    );
+}